@@ -0,0 +1,125 @@
+//! Detects which version of the rustc APIs the toolchain provides, and sets
+//! the corresponding `cfg`s used by the shims in `src/rustc_interface`.
+//!
+//! Rather than mapping nightly dates to API versions, each change is detected
+//! by checking whether a small program using the newer API compiles.
+
+use std::{
+    env,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+/// A change in the rustc APIs, and a program that compiles iff it applies.
+struct ApiChange {
+    cfg: &'static str,
+    probe: Probe,
+}
+
+enum Probe {
+    /// The change applies if the program compiles, e.g. it uses an added item
+    Compiles(&'static str),
+    /// The change applies if the program fails to compile, e.g. it uses a
+    /// removed item
+    Fails(&'static str),
+}
+
+const API_CHANGES: &[ApiChange] = &[
+    // `LocationTable` was renamed to `PoloniusLocationTable`
+    ApiChange {
+        cfg: "pcs_polonius_location_table",
+        probe: Probe::Compiles(
+            "extern crate rustc_borrowck;
+                pub use rustc_borrowck::consumers::PoloniusLocationTable;",
+        ),
+    },
+    // `AnalysisDomain` was merged into `Analysis`
+    ApiChange {
+        cfg: "pcs_no_analysis_domain",
+        probe: Probe::Fails(
+            "extern crate rustc_mir_dataflow;
+             pub use rustc_mir_dataflow::AnalysisDomain;",
+        ),
+    },
+    // `apply_before_*_effect` and `apply_*_effect` were renamed to
+    // `apply_early_*_effect` and `apply_primary_*_effect`
+    ApiChange {
+        cfg: "pcs_early_effects",
+        probe: Probe::Compiles(
+            "extern crate rustc_middle;
+                extern crate rustc_mir_dataflow;
+                pub fn f<'tcx, A: rustc_mir_dataflow::Analysis<'tcx>>(
+                    a: &mut A,
+                    state: &mut A::Domain,
+                    statement: &rustc_middle::mir::Statement<'tcx>,
+                    location: rustc_middle::mir::Location,
+                ) {
+                    a.apply_early_statement_effect(state, statement, location);
+                }",
+        ),
+    },
+];
+
+/// A program that compiles with every toolchain. A [`Probe::Fails`] program
+/// failing to compile is only trusted if this one compiles, since the probes
+/// also fail if e.g. rustc cannot be run or the `rustc-dev` component is
+/// missing.
+const CONTROL_PROBE: &str = "extern crate rustc_middle;";
+
+fn compiles(probe: &str) -> bool {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let child = Command::new(rustc)
+        .args([
+            "--edition",
+            "2021",
+            "--crate-type",
+            "lib",
+            "--emit",
+            "metadata",
+        ])
+        .args(["--crate-name", "pcs_probe", "--out-dir"])
+        .arg(&out_dir)
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    let Ok(mut child) = child else {
+        return false;
+    };
+    let source = format!("#![feature(rustc_private)]\n{probe}\n");
+    if child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(source.as_bytes())
+        .is_err()
+    {
+        return false;
+    }
+    child.wait().map(|status| status.success()).unwrap_or(false)
+}
+
+fn main() {
+    println!("cargo::rerun-if-changed=build.rs");
+    println!("cargo::rerun-if-env-changed=RUSTC");
+    let probes_work = compiles(CONTROL_PROBE);
+    if !probes_work {
+        println!(
+            "cargo::warning=Could not compile a program using the rustc crates, so the changes \
+             of the rustc APIs that remove items are assumed not to apply"
+        );
+    }
+    for change in API_CHANGES {
+        println!("cargo::rustc-check-cfg=cfg({})", change.cfg);
+        let applies = match change.probe {
+            Probe::Compiles(program) => compiles(program),
+            Probe::Fails(program) => probes_work && !compiles(program),
+        };
+        if applies {
+            println!("cargo::rustc-cfg={}", change.cfg);
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! Timing and size metrics of the analysis of each function.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use pcs::{
+    combined_pcs::BodyWithBorrowckFacts,
+    run_combined_pcs,
+    rustc_interface::{hir::def_id::LocalDefId, middle::ty::TyCtxt},
+};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{analyze_crate, crates};
+
+/// The metrics of the analysis of a function
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct FunctionMetrics {
+    krate: String,
+    function: String,
+    blocks: usize,
+    statements: usize,
+    /// The number of block visits before the fixpoint was reached
+    iterations: usize,
+    /// The largest number of edges of the borrows graph after a statement
+    max_edges: usize,
+    /// The sum over all statements of the number of edges after them
+    total_edges: usize,
+    /// The time taken to compute the fixpoint, in microseconds
+    fixpoint_us: u64,
+    /// The time taken to compute the results at every statement from the
+    /// fixpoint, in microseconds
+    results_us: u64,
+}
+
+const CSV_HEADER: &str =
+    "crate,function,blocks,statements,iterations,max_edges,total_edges,fixpoint_us,results_us";
+
+impl FunctionMetrics {
+    fn to_csv_line(&self) -> String {
+        format!(
+            "{},\"{}\",{},{},{},{},{},{},{}",
+            self.krate,
+            self.function.replace('"', "\"\""),
+            self.blocks,
+            self.statements,
+            self.iterations,
+            self.max_edges,
+            self.total_edges,
+            self.fixpoint_us,
+            self.results_us
+        )
+    }
+}
+
+/// Measures the analysis of `body`, running it `runs` times and keeping the
+/// fastest times
+fn measure<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    krate: &str,
+    def_id: LocalDefId,
+    body: &BodyWithBorrowckFacts<'tcx>,
+    runs: usize,
+) -> FunctionMetrics {
+    let mut fixpoint_time = Duration::MAX;
+    let mut results_time = Duration::MAX;
+    let mut metrics = None;
+    for _ in 0..runs {
+        let start = Instant::now();
+        let mut output = run_combined_pcs(body, tcx, None);
+        fixpoint_time = fixpoint_time.min(start.elapsed());
+        let start = Instant::now();
+        let (mut statements, mut max_edges, mut total_edges) = (0, 0, 0);
+        for block in body.body.basic_blocks.indices() {
+            for stmt in output.get_all_for_bb(block).statements {
+                let edges = stmt.extra.after.graph_edges().count();
+                statements += 1;
+                max_edges = max_edges.max(edges);
+                total_edges += edges;
+            }
+        }
+        results_time = results_time.min(start.elapsed());
+        metrics = Some(FunctionMetrics {
+            krate: krate.to_string(),
+            function: tcx.def_path_str(def_id.to_def_id()),
+            blocks: body.body.basic_blocks.len(),
+            statements,
+            iterations: output.stats().iterations(),
+            max_edges,
+            total_edges,
+            fixpoint_us: 0,
+            results_us: 0,
+        });
+    }
+    let mut metrics = metrics.unwrap();
+    metrics.fixpoint_us = fixpoint_time.as_micros() as u64;
+    metrics.results_us = results_time.as_micros() as u64;
+    metrics
+}
+
+/// Measures the analysis of every function of the crates in `corpus`
+pub fn run(corpus: &Path, output: &Path, runs: usize) {
+    let mut metrics = vec![];
+    for (name, root) in crates(corpus) {
+        let mut crate_metrics = vec![];
+        let errors = analyze_crate(&name, &root, &mut |tcx, def_id, body| {
+            crate_metrics.push(measure(tcx, &name, def_id, body, runs))
+        });
+        let Some(errors) = errors else {
+            eprintln!("{name}: failed to compile, skipping");
+            continue;
+        };
+        eprintln!("{name}: analyzed {} functions", crate_metrics.len());
+        for (function, err) in errors {
+            eprintln!("{name}: failed to analyze {function}: {err}");
+        }
+        metrics.extend(crate_metrics);
+    }
+    let contents = if output.extension().is_some_and(|ext| ext == "csv") {
+        let mut csv = format!("{CSV_HEADER}\n");
+        for function in &metrics {
+            writeln!(csv, "{}", function.to_csv_line()).unwrap();
+        }
+        csv
+    } else {
+        serde_json::to_string_pretty(&metrics).unwrap()
+    };
+    std::fs::write(output, contents).expect("Failed to write the metrics");
+}
+
+fn read_metrics(path: &str) -> BTreeMap<(String, String), FunctionMetrics> {
+    let metrics: Vec<FunctionMetrics> =
+        serde_json::from_str(&std::fs::read_to_string(path).expect("Failed to read metrics"))
+            .expect("Failed to parse metrics, only JSON can be compared");
+    metrics
+        .into_iter()
+        .map(|function| {
+            (
+                (function.krate.clone(), function.function.clone()),
+                function,
+            )
+        })
+        .collect()
+}
+
+/// Prints the changes between two runs, returning whether any function
+/// regressed by more than `threshold` percent
+pub fn compare(baseline: &str, current: &str, threshold: f64) -> bool {
+    let baseline = read_metrics(baseline);
+    let current = read_metrics(current);
+    let mut regressed = false;
+    println!("function\tfixpoint_us\tchange\titerations\tmax_edges");
+    for (key, new) in &current {
+        let Some(old) = baseline.get(key) else {
+            println!("{}::{}\tnew", key.0, key.1);
+            continue;
+        };
+        let change = if old.fixpoint_us == 0 {
+            0.0
+        } else {
+            (new.fixpoint_us as f64 - old.fixpoint_us as f64) * 100.0 / old.fixpoint_us as f64
+        };
+        let is_regression = change > threshold || new.iterations > old.iterations;
+        regressed |= is_regression;
+        println!(
+            "{}::{}\t{} -> {}\t{change:+.1}%\t{} -> {}\t{} -> {}{}",
+            key.0,
+            key.1,
+            old.fixpoint_us,
+            new.fixpoint_us,
+            old.iterations,
+            new.iterations,
+            old.max_edges,
+            new.max_edges,
+            if is_regression { "\tREGRESSION" } else { "" }
+        );
+    }
+    for key in baseline.keys().filter(|key| !current.contains_key(*key)) {
+        println!("{}::{}\tremoved", key.0, key.1);
+    }
+    regressed
+}
@@ -0,0 +1,194 @@
+//! Runs the analysis over a corpus of crates, to measure its performance and
+//! to detect changes in its results.
+//!
+//! Usage:
+//!
+//! - `pcs-bench run CORPUS OUTPUT [RUNS]` analyzes every crate in the
+//!   directory `CORPUS` and writes the metrics of each function to `OUTPUT`,
+//!   as CSV if its extension is `.csv` and as JSON otherwise. Each function is
+//!   analyzed `RUNS` times (1 by default) and the fastest run is recorded.
+//! - `pcs-bench compare BASELINE.json CURRENT.json [THRESHOLD]` compares the
+//!   metrics of two runs and fails if the analysis of some function became
+//!   more than `THRESHOLD` percent (10 by default) slower, or needed more
+//!   iterations to reach its fixpoint.
+//! - `pcs-bench bless CORPUS EXPECTED` stores the results of every function
+//!   of the corpus in the directory `EXPECTED`, see [`regress`].
+//! - `pcs-bench check CORPUS EXPECTED` compares the results of every function
+//!   of the corpus with those stored in `EXPECTED`, and prints the changes as
+//!   JSON.
+//!
+//! A crate of the corpus is either a `.rs` file or a directory with a
+//! `src/lib.rs` or `src/main.rs`, and must not have dependencies.
+
+#![feature(rustc_private)]
+
+mod bench;
+mod regress;
+
+use std::{
+    panic::AssertUnwindSafe,
+    path::{Path, PathBuf},
+};
+
+use pcs::{
+    combined_pcs::BodyWithBorrowckFacts,
+    error::{catch_internal_errors, PcsError},
+    rustc_interface::{
+        borrowck::consumers,
+        driver::{self, Compilation},
+        hir::{def::DefKind, def_id::LocalDefId},
+        interface::{interface::Compiler, Queries},
+        middle::ty::TyCtxt,
+    },
+};
+
+/// Called with each function of a crate and its body
+type FunctionCallback<'a> =
+    dyn for<'tcx> FnMut(TyCtxt<'tcx>, LocalDefId, &BodyWithBorrowckFacts<'tcx>) + Send + 'a;
+
+struct Callbacks<'a, 'b> {
+    on_function: &'a mut FunctionCallback<'b>,
+    /// The functions whose analysis failed, by path
+    errors: Vec<(String, PcsError)>,
+}
+
+impl Callbacks<'_, '_> {
+    fn analyze_all(&mut self, tcx: TyCtxt<'_>) {
+        for def_id in tcx.hir().body_owners() {
+            if !matches!(
+                tcx.def_kind(def_id),
+                DefKind::Fn | DefKind::AssocFn | DefKind::Closure
+            ) {
+                continue;
+            }
+            let body: BodyWithBorrowckFacts<'_> = consumers::get_body_with_borrowck_facts(
+                tcx,
+                def_id,
+                consumers::ConsumerOptions::PoloniusOutputFacts,
+            )
+            .into();
+            let on_function = &mut self.on_function;
+            if let Err(err) =
+                catch_internal_errors(AssertUnwindSafe(|| on_function(tcx, def_id, &body)))
+            {
+                self.errors
+                    .push((tcx.def_path_str(def_id.to_def_id()), err));
+            }
+        }
+    }
+}
+
+impl driver::Callbacks for Callbacks<'_, '_> {
+    // The analysis runs the borrow checker itself, which requires the MIR that
+    // is stolen by the time `after_analysis` runs
+    fn after_expansion<'tcx>(
+        &mut self,
+        _compiler: &Compiler,
+        queries: &'tcx Queries<'tcx>,
+    ) -> Compilation {
+        queries
+            .global_ctxt()
+            .unwrap()
+            .enter(|tcx| self.analyze_all(tcx));
+        Compilation::Stop
+    }
+}
+
+/// The crates of `corpus`, by name, with the path of their root module
+fn crates(corpus: &Path) -> Vec<(String, PathBuf)> {
+    let mut entries: Vec<_> = std::fs::read_dir(corpus)
+        .expect("Failed to read the corpus directory")
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    entries.sort();
+    entries
+        .into_iter()
+        .filter_map(|path| {
+            let root = if path.is_dir() {
+                ["src/lib.rs", "src/main.rs"]
+                    .iter()
+                    .map(|root| path.join(root))
+                    .find(|root| root.exists())?
+            } else if path.extension().is_some_and(|ext| ext == "rs") {
+                path.clone()
+            } else {
+                return None;
+            };
+            let name = path
+                .file_stem()
+                .unwrap()
+                .to_string_lossy()
+                .replace('-', "_");
+            Some((name, root))
+        })
+        .collect()
+}
+
+/// Compiles the crate `name` whose root module is at `root`, calling
+/// `on_function` with each of its functions and closures. Returns the
+/// functions for which `on_function` panicked, or `None` if the crate could
+/// not be compiled.
+fn analyze_crate(
+    name: &str,
+    root: &Path,
+    on_function: &mut FunctionCallback<'_>,
+) -> Option<Vec<(String, PcsError)>> {
+    // The first argument is taken to be the name of the binary
+    let rustc_args = vec![
+        "pcs-bench".to_string(),
+        "-Zpolonius=next".to_string(),
+        "-Zcrate-attr=feature(register_tool)".to_string(),
+        "-Zcrate-attr=register_tool(pcs)".to_string(),
+        "-Awarnings".to_string(),
+        "--edition=2021".to_string(),
+        "--crate-type=lib".to_string(),
+        format!("--crate-name={name}"),
+        root.to_string_lossy().into_owned(),
+    ];
+    let mut callbacks = Callbacks {
+        on_function,
+        errors: vec![],
+    };
+    // Fatal compilation errors unwind
+    let run = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        driver::RunCompiler::new(&rustc_args, &mut callbacks).run()
+    }));
+    matches!(run, Ok(Ok(()))).then_some(callbacks.errors)
+}
+
+fn usage(program: &str) -> ! {
+    eprintln!("Usage: {program} run CORPUS OUTPUT [RUNS]");
+    eprintln!("       {program} compare BASELINE.json CURRENT.json [THRESHOLD]");
+    eprintln!("       {program} bless CORPUS EXPECTED");
+    eprintln!("       {program} check CORPUS EXPECTED");
+    std::process::exit(2);
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1..).unwrap_or_default() {
+        [command, corpus, output, rest @ ..] if command == "run" && rest.len() <= 1 => {
+            let runs: usize = rest
+                .first()
+                .map_or(Ok(1), |runs| runs.parse())
+                .unwrap_or_else(|_| usage(&args[0]));
+            bench::run(Path::new(corpus), Path::new(output), runs.max(1));
+        }
+        [command, baseline, current, rest @ ..] if command == "compare" && rest.len() <= 1 => {
+            let threshold: f64 = rest
+                .first()
+                .map_or(Ok(10.0), |threshold| threshold.parse())
+                .unwrap_or_else(|_| usage(&args[0]));
+            if bench::compare(baseline, current, threshold) {
+                std::process::exit(1);
+            }
+        }
+        [command, corpus, expected] if command == "bless" => {
+            regress::bless(Path::new(corpus), Path::new(expected));
+        }
+        [command, corpus, expected] if command == "check" => {
+            regress::check(Path::new(corpus), Path::new(expected));
+        }
+        _ => usage(&args[0]),
+    }
+}
@@ -0,0 +1,264 @@
+//! Detects changes in the results of the analysis, by comparing the
+//! canonical dump of the results of each function (see
+//! `pcs::combined_pcs::result_dump`) with a stored expectation.
+//!
+//! The expectations of a crate are stored in `EXPECTED/[CRATE].json`, mapping
+//! the path of each function to the digest of its dump and the dump itself.
+//! Rather than failing, `check` reports how the results of each function
+//! changed: the lines of the dump added and removed at each location, and
+//! how many edges of the borrows graph and capability states that amounts to.
+//!
+//! A crate root containing a line `// pcs-projection-depth-limit: N` is
+//! analyzed with that limit, see `PcsContext::with_projection_depth_limit`.
+
+use std::{collections::BTreeMap, path::Path};
+
+use pcs::{
+    combined_pcs::{result_dump, PcsContext},
+    error::PcsError,
+    run_combined_pcs_with_context,
+};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{analyze_crate, crates};
+
+/// The stored results of a function
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Expectation {
+    digest: String,
+    lines: Vec<String>,
+}
+
+/// The stored results of the functions of a crate, by path
+type Expectations = BTreeMap<String, Expectation>;
+
+/// The changes in the dump at a location
+#[derive(Clone, Debug, Serialize)]
+struct LocationChange {
+    location: String,
+    removed: Vec<String>,
+    added: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "snake_case", tag = "status")]
+enum Status {
+    Unchanged,
+    Changed {
+        edges_added: usize,
+        edges_removed: usize,
+        states_changed: usize,
+        locations: Vec<LocationChange>,
+    },
+    /// The function has no expectation
+    New,
+    /// The function has an expectation but is no longer in the corpus
+    Removed,
+    /// The analysis of the function panicked
+    Failed {
+        message: String,
+    },
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct FunctionReport {
+    #[serde(rename = "crate")]
+    krate: String,
+    function: String,
+    #[serde(flatten)]
+    status: Status,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+struct Report {
+    /// The crates that failed to compile
+    failed_crates: Vec<String>,
+    functions: Vec<FunctionReport>,
+}
+
+/// The expectations of every function of `krate` whose analysis succeeded,
+/// and the errors of the others, or `None` if the crate failed to compile
+fn expectations(krate: &str, root: &Path) -> Option<(Expectations, Vec<(String, PcsError)>)> {
+    let mut expectations = BTreeMap::new();
+    let projection_depth_limit = projection_depth_limit(root);
+    let errors = analyze_crate(krate, root, &mut |tcx, def_id, body| {
+        let mut cgx = PcsContext::new(tcx, body);
+        if let Some(limit) = projection_depth_limit {
+            cgx = cgx.with_projection_depth_limit(limit);
+        }
+        let dump = result_dump(&mut run_combined_pcs_with_context(cgx, None));
+        expectations.insert(
+            tcx.def_path_str(def_id.to_def_id()),
+            Expectation {
+                digest: dump.digest(),
+                lines: dump.lines,
+            },
+        );
+    });
+    errors.map(|errors| (expectations, errors))
+}
+
+/// The limit given by a `// pcs-projection-depth-limit: N` line of the crate
+/// root, if any
+fn projection_depth_limit(root: &Path) -> Option<usize> {
+    let source = std::fs::read_to_string(root).ok()?;
+    source.lines().find_map(|line| {
+        line.strip_prefix("// pcs-projection-depth-limit:")?
+            .trim()
+            .parse()
+            .ok()
+    })
+}
+
+/// The lines of two dumps grouped by location, in the order they first appear
+fn lines_by_location<'a>(
+    old: &'a [String],
+    new: &'a [String],
+) -> Vec<(&'a str, Vec<&'a String>, Vec<&'a String>)> {
+    let mut groups: Vec<(&str, Vec<&String>, Vec<&String>)> = vec![];
+    for (line, is_new) in old
+        .iter()
+        .map(|line| (line, false))
+        .chain(new.iter().map(|line| (line, true)))
+    {
+        let location = line.split('\t').next().unwrap();
+        let index = match groups.iter().position(|(l, _, _)| *l == location) {
+            Some(index) => index,
+            None => {
+                groups.push((location, vec![], vec![]));
+                groups.len() - 1
+            }
+        };
+        if is_new {
+            groups[index].2.push(line);
+        } else {
+            groups[index].1.push(line);
+        }
+    }
+    groups
+}
+
+fn diff(old: &Expectation, new: &Expectation) -> Status {
+    if old.digest == new.digest {
+        return Status::Unchanged;
+    }
+    let (mut edges_added, mut edges_removed, mut states_changed) = (0, 0, 0);
+    let mut locations = vec![];
+    for (location, old, new) in lines_by_location(&old.lines, &new.lines) {
+        let removed: Vec<String> = old
+            .iter()
+            .filter(|line| !new.contains(line))
+            .map(|line| line.to_string())
+            .collect();
+        let added: Vec<String> = new
+            .iter()
+            .filter(|line| !old.contains(line))
+            .map(|line| line.to_string())
+            .collect();
+        if removed.is_empty() && added.is_empty() {
+            continue;
+        }
+        let is_edge = |line: &&String| line.split('\t').nth(1) == Some("edge");
+        edges_added += added.iter().filter(is_edge).count();
+        edges_removed += removed.iter().filter(is_edge).count();
+        if added.iter().chain(&removed).any(|line| !is_edge(&line)) {
+            states_changed += 1;
+        }
+        locations.push(LocationChange {
+            location: location.to_string(),
+            removed,
+            added,
+        });
+    }
+    Status::Changed {
+        edges_added,
+        edges_removed,
+        states_changed,
+        locations,
+    }
+}
+
+fn expectation_path(expected: &Path, krate: &str) -> std::path::PathBuf {
+    expected.join(format!("{krate}.json"))
+}
+
+/// Stores the results of every function of the crates in `corpus` as the
+/// expectations in `expected`
+pub fn bless(corpus: &Path, expected: &Path) {
+    std::fs::create_dir_all(expected).expect("Failed to create the expectations directory");
+    for (name, root) in crates(corpus) {
+        let Some((expectations, errors)) = expectations(&name, &root) else {
+            eprintln!("{name}: failed to compile, skipping");
+            continue;
+        };
+        for (function, err) in errors {
+            eprintln!("{name}: failed to analyze {function}: {err}");
+        }
+        let json = serde_json::to_string_pretty(&expectations).unwrap();
+        std::fs::write(expectation_path(expected, &name), json + "\n")
+            .expect("Failed to write expectations");
+        eprintln!("{name}: stored {} functions", expectations.len());
+    }
+}
+
+/// Compares the results of every function of the crates in `corpus` with the
+/// expectations in `expected`, printing a [`Report`] as JSON
+pub fn check(corpus: &Path, expected: &Path) {
+    let mut report = Report::default();
+    for (name, root) in crates(corpus) {
+        let old: Expectations = match std::fs::read_to_string(expectation_path(expected, &name)) {
+            Ok(json) => serde_json::from_str(&json).expect("Failed to parse expectations"),
+            Err(_) => BTreeMap::new(),
+        };
+        let Some((new, errors)) = expectations(&name, &root) else {
+            report.failed_crates.push(name);
+            continue;
+        };
+        let mut report_function = |function: &String, status| {
+            report.functions.push(FunctionReport {
+                krate: name.clone(),
+                function: function.clone(),
+                status,
+            })
+        };
+        for (function, new) in &new {
+            match old.get(function) {
+                Some(old) => report_function(function, diff(old, new)),
+                None => report_function(function, Status::New),
+            }
+        }
+        for (function, err) in &errors {
+            let message = err.to_string();
+            report_function(function, Status::Failed { message });
+        }
+        let failed = |function: &String| errors.iter().any(|(f, _)| f == function);
+        for function in old
+            .keys()
+            .filter(|function| !new.contains_key(*function) && !failed(function))
+        {
+            report_function(function, Status::Removed);
+        }
+    }
+    for function in &report.functions {
+        let summary = match &function.status {
+            Status::Unchanged => continue,
+            Status::Changed {
+                edges_added,
+                edges_removed,
+                states_changed,
+                ..
+            } => format!(
+                "changed: {edges_added} edges added, {edges_removed} removed, \
+                 {states_changed} states changed"
+            ),
+            Status::New => "new".to_string(),
+            Status::Removed => "removed".to_string(),
+            Status::Failed { message } => format!("failed: {message}"),
+        };
+        eprintln!("{}::{}: {summary}", function.krate, function.function);
+    }
+    for krate in &report.failed_crates {
+        eprintln!("{krate}: failed to compile");
+    }
+    println!("{}", serde_json::to_string_pretty(&report).unwrap());
+}
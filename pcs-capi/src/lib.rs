@@ -0,0 +1,187 @@
+//! A C API to the analysis, for tools that are not written in Rust.
+//!
+//! [`pcs_analyze_crate`] compiles a crate and returns the results of every
+//! function in it as JSON, in the format of [`pcs::api::FunctionPcs`], or the
+//! error the analysis of the function failed with. The
+//! returned buffer must be released with [`pcs_free_string`]:
+//!
+//! ```c
+//! char *pcs_analyze_crate(const char *path, const char *options);
+//! void pcs_free_string(char *json);
+//! ```
+
+#![feature(rustc_private)]
+
+use std::{
+    collections::BTreeMap,
+    ffi::{c_char, CStr, CString},
+    panic::AssertUnwindSafe,
+};
+
+use pcs::{
+    api::{function_path, FunctionPcs, PcsEngine},
+    rustc_interface::{
+        driver::{self, Compilation},
+        hir::def::DefKind,
+        interface::{interface::Compiler, Queries},
+        middle::ty::TyCtxt,
+    },
+};
+use serde_derive::{Deserialize, Serialize};
+
+/// The options of [`pcs_analyze_crate`], given as a JSON object. All fields
+/// are optional.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Options {
+    /// Additional arguments to rustc, e.g. `["--edition=2021"]`
+    rustc_args: Vec<String>,
+    /// The paths of the functions to analyze, e.g. `["module::f"]` (see
+    /// [`function_path`]). All functions and closures are analyzed if empty.
+    /// A path that names no function is mapped to an error.
+    functions: Vec<String>,
+}
+
+/// The result of [`pcs_analyze_crate`]: either the results of each function,
+/// by path, or why the crate could not be analyzed
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Output {
+    Functions(BTreeMap<String, FunctionOutput>),
+    Error(String),
+}
+
+/// The results of a function, or why its analysis failed, as
+/// `{"error": ...}`
+#[derive(Serialize)]
+#[serde(untagged)]
+enum FunctionOutput {
+    Pcs(Box<FunctionPcs>),
+    Error { error: String },
+}
+
+struct Callbacks {
+    functions: Vec<String>,
+    results: BTreeMap<String, FunctionOutput>,
+}
+
+impl Callbacks {
+    fn analyze_all(&mut self, tcx: TyCtxt<'_>) {
+        for def_id in tcx.hir().body_owners() {
+            if !matches!(
+                tcx.def_kind(def_id),
+                DefKind::Fn | DefKind::AssocFn | DefKind::Closure
+            ) {
+                continue;
+            }
+            let name = tcx.def_path_str(def_id.to_def_id());
+            if !self.functions.is_empty()
+                && !self
+                    .functions
+                    .iter()
+                    .any(|path| function_path(path) == name)
+            {
+                continue;
+            }
+            let output = match PcsEngine::analyze(tcx, def_id) {
+                Ok(pcs) => FunctionOutput::Pcs(Box::new(pcs)),
+                Err(err) => FunctionOutput::Error {
+                    error: err.to_string(),
+                },
+            };
+            self.results.insert(name, output);
+        }
+        for path in &self.functions {
+            if !self.results.contains_key(function_path(path)) {
+                let error = format!("No function `{path}`");
+                self.results
+                    .insert(path.clone(), FunctionOutput::Error { error });
+            }
+        }
+    }
+}
+
+impl driver::Callbacks for Callbacks {
+    // The analysis runs the borrow checker itself, which requires the MIR that
+    // is stolen by the time `after_analysis` runs
+    fn after_expansion<'tcx>(
+        &mut self,
+        _compiler: &Compiler,
+        queries: &'tcx Queries<'tcx>,
+    ) -> Compilation {
+        queries
+            .global_ctxt()
+            .unwrap()
+            .enter(|tcx| self.analyze_all(tcx));
+        Compilation::Stop
+    }
+}
+
+fn analyze_crate(path: &str, options: Options) -> Output {
+    // The first argument is taken to be the name of the binary
+    let mut rustc_args = vec![
+        "pcs".to_string(),
+        "-Zpolonius=next".to_string(),
+        "-Zcrate-attr=feature(register_tool)".to_string(),
+        "-Zcrate-attr=register_tool(pcs)".to_string(),
+    ];
+    rustc_args.extend(options.rustc_args);
+    rustc_args.push(path.to_string());
+    let mut callbacks = Callbacks {
+        functions: options.functions,
+        results: BTreeMap::new(),
+    };
+    let run = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        driver::RunCompiler::new(&rustc_args, &mut callbacks).run()
+    }));
+    match run {
+        Ok(Ok(())) => Output::Functions(callbacks.results),
+        // Fatal compilation errors unwind as well
+        Ok(Err(_)) | Err(_) => Output::Error(format!(
+            "Failed to analyze {path}, see the diagnostics on stderr"
+        )),
+    }
+}
+
+/// Analyzes the crate whose root module is at `path`.
+///
+/// `options` is a JSON object (see `Options`) or null. Returns a JSON object
+/// whose `functions` field maps the path of each function to its results (or
+/// to an object whose `error` field describes why its analysis failed), or
+/// whose `error` field describes why the crate could not be analyzed.
+///
+/// # Safety
+///
+/// `path` and, if not null, `options` must be valid NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn pcs_analyze_crate(
+    path: *const c_char,
+    options: *const c_char,
+) -> *mut c_char {
+    let path = CStr::from_ptr(path).to_string_lossy();
+    let options = if options.is_null() {
+        Ok(Options::default())
+    } else {
+        serde_json::from_str(&CStr::from_ptr(options).to_string_lossy())
+            .map_err(|err| format!("Invalid options: {err}"))
+    };
+    let output = match options {
+        Ok(options) => analyze_crate(&path, options),
+        Err(err) => Output::Error(err),
+    };
+    let json = serde_json::to_string(&output).expect("Failed to serialize results");
+    CString::new(json).unwrap().into_raw()
+}
+
+/// Releases a string returned by [`pcs_analyze_crate`].
+///
+/// # Safety
+///
+/// `json` must have been returned by [`pcs_analyze_crate`] and not been
+/// released before.
+#[no_mangle]
+pub unsafe extern "C" fn pcs_free_string(json: *mut c_char) {
+    if !json.is_null() {
+        drop(CString::from_raw(json));
+    }
+}
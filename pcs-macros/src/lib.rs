@@ -0,0 +1,265 @@
+// © 2023, ETH Zurich
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Derive macros for the `pcs` crate.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, punctuated::Punctuated, spanned::Spanned, Attribute, Data, DeriveInput,
+    Error, Fields, Ident, Token, Type,
+};
+
+/// Derives `HasPcsElems<T>` for each element type `T` listed in the
+/// `#[pcs_elems(..)]` attribute of the type, e.g.
+/// `#[pcs_elems(MaybeOldPlace<'tcx>, RegionProjection<'tcx>)]`.
+///
+/// Every field (of every variant) must say how it contributes to the
+/// elements, so that adding a field cannot silently leave its elements out:
+///
+/// - `#[pcs_elems]`: the elements of the field, which implements
+///   `HasPcsElems<T>`
+/// - `#[pcs_elems(iter)]`: the elements of each item of the field
+/// - `#[pcs_elem]`: the field, which is itself an element
+/// - `#[pcs_elems(skip)]`: nothing
+///
+/// The first three may be restricted to some of the element types by
+/// listing them, e.g. `#[pcs_elem(RegionProjection<'tcx>)]`; a field may
+/// have several such attributes. The generated code refers to the trait by
+/// its path in `pcs`, so it can only be used within that crate.
+#[proc_macro_derive(HasPcsElems, attributes(pcs_elems, pcs_elem))]
+pub fn derive_has_pcs_elems(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match has_pcs_elems_impls(&input) {
+        Ok(impls) => impls.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// How a field contributes to the elements of type `T`
+#[derive(Clone, Copy)]
+enum Contribution {
+    /// `field.pcs_elems()`
+    Elems,
+    /// `field.iter_mut().flat_map(|item| item.pcs_elems())`
+    Iter,
+    /// `field` itself
+    Elem,
+}
+
+/// A field attribute: how the field contributes to the elements of the
+/// given types (of all types, if there are none), or `None` if it does not
+struct FieldAttr {
+    contribution: Option<Contribution>,
+    types: Vec<Type>,
+}
+
+impl FieldAttr {
+    fn parse(attr: &Attribute) -> syn::Result<Option<Self>> {
+        let is_elem = attr.path().is_ident("pcs_elem");
+        if !is_elem && !attr.path().is_ident("pcs_elems") {
+            return Ok(None);
+        }
+        let args: Vec<Type> = match &attr.meta {
+            syn::Meta::Path(_) => vec![],
+            _ => attr
+                .parse_args_with(Punctuated::<Type, Token![,]>::parse_terminated)?
+                .into_iter()
+                .collect(),
+        };
+        let keyword =
+            |ty: &Type, keyword: &str| matches!(ty, Type::Path(p) if p.path.is_ident(keyword));
+        let mut contribution = Some(if is_elem {
+            Contribution::Elem
+        } else {
+            Contribution::Elems
+        });
+        let mut types = vec![];
+        for arg in args {
+            if !is_elem && keyword(&arg, "iter") {
+                contribution = Some(Contribution::Iter);
+            } else if !is_elem && keyword(&arg, "skip") {
+                contribution = None;
+            } else {
+                types.push(arg);
+            }
+        }
+        if contribution.is_none() && !types.is_empty() {
+            return Err(Error::new(
+                attr.span(),
+                "skipped fields cannot list element types",
+            ));
+        }
+        Ok(Some(Self {
+            contribution,
+            types,
+        }))
+    }
+
+    fn applies_to(&self, elem: &Type) -> bool {
+        self.types.is_empty()
+            || self
+                .types
+                .iter()
+                .any(|ty| quote!(#ty).to_string() == quote!(#elem).to_string())
+    }
+}
+
+/// A field with its attributes, and how it is bound in patterns
+struct Field {
+    /// The field pattern, binding the field to `binding`
+    pattern: TokenStream2,
+    binding: Ident,
+    attrs: Vec<FieldAttr>,
+}
+
+impl Field {
+    fn contributions(&self, elem: &Type) -> Vec<Contribution> {
+        self.attrs
+            .iter()
+            .filter(|attr| attr.applies_to(elem))
+            .filter_map(|attr| attr.contribution)
+            .collect()
+    }
+}
+
+fn fields(fields: &Fields) -> syn::Result<Vec<Field>> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(idx, field)| {
+            let (pattern, binding) = match &field.ident {
+                Some(ident) => (quote!(#ident), ident.clone()),
+                None => {
+                    let binding = format_ident!("field_{}", idx);
+                    let idx = syn::Index::from(idx);
+                    (quote!(#idx: #binding), binding)
+                }
+            };
+            let mut attrs = vec![];
+            for attr in &field.attrs {
+                attrs.extend(FieldAttr::parse(attr)?);
+            }
+            if attrs.is_empty() {
+                return Err(Error::new(
+                    field.span(),
+                    "fields of types deriving `HasPcsElems` must be annotated with \
+                     `#[pcs_elems]`, `#[pcs_elems(iter)]`, `#[pcs_elem]` or `#[pcs_elems(skip)]`",
+                ));
+            }
+            Ok(Field {
+                pattern,
+                binding,
+                attrs,
+            })
+        })
+        .collect()
+}
+
+/// Collects the elements of type `elem` of the `fields` bound by `pattern`,
+/// by reference if `by_ref` and by mutable reference otherwise
+fn collect_arm(pattern: TokenStream2, fields: &[Field], elem: &Type, by_ref: bool) -> TokenStream2 {
+    let trait_path = quote!(crate::borrows::has_pcs_elem::HasPcsElems::<#elem>);
+    let (elems, iter) = if by_ref {
+        (quote!(pcs_elems_ref), quote!(iter))
+    } else {
+        (quote!(pcs_elems), quote!(iter_mut))
+    };
+    let mut bound = vec![];
+    let mut pushes = vec![];
+    for field in fields {
+        let contributions = field.contributions(elem);
+        if contributions.is_empty() {
+            continue;
+        }
+        let binding = &field.binding;
+        bound.push(field.pattern.clone());
+        for contribution in contributions {
+            pushes.push(match contribution {
+                Contribution::Elems => quote!(result.extend(#trait_path::#elems(#binding));),
+                Contribution::Iter => quote!(
+                    result.extend(#binding.#iter().flat_map(|item| #trait_path::#elems(item)));
+                ),
+                Contribution::Elem => quote!(result.push(#binding);),
+            });
+        }
+    }
+    quote!(#pattern { #(#bound,)* .. } => { #(#pushes)* })
+}
+
+fn has_pcs_elems_impls(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let elems: Vec<Type> = input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("pcs_elems"))
+        .map(|attr| {
+            attr.parse_args_with(Punctuated::<Type, Token![,]>::parse_terminated)
+                .map(|types| types.into_iter().collect::<Vec<_>>())
+        })
+        .collect::<syn::Result<Vec<_>>>()?
+        .into_iter()
+        .flatten()
+        .collect();
+    if elems.is_empty() {
+        return Err(Error::new(
+            input.ident.span(),
+            "types deriving `HasPcsElems` must list their element types in `#[pcs_elems(..)]`",
+        ));
+    }
+    let variants: Vec<(TokenStream2, Vec<Field>)> = match &input.data {
+        Data::Struct(data) => vec![(quote!(Self), fields(&data.fields)?)],
+        Data::Enum(data) => data
+            .variants
+            .iter()
+            .map(|variant| {
+                let ident = &variant.ident;
+                Ok((quote!(Self::#ident), fields(&variant.fields)?))
+            })
+            .collect::<syn::Result<_>>()?,
+        Data::Union(_) => {
+            return Err(Error::new(
+                input.ident.span(),
+                "`HasPcsElems` cannot be derived for unions",
+            ))
+        }
+    };
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let impls = elems.iter().map(|elem| {
+        let arms = |by_ref| {
+            variants
+                .iter()
+                .map(|(pattern, fields)| collect_arm(pattern.clone(), fields, elem, by_ref))
+                .collect::<Vec<_>>()
+        };
+        let (arms, ref_arms) = (arms(false), arms(true));
+        quote! {
+            impl #impl_generics crate::borrows::has_pcs_elem::HasPcsElems<#elem>
+                for #name #ty_generics #where_clause
+            {
+                #[allow(unused_mut)]
+                fn pcs_elems(&mut self) -> Vec<&mut #elem> {
+                    let mut result: Vec<&mut #elem> = vec![];
+                    match self {
+                        #(#arms)*
+                    }
+                    result
+                }
+
+                #[allow(unused_mut)]
+                fn pcs_elems_ref(&self) -> Vec<&#elem> {
+                    let mut result: Vec<&#elem> = vec![];
+                    match self {
+                        #(#ref_arms)*
+                    }
+                    result
+                }
+            }
+        }
+    });
+    Ok(quote!(#(#impls)*))
+}
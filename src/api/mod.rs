@@ -0,0 +1,99 @@
+// © 2023, ETH Zurich
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The entry point for verification tools that consume the analysis results.
+//!
+//! The other modules of this crate, e.g. `borrows` and `free_pcs`, expose the
+//! internals of the analysis and change frequently, and are hidden from the
+//! documentation. Tools that only need the results should instead use
+//! [`PcsEngine::analyze`] and the types re-exported here, which are those of
+//! the internal `export` module under shorter names.
+
+use std::panic::AssertUnwindSafe;
+
+use crate::{
+    combined_pcs::BodyWithBorrowckFacts,
+    error::catch_internal_errors,
+    export::{
+        ExportedBasicBlock, ExportedBody, ExportedBorrowsState, ExportedPcsLocation,
+        ExportedReborrowBridge,
+    },
+    run_combined_pcs,
+    rustc_interface::{borrowck::consumers, hir::def_id::LocalDefId, middle::ty::TyCtxt},
+};
+
+pub use crate::error::PcsError;
+pub use crate::export::{
+    ExportedBorrowsEdge as Edge, ExportedBorrowsEdgeKind as EdgeKind,
+    ExportedCapability as Capability, ExportedCapabilitySummaries as States,
+    ExportedCapabilitySummary as State, ExportedLocation as Location,
+    ExportedMaybeOldPlace as MaybeOldPlace, ExportedMaybeRemotePlace as MaybeRemotePlace,
+    ExportedPermission as Permission, ExportedPermissionAmount as PermissionAmount,
+    ExportedPermissionBlock as PermissionBlock, ExportedPermissionLocation as PermissionLocation,
+    ExportedPermissionOp as PermissionOp, ExportedPermissionOpKind as PermissionOpKind,
+    ExportedPlace as Place, ExportedPlaceCapability as PlaceCapability,
+    ExportedPredicate as Predicate, ExportedReborrow as Reborrow, ExportedRepackOp as Op,
+};
+
+/// The results of the analysis of a function
+pub type FunctionPcs = ExportedBody<ExportedBorrowsState, ExportedReborrowBridge>;
+
+/// The results for a basic block of a [`FunctionPcs`]
+pub type BlockPcs = ExportedBasicBlock<ExportedBorrowsState, ExportedReborrowBridge>;
+
+/// The results for a statement, or a control-flow edge out of a terminator, of
+/// a [`FunctionPcs`]
+pub type LocationPcs = ExportedPcsLocation<ExportedBorrowsState, ExportedReborrowBridge>;
+
+/// The path under which a function is looked up, given the `path` a user
+/// named it by.
+///
+/// Functions are named as by `TyCtxt::def_path_str`, which omits the
+/// `crate::` prefix for the analyzed crate, e.g. `module::f`; a prefixed path
+/// such as `crate::module::f` is accepted as well.
+pub fn function_path(path: &str) -> &str {
+    path.strip_prefix("crate::").unwrap_or(path)
+}
+
+/// Runs the combined analysis with its default configuration.
+pub struct PcsEngine;
+
+impl PcsEngine {
+    /// Analyzes the body of `def_id`, running the borrow checker again to
+    /// obtain its facts.
+    ///
+    /// This must be called before the borrow checker has run on the body in
+    /// the compiler session, e.g. from `Callbacks::after_expansion`, since the
+    /// MIR it starts from is stolen afterwards. A panic of the analysis is
+    /// returned as a [`PcsError`], so that the other functions of the crate
+    /// can still be analyzed.
+    pub fn analyze(tcx: TyCtxt<'_>, def_id: LocalDefId) -> Result<FunctionPcs, PcsError> {
+        let body = consumers::get_body_with_borrowck_facts(
+            tcx,
+            def_id,
+            consumers::ConsumerOptions::PoloniusOutputFacts,
+        );
+        Self::analyze_body(tcx, body)
+    }
+
+    /// Analyzes a body whose borrow-checker facts the caller already has, e.g.
+    /// from overriding the `mir_borrowck` query. The facts must include the
+    /// Polonius output facts. A panic of the analysis is returned as a
+    /// [`PcsError`].
+    pub fn analyze_body<'tcx>(
+        tcx: TyCtxt<'tcx>,
+        body: consumers::BodyWithBorrowckFacts<'tcx>,
+    ) -> Result<FunctionPcs, PcsError> {
+        let body: BodyWithBorrowckFacts<'tcx> = body.into();
+        catch_internal_errors(AssertUnwindSafe(|| {
+            let mut output = run_combined_pcs(&body, tcx, None);
+            let mut export = output.export();
+            export.permission_ops = output.export_permission_ops();
+            export.precision_losses = output.export_precision_losses();
+            export
+        }))
+    }
+}
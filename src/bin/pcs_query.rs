@@ -0,0 +1,28 @@
+//! Evaluates a query over the results exported to `PCS_EXPORT_DIR`, see
+//! `pcs::export::query`.
+//!
+//! Usage: `pcs_query [FUNCTION].json '(blocked-by "x" at "bb1[2]")'`
+
+#![feature(rustc_private)]
+
+use pcs::export::query::{Query, QueryBody};
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let [_, path, query] = &args[..] else {
+        eprintln!("Usage: {} [FUNCTION].json QUERY", args[0]);
+        std::process::exit(2);
+    };
+    let body: QueryBody = serde_json::from_str(
+        &std::fs::read_to_string(path).expect("Failed to read exported results"),
+    )
+    .expect("Failed to parse exported results");
+    let result = query.parse::<Query>().and_then(|query| query.eval(&body));
+    match result {
+        Ok(result) => println!("{}", serde_json::to_string_pretty(&result).unwrap()),
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    }
+}
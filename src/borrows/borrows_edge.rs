@@ -1,22 +1,94 @@
-use rustc_interface::{ast::Mutability, data_structures::fx::FxHashSet, middle::mir::BasicBlock};
+use rustc_interface::{
+    ast::Mutability,
+    middle::mir::{BasicBlock, Location},
+};
+
+use crate::{
+    rustc_interface,
+    utils::{PlaceRepacker, SmallSet},
+};
+#[cfg(feature = "json")]
+use serde_json::json;
 
-use crate::{rustc_interface, utils::PlaceRepacker};
+#[cfg(feature = "json")]
+use super::domain::ToJsonWithRepacker;
 
 use super::{
     borrows_graph::Conditioned,
     deref_expansion::DerefExpansion,
-    domain::{MaybeOldPlace, MaybeRemotePlace, Reborrow},
-    has_pcs_elem::HasPcsElems,
+    domain::{AbstractionTarget, MaybeOldPlace, MaybeRemotePlace, Reborrow},
+    has_pcs_elem::{HasPcsElems, ReferencesPcsElem},
     path_condition::{PathCondition, PathConditions},
     region_abstraction::AbstractionEdge,
     region_projection::RegionProjection,
     region_projection_member::{RegionProjectionMember, RegionProjectionMemberDirection},
 };
 
-#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+/// Records why an edge was added to the graph, e.g. which statement created
+/// it. This is purely informational: edges that only differ in their
+/// provenance are considered equal.
+#[derive(Clone, Debug, Default)]
+pub struct EdgeProvenance {
+    reason: String,
+    location: Option<Location>,
+}
+
+impl EdgeProvenance {
+    pub fn new(reason: impl Into<String>, location: Option<Location>) -> Self {
+        Self {
+            reason: reason.into(),
+            location,
+        }
+    }
+
+    /// The provenance of an edge created by the statement at `location`
+    pub fn at(reason: impl Into<String>, location: Location) -> Self {
+        Self::new(reason, Some(location))
+    }
+
+    /// A short description of what caused the edge to be created, e.g.
+    /// `Rvalue::Ref`
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    /// The location of the statement that caused the edge to be created, if
+    /// any
+    pub fn location(&self) -> Option<Location> {
+        self.location
+    }
+}
+
+impl std::fmt::Display for EdgeProvenance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.location {
+            Some(location) => write!(f, "{} in {:?}", self.reason, location),
+            None if self.reason.is_empty() => write!(f, "unknown"),
+            None => write!(f, "{}", self.reason),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct BorrowsEdge<'tcx> {
     conditions: PathConditions,
     pub(crate) kind: BorrowsEdgeKind<'tcx>,
+    provenance: EdgeProvenance,
+}
+
+impl<'tcx> PartialEq for BorrowsEdge<'tcx> {
+    fn eq(&self, other: &Self) -> bool {
+        self.conditions == other.conditions && self.kind == other.kind
+    }
+}
+
+impl<'tcx> Eq for BorrowsEdge<'tcx> {}
+
+impl<'tcx> std::hash::Hash for BorrowsEdge<'tcx> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.conditions.hash(state);
+        self.kind.hash(state);
+    }
 }
 
 impl<'tcx> BorrowsEdge<'tcx> {
@@ -32,6 +104,15 @@ impl<'tcx> BorrowsEdge<'tcx> {
     pub fn conditions(&self) -> &PathConditions {
         &self.conditions
     }
+
+    /// The kind of this edge under its conditions, without cloning the kind
+    pub fn as_conditioned(&self) -> Conditioned<&BorrowsEdgeKind<'tcx>> {
+        Conditioned::new(&self.kind, self.conditions.clone())
+    }
+
+    pub fn set_conditions(&mut self, conditions: PathConditions) {
+        self.conditions = conditions;
+    }
     pub fn valid_for_path(&self, path: &[BasicBlock]) -> bool {
         self.conditions.valid_for_path(path)
     }
@@ -45,10 +126,24 @@ impl<'tcx> BorrowsEdge<'tcx> {
     }
 
     pub fn new(kind: BorrowsEdgeKind<'tcx>, conditions: PathConditions) -> Self {
-        Self { conditions, kind }
+        Self {
+            conditions,
+            kind,
+            provenance: EdgeProvenance::default(),
+        }
+    }
+
+    pub fn with_provenance(mut self, provenance: EdgeProvenance) -> Self {
+        self.provenance = provenance;
+        self
+    }
+
+    /// Why this edge was created
+    pub fn provenance(&self) -> &EdgeProvenance {
+        &self.provenance
     }
 
-    pub fn blocked_places(&self) -> FxHashSet<MaybeRemotePlace<'tcx>> {
+    pub fn blocked_places(&self) -> SmallSet<MaybeRemotePlace<'tcx>> {
         self.kind.blocked_places()
     }
 
@@ -68,7 +163,7 @@ impl<'tcx> BorrowsEdge<'tcx> {
     pub fn blocked_by_places(
         &self,
         repacker: PlaceRepacker<'_, 'tcx>,
-    ) -> FxHashSet<MaybeOldPlace<'tcx>> {
+    ) -> SmallSet<MaybeOldPlace<'tcx>> {
         self.kind.blocked_by_places(repacker)
     }
 }
@@ -80,6 +175,37 @@ where
     fn pcs_elems(&mut self) -> Vec<&mut T> {
         self.kind.pcs_elems()
     }
+
+    fn pcs_elems_ref(&self) -> Vec<&T> {
+        self.kind.pcs_elems_ref()
+    }
+}
+
+impl<'tcx, T> ReferencesPcsElem<'tcx, T> for BorrowsEdge<'tcx>
+where
+    BorrowsEdgeKind<'tcx>: ReferencesPcsElem<'tcx, T>,
+{
+    fn references(&self, elem: &T, repacker: PlaceRepacker<'_, 'tcx>) -> bool {
+        self.kind.references(elem, repacker)
+    }
+}
+
+impl<'tcx> ReferencesPcsElem<'tcx, MaybeOldPlace<'tcx>> for BorrowsEdgeKind<'tcx> {
+    fn references(&self, elem: &MaybeOldPlace<'tcx>, repacker: PlaceRepacker<'_, 'tcx>) -> bool {
+        self.blocked_places().contains(&(*elem).into())
+            || self.blocked_by_places(repacker).contains(elem)
+            || self.region_projections().iter().any(|rp| rp.place == *elem)
+    }
+}
+
+impl<'tcx> ReferencesPcsElem<'tcx, RegionProjection<'tcx>> for BorrowsEdgeKind<'tcx> {
+    fn references(
+        &self,
+        elem: &RegionProjection<'tcx>,
+        _repacker: PlaceRepacker<'_, 'tcx>,
+    ) -> bool {
+        self.region_projections().contains(elem)
+    }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
@@ -94,6 +220,15 @@ impl<'tcx> HasPcsElems<RegionProjection<'tcx>> for BorrowsEdgeKind<'tcx> {
     fn pcs_elems(&mut self) -> Vec<&mut RegionProjection<'tcx>> {
         match self {
             BorrowsEdgeKind::RegionProjectionMember(member) => member.pcs_elems(),
+            BorrowsEdgeKind::Abstraction(abstraction) => abstraction.pcs_elems(),
+            _ => vec![],
+        }
+    }
+
+    fn pcs_elems_ref(&self) -> Vec<&RegionProjection<'tcx>> {
+        match self {
+            BorrowsEdgeKind::RegionProjectionMember(member) => member.pcs_elems_ref(),
+            BorrowsEdgeKind::Abstraction(abstraction) => abstraction.pcs_elems_ref(),
             _ => vec![],
         }
     }
@@ -114,6 +249,15 @@ where
             BorrowsEdgeKind::Abstraction(abstraction_edge) => abstraction_edge.pcs_elems(),
         }
     }
+
+    fn pcs_elems_ref(&self) -> Vec<&T> {
+        match self {
+            BorrowsEdgeKind::RegionProjectionMember(member) => member.pcs_elems_ref(),
+            BorrowsEdgeKind::Reborrow(reborrow) => reborrow.pcs_elems_ref(),
+            BorrowsEdgeKind::DerefExpansion(deref_expansion) => deref_expansion.pcs_elems_ref(),
+            BorrowsEdgeKind::Abstraction(abstraction_edge) => abstraction_edge.pcs_elems_ref(),
+        }
+    }
 }
 
 impl<'tcx> BorrowsEdgeKind<'tcx> {
@@ -128,6 +272,37 @@ impl<'tcx> BorrowsEdgeKind<'tcx> {
         self.blocked_places().contains(&place)
     }
 
+    /// The location at which the edge was created, if it has one. Expansions
+    /// of owned places are not tied to a location.
+    pub fn location(&self) -> Option<Location> {
+        match self {
+            BorrowsEdgeKind::Reborrow(reborrow) => Some(reborrow.reserve_location()),
+            BorrowsEdgeKind::DerefExpansion(de) => de.borrow_expansion().map(|e| e.location),
+            BorrowsEdgeKind::Abstraction(abstraction) => Some(abstraction.location()),
+            BorrowsEdgeKind::RegionProjectionMember(member) => Some(member.location()),
+        }
+    }
+
+    /// All region projections mentioned by this edge, either as a member of
+    /// a region or as an input or output of an abstraction
+    pub fn region_projections(&self) -> Vec<RegionProjection<'tcx>> {
+        match self {
+            BorrowsEdgeKind::RegionProjectionMember(member) => vec![member.projection],
+            BorrowsEdgeKind::Abstraction(abstraction) => {
+                let inputs = abstraction.inputs().filter_map(|i| match i {
+                    AbstractionTarget::RegionProjection(rp) => Some(*rp),
+                    AbstractionTarget::Place(_) => None,
+                });
+                let outputs = abstraction.outputs().filter_map(|o| match o {
+                    AbstractionTarget::RegionProjection(rp) => Some(*rp),
+                    AbstractionTarget::Place(_) => None,
+                });
+                inputs.chain(outputs).collect()
+            }
+            BorrowsEdgeKind::Reborrow(_) | BorrowsEdgeKind::DerefExpansion(_) => vec![],
+        }
+    }
+
     pub fn blocked_by_place(
         &self,
         place: MaybeOldPlace<'tcx>,
@@ -136,20 +311,18 @@ impl<'tcx> BorrowsEdgeKind<'tcx> {
         self.blocked_by_places(repacker).contains(&place)
     }
 
-    pub fn blocked_places(&self) -> FxHashSet<MaybeRemotePlace<'tcx>> {
+    pub fn blocked_places(&self) -> SmallSet<MaybeRemotePlace<'tcx>> {
         match &self {
             BorrowsEdgeKind::Reborrow(reborrow) => {
-                vec![reborrow.blocked_place].into_iter().collect()
-            }
-            BorrowsEdgeKind::DerefExpansion(de) => vec![de.base().into()].into_iter().collect(),
-            BorrowsEdgeKind::Abstraction(ra) => {
-                ra.blocks_places().into_iter().map(|p| p.into()).collect()
+                std::iter::once(reborrow.blocked_place).collect()
             }
+            BorrowsEdgeKind::DerefExpansion(de) => std::iter::once(de.base().into()).collect(),
+            BorrowsEdgeKind::Abstraction(ra) => ra.blocks_places(),
             BorrowsEdgeKind::RegionProjectionMember(member) => match member.direction {
                 RegionProjectionMemberDirection::PlaceIsRegionInput => {
-                    vec![member.place.into()].into_iter().collect()
+                    member.places.iter().copied().collect()
                 }
-                RegionProjectionMemberDirection::PlaceIsRegionOutput => FxHashSet::default(),
+                RegionProjectionMemberDirection::PlaceIsRegionOutput => SmallSet::new(),
             },
         }
     }
@@ -158,22 +331,22 @@ impl<'tcx> BorrowsEdgeKind<'tcx> {
     pub fn blocked_by_places(
         &self,
         repacker: PlaceRepacker<'_, 'tcx>,
-    ) -> FxHashSet<MaybeOldPlace<'tcx>> {
+    ) -> SmallSet<MaybeOldPlace<'tcx>> {
         match &self {
             BorrowsEdgeKind::Reborrow(reborrow) => {
-                vec![reborrow.assigned_place].into_iter().collect()
+                std::iter::once(reborrow.assigned_place).collect()
             }
             BorrowsEdgeKind::DerefExpansion(de) => de.expansion(repacker).into_iter().collect(),
             BorrowsEdgeKind::Abstraction(ra) => ra.blocked_by_places(),
             BorrowsEdgeKind::RegionProjectionMember(member) => match member.direction {
                 RegionProjectionMemberDirection::PlaceIsRegionInput => {
-                    vec![member.projection.place].into_iter().collect()
-                }
-                RegionProjectionMemberDirection::PlaceIsRegionOutput => {
-                    vec![member.place.as_local_place().unwrap()]
-                        .into_iter()
-                        .collect()
+                    std::iter::once(member.projection.place).collect()
                 }
+                RegionProjectionMemberDirection::PlaceIsRegionOutput => member
+                    .places
+                    .iter()
+                    .map(|place| place.as_local_place().unwrap())
+                    .collect(),
             },
         }
     }
@@ -184,37 +357,25 @@ pub trait ToBorrowsEdge<'tcx> {
 
 impl<'tcx> ToBorrowsEdge<'tcx> for DerefExpansion<'tcx> {
     fn to_borrows_edge(self, conditions: PathConditions) -> BorrowsEdge<'tcx> {
-        BorrowsEdge {
-            conditions,
-            kind: BorrowsEdgeKind::DerefExpansion(self),
-        }
+        BorrowsEdge::new(BorrowsEdgeKind::DerefExpansion(self), conditions)
     }
 }
 
 impl<'tcx> ToBorrowsEdge<'tcx> for AbstractionEdge<'tcx> {
     fn to_borrows_edge(self, conditions: PathConditions) -> BorrowsEdge<'tcx> {
-        BorrowsEdge {
-            conditions,
-            kind: BorrowsEdgeKind::Abstraction(self),
-        }
+        BorrowsEdge::new(BorrowsEdgeKind::Abstraction(self), conditions)
     }
 }
 
 impl<'tcx> ToBorrowsEdge<'tcx> for Reborrow<'tcx> {
     fn to_borrows_edge(self, conditions: PathConditions) -> BorrowsEdge<'tcx> {
-        BorrowsEdge {
-            conditions,
-            kind: BorrowsEdgeKind::Reborrow(self),
-        }
+        BorrowsEdge::new(BorrowsEdgeKind::Reborrow(self), conditions)
     }
 }
 
 impl<'tcx> ToBorrowsEdge<'tcx> for RegionProjectionMember<'tcx> {
     fn to_borrows_edge(self, conditions: PathConditions) -> BorrowsEdge<'tcx> {
-        BorrowsEdge {
-            conditions,
-            kind: BorrowsEdgeKind::RegionProjectionMember(self),
-        }
+        BorrowsEdge::new(BorrowsEdgeKind::RegionProjectionMember(self), conditions)
     }
 }
 
@@ -223,3 +384,36 @@ impl<'tcx, T: ToBorrowsEdge<'tcx>> Into<BorrowsEdge<'tcx>> for Conditioned<T> {
         self.value.to_borrows_edge(self.conditions)
     }
 }
+
+#[cfg(feature = "json")]
+impl<'tcx> ToJsonWithRepacker<'tcx> for BorrowsEdgeKind<'tcx> {
+    fn to_json(&self, repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value {
+        let (kind, edge) = match self {
+            BorrowsEdgeKind::Reborrow(reborrow) => ("Reborrow", reborrow.to_json(repacker)),
+            BorrowsEdgeKind::DerefExpansion(expansion) => {
+                ("DerefExpansion", expansion.to_json(repacker))
+            }
+            BorrowsEdgeKind::Abstraction(abstraction) => {
+                ("Abstraction", abstraction.to_json(repacker))
+            }
+            BorrowsEdgeKind::RegionProjectionMember(member) => {
+                ("RegionProjectionMember", member.to_json(repacker))
+            }
+        };
+        json!({
+            "type": kind,
+            "edge": edge,
+        })
+    }
+}
+
+#[cfg(feature = "json")]
+impl<'tcx> ToJsonWithRepacker<'tcx> for BorrowsEdge<'tcx> {
+    fn to_json(&self, repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value {
+        json!({
+            "conditions": self.conditions.to_json(repacker),
+            "kind": self.kind.to_json(repacker),
+            "provenance": self.provenance.to_string(),
+        })
+    }
+}
@@ -1,51 +1,155 @@
 use rustc_interface::{
     ast::Mutability,
     borrowck::consumers::{LocationTable, PoloniusOutput},
-    data_structures::fx::FxHashSet,
+    data_structures::fx::{FxHashMap, FxHashSet},
     middle::mir::{self, BasicBlock, Location, START_BLOCK},
     middle::ty::{Region, TyCtxt},
+    target::abi::VariantIdx,
 };
+#[cfg(feature = "json")]
 use serde_json::json;
+use std::{collections::VecDeque, rc::Rc};
 
 use crate::{
     coupling, rustc_interface,
-    utils::{Place, PlaceRepacker, SnapshotLocation},
+    utils::{
+        post_dominators::PostDominators, sorted_by_debug, Place, PlaceRepacker, SnapshotLocation,
+    },
 };
 
+#[cfg(feature = "json")]
+use super::domain::ToJsonWithRepacker;
 use super::{
-    borrows_edge::{BorrowsEdge, BorrowsEdgeKind, ToBorrowsEdge},
+    borrows_edge::{BorrowsEdge, BorrowsEdgeKind, EdgeProvenance, ToBorrowsEdge},
+    borrows_state::ExpansionOp,
     borrows_visitor::DebugCtx,
     coupling_graph_constructor::{CGNode, CouplingGraphConstructor},
     deref_expansion::{DerefExpansion, OwnedExpansion},
     domain::{
         AbstractionBlockEdge, AbstractionTarget, AbstractionType, LoopAbstraction, MaybeOldPlace,
-        MaybeRemotePlace, Reborrow, ToJsonWithRepacker,
+        MaybeRemotePlace, Reborrow, ReborrowEquivalence, ReborrowPhase, RemotePlace,
     },
-    has_pcs_elem::{HasPcsElems, MakePlaceOld},
+    edge_set::EdgeSet,
+    has_pcs_elem::{HasPcsElems, MakePlaceOld, ReferencesPcsElem},
     latest::Latest,
+    mutation_log::{GraphMutation, MutationLog, MutationLogEntry},
     path_condition::{PathCondition, PathConditions},
+    projection_summary::deref_depth,
     region_abstraction::AbstractionEdge,
     region_projection::RegionProjection,
+    region_projection_member::RegionProjectionMember,
 };
 
-#[derive(Clone, Debug, Eq, PartialEq)]
-pub struct BorrowsGraph<'tcx>(FxHashSet<BorrowsEdge<'tcx>>);
+/// A join into a graph that did not change it: joining the same edges from
+/// the same block again would not change it either.
+#[derive(Clone, Debug)]
+struct UnchangedJoin<'tcx> {
+    self_block: BasicBlock,
+    other_block: BasicBlock,
+    other_edges: Rc<EdgeSet<'tcx>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct BorrowsGraph<'tcx> {
+    /// Shared by clones of the graph until either of them changes it
+    edges: Rc<EdgeSet<'tcx>>,
+    /// If enabled, records every change made to `edges`
+    log: Option<MutationLog<'tcx>>,
+    /// The joins into this graph since it last changed. These are skipped
+    /// when they are repeated, e.g. when a predecessor is analysed again but
+    /// its state does not change.
+    unchanged_joins: Vec<UnchangedJoin<'tcx>>,
+}
+
+impl<'tcx> PartialEq for BorrowsGraph<'tcx> {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.edges, &other.edges) || self.edges == other.edges
+    }
+}
+
+impl<'tcx> Eq for BorrowsGraph<'tcx> {}
 
 impl<'tcx> BorrowsGraph<'tcx> {
     pub fn is_empty(&self) -> bool {
-        self.0.is_empty()
+        self.edges.is_empty()
     }
 
     pub fn new() -> Self {
-        Self(FxHashSet::default())
+        Self {
+            edges: Rc::default(),
+            log: None,
+            unchanged_joins: vec![],
+        }
+    }
+
+    /// Mutable access to the edges; any change must be reported with
+    /// [`Self::edges_changed`]
+    fn edges_mut(&mut self) -> &mut EdgeSet<'tcx> {
+        Rc::make_mut(&mut self.edges)
+    }
+
+    fn edges_changed(&mut self) {
+        self.unchanged_joins.clear();
+    }
+
+    /// Starts recording the mutations made to this graph (and to graphs
+    /// cloned from it). Mutations made before this call are not recorded.
+    pub fn enable_mutation_log(&mut self) {
+        if self.log.is_none() {
+            self.log = Some(MutationLog::new());
+        }
+    }
+
+    pub fn mutation_log(&self) -> Option<&MutationLog<'tcx>> {
+        self.log.as_ref()
+    }
+
+    /// Sets the location attached to subsequently logged mutations
+    pub fn set_mutation_location(&mut self, location: SnapshotLocation) {
+        if let Some(log) = &mut self.log {
+            log.set_location(location);
+        }
+    }
+
+    /// Reconstructs a graph by applying the logged `entries` to an empty
+    /// graph. Replaying a prefix of a log yields the graph at that point of
+    /// the analysis.
+    pub fn replay<'a>(entries: impl IntoIterator<Item = &'a MutationLogEntry<'tcx>>) -> Self
+    where
+        'tcx: 'a,
+    {
+        let mut graph = Self::new();
+        let edges = graph.edges_mut();
+        for entry in entries {
+            match entry.mutation() {
+                GraphMutation::Insert(edge) => {
+                    edges.insert(edge.clone());
+                }
+                GraphMutation::Remove(edge) => {
+                    edges.remove(edge);
+                }
+                GraphMutation::Update { from, to } => {
+                    edges.remove(from);
+                    edges.insert(to.clone());
+                }
+            }
+        }
+        graph
     }
 
     pub fn edge_count(&self) -> usize {
-        self.0.len()
+        self.edges.len()
     }
 
+    /// The edges of the graph, in no particular order
     pub fn edges(&self) -> impl Iterator<Item = &BorrowsEdge<'tcx>> {
-        self.0.iter()
+        self.edges.iter()
+    }
+
+    /// The edges of the graph, in a deterministic order. This is meant for
+    /// producing output; use [`Self::edges`] otherwise.
+    pub fn sorted_edges(&self) -> Vec<&BorrowsEdge<'tcx>> {
+        sorted_by_debug(self.edges.iter())
     }
 
     pub fn region_projection_graph(
@@ -53,7 +157,7 @@ impl<'tcx> BorrowsGraph<'tcx> {
         repacker: PlaceRepacker<'_, 'tcx>,
     ) -> coupling::Graph<RegionProjection<'tcx>> {
         let mut graph = coupling::Graph::new();
-        for edge in self.0.iter() {
+        for edge in self.edges.iter() {
             if let BorrowsEdgeKind::Reborrow(reborrow) = &edge.kind() {
                 if let Some((from, to)) = self.region_projection_edge(reborrow, repacker) {
                     graph.add_edge(from, to);
@@ -86,96 +190,125 @@ impl<'tcx> BorrowsGraph<'tcx> {
         Some((from, to))
     }
 
-    pub fn abstraction_edges(&self) -> FxHashSet<Conditioned<AbstractionEdge<'tcx>>> {
-        self.0
-            .iter()
-            .filter_map(|edge| match &edge.kind() {
-                BorrowsEdgeKind::Abstraction(abstraction) => Some(Conditioned {
-                    conditions: edge.conditions().clone(),
-                    value: abstraction.clone(),
-                }),
-                _ => None,
-            })
-            .collect()
+    /// The edges of the graph as conditioned kinds, without cloning the edges
+    /// themselves.
+    pub fn conditioned_edges(
+        &self,
+    ) -> impl Iterator<Item = Conditioned<&BorrowsEdgeKind<'tcx>>> + '_ {
+        self.edges.iter().map(BorrowsEdge::as_conditioned)
     }
 
-    pub fn deref_expansions(&self) -> FxHashSet<Conditioned<DerefExpansion<'tcx>>> {
-        self.0
-            .iter()
-            .filter_map(|edge| match &edge.kind() {
-                BorrowsEdgeKind::DerefExpansion(de) => Some(Conditioned {
-                    conditions: edge.conditions().clone(),
-                    value: de.clone(),
-                }),
-                _ => None,
-            })
-            .collect()
+    /// The reborrow edges of the graph, without cloning the edges themselves.
+    pub fn reborrow_edges(&self) -> impl Iterator<Item = Conditioned<&Reborrow<'tcx>>> + '_ {
+        self.edges.iter().filter_map(|edge| match &edge.kind {
+            BorrowsEdgeKind::Reborrow(reborrow) => {
+                Some(Conditioned::new(reborrow, edge.conditions().clone()))
+            }
+            _ => None,
+        })
     }
 
-    pub fn reborrows(&self) -> FxHashSet<Conditioned<Reborrow<'tcx>>> {
-        self.0
-            .iter()
-            .filter_map(|edge| match &edge.kind() {
-                BorrowsEdgeKind::Reborrow(reborrow) => Some(Conditioned {
-                    conditions: edge.conditions().clone(),
-                    value: reborrow.clone(),
-                }),
-                _ => None,
-            })
-            .collect()
+    /// The deref expansion edges of the graph, without cloning the edges
+    /// themselves.
+    pub fn expansion_edges(&self) -> impl Iterator<Item = Conditioned<&DerefExpansion<'tcx>>> + '_ {
+        self.edges.iter().filter_map(|edge| match &edge.kind {
+            BorrowsEdgeKind::DerefExpansion(de) => {
+                Some(Conditioned::new(de, edge.conditions().clone()))
+            }
+            _ => None,
+        })
+    }
+
+    /// The abstraction edges of the graph, without cloning the edges
+    /// themselves.
+    pub fn abstraction_edges(
+        &self,
+    ) -> impl Iterator<Item = Conditioned<&AbstractionEdge<'tcx>>> + '_ {
+        self.edges.iter().filter_map(|edge| match &edge.kind {
+            BorrowsEdgeKind::Abstraction(abstraction) => {
+                Some(Conditioned::new(abstraction, edge.conditions().clone()))
+            }
+            _ => None,
+        })
+    }
+
+    /// The region projection member edges of the graph, without cloning the
+    /// edges themselves.
+    pub fn region_member_edges(
+        &self,
+    ) -> impl Iterator<Item = Conditioned<&RegionProjectionMember<'tcx>>> + '_ {
+        self.edges.iter().filter_map(|edge| match &edge.kind {
+            BorrowsEdgeKind::RegionProjectionMember(member) => {
+                Some(Conditioned::new(member, edge.conditions().clone()))
+            }
+            _ => None,
+        })
     }
 
     pub fn has_reborrow_at_location(&self, location: Location) -> bool {
-        self.0.iter().any(|edge| match &edge.kind() {
+        self.edges.iter().any(|edge| match &edge.kind() {
             BorrowsEdgeKind::Reborrow(reborrow) => reborrow.reserve_location() == location,
             _ => false,
         })
     }
+
+    /// Moves the reborrows reserved at `reserve_location` into `phase`,
+    /// returning `true` iff any edge changed.
+    pub fn set_reborrow_phase(&mut self, reserve_location: Location, phase: ReborrowPhase) -> bool {
+        self.mut_edges(|edge| match edge.mut_kind() {
+            BorrowsEdgeKind::Reborrow(reborrow)
+                if reborrow.reserve_location() == reserve_location && reborrow.phase() != phase =>
+            {
+                reborrow.set_phase(phase);
+                true
+            }
+            _ => false,
+        })
+    }
+
+    /// Identifies each reborrow with an `equivalence`-equivalent one in
+    /// `other` that was created at a different location, by moving it to that
+    /// location. Returns `true` iff any reborrow was moved.
+    pub fn align_reborrows_with(&mut self, other: &Self, equivalence: ReborrowEquivalence) -> bool {
+        if equivalence == ReborrowEquivalence::Identical {
+            return false;
+        }
+        let others: Vec<Reborrow<'tcx>> = other.reborrow_edges().values().cloned().collect();
+        self.mut_edges(|edge| match edge.mut_kind() {
+            BorrowsEdgeKind::Reborrow(reborrow) => {
+                match others.iter().find(|other| {
+                    other.reserve_location() != reborrow.reserve_location()
+                        && equivalence.equivalent(other, reborrow)
+                }) {
+                    Some(other) => {
+                        reborrow.set_reserve_location(other.reserve_location());
+                        true
+                    }
+                    None => false,
+                }
+            }
+            _ => false,
+        })
+    }
+
     pub fn reborrows_blocking(
         &self,
         place: MaybeRemotePlace<'tcx>,
-    ) -> FxHashSet<Conditioned<Reborrow<'tcx>>> {
-        self.0
-            .iter()
-            .filter_map(|edge| match &edge.kind() {
-                BorrowsEdgeKind::Reborrow(reborrow) => {
-                    if reborrow.blocked_place == place {
-                        Some(Conditioned {
-                            conditions: edge.conditions().clone(),
-                            value: reborrow.clone(),
-                        })
-                    } else {
-                        None
-                    }
-                }
-                _ => None,
-            })
-            .collect()
+    ) -> impl Iterator<Item = Conditioned<&Reborrow<'tcx>>> + '_ {
+        self.reborrow_edges()
+            .filter(move |rb| rb.value.blocked_place == place)
     }
 
     pub fn reborrows_blocked_by(
         &self,
         place: MaybeOldPlace<'tcx>,
-    ) -> FxHashSet<Conditioned<Reborrow<'tcx>>> {
-        self.0
-            .iter()
-            .filter_map(|edge| match &edge.kind() {
-                BorrowsEdgeKind::Reborrow(reborrow) => {
-                    if reborrow.assigned_place == place {
-                        Some(Conditioned {
-                            conditions: edge.conditions().clone(),
-                            value: reborrow.clone(),
-                        })
-                    } else {
-                        None
-                    }
-                }
-                _ => None,
-            })
-            .collect()
+    ) -> impl Iterator<Item = Conditioned<&Reborrow<'tcx>>> + '_ {
+        self.reborrow_edges()
+            .filter(move |rb| rb.value.assigned_place == place)
     }
 
-    /// All edges that are not blocked by any other edge
+    /// Whether no place blocking the edge is itself blocked by another edge,
+    /// i.e. the edge is on the frontier of the graph.
     pub fn is_leaf_edge(
         &self,
         edge: &BorrowsEdge<'tcx>,
@@ -187,12 +320,17 @@ impl<'tcx> BorrowsGraph<'tcx> {
             .all(|p| !self.has_edge_blocking(*p))
     }
 
+    /// All edges for which [`Self::is_leaf_edge`] holds.
     pub fn leaf_edges(&self, repacker: PlaceRepacker<'_, 'tcx>) -> FxHashSet<BorrowsEdge<'tcx>> {
-        let mut candidates = self.0.clone();
-        candidates.retain(|edge| self.is_leaf_edge(edge, repacker));
-        candidates
+        self.edges
+            .iter()
+            .filter(|edge| self.is_leaf_edge(edge, repacker))
+            .cloned()
+            .collect()
     }
 
+    /// The places blocking the leaf edges. These places are not blocked by
+    /// anything, and hence currently hold their capability.
     pub fn leaf_nodes(&self, repacker: PlaceRepacker<'_, 'tcx>) -> FxHashSet<MaybeOldPlace<'tcx>> {
         self.leaf_edges(repacker)
             .into_iter()
@@ -221,8 +359,111 @@ impl<'tcx> BorrowsGraph<'tcx> {
         count
     }
 
+    /// All paths of edges by which `place` transitively blocks a function
+    /// input, together with that input. Each path starts with an edge blocked
+    /// by `place` and ends with one blocking the remote place. Paths through
+    /// abstraction edges are included, cyclic paths are not.
+    pub fn paths_to_remote_places(
+        &self,
+        place: MaybeOldPlace<'tcx>,
+        repacker: PlaceRepacker<'_, 'tcx>,
+    ) -> Vec<(RemotePlace, Vec<BorrowsEdge<'tcx>>)> {
+        let mut result = vec![];
+        self.collect_paths_to_remote_places(
+            place,
+            &mut vec![place],
+            &mut vec![],
+            &mut result,
+            repacker,
+        );
+        result
+    }
+
+    fn collect_paths_to_remote_places(
+        &self,
+        place: MaybeOldPlace<'tcx>,
+        visited: &mut Vec<MaybeOldPlace<'tcx>>,
+        path: &mut Vec<BorrowsEdge<'tcx>>,
+        result: &mut Vec<(RemotePlace, Vec<BorrowsEdge<'tcx>>)>,
+        repacker: PlaceRepacker<'_, 'tcx>,
+    ) {
+        for edge in self
+            .edges
+            .iter()
+            .filter(|e| e.is_blocked_by_place(place, repacker))
+        {
+            path.push(edge.clone());
+            for blocked in edge.blocked_places() {
+                match blocked {
+                    MaybeRemotePlace::Remote(remote) => result.push((remote, path.clone())),
+                    MaybeRemotePlace::Local(blocked) => {
+                        if !visited.contains(&blocked) {
+                            visited.push(blocked);
+                            self.collect_paths_to_remote_places(
+                                blocked, visited, path, result, repacker,
+                            );
+                            visited.pop();
+                        }
+                    }
+                }
+            }
+            path.pop();
+        }
+    }
+
+    /// The shortest sequence of edges by which `from` transitively blocks
+    /// `to`. The first edge is blocked by `from`, and the last one blocks `to`.
+    /// Returns `None` if `from` does not block `to`.
+    pub fn blocking_path(
+        &self,
+        from: MaybeOldPlace<'tcx>,
+        to: MaybeRemotePlace<'tcx>,
+        repacker: PlaceRepacker<'_, 'tcx>,
+    ) -> Option<Vec<BorrowsEdge<'tcx>>> {
+        if to == from.into() {
+            return Some(vec![]);
+        }
+        // For each reached place, the edge it was reached through and the
+        // place blocking that edge
+        let mut reached_from: FxHashMap<
+            MaybeRemotePlace<'tcx>,
+            (&BorrowsEdge<'tcx>, MaybeOldPlace<'tcx>),
+        > = FxHashMap::default();
+        let mut queue = VecDeque::from([from]);
+        while let Some(place) = queue.pop_front() {
+            for edge in self
+                .edges
+                .iter()
+                .filter(|e| e.is_blocked_by_place(place, repacker))
+            {
+                for blocked in edge.blocked_places() {
+                    if blocked == from.into() || reached_from.contains_key(&blocked) {
+                        continue;
+                    }
+                    reached_from.insert(blocked, (edge, place));
+                    if blocked == to {
+                        let mut path = vec![];
+                        let mut curr = to;
+                        while curr != from.into() {
+                            let (edge, prev) = reached_from[&curr];
+                            path.push(edge.clone());
+                            curr = prev.into();
+                        }
+                        path.reverse();
+                        return Some(path);
+                    }
+                    if let Some(blocked) = blocked.as_local_place() {
+                        queue.push_back(blocked);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// All edges for which every blocked place is a root, see [`Self::is_root`].
     pub fn root_edges(&self, repacker: PlaceRepacker<'_, 'tcx>) -> FxHashSet<BorrowsEdge<'tcx>> {
-        self.0
+        self.edges
             .iter()
             .filter(|edge| {
                 edge.blocked_places().iter().all(|p| match p {
@@ -236,6 +477,9 @@ impl<'tcx> BorrowsGraph<'tcx> {
             .collect::<FxHashSet<_>>()
     }
 
+    /// The places blocked by the root edges. These places do not block
+    /// anything themselves: they are the original owners (or, for remote
+    /// places, the function inputs) from which everything else is borrowed.
     pub fn roots(&self, repacker: PlaceRepacker<'_, 'tcx>) -> FxHashSet<MaybeRemotePlace<'tcx>> {
         self.root_edges(repacker)
             .into_iter()
@@ -244,11 +488,12 @@ impl<'tcx> BorrowsGraph<'tcx> {
     }
 
     pub fn has_edge_blocking(&self, place: MaybeOldPlace<'tcx>) -> bool {
-        self.0
+        self.edges
             .iter()
             .any(|edge| edge.blocked_places().contains(&(place.into())))
     }
 
+    /// A place is a root if it does not block any other place.
     pub fn is_root(&self, place: MaybeOldPlace<'tcx>, repacker: PlaceRepacker<'_, 'tcx>) -> bool {
         !self.has_edge_blocked_by(place, repacker)
     }
@@ -258,7 +503,7 @@ impl<'tcx> BorrowsGraph<'tcx> {
         place: MaybeOldPlace<'tcx>,
         repacker: PlaceRepacker<'_, 'tcx>,
     ) -> bool {
-        self.0
+        self.edges
             .iter()
             .any(|edge| edge.blocked_by_places(repacker).contains(&place))
     }
@@ -268,7 +513,7 @@ impl<'tcx> BorrowsGraph<'tcx> {
         place: MaybeOldPlace<'tcx>,
         repacker: PlaceRepacker<'_, 'tcx>,
     ) -> FxHashSet<BorrowsEdge<'tcx>> {
-        self.0
+        self.edges
             .iter()
             .filter(|edge| edge.blocked_by_places(repacker).contains(&place))
             .cloned()
@@ -287,6 +532,23 @@ impl<'tcx> BorrowsGraph<'tcx> {
         });
     }
 
+    /// Makes every place in `places` (and their descendants) old, traversing
+    /// the edges of the graph only once.
+    pub fn make_places_old(
+        &mut self,
+        places: &FxHashSet<Place<'tcx>>,
+        latest: &Latest<'tcx>,
+        _debug_ctx: Option<DebugCtx>,
+    ) {
+        if places.is_empty() {
+            return;
+        }
+        self.mut_edges(|edge| {
+            edge.make_places_old(places, latest);
+            true
+        });
+    }
+
     fn construct_coupling_graph(
         &self,
         output_facts: &PoloniusOutput,
@@ -308,40 +570,88 @@ impl<'tcx> BorrowsGraph<'tcx> {
         output_facts: &PoloniusOutput,
         location_table: &LocationTable,
     ) -> bool {
-        eprintln!("Attempt join loop {:?} -> {:?}", self_block, exit_block);
         let self_coupling_graph =
             self.construct_coupling_graph(output_facts, location_table, repacker, exit_block);
         let other_coupling_graph =
             other.construct_coupling_graph(output_facts, location_table, repacker, exit_block);
-        self_coupling_graph.render_with_imgcat().unwrap();
-        other_coupling_graph.render_with_imgcat().unwrap();
         let result = self_coupling_graph
             .union(&other_coupling_graph)
             .transitive_reduction();
-        result.render_with_imgcat().unwrap();
         let mut changed = false;
         for (blocked, assigned) in result.edges() {
-            let abstraction = LoopAbstraction::new(
-                AbstractionBlockEdge::new(
-                    vec![blocked.to_abstraction_input_target()]
-                        .into_iter()
-                        .collect(),
-                    vec![assigned.to_abstraction_output_target()]
-                        .into_iter()
-                        .collect(),
-                ),
-                self_block,
-            )
-            .to_borrows_edge(PathConditions::new(self_block));
-            if self.insert(abstraction) {
+            let edge = AbstractionBlockEdge::new(
+                vec![blocked.to_abstraction_input_target()]
+                    .into_iter()
+                    .collect(),
+                vec![assigned.to_abstraction_output_target()]
+                    .into_iter()
+                    .collect(),
+            );
+            if self.insert_loop_abstraction(edge, self_block, "loop abstraction", repacker) {
                 changed = true;
             }
-            for borrow in self.reborrows_blocking(blocked.place.project_deref(repacker).into()) {
-                self.remove(&borrow.into(), DebugCtx::Other);
+        }
+        return changed;
+    }
+
+    /// Abstracts the loop with head `loop_head` by the given `edges` rather
+    /// than by the inferred abstraction, see
+    /// [`crate::combined_pcs::PcsContext::with_loop_abstraction`].
+    pub fn apply_loop_abstraction(
+        &mut self,
+        edges: &[AbstractionBlockEdge<'tcx>],
+        loop_head: BasicBlock,
+        repacker: PlaceRepacker<'_, 'tcx>,
+    ) -> bool {
+        let mut changed = false;
+        for edge in edges {
+            if self.insert_loop_abstraction(
+                edge.clone(),
+                loop_head,
+                "user-specified loop abstraction",
+                repacker,
+            ) {
                 changed = true;
             }
         }
-        return changed;
+        changed
+    }
+
+    /// Inserts `edge` as part of the abstraction of the loop with head
+    /// `loop_head`. The reborrows of the inputs of the edge (of the targets
+    /// of region projection inputs) are subsumed by it, and thus removed.
+    fn insert_loop_abstraction(
+        &mut self,
+        edge: AbstractionBlockEdge<'tcx>,
+        loop_head: BasicBlock,
+        description: &str,
+        repacker: PlaceRepacker<'_, 'tcx>,
+    ) -> bool {
+        let inputs = edge.inputs().to_vec();
+        let abstraction = LoopAbstraction::new(edge, loop_head)
+            .to_borrows_edge(PathConditions::new(loop_head))
+            .with_provenance(EdgeProvenance::new(
+                format!("{} for loop head {:?}", description, loop_head),
+                None,
+            ));
+        let mut changed = self.insert(abstraction);
+        for input in inputs {
+            let blocked = match input {
+                AbstractionTarget::Place(place) => place,
+                AbstractionTarget::RegionProjection(projection) => {
+                    projection.place.project_deref(repacker).into()
+                }
+            };
+            let blocking: Vec<BorrowsEdge<'tcx>> = self
+                .reborrows_blocking(blocked)
+                .map(|borrow| borrow.cloned().into())
+                .collect();
+            for borrow in blocking {
+                self.remove(&borrow, DebugCtx::Other);
+                changed = true;
+            }
+        }
+        changed
     }
 
     pub fn join(
@@ -353,9 +663,105 @@ impl<'tcx> BorrowsGraph<'tcx> {
         location_table: &LocationTable,
         repacker: PlaceRepacker<'_, 'tcx>,
     ) -> bool {
-        let mut changed = false;
+        if self.unchanged_joins.iter().any(|join| {
+            join.self_block == self_block
+                && join.other_block == other_block
+                && (Rc::ptr_eq(&join.other_edges, &other.edges) || join.other_edges == other.edges)
+        }) {
+            return false;
+        }
+        let edges = self.edges.clone();
+        let changed = self.join_edges(
+            other,
+            self_block,
+            other_block,
+            output_facts,
+            location_table,
+            repacker,
+        );
+        // Joins are deterministic, so one that left the graph unchanged would
+        // do so again as long as neither graph changes
+        if Rc::ptr_eq(&edges, &self.edges) || edges == self.edges {
+            self.unchanged_joins.push(UnchangedJoin {
+                self_block,
+                other_block,
+                other_edges: other.edges.clone(),
+            });
+        }
+        changed
+    }
+
+    fn join_edges(
+        &mut self,
+        other: &Self,
+        self_block: BasicBlock,
+        other_block: BasicBlock,
+        output_facts: &PoloniusOutput,
+        location_table: &LocationTable,
+        repacker: PlaceRepacker<'_, 'tcx>,
+    ) -> bool {
+        // A state that has not been reached before takes on the history of
+        // the first state joined into it
+        if self.log.is_none() && other.log.is_some() && self.is_empty() {
+            *self = other.clone();
+            self.set_mutation_location(SnapshotLocation::Join(self_block));
+            return !self.is_empty();
+        }
+
+        self.set_mutation_location(SnapshotLocation::Join(self_block));
+        if repacker.is_back_edge(other_block, self_block) {
+            self.join_back_edge(
+                other,
+                self_block,
+                other_block,
+                output_facts,
+                location_table,
+                repacker,
+            )
+        } else {
+            self.join_forward(other, self_block, other_block, repacker)
+        }
+    }
 
-        // Optimization
+    /// Joins the state at the end of the latch `other_block` of the loop with
+    /// head `self_block`. Rather than overlaying the graphs, which need not
+    /// converge, the loop is abstracted.
+    fn join_back_edge(
+        &mut self,
+        other: &Self,
+        self_block: BasicBlock,
+        other_block: BasicBlock,
+        output_facts: &PoloniusOutput,
+        location_table: &LocationTable,
+        repacker: PlaceRepacker<'_, 'tcx>,
+    ) -> bool {
+        let exit_blocks = repacker.get_loop_exit_blocks(self_block, other_block);
+        // TODO: Handle multiple exit blocks
+        match exit_blocks.first() {
+            Some(&exit_block) => self.join_loop(
+                other,
+                self_block,
+                exit_block,
+                repacker,
+                output_facts,
+                location_table,
+            ),
+            // A loop that is never left is not abstracted
+            None => self.overlay(other, repacker),
+        }
+    }
+
+    /// Joins the state at the end of `other_block`, which is not reached
+    /// from `self_block`, precisely.
+    fn join_forward(
+        &mut self,
+        other: &Self,
+        self_block: BasicBlock,
+        other_block: BasicBlock,
+        repacker: PlaceRepacker<'_, 'tcx>,
+    ) -> bool {
+        // Every path to `self_block` passes through `other_block`, so its
+        // state is the only one that matters
         if repacker
             .body()
             .basic_blocks
@@ -369,30 +775,34 @@ impl<'tcx> BorrowsGraph<'tcx> {
                 return false;
             }
         }
-        let our_edges = self.0.clone();
-        if repacker.is_back_edge(other_block, self_block) {
-            let exit_blocks = repacker.get_loop_exit_blocks(self_block, other_block);
-            if exit_blocks.len() >= 1 {
-                return self.join_loop(
-                    other,
-                    self_block,
-                    exit_blocks[0],
-                    repacker,
-                    output_facts,
-                    location_table,
-                );
+        self.overlay(other, repacker)
+    }
+
+    /// Adds the edges of `other` to this graph, weakening the conditions of
+    /// edges in both, and removes the edges blocked by leaves that are not
+    /// leaves of `other`.
+    fn overlay(&mut self, other: &Self, repacker: PlaceRepacker<'_, 'tcx>) -> bool {
+        let mut changed = false;
+        let our_edges = self.edges.clone();
+        for other_edge in other.edges.iter() {
+            // Most edges are unchanged since the last join
+            if our_edges.contains(other_edge) {
+                continue;
             }
-            // TODO: Handle multiple exit blocks
-        }
-        for other_edge in other.0.iter() {
-            match our_edges.iter().find(|e| e.kind() == other_edge.kind()) {
+            match our_edges.with_kind(other_edge.kind()).next() {
                 Some(our_edge) => {
-                    if our_edge.conditions() != other_edge.conditions() {
+                    // Nothing to do if the edge is already valid on every path
+                    // where the other one is
+                    if !other_edge.conditions().implies(our_edge.conditions()) {
                         let mut new_conditions = our_edge.conditions().clone();
-                        new_conditions.join(&other_edge.conditions());
-                        self.0.remove(our_edge);
-                        self.insert(BorrowsEdge::new(other_edge.kind().clone(), new_conditions));
-                        changed = true;
+                        if new_conditions.join(&other_edge.conditions()) {
+                            self.remove(our_edge, DebugCtx::Other);
+                            self.insert(
+                                BorrowsEdge::new(other_edge.kind().clone(), new_conditions)
+                                    .with_provenance(our_edge.provenance().clone()),
+                            );
+                            changed = true;
+                        }
                     }
                 }
                 None => {
@@ -401,11 +811,12 @@ impl<'tcx> BorrowsGraph<'tcx> {
                 }
             }
         }
+        let other_leaf_nodes = other.leaf_nodes(repacker);
         let mut finished = false;
         while !finished {
             finished = true;
             for leaf_node in self.leaf_nodes(repacker) {
-                if !other.leaf_nodes(repacker).contains(&leaf_node) {
+                if !other_leaf_nodes.contains(&leaf_node) {
                     for edge in self.edges_blocked_by(leaf_node.into(), repacker) {
                         finished = false;
                         self.remove(&edge, DebugCtx::Other);
@@ -414,22 +825,55 @@ impl<'tcx> BorrowsGraph<'tcx> {
                 }
             }
         }
+        if self.collapse_conflicting_variant_expansions(repacker) {
+            changed = true;
+        }
+        if self.merge_duplicate_edges() {
+            changed = true;
+        }
         changed
     }
 
-    pub fn change_pcs_elem<T: 'tcx>(&mut self, old: T, new: T) -> bool
+    pub fn change_pcs_elem<T: 'tcx>(
+        &mut self,
+        old: T,
+        new: T,
+        repacker: PlaceRepacker<'_, 'tcx>,
+    ) -> bool
     where
-        T: PartialEq + Clone,
-        BorrowsEdge<'tcx>: HasPcsElems<T>,
+        T: PartialEq + Clone + std::fmt::Debug,
+        BorrowsEdge<'tcx>: HasPcsElems<T> + ReferencesPcsElem<'tcx, T>,
     {
-        self.mut_pcs_elems(|thing| {
+        let changed = self.mut_pcs_elems(|thing| {
             if *thing == old {
                 *thing = new.clone();
                 true
             } else {
                 false
             }
-        })
+        });
+        if cfg!(debug_assertions) && old != new {
+            self.check_renamed(&old, &new, repacker);
+        }
+        changed
+    }
+
+    /// Checks that no edge still references `old` after it has been renamed
+    /// to `new`, panicking with the offending edge otherwise.
+    fn check_renamed<T: std::fmt::Debug>(&self, old: &T, new: &T, repacker: PlaceRepacker<'_, 'tcx>)
+    where
+        BorrowsEdge<'tcx>: ReferencesPcsElem<'tcx, T>,
+    {
+        if let Some(edge) = self
+            .edges
+            .iter()
+            .find(|edge| edge.references(old, repacker))
+        {
+            panic!(
+                "Edge {:?} still references {:?} after it was renamed to {:?}",
+                edge, old, new
+            );
+        }
     }
 
     pub fn add_reborrow(
@@ -439,6 +883,7 @@ impl<'tcx> BorrowsGraph<'tcx> {
         mutability: Mutability,
         location: Location,
         region: Region<'tcx>,
+        provenance: EdgeProvenance,
     ) -> bool {
         self.insert(
             Reborrow::new(
@@ -448,25 +893,86 @@ impl<'tcx> BorrowsGraph<'tcx> {
                 location,
                 region,
             )
-            .to_borrows_edge(PathConditions::new(location.block)),
+            .to_borrows_edge(PathConditions::new(location.block))
+            .with_provenance(provenance),
         )
     }
 
+    /// Inserts `edge`, merging it into an existing edge of the same kind by
+    /// unioning their conditions if possible, so that the graph does not
+    /// contain copies of an edge that differ only in their conditions. The
+    /// merged edge keeps the provenance of the existing one.
     pub fn insert(&mut self, edge: BorrowsEdge<'tcx>) -> bool {
-        self.0.insert(edge)
+        let existing = self
+            .edges
+            .with_kind(edge.kind())
+            .find(|e| {
+                e.conditions() != edge.conditions() && e.conditions().can_join(edge.conditions())
+            })
+            .cloned();
+        if let Some(existing) = existing {
+            let mut conditions = existing.conditions().clone();
+            if !conditions.join(edge.conditions()) {
+                return false;
+            }
+            self.remove(&existing, DebugCtx::Other);
+            return self.insert_unmerged(
+                BorrowsEdge::new(edge.kind, conditions)
+                    .with_provenance(existing.provenance().clone()),
+            );
+        }
+        self.insert_unmerged(edge)
+    }
+
+    fn insert_unmerged(&mut self, edge: BorrowsEdge<'tcx>) -> bool {
+        if self.edges.contains(&edge) {
+            return false;
+        }
+        if let Some(log) = &mut self.log {
+            log.record(GraphMutation::Insert(edge.clone()));
+        }
+        self.edges_mut().insert(edge);
+        self.edges_changed();
+        true
+    }
+
+    /// Merges edges that differ only in their conditions, which can arise
+    /// when renaming places makes two edges equal. Returns `true` iff any
+    /// edges were merged.
+    fn merge_duplicate_edges(&mut self) -> bool {
+        let mut changed = false;
+        let candidates: Vec<_> = self
+            .edges
+            .iter()
+            .filter(|edge| self.edges.with_kind(edge.kind()).nth(1).is_some())
+            .cloned()
+            .collect();
+        for edge in candidates {
+            let has_duplicate = self.edges.contains(&edge)
+                && self.edges.with_kind(edge.kind()).any(|e| {
+                    e.conditions() != edge.conditions()
+                        && e.conditions().can_join(edge.conditions())
+                });
+            if has_duplicate {
+                self.remove(&edge, DebugCtx::Other);
+                self.insert(edge);
+                changed = true;
+            }
+        }
+        changed
     }
 
     pub fn edges_blocking(
         &self,
         place: MaybeRemotePlace<'tcx>,
     ) -> impl Iterator<Item = &BorrowsEdge<'tcx>> {
-        self.0
+        self.edges
             .iter()
             .filter(move |edge| edge.blocked_places().contains(&place))
     }
 
     pub fn remove_abstraction_at(&mut self, location: Location) {
-        self.0.retain(|edge| {
+        self.retain(|edge| {
             if let BorrowsEdgeKind::Abstraction(abstraction) = &edge.kind() {
                 abstraction.location() != location
             } else {
@@ -476,7 +982,36 @@ impl<'tcx> BorrowsGraph<'tcx> {
     }
 
     pub fn remove(&mut self, edge: &BorrowsEdge<'tcx>, debug_ctx: DebugCtx) -> bool {
-        self.0.remove(edge)
+        if !self.edges.contains(edge) {
+            return false;
+        }
+        match self.edges_mut().take(edge) {
+            Some(removed) => {
+                if let Some(log) = &mut self.log {
+                    log.record(GraphMutation::Remove(removed));
+                }
+                self.edges_changed();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn retain(&mut self, mut f: impl FnMut(&BorrowsEdge<'tcx>) -> bool) {
+        let removed: Vec<_> = self.edges.iter().filter(|edge| !f(edge)).cloned().collect();
+        if removed.is_empty() {
+            return;
+        }
+        let edges = self.edges_mut();
+        for edge in &removed {
+            edges.remove(edge);
+        }
+        if let Some(log) = &mut self.log {
+            for edge in removed {
+                log.record(GraphMutation::Remove(edge));
+            }
+        }
+        self.edges_changed();
     }
 
     pub fn move_region_projection_member_projections(
@@ -488,8 +1023,16 @@ impl<'tcx> BorrowsGraph<'tcx> {
         self.mut_edges(|edge| {
             if let BorrowsEdgeKind::RegionProjectionMember(member) = &mut edge.kind {
                 if member.projection.place == old_projection_place {
-                    let idx = member.projection_index(repacker);
-                    member.projection = new_projection_place.region_projection(idx, repacker);
+                    member.projection = member
+                        .projection
+                        .with_place(new_projection_place, repacker)
+                        .unwrap_or_else(|| {
+                            panic!(
+                                "No region projection of {:?} corresponds to {:?}",
+                                new_projection_place,
+                                member.projection.id()
+                            )
+                        });
                 }
             }
             true
@@ -512,7 +1055,7 @@ impl<'tcx> BorrowsGraph<'tcx> {
     }
 
     pub fn contains_deref_expansion_from(&self, place: &MaybeOldPlace<'tcx>) -> bool {
-        self.0.iter().any(|edge| {
+        self.edges.iter().any(|edge| {
             if let BorrowsEdgeKind::DerefExpansion(de) = &edge.kind {
                 de.base() == *place
             } else {
@@ -521,22 +1064,105 @@ impl<'tcx> BorrowsGraph<'tcx> {
         })
     }
 
+    /// Whether `place` is expanded into its `variant`, see
+    /// [`DerefExpansion::downcast_variant`].
+    fn contains_variant_expansion_from(
+        &self,
+        place: &MaybeOldPlace<'tcx>,
+        variant: VariantIdx,
+    ) -> bool {
+        self.edges.iter().any(|edge| match &edge.kind {
+            BorrowsEdgeKind::DerefExpansion(de) => {
+                de.base() == *place && de.downcast_variant() == Some(variant)
+            }
+            _ => false,
+        })
+    }
+
+    /// At a join, an enum-typed place may be expanded into a different variant
+    /// on each side. The variant is no longer known afterwards, so such
+    /// guarded expansions are collapsed unless a child of the variant is still
+    /// in use; those remain, conditioned on the paths that selected the
+    /// variant.
+    fn collapse_conflicting_variant_expansions(
+        &mut self,
+        repacker: PlaceRepacker<'_, 'tcx>,
+    ) -> bool {
+        let mut variants: FxHashMap<MaybeOldPlace<'tcx>, FxHashSet<VariantIdx>> =
+            FxHashMap::default();
+        for edge in self.edges.iter() {
+            if let BorrowsEdgeKind::DerefExpansion(de) = &edge.kind
+                && let Some(variant) = de.downcast_variant()
+            {
+                variants.entry(de.base()).or_default().insert(variant);
+            }
+        }
+        let to_remove: Vec<_> = self
+            .edges
+            .iter()
+            .filter(|edge| match &edge.kind {
+                BorrowsEdgeKind::DerefExpansion(de) => {
+                    de.downcast_variant().is_some()
+                        && variants[&de.base()].len() > 1
+                        && de
+                            .expansion(repacker)
+                            .into_iter()
+                            .all(|p| !self.has_edge_blocking(p))
+                }
+                _ => false,
+            })
+            .cloned()
+            .collect();
+        for edge in to_remove.iter() {
+            self.remove(edge, DebugCtx::Other);
+        }
+        !to_remove.is_empty()
+    }
+
+    /// Expands the graph so that it contains `place`, returning the
+    /// expansions that were inserted.
+    ///
+    /// If `depth_limit` is given, places with more dereferences than the
+    /// limit are not expanded: `place` instead blocks its ancestor at the
+    /// limit through a summarizing abstraction, see
+    /// [`super::projection_summary`].
     pub fn ensure_deref_expansion_to_at_least(
         &mut self,
-        place: Place<'tcx>,
+        target: Place<'tcx>,
         body: &mir::Body<'tcx>,
         tcx: TyCtxt<'tcx>,
         location: Location,
-    ) {
+        depth_limit: Option<usize>,
+    ) -> Vec<ExpansionOp<'tcx>> {
+        let mut inserted = vec![];
         let mut in_dag = false;
-        for (place, elem) in place.iter_projections() {
+        for (place, elem) in target.iter_projections() {
             let place: Place<'tcx> = place.into();
             if place.is_ref(body, tcx) {
                 in_dag = true;
             }
+            if in_dag
+                && elem == mir::ProjectionElem::Deref
+                && depth_limit.is_some_and(|limit| deref_depth(place) >= limit)
+            {
+                inserted.extend(
+                    self.insert_projection_summary(
+                        place,
+                        target,
+                        location,
+                        PlaceRepacker::new(body, tcx),
+                    )
+                    .map(ExpansionOp::Summarize),
+                );
+                break;
+            }
             let expansion = match elem {
                 mir::ProjectionElem::Downcast(_, _) | // For downcast we can't blindly expand since we don't know which instance, use this specific one
-                mir::ProjectionElem::Deref // For Box we don't want to expand fields because it's actually an ADT w/ a ptr inside
+                mir::ProjectionElem::Deref | // For Box we don't want to expand fields because it's actually an ADT w/ a ptr inside
+                // Arrays and slices cannot be expanded into all of their elements
+                mir::ProjectionElem::Index(_) |
+                mir::ProjectionElem::ConstantIndex { .. } |
+                mir::ProjectionElem::Subslice { .. }
                         => {
                             vec![place.project_deeper(&[elem], tcx).into()]
                         }
@@ -544,16 +1170,24 @@ impl<'tcx> BorrowsGraph<'tcx> {
                     };
             if in_dag {
                 let origin_place = place.into();
-                if !self.contains_deref_expansion_from(&origin_place) {
-                    self.insert_deref_expansion(
+                // An expansion into another variant does not cover this one
+                let expanded = match elem {
+                    mir::ProjectionElem::Downcast(_, variant) => {
+                        self.contains_variant_expansion_from(&origin_place, variant)
+                    }
+                    _ => self.contains_deref_expansion_from(&origin_place),
+                };
+                if !expanded {
+                    inserted.push(ExpansionOp::Expand(self.insert_deref_expansion(
                         origin_place,
                         expansion,
                         location,
                         PlaceRepacker::new(&body, tcx),
-                    );
+                    )));
                 }
             }
         }
+        inserted
     }
 
     fn insert_deref_expansion(
@@ -562,7 +1196,7 @@ impl<'tcx> BorrowsGraph<'tcx> {
         expansion: Vec<Place<'tcx>>,
         location: Location,
         repacker: PlaceRepacker<'_, 'tcx>,
-    ) {
+    ) -> DerefExpansion<'tcx> {
         for p in expansion.iter() {
             assert!(p.projection.len() > place.place().projection.len());
         }
@@ -572,10 +1206,17 @@ impl<'tcx> BorrowsGraph<'tcx> {
         } else {
             DerefExpansion::borrowed(place, expansion, location, repacker)
         };
-        self.insert(BorrowsEdge::new(
-            BorrowsEdgeKind::DerefExpansion(de),
-            PathConditions::new(location.block),
-        ));
+        self.insert(
+            BorrowsEdge::new(
+                BorrowsEdgeKind::DerefExpansion(de.clone()),
+                PathConditions::new(location.block),
+            )
+            .with_provenance(EdgeProvenance::at(
+                format!("expansion of {}", place),
+                location,
+            )),
+        );
+        de
     }
 
     fn mut_pcs_elems<'slf, T: 'tcx>(&'slf mut self, mut f: impl FnMut(&mut T) -> bool) -> bool
@@ -595,21 +1236,76 @@ impl<'tcx> BorrowsGraph<'tcx> {
 
     fn mut_edges<'slf>(&'slf mut self, mut f: impl FnMut(&mut BorrowsEdge<'tcx>) -> bool) -> bool {
         let mut changed = false;
-        self.0 = self
-            .0
-            .drain()
+        let log = &mut self.log;
+        let edges = Rc::try_unwrap(std::mem::take(&mut self.edges))
+            .unwrap_or_else(|edges| (*edges).clone());
+        self.edges = edges
+            .into_iter()
             .map(|mut edge| {
+                let before = log.as_ref().map(|_| edge.clone());
                 if f(&mut edge) {
                     changed = true;
                 }
+                if let (Some(log), Some(before)) = (log.as_mut(), before) {
+                    if before != edge {
+                        log.record(GraphMutation::Update {
+                            from: before,
+                            to: edge.clone(),
+                        });
+                    }
+                }
                 edge
             })
-            .collect();
+            .collect::<EdgeSet<'tcx>>()
+            .into();
+        if changed {
+            self.edges_changed();
+        }
         changed
     }
 
+    /// Retains only the edges that are valid for the given path of blocks.
+    ///
+    /// The path may traverse loops any number of times. Loop abstractions are
+    /// conditioned on the loop head and summarise every iteration of the loop,
+    /// so they are retained (as a whole) for any path that passes through the
+    /// head, irrespective of how often the loop is unrolled.
     pub fn filter_for_path(&mut self, path: &[BasicBlock]) {
-        self.0.retain(|edge| edge.conditions().valid_for_path(path));
+        // Many edges share the same conditions, so only check each once
+        let mut valid: FxHashMap<PathConditions, bool> = FxHashMap::default();
+        self.retain(|edge| {
+            *valid
+                .entry(edge.conditions().clone())
+                .or_insert_with(|| edge.conditions().valid_for_path(path))
+        });
+    }
+
+    /// Removes the path conditions of edges that are known to hold whenever
+    /// `block` is reached, i.e. those whose conditions cover every path from
+    /// the block where the edge was created to `block`. This requires that
+    /// the creating block dominates `block` and is post-dominated by it.
+    pub fn simplify_path_conditions(
+        &mut self,
+        block: BasicBlock,
+        post_dominators: &PostDominators,
+        repacker: PlaceRepacker<'_, 'tcx>,
+    ) -> bool {
+        let blocks = &repacker.body().basic_blocks;
+        self.mut_edges(|edge| {
+            let conditions = edge.conditions();
+            let Some(root) = conditions.root() else {
+                return false;
+            };
+            if root == block
+                || !blocks.dominators().dominates(root, block)
+                || !post_dominators.post_dominates(block, root)
+                || !conditions.covers_all_paths(root, block, blocks)
+            {
+                return false;
+            }
+            edge.set_conditions(PathConditions::new(block));
+            true
+        })
     }
 
     pub fn add_path_condition(&mut self, pc: PathCondition) -> bool {
@@ -627,8 +1323,58 @@ impl<T> Conditioned<T> {
     pub fn new(value: T, conditions: PathConditions) -> Self {
         Self { conditions, value }
     }
+
+    /// A view of the value under the same conditions. Only the conditions
+    /// are cloned, which is cheap as their graphs are shared.
+    pub fn as_ref(&self) -> Conditioned<&T> {
+        Conditioned::new(&self.value, self.conditions.clone())
+    }
+
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Conditioned<U> {
+        Conditioned::new(f(self.value), self.conditions)
+    }
+
+    pub fn valid_for_path(&self, path: &[BasicBlock]) -> bool {
+        self.conditions.valid_for_path(path)
+    }
+}
+
+/// Adapters for iterators over conditioned values, e.g. those returned by
+/// [`BorrowsGraph::reborrow_edges`].
+pub trait ConditionedIterator<T>: Iterator<Item = Conditioned<T>> + Sized {
+    /// The values, without their conditions
+    fn values(self) -> impl Iterator<Item = T> {
+        self.map(|conditioned| conditioned.value)
+    }
+
+    /// The values whose conditions hold on `path`, see
+    /// [`PathConditions::valid_for_path`]
+    fn valid_for_path<'p>(self, path: &'p [BasicBlock]) -> impl Iterator<Item = Conditioned<T>> + 'p
+    where
+        Self: 'p,
+    {
+        self.filter(move |conditioned| conditioned.valid_for_path(path))
+    }
+}
+
+impl<T, I: Iterator<Item = Conditioned<T>>> ConditionedIterator<T> for I {}
+
+impl<T: Clone> Conditioned<&T> {
+    pub fn cloned(self) -> Conditioned<T> {
+        Conditioned::new(self.value.clone(), self.conditions)
+    }
+}
+
+#[cfg(feature = "json")]
+impl<'tcx> ToJsonWithRepacker<'tcx> for BorrowsGraph<'tcx> {
+    fn to_json(&self, repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value {
+        json!({
+            "edges": self.sorted_edges().to_json(repacker),
+        })
+    }
 }
 
+#[cfg(feature = "json")]
 impl<'tcx, T: ToJsonWithRepacker<'tcx>> ToJsonWithRepacker<'tcx> for Conditioned<T> {
     fn to_json(&self, repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value {
         json!({
@@ -2,32 +2,181 @@ use rustc_interface::{
     ast::Mutability,
     borrowck::consumers::{LocationTable, PoloniusOutput},
     data_structures::fx::FxHashSet,
-    middle::mir::{self, BasicBlock, Location},
+    middle::mir::{self, BasicBlock, Location, RETURN_PLACE},
     middle::ty::{self, TyCtxt},
 };
+#[cfg(feature = "json")]
 use serde_json::{json, Value};
 
 use crate::{
+    combined_pcs::UnblockAction,
     free_pcs::{CapabilityKind, CapabilityLocal, CapabilitySummary},
     rustc_interface,
-    utils::{Place, PlaceRepacker, SnapshotLocation},
+    utils::{
+        post_dominators::PostDominators, sorted_by_debug, Place, PlaceRepacker, SnapshotLocation,
+    },
     ReborrowBridge,
 };
 
+#[cfg(feature = "json")]
+use super::domain::ToJsonWithRepacker;
 use super::{
-    borrows_edge::{BorrowsEdge, BorrowsEdgeKind, ToBorrowsEdge},
+    borrows_edge::{BorrowsEdge, BorrowsEdgeKind, EdgeProvenance, ToBorrowsEdge},
     borrows_graph::{BorrowsGraph, Conditioned},
     borrows_visitor::DebugCtx,
     deref_expansion::DerefExpansion,
-    domain::{MaybeOldPlace, MaybeRemotePlace, Reborrow},
-    has_pcs_elem::HasPcsElems,
+    domain::{
+        AbstractionBlockEdge, ExternalAbstraction, MaybeOldPlace, MaybeRemotePlace, Reborrow,
+        ReborrowEquivalence, ReborrowPhase, RemotePlace,
+    },
+    has_pcs_elem::{HasPcsElems, ReferencesPcsElem},
+    invariants::InvariantViolation,
     latest::Latest,
+    mutation_log::MutationLog,
     path_condition::{PathCondition, PathConditions},
     region_abstraction::AbstractionEdge,
     region_projection_member::{RegionProjectionMember, RegionProjectionMemberDirection},
     unblock_graph::UnblockGraph,
 };
 
+/// When edges whose blockers are all old leaves of the graph are removed.
+///
+/// See [`BorrowsState::trim_old_leaves`]. Consumers that place expiries
+/// themselves (e.g. to encode magic wands) can delay or disable trimming.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum OldLeafTrimming {
+    /// After every statement, and before every statement and terminator when
+    /// minimizing the graph
+    #[default]
+    Eager,
+    /// Only when minimizing the graph before the terminator of each block
+    AtBlockExit,
+    /// Never; old leaves remain until their blocked places are required
+    Never,
+}
+
+impl std::str::FromStr for OldLeafTrimming {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "eager" => Ok(OldLeafTrimming::Eager),
+            "block_exit" => Ok(OldLeafTrimming::AtBlockExit),
+            "never" => Ok(OldLeafTrimming::Never),
+            other => Err(format!("Unknown old leaf trimming policy: {other}")),
+        }
+    }
+}
+
+impl OldLeafTrimming {
+    /// Whether old leaves are trimmed at a statement, or at a terminator if
+    /// `at_terminator` is set.
+    pub fn trims_at(self, at_terminator: bool) -> bool {
+        match self {
+            OldLeafTrimming::Eager => true,
+            OldLeafTrimming::AtBlockExit => at_terminator,
+            OldLeafTrimming::Never => false,
+        }
+    }
+}
+
+/// The effect of applying an [`UnblockGraph`] to a [`BorrowsState`].
+#[derive(Clone, Debug, Default)]
+pub struct AppliedUnblock<'tcx> {
+    pub changed: bool,
+    /// The reborrow edges that were removed, with the path conditions under
+    /// which they held
+    pub killed_reborrows: Vec<Conditioned<Reborrow<'tcx>>>,
+}
+
+/// An operation performed on the graph by
+/// [`BorrowsState::ensure_expansion_to_exactly`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExpansionOp<'tcx> {
+    /// A borrow assigned to a descendant of the expanded place was made a
+    /// member of one of the place's region projections
+    AddRegionProjectionMember(RegionProjectionMember<'tcx>),
+    /// The place was unblocked, as for the unblock graph of a bridge
+    Unblock(UnblockAction<'tcx>),
+    /// A reborrow edge was removed by the preceding `Unblock` operation
+    KillReborrow(Conditioned<Reborrow<'tcx>>),
+    /// The graph was expanded towards the place
+    Expand(DerefExpansion<'tcx>),
+    /// The place was summarized rather than expanded, because it is beyond
+    /// the projection depth limit
+    Summarize(ExternalAbstraction<'tcx>),
+}
+
+impl<'tcx> ExpansionOp<'tcx> {
+    pub fn to_short_string(&self, repacker: PlaceRepacker<'_, 'tcx>) -> String {
+        let places = |places: Vec<String>| places.join(", ");
+        match self {
+            ExpansionOp::AddRegionProjectionMember(member) => format!(
+                "member {} -> {}",
+                places(
+                    member
+                        .places
+                        .iter()
+                        .map(|place| place.to_short_string(repacker))
+                        .collect()
+                ),
+                member.projection.place.to_short_string(repacker)
+            ),
+            ExpansionOp::Unblock(UnblockAction::TerminateReborrow {
+                blocked_place,
+                assigned_place,
+                ..
+            }) => format!(
+                "terminate reborrow {} -> {}",
+                blocked_place.to_short_string(repacker),
+                assigned_place.to_short_string(repacker)
+            ),
+            ExpansionOp::Unblock(UnblockAction::Collapse(place, _)) => {
+                format!("collapse {}", place.to_short_string(repacker))
+            }
+            ExpansionOp::Unblock(UnblockAction::TerminateAbstraction(location, _)) => {
+                format!("terminate abstraction {location:?}")
+            }
+            ExpansionOp::KillReborrow(reborrow) => format!(
+                "kill reborrow {} -> {}\t{}",
+                reborrow.value.blocked_place.to_short_string(repacker),
+                reborrow.value.assigned_place.to_short_string(repacker),
+                reborrow.conditions
+            ),
+            ExpansionOp::Expand(expansion) => format!(
+                "expand {} -> {}",
+                expansion.base().to_short_string(repacker),
+                places(
+                    expansion
+                        .expansion(repacker)
+                        .iter()
+                        .map(|place| place.to_short_string(repacker))
+                        .collect()
+                )
+            ),
+            ExpansionOp::Summarize(summary) => {
+                let edge = summary
+                    .clone()
+                    .to_borrows_edge(PathConditions::new(summary.location().block));
+                format!(
+                    "summarize {} -> {}",
+                    places(
+                        edge.blocked_places()
+                            .iter()
+                            .map(|place| place.to_short_string(repacker))
+                            .collect()
+                    ),
+                    places(
+                        edge.blocked_by_places(repacker)
+                            .iter()
+                            .map(|place| place.to_short_string(repacker))
+                            .collect()
+                    )
+                )
+            }
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct BorrowsState<'tcx> {
     pub latest: Latest<'tcx>,
@@ -39,6 +188,24 @@ impl<'tcx> BorrowsState<'tcx> {
         &self.graph
     }
 
+    /// Starts recording the mutations made to the graph, see
+    /// [`BorrowsGraph::enable_mutation_log`]
+    pub fn enable_mutation_log(&mut self) {
+        self.graph.enable_mutation_log();
+    }
+
+    pub fn mutation_log(&self) -> Option<&MutationLog<'tcx>> {
+        self.graph.mutation_log()
+    }
+
+    pub fn set_mutation_location(&mut self, location: SnapshotLocation) {
+        self.graph.set_mutation_location(location);
+    }
+
+    /// Joins `other`, the state at the end of `other_block`, into this state
+    /// at the start of `self_block`. If `self_block` is a loop head reached
+    /// over a back edge the graph is widened by abstracting the loop, other
+    /// joins are precise.
     pub fn join<'mir>(
         &mut self,
         other: &Self,
@@ -68,12 +235,50 @@ impl<'tcx> BorrowsState<'tcx> {
         changed
     }
 
-    pub fn change_pcs_elem<T: 'tcx>(&mut self, old: T, new: T) -> bool
+    /// See [`BorrowsGraph::apply_loop_abstraction`].
+    pub fn apply_loop_abstraction(
+        &mut self,
+        edges: &[AbstractionBlockEdge<'tcx>],
+        loop_head: BasicBlock,
+        repacker: PlaceRepacker<'_, 'tcx>,
+    ) -> bool {
+        self.set_mutation_location(SnapshotLocation::Join(loop_head));
+        self.graph
+            .apply_loop_abstraction(edges, loop_head, repacker)
+    }
+
+    /// See [`BorrowsGraph::insert_external_abstraction`].
+    pub fn insert_external_abstraction(
+        &mut self,
+        abstraction: Conditioned<AbstractionEdge<'tcx>>,
+        location: Location,
+        repacker: PlaceRepacker<'_, 'tcx>,
+    ) -> Result<bool, Vec<InvariantViolation<'tcx>>> {
+        self.graph
+            .insert_external_abstraction(abstraction, location, repacker)
+    }
+
+    pub fn simplify_path_conditions(
+        &mut self,
+        block: BasicBlock,
+        post_dominators: &PostDominators,
+        repacker: PlaceRepacker<'_, 'tcx>,
+    ) -> bool {
+        self.graph
+            .simplify_path_conditions(block, post_dominators, repacker)
+    }
+
+    pub fn change_pcs_elem<T: 'tcx>(
+        &mut self,
+        old: T,
+        new: T,
+        repacker: PlaceRepacker<'_, 'tcx>,
+    ) -> bool
     where
-        T: PartialEq + Clone,
-        BorrowsEdge<'tcx>: HasPcsElems<T>,
+        T: PartialEq + Clone + std::fmt::Debug,
+        BorrowsEdge<'tcx>: HasPcsElems<T> + ReferencesPcsElem<'tcx, T>,
     {
-        self.graph.change_pcs_elem(old, new)
+        self.graph.change_pcs_elem(old, new, repacker)
     }
 
     pub fn remove_edge_and_set_latest(
@@ -98,35 +303,37 @@ impl<'tcx> BorrowsState<'tcx> {
     pub fn reborrow_edges_reserved_at(
         &self,
         location: Location,
-    ) -> FxHashSet<Conditioned<Reborrow<'tcx>>> {
-        self.graph
-            .edges()
-            .filter_map(|edge| match &edge.kind() {
-                BorrowsEdgeKind::Reborrow(reborrow) if reborrow.reserve_location() == location => {
-                    Some(Conditioned {
-                        conditions: edge.conditions().clone(),
-                        value: reborrow.clone(),
-                    })
-                }
-                _ => None,
-            })
-            .collect()
+    ) -> impl Iterator<Item = Conditioned<&Reborrow<'tcx>>> + '_ {
+        self.reborrow_edges()
+            .filter(move |rb| rb.value.reserve_location() == location)
     }
 
     /// Collapses nodes using the following rules:
     /// - If a node is only blocked by old leaves, then the node should be collapsed
     /// - If a PCS node's expansion is only leaves, the node should be collapsed
-    /// This function performs such collapses until a fixpoint is reached
-    pub fn minimize(&mut self, repacker: PlaceRepacker<'_, 'tcx>, location: Location) {
+    ///
+    /// This function performs such collapses until a fixpoint is reached.
+    /// Edges that `capabilities` depends on (see
+    /// [`Self::edge_restricts_capability`]) are never collapsed, and the
+    /// first rule only applies if `trim_old_leaves` is set.
+    pub fn minimize(
+        &mut self,
+        repacker: PlaceRepacker<'_, 'tcx>,
+        capabilities: &CapabilitySummary<'tcx>,
+        trim_old_leaves: bool,
+        location: Location,
+    ) {
         loop {
             let to_remove = self
                 .graph
                 .edges()
+                .filter(|edge| !Self::edge_restricts_capability(edge, capabilities))
                 .filter(|edge| {
-                    let is_old_unblocked = edge
-                        .blocked_by_places(repacker)
-                        .iter()
-                        .all(|p| p.is_old() && !self.graph.has_edge_blocking((*p).into()));
+                    let is_old_unblocked = trim_old_leaves
+                        && edge
+                            .blocked_by_places(repacker)
+                            .iter()
+                            .all(|p| p.is_old() && !self.graph.has_edge_blocking((*p).into()));
                     is_old_unblocked
                         || match &edge.kind() {
                             BorrowsEdgeKind::DerefExpansion(de) => {
@@ -150,6 +357,27 @@ impl<'tcx> BorrowsState<'tcx> {
         }
     }
 
+    /// Whether `edge` blocks a current place that `capabilities` holds with
+    /// less than [`CapabilityKind::Exclusive`]. Removing such an edge would
+    /// leave the place unblocked in the graph while the owned state still
+    /// treats it as (partially) given away.
+    fn edge_restricts_capability(
+        edge: &BorrowsEdge<'tcx>,
+        capabilities: &CapabilitySummary<'tcx>,
+    ) -> bool {
+        edge.blocked_places().into_iter().any(|place| match place {
+            MaybeRemotePlace::Local(MaybeOldPlace::Current { place }) => {
+                match capabilities.get(place.local) {
+                    Some(CapabilityLocal::Allocated(projections)) => projections
+                        .get(&place)
+                        .is_some_and(|kind| *kind != CapabilityKind::Exclusive),
+                    _ => false,
+                }
+            }
+            _ => false,
+        })
+    }
+
     pub fn add_path_condition(&mut self, pc: PathCondition) -> bool {
         self.graph.add_path_condition(pc)
     }
@@ -161,16 +389,14 @@ impl<'tcx> BorrowsState<'tcx> {
     pub fn reborrows_blocking_prefix_of(
         &self,
         place: Place<'tcx>,
-    ) -> FxHashSet<Conditioned<Reborrow<'tcx>>> {
-        self.reborrows()
-            .into_iter()
-            .filter(|rb| match rb.value.blocked_place {
+    ) -> impl Iterator<Item = Conditioned<&Reborrow<'tcx>>> + '_ {
+        self.reborrow_edges()
+            .filter(move |rb| match rb.value.blocked_place {
                 MaybeRemotePlace::Local(MaybeOldPlace::Current {
                     place: blocked_place,
                 }) => blocked_place.is_prefix(place),
                 _ => false,
             })
-            .collect()
     }
 
     pub fn delete_descendants_of(
@@ -216,8 +442,8 @@ impl<'tcx> BorrowsState<'tcx> {
         self.graph.edges()
     }
 
-    pub fn deref_expansions(&self) -> FxHashSet<Conditioned<DerefExpansion<'tcx>>> {
-        self.graph.deref_expansions()
+    pub fn expansion_edges(&self) -> impl Iterator<Item = Conditioned<&DerefExpansion<'tcx>>> + '_ {
+        self.graph.expansion_edges()
     }
 
     pub fn move_region_projection_member_projections(
@@ -245,12 +471,24 @@ impl<'tcx> BorrowsState<'tcx> {
     pub fn reborrows_blocked_by(
         &self,
         place: MaybeOldPlace<'tcx>,
-    ) -> FxHashSet<Conditioned<Reborrow<'tcx>>> {
+    ) -> impl Iterator<Item = Conditioned<&Reborrow<'tcx>>> + '_ {
         self.graph.reborrows_blocked_by(place)
     }
 
-    pub fn reborrows(&self) -> FxHashSet<Conditioned<Reborrow<'tcx>>> {
-        self.graph.reborrows()
+    pub fn reborrow_edges(&self) -> impl Iterator<Item = Conditioned<&Reborrow<'tcx>>> + '_ {
+        self.graph.reborrow_edges()
+    }
+
+    pub fn abstraction_edges(
+        &self,
+    ) -> impl Iterator<Item = Conditioned<&AbstractionEdge<'tcx>>> + '_ {
+        self.graph.abstraction_edges()
+    }
+
+    pub fn region_member_edges(
+        &self,
+    ) -> impl Iterator<Item = Conditioned<&RegionProjectionMember<'tcx>>> + '_ {
+        self.graph.region_member_edges()
     }
 
     pub fn bridge(
@@ -259,40 +497,69 @@ impl<'tcx> BorrowsState<'tcx> {
         debug_ctx: DebugCtx,
         repacker: PlaceRepacker<'_, 'tcx>,
     ) -> ReborrowBridge<'tcx> {
+        // Reborrows are identified by their reserve locations; collecting these
+        // once avoids scanning one graph for every reborrow of the other
+        let reserve_locations = |state: &Self, phase: Option<ReborrowPhase>| {
+            state
+                .reborrow_edges()
+                .filter(|rb| phase.map_or(true, |phase| rb.value.phase() == phase))
+                .map(|rb| rb.value.reserve_location())
+                .collect::<FxHashSet<_>>()
+        };
+        let self_reborrows = reserve_locations(self, None);
+        let self_reserved_reborrows = reserve_locations(self, Some(ReborrowPhase::Reserved));
+        let to_reborrows = reserve_locations(to, None);
+
         let added_reborrows: FxHashSet<Conditioned<Reborrow<'tcx>>> = to
-            .reborrows()
-            .into_iter()
-            .filter(|rb| !self.has_reborrow_at_location(rb.value.reserve_location()))
+            .reborrow_edges()
+            .filter(|rb| !self_reborrows.contains(&rb.value.reserve_location()))
+            .map(Conditioned::cloned)
+            .collect();
+
+        let activated_reborrows: FxHashSet<Conditioned<Reborrow<'tcx>>> = to
+            .reborrow_edges()
+            .filter(|rb| {
+                rb.value.phase() == ReborrowPhase::Active
+                    && self_reserved_reborrows.contains(&rb.value.reserve_location())
+            })
+            .map(Conditioned::cloned)
             .collect();
 
-        let expands = to
-            .deref_expansions()
-            .difference(&self.deref_expansions())
+        let self_expansions: FxHashSet<_> = self.expansion_edges().collect();
+        let to_expansions: FxHashSet<_> = to.expansion_edges().collect();
+
+        let expands = to_expansions
+            .difference(&self_expansions)
             .cloned()
+            .map(Conditioned::cloned)
             .collect();
 
         let mut ug = UnblockGraph::new();
 
-        for reborrow in self.reborrows() {
-            if !to.has_reborrow_at_location(reborrow.value.reserve_location()) {
-                ug.kill_reborrow(reborrow, self, repacker);
+        for reborrow in self.reborrow_edges() {
+            if !to_reborrows.contains(&reborrow.value.reserve_location()) {
+                ug.kill_reborrow(reborrow.cloned(), self, repacker);
             }
         }
 
-        for exp in self.deref_expansions().difference(&to.deref_expansions()) {
+        for exp in self_expansions.difference(&to_expansions) {
             ug.unblock_place(exp.value.base().into(), self, repacker);
         }
 
-        for abstraction in self.region_abstractions() {
-            if !to.region_abstractions().contains(&abstraction) {
-                ug.kill_abstraction(self, abstraction, repacker);
+        let to_abstractions: FxHashSet<_> = to.abstraction_edges().collect();
+        for abstraction in self.abstraction_edges() {
+            if !to_abstractions.contains(&abstraction) {
+                ug.kill_abstraction(self, abstraction.cloned(), repacker);
             }
         }
 
         ReborrowBridge {
             added_reborrows,
+            activated_reborrows,
             expands,
             ug,
+            expansion_ops: vec![],
+            killed_reborrows: vec![],
         }
     }
 
@@ -302,6 +569,7 @@ impl<'tcx> BorrowsState<'tcx> {
         body: &mir::Body<'tcx>,
         summary: &CapabilitySummary<'tcx>,
         location: Location,
+        depth_limit: Option<usize>,
     ) {
         for c in (*summary).iter() {
             match c {
@@ -315,6 +583,7 @@ impl<'tcx> BorrowsState<'tcx> {
                                         body,
                                         tcx,
                                         location,
+                                        depth_limit,
                                     );
                                 }
                             }
@@ -327,13 +596,23 @@ impl<'tcx> BorrowsState<'tcx> {
         }
     }
 
+    /// Makes `place` a node of the graph: the borrows assigned to its
+    /// descendants are made members of its region projections, anything
+    /// blocking `place` is unblocked, and the graph is expanded to `place`
+    /// (up to `depth_limit`, see
+    /// [`BorrowsGraph::ensure_deref_expansion_to_at_least`]).
+    ///
+    /// Returns the operations that changed the graph, in the order they were
+    /// performed.
     pub fn ensure_expansion_to_exactly(
         &mut self,
         tcx: TyCtxt<'tcx>,
         body: &mir::Body<'tcx>,
         place: Place<'tcx>,
         location: Location,
-    ) {
+        depth_limit: Option<usize>,
+    ) -> Vec<ExpansionOp<'tcx>> {
+        let mut ops = vec![];
         let mut ug = UnblockGraph::new();
         let repacker = PlaceRepacker::new(body, tcx);
         let graph_edges = self.graph_edges().cloned().collect::<Vec<_>>();
@@ -344,12 +623,21 @@ impl<'tcx> BorrowsState<'tcx> {
                         place: assigned_place,
                     } if place.is_prefix(assigned_place) && !place.is_ref(body, tcx) => {
                         for ra in place.region_projections(repacker) {
-                            self.add_region_projection_member(RegionProjectionMember::new(
-                                reborrow.blocked_place,
+                            let member = RegionProjectionMember::new(
+                                vec![reborrow.blocked_place],
                                 ra,
                                 location,
                                 RegionProjectionMemberDirection::PlaceIsRegionInput,
-                            ));
+                            );
+                            if self.add_region_projection_member(
+                                member.clone(),
+                                EdgeProvenance::at(
+                                    format!("expansion of {}", place.to_short_string(repacker)),
+                                    location,
+                                ),
+                            ) {
+                                ops.push(ExpansionOp::AddRegionProjectionMember(member));
+                            }
                         }
                     }
                     _ => {}
@@ -358,31 +646,84 @@ impl<'tcx> BorrowsState<'tcx> {
             }
         }
         ug.unblock_place(place.into(), self, repacker);
-        self.apply_unblock_graph(ug, repacker, location);
+        for action in ug.actions(repacker) {
+            let applied = self.apply_unblock_action(&action, repacker, location);
+            ops.push(ExpansionOp::Unblock(action));
+            ops.extend(
+                applied
+                    .killed_reborrows
+                    .into_iter()
+                    .map(ExpansionOp::KillReborrow),
+            );
+        }
 
         // Originally we may not have been expanded enough
-        self.graph
-            .ensure_deref_expansion_to_at_least(place.into(), body, tcx, location);
-    }
-
+        ops.extend(self.graph.ensure_deref_expansion_to_at_least(
+            place,
+            body,
+            tcx,
+            location,
+            depth_limit,
+        ));
+        ops
+    }
+
+    /// The places at the bottom of the graph: those that are blocked, but do
+    /// not block anything themselves. See [`BorrowsGraph::roots`].
     pub fn roots(&self, repacker: PlaceRepacker<'_, 'tcx>) -> FxHashSet<MaybeRemotePlace<'tcx>> {
         self.graph.roots(repacker)
     }
 
+    /// The shortest sequence of edges by which `from` transitively blocks `to`,
+    /// see [`BorrowsGraph::blocking_path`].
+    pub fn blocking_path(
+        &self,
+        from: MaybeOldPlace<'tcx>,
+        to: MaybeRemotePlace<'tcx>,
+        repacker: PlaceRepacker<'_, 'tcx>,
+    ) -> Option<Vec<BorrowsEdge<'tcx>>> {
+        self.graph.blocking_path(from, to, repacker)
+    }
+
+    /// All paths by which `place` transitively blocks a function input, see
+    /// [`BorrowsGraph::paths_to_remote_places`]. This can be used to determine
+    /// which function parameters a (returned) reference may point into.
+    pub fn paths_to_remote_places(
+        &self,
+        place: MaybeOldPlace<'tcx>,
+        repacker: PlaceRepacker<'_, 'tcx>,
+    ) -> Vec<(RemotePlace, Vec<BorrowsEdge<'tcx>>)> {
+        self.graph.paths_to_remote_places(place, repacker)
+    }
+
+    /// The places at the frontier of the graph: those that block something,
+    /// but are not blocked themselves. These are the places that currently
+    /// hold capability. See [`BorrowsGraph::leaf_nodes`].
+    pub fn leaves(&self, repacker: PlaceRepacker<'_, 'tcx>) -> FxHashSet<MaybeOldPlace<'tcx>> {
+        self.graph.leaf_nodes(repacker)
+    }
+
+    /// The edges whose blocking places are all leaves, see [`Self::leaves`].
+    pub fn leaf_edges(&self, repacker: PlaceRepacker<'_, 'tcx>) -> FxHashSet<BorrowsEdge<'tcx>> {
+        self.graph.leaf_edges(repacker)
+    }
+
+    /// Removes the reborrow edges reserved at `reserve_location`, returning
+    /// them with the path conditions under which they held.
     pub fn kill_reborrows(
         &mut self,
         reserve_location: Location,
         kill_location: Location,
         repacker: PlaceRepacker<'_, 'tcx>,
-    ) -> bool {
-        let edges_to_remove = self.reborrow_edges_reserved_at(reserve_location);
-        if edges_to_remove.is_empty() {
-            return false;
-        }
-        for edge in edges_to_remove {
-            self.remove_edge_and_set_latest(&edge.into(), repacker, kill_location);
+    ) -> FxHashSet<Conditioned<Reborrow<'tcx>>> {
+        let edges_to_remove: FxHashSet<_> = self
+            .reborrow_edges_reserved_at(reserve_location)
+            .map(Conditioned::cloned)
+            .collect();
+        for edge in edges_to_remove.iter() {
+            self.remove_edge_and_set_latest(&edge.clone().into(), repacker, kill_location);
         }
-        true
+        edges_to_remove
     }
 
     pub fn apply_unblock_graph(
@@ -390,28 +731,41 @@ impl<'tcx> BorrowsState<'tcx> {
         graph: UnblockGraph<'tcx>,
         repacker: PlaceRepacker<'_, 'tcx>,
         location: Location,
-    ) -> bool {
-        let mut changed = false;
+    ) -> AppliedUnblock<'tcx> {
+        let mut applied = AppliedUnblock::default();
         for action in graph.actions(repacker) {
-            match action {
-                crate::combined_pcs::UnblockAction::TerminateReborrow {
-                    reserve_location, ..
-                } => {
-                    if self.kill_reborrows(reserve_location, location, repacker) {
-                        changed = true;
-                    }
-                }
-                crate::combined_pcs::UnblockAction::Collapse(place, _) => {
-                    if self.delete_descendants_of(place, repacker, location) {
-                        changed = true;
-                    }
-                }
-                crate::combined_pcs::UnblockAction::TerminateAbstraction(location, _call) => {
-                    self.graph.remove_abstraction_at(location);
+            let result = self.apply_unblock_action(&action, repacker, location);
+            applied.changed |= result.changed;
+            applied.killed_reborrows.extend(result.killed_reborrows);
+        }
+        applied
+    }
+
+    fn apply_unblock_action(
+        &mut self,
+        action: &UnblockAction<'tcx>,
+        repacker: PlaceRepacker<'_, 'tcx>,
+        location: Location,
+    ) -> AppliedUnblock<'tcx> {
+        match *action {
+            UnblockAction::TerminateReborrow {
+                reserve_location, ..
+            } => {
+                let killed = self.kill_reborrows(reserve_location, location, repacker);
+                AppliedUnblock {
+                    changed: !killed.is_empty(),
+                    killed_reborrows: sorted_by_debug(killed),
                 }
             }
+            UnblockAction::Collapse(place, _) => AppliedUnblock {
+                changed: self.delete_descendants_of(place, repacker, location),
+                killed_reborrows: vec![],
+            },
+            UnblockAction::TerminateAbstraction(location, _) => {
+                self.graph.remove_abstraction_at(location);
+                AppliedUnblock::default()
+            }
         }
-        changed
     }
 
     pub fn set_latest<T: Into<SnapshotLocation>>(&mut self, place: Place<'tcx>, location: T) {
@@ -422,18 +776,76 @@ impl<'tcx> BorrowsState<'tcx> {
         self.latest.get(place)
     }
 
-    pub fn add_region_projection_member(&mut self, member: RegionProjectionMember<'tcx>) {
+    /// Connects each region projection of the return place to the remote
+    /// places (i.e. the function's inputs) that it transitively blocks, by a
+    /// single member whose places are those remote places. The projection
+    /// reaches the inputs through the place that the return place references
+    /// in that region, and through the members already flowing into it (e.g.
+    /// from the fields of a returned aggregate).
+    ///
+    /// This makes the borrow signature of the function explicit in the state
+    /// at its return. Returns `true` iff any member was added.
+    pub fn connect_return_place_to_remote_places(
+        &mut self,
+        repacker: PlaceRepacker<'_, 'tcx>,
+        location: Location,
+    ) -> bool {
+        let return_place: Place<'tcx> = RETURN_PLACE.into();
+        let mut changed = false;
+        for projection in return_place.region_projections(repacker) {
+            let mut sources: Vec<MaybeOldPlace<'tcx>> = self
+                .region_member_edges()
+                .filter(|member| {
+                    member.value.projection == projection
+                        && member.value.direction
+                            == RegionProjectionMemberDirection::PlaceIsRegionInput
+                })
+                .flat_map(|member| member.value.places.clone())
+                .filter_map(|place| place.as_local_place())
+                .collect();
+            if let Some(target) = return_place.deref_to_region(projection.region(), repacker) {
+                sources.push(target.into());
+            }
+            let remote_places: FxHashSet<_> = sources
+                .into_iter()
+                .flat_map(|source| self.paths_to_remote_places(source, repacker))
+                .map(|(remote, _)| MaybeRemotePlace::Remote(remote))
+                .collect();
+            if remote_places.is_empty() {
+                continue;
+            }
+            let member = RegionProjectionMember::new(
+                sorted_by_debug(remote_places),
+                projection,
+                location,
+                RegionProjectionMemberDirection::PlaceIsRegionInput,
+            );
+            if self
+                .add_region_projection_member(member, EdgeProvenance::at("return place", location))
+            {
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    pub fn add_region_projection_member(
+        &mut self,
+        member: RegionProjectionMember<'tcx>,
+        provenance: EdgeProvenance,
+    ) -> bool {
         self.graph.insert(
             member
                 .clone()
-                .to_borrows_edge(PathConditions::new(member.location().block)),
-        );
+                .to_borrows_edge(PathConditions::new(member.location().block))
+                .with_provenance(provenance),
+        )
     }
 
     pub fn trim_old_leaves(&mut self, repacker: PlaceRepacker<'_, 'tcx>, location: Location) {
         loop {
             let mut cont = false;
-            let edges = self.graph.leaf_edges(repacker);
+            let edges = self.leaf_edges(repacker);
             for edge in edges {
                 if edge.blocked_by_places(repacker).iter().all(|p| p.is_old()) {
                     self.remove_edge_and_set_latest(&edge, repacker, location);
@@ -453,21 +865,35 @@ impl<'tcx> BorrowsState<'tcx> {
         mutability: Mutability,
         location: Location,
         region: ty::Region<'tcx>,
+        provenance: EdgeProvenance,
     ) {
-        self.graph
-            .add_reborrow(blocked_place, assigned_place, mutability, location, region);
+        self.graph.add_reborrow(
+            blocked_place,
+            assigned_place,
+            mutability,
+            location,
+            region,
+            provenance,
+        );
     }
 
     pub fn has_reborrow_at_location(&self, location: Location) -> bool {
         self.graph.has_reborrow_at_location(location)
     }
 
-    pub fn region_abstractions(&self) -> FxHashSet<Conditioned<AbstractionEdge<'tcx>>> {
-        self.graph.abstraction_edges()
+    pub fn align_reborrows_with(&mut self, other: &Self, equivalence: ReborrowEquivalence) -> bool {
+        self.graph.align_reborrows_with(&other.graph, equivalence)
+    }
+
+    pub fn set_reborrow_phase(&mut self, reserve_location: Location, phase: ReborrowPhase) -> bool {
+        self.graph.set_reborrow_phase(reserve_location, phase)
     }
 
-    pub fn to_json(&self, _repacker: PlaceRepacker<'_, 'tcx>) -> Value {
-        json!({})
+    #[cfg(feature = "json")]
+    pub fn to_json(&self, repacker: PlaceRepacker<'_, 'tcx>) -> Value {
+        json!({
+            "graph": self.graph.to_json(repacker),
+        })
     }
 
     pub fn new() -> Self {
@@ -481,9 +907,13 @@ impl<'tcx> BorrowsState<'tcx> {
         &mut self,
         abstraction: AbstractionEdge<'tcx>,
         block: BasicBlock,
+        provenance: EdgeProvenance,
     ) {
-        self.graph
-            .insert(abstraction.to_borrows_edge(PathConditions::new(block)));
+        self.graph.insert(
+            abstraction
+                .to_borrows_edge(PathConditions::new(block))
+                .with_provenance(provenance),
+        );
     }
 
     pub fn make_place_old(
@@ -494,4 +924,12 @@ impl<'tcx> BorrowsState<'tcx> {
     ) {
         self.graph.make_place_old(place, &self.latest, debug_ctx);
     }
+
+    pub fn make_places_old(
+        &mut self,
+        places: &FxHashSet<Place<'tcx>>,
+        debug_ctx: Option<DebugCtx>,
+    ) {
+        self.graph.make_places_old(places, &self.latest, debug_ctx);
+    }
 }
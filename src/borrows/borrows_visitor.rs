@@ -1,22 +1,26 @@
-use std::{collections::BTreeSet, rc::Rc};
+use std::{
+    collections::{BTreeSet, HashSet},
+    rc::Rc,
+};
 
 use rustc_interface::{
     ast::Mutability,
     borrowck::{
-        borrow_set::BorrowSet,
+        borrow_set::{BorrowSet, TwoPhaseActivation},
         consumers::{
             BorrowIndex, LocationTable, PoloniusInput, PoloniusOutput, RegionInferenceContext,
         },
     },
+    data_structures::fx::{FxHashMap, FxHashSet},
+    hir::{def::DefKind, def_id::DefId},
     middle::{
         mir::{
-            visit::Visitor, AggregateKind, Body, Const, Location, Operand, Place, Rvalue,
-            Statement, StatementKind, Terminator, TerminatorKind,
-        },
-        ty::{
-            self, EarlyBinder, Region, RegionKind, RegionVid, TyCtxt, TypeVisitable, TypeVisitor,
+            visit::Visitor, AggregateKind, Body, BorrowKind, Const, Location, Operand, Place,
+            Rvalue, Statement, StatementKind, Terminator, TerminatorKind,
         },
+        ty::{self, Region, RegionKind, RegionVid, TyCtxt, TypeVisitable, TypeVisitor},
     },
+    span::{sym, Symbol},
 };
 
 use crate::{
@@ -25,16 +29,19 @@ use crate::{
         region_abstraction::AbstractionEdge,
     },
     rustc_interface,
-    utils::{self, PlaceRepacker, PlaceSnapshot},
+    utils::{self, PlaceRepacker, PlaceSnapshot, SnapshotLocation},
 };
 
 use super::{
-    domain::MaybeOldPlace,
+    borrows_edge::EdgeProvenance,
+    borrows_state::OldLeafTrimming,
+    domain::{MaybeOldPlace, ReborrowPhase},
+    function_summary::FunctionSummaries,
     region_projection_member::{RegionProjectionMember, RegionProjectionMemberDirection},
     unblock_graph::UnblockGraph,
 };
 use super::{
-    domain::{AbstractionOutputTarget, AbstractionType, FunctionCallAbstraction},
+    domain::{AbstractionOutputTarget, AbstractionType, CallAbstraction, FunctionCallAbstraction},
     engine::{BorrowsDomain, BorrowsEngine},
 };
 
@@ -68,6 +75,11 @@ pub struct BorrowsVisitor<'tcx, 'mir, 'state> {
     preparing: bool,
     region_inference_context: Rc<RegionInferenceContext<'tcx>>,
     debug_ctx: Option<DebugCtx>,
+    old_leaf_trimming: OldLeafTrimming,
+    projection_depth_limit: Option<usize>,
+    call_abstraction: CallAbstraction,
+    call_abstractions: Rc<FxHashMap<DefId, CallAbstraction>>,
+    function_summaries: Rc<FunctionSummaries<'tcx>>,
     #[allow(dead_code)]
     output_facts: &'mir PoloniusOutput,
 }
@@ -109,13 +121,54 @@ impl<'tcx, 'mir, 'state> BorrowsVisitor<'tcx, 'mir, 'state> {
             borrow_set: engine.borrow_set.clone(),
             region_inference_context: engine.region_inference_context.clone(),
             debug_ctx: None,
+            old_leaf_trimming: engine.old_leaf_trimming,
+            projection_depth_limit: engine.projection_depth_limit,
+            call_abstraction: engine.call_abstraction,
+            call_abstractions: engine.call_abstractions.clone(),
+            function_summaries: engine.function_summaries.clone(),
             output_facts: engine.output_facts,
         }
     }
+    /// Expands the graph to the place read by `operand`. If the operand is a
+    /// move, also records the move as the latest update to the place and
+    /// returns it; the caller is responsible for making it old.
+    fn prepare_operand(
+        &mut self,
+        operand: &Operand<'tcx>,
+        location: Location,
+    ) -> Option<utils::Place<'tcx>> {
+        match operand {
+            Operand::Copy(place) => {
+                self.ensure_expansion_to_exactly((*place).into(), location);
+                None
+            }
+            Operand::Move(place) => {
+                let place: utils::Place<'tcx> = (*place).into();
+                self.ensure_expansion_to_exactly(place, location);
+                self.state
+                    .after
+                    .set_latest(place, SnapshotLocation::mid(location));
+                Some(place)
+            }
+            Operand::Constant(_) => None,
+        }
+    }
+
+    /// Expands the graph to `place`, recording the operations for the bridge
+    /// of the current phase.
     fn ensure_expansion_to_exactly(&mut self, place: utils::Place<'tcx>, location: Location) {
-        self.state
-            .after
-            .ensure_expansion_to_exactly(self.tcx, self.body, place, location)
+        let ops = self.state.after.ensure_expansion_to_exactly(
+            self.tcx,
+            self.body,
+            place,
+            location,
+            self.projection_depth_limit,
+        );
+        if self.before {
+            self.state.start_expansion_ops.extend(ops);
+        } else {
+            self.state.middle_expansion_ops.extend(ops);
+        }
     }
 
     fn _loans_invalidated_at(&self, location: Location, start: bool) -> Vec<BorrowIndex> {
@@ -160,6 +213,136 @@ impl<'tcx, 'mir, 'state> BorrowsVisitor<'tcx, 'mir, 'state> {
         false
     }
 
+    /// How calls to `def_id` are abstracted: as given in
+    /// [`BorrowsEngine::call_abstractions`], otherwise by the
+    /// `#[pcs::havoc]` (or its alias `#[pcs::trusted]`) or `#[pcs::precise]`
+    /// attribute of the function, otherwise by
+    /// [`BorrowsEngine::call_abstraction`].
+    fn call_abstraction(&self, def_id: DefId) -> CallAbstraction {
+        if let Some(call_abstraction) = self.call_abstractions.get(&def_id) {
+            return *call_abstraction;
+        }
+        let has_attr = |name| {
+            self.tcx
+                .has_attrs_with_path(def_id, &[Symbol::intern("pcs"), Symbol::intern(name)])
+        };
+        if has_attr("havoc") || has_attr("trusted") {
+            CallAbstraction::Havoc
+        } else if has_attr("precise") {
+            CallAbstraction::Precise
+        } else {
+            self.call_abstraction
+        }
+    }
+
+    /// The edges of the [`CallAbstraction::Havoc`] abstraction of a call:
+    /// each region projection of an argument, and the target of each
+    /// reference argument, blocks everything the callee may have stored
+    /// borrows in, i.e. the result and the targets of the mutable reference
+    /// arguments. The target of an argument does not block its own region
+    /// projections, which would make it block itself.
+    fn havoc_call_edges(
+        &self,
+        args: &[&Operand<'tcx>],
+        destination: Place<'tcx>,
+    ) -> Vec<(usize, AbstractionBlockEdge<'tcx>)> {
+        let repacker = self.repacker();
+        let destination: utils::Place<'tcx> = destination.into();
+        let mut result: Vec<AbstractionOutputTarget<'tcx>> = destination
+            .region_projections(repacker)
+            .into_iter()
+            .map(AbstractionTarget::RegionProjection)
+            .collect();
+        if destination.is_mut_ref(self.body, self.tcx) {
+            result.push(AbstractionTarget::Place(
+                destination.project_deref(repacker).into(),
+            ));
+        }
+        // (argument index, input, whether it is the target of the argument)
+        let mut inputs = vec![];
+        // The region projections of the targets of the mutable reference
+        // arguments, by argument index
+        let mut written = vec![];
+        for (idx, arg) in args.iter().enumerate() {
+            let Some(place) = arg.place() else {
+                continue;
+            };
+            let place: utils::Place<'tcx> = place.into();
+            let old_place = MaybeOldPlace::OldPlace(PlaceSnapshot::new(
+                place,
+                self.state.after.get_latest(place),
+            ));
+            inputs.extend(
+                old_place
+                    .region_projections(repacker)
+                    .into_iter()
+                    .map(|rp| (idx, AbstractionTarget::RegionProjection(rp), false)),
+            );
+            if let Some(mutability) = place.ref_mutability(self.body, self.tcx) {
+                inputs.push((
+                    idx,
+                    AbstractionTarget::Place(old_place.project_deref(repacker).into()),
+                    true,
+                ));
+                if mutability.is_mut() {
+                    written.push((
+                        idx,
+                        place.project_deref(repacker).region_projections(repacker),
+                    ));
+                }
+            }
+        }
+        let mut edges = vec![];
+        for (idx, input, is_target) in inputs {
+            let mut outputs = result.clone();
+            for (written_idx, projections) in &written {
+                if !is_target || *written_idx != idx {
+                    outputs.extend(
+                        projections
+                            .iter()
+                            .map(|rp| AbstractionTarget::RegionProjection(*rp)),
+                    );
+                }
+            }
+            if !outputs.is_empty() {
+                edges.push((
+                    idx,
+                    AbstractionBlockEdge::new(
+                        HashSet::from([input]),
+                        outputs.into_iter().collect(),
+                    ),
+                ));
+            }
+        }
+        edges
+    }
+
+    /// The method of the impl that a call to the trait method `def_id` with
+    /// `substs` dispatches to and its generic arguments, if it is known in the
+    /// body, and otherwise `def_id` itself with `substs`. The signature of the
+    /// impl method may be more precise than that of the trait method: the
+    /// types of its arguments can mention the lifetimes of the implementing
+    /// type, and its result may outlive some of them.
+    fn resolve_callee(
+        &self,
+        def_id: DefId,
+        substs: ty::GenericArgsRef<'tcx>,
+    ) -> (DefId, ty::GenericArgsRef<'tcx>) {
+        if self.tcx.trait_of_item(def_id).is_none() {
+            return (def_id, substs);
+        }
+        let param_env = self.tcx.param_env(self.body.source.def_id());
+        match ty::Instance::try_resolve(self.tcx, param_env, def_id, substs) {
+            // Calls of closures through the `Fn*` traits resolve to the
+            // closure, whose signature is not available in this form
+            Ok(Some(ty::Instance {
+                def: ty::InstanceKind::Item(impl_def_id),
+                args,
+            })) if self.tcx.def_kind(impl_def_id) == DefKind::AssocFn => (impl_def_id, args),
+            _ => (def_id, substs),
+        }
+    }
+
     fn construct_region_abstraction_if_necessary(
         &mut self,
         func: &Operand<'tcx>,
@@ -177,146 +360,248 @@ impl<'tcx, 'mir, 'state> BorrowsVisitor<'tcx, 'mir, 'state> {
             },
             _ => unreachable!(),
         };
-        let sig = EarlyBinder::instantiate_identity(self.tcx.fn_sig(func_def_id));
-        let sig = self.tcx.liberate_late_bound_regions(*func_def_id, sig);
-        let output_lifetimes = extract_lifetimes(sig.output());
-        if output_lifetimes.is_empty() {
+        let (callee, callee_args) = self.resolve_callee(*func_def_id, substs);
+        if self.call_abstraction(callee) == CallAbstraction::Havoc {
+            let edges = self.havoc_call_edges(args, destination);
+            self.add_call_abstraction(*func_def_id, substs, edges, location);
             return;
         }
-        let param_env = self.tcx.param_env(func_def_id);
-        let mut edges = vec![];
-
-        for (idx, ty) in sig.inputs().iter().enumerate() {
-            let input_place: utils::Place<'tcx> = match args[idx].place() {
-                Some(place) => place.into(),
-                None => continue,
-            };
-            let input_place = MaybeOldPlace::OldPlace(PlaceSnapshot::new(
-                input_place,
-                self.state.after.get_latest(input_place),
-            ));
-            let ty = match ty.kind() {
-                ty::TyKind::Ref(region, ty, m) => {
-                    if m.is_mut() {
-                        for output in self.matches_for_input_lifetime(
-                            *region,
-                            param_env,
-                            substs,
-                            sig.output(),
-                            destination.into(),
-                        ) {
-                            let input_place = input_place.project_deref(self.repacker());
-                            edges.push((
-                                idx,
-                                AbstractionBlockEdge::new(
-                                    vec![AbstractionTarget::Place(input_place.into())]
-                                        .into_iter()
-                                        .collect(),
-                                    vec![output].into_iter().collect(),
-                                ),
-                            ));
-                        }
-                    }
-                    *ty
-                }
-                _ => *ty,
-            };
-            for (lifetime_idx, input_lifetime) in extract_lifetimes(ty).into_iter().enumerate() {
-                for output in self.matches_for_input_lifetime(
-                    input_lifetime,
-                    param_env,
-                    substs,
-                    sig.output(),
-                    destination.into(),
-                ) {
-                    edges.push((
-                        idx,
-                        AbstractionBlockEdge::new(
-                            vec![AbstractionTarget::RegionProjection(
-                                input_place.region_projection(lifetime_idx, self.repacker()),
-                            )]
-                            .into_iter()
-                            .collect(),
-                            vec![output].into_iter().collect(),
-                        ),
-                    ));
-                }
-            }
+        if self.is_box_leak(*func_def_id) {
+            self.add_box_leak_reborrow(args[0], destination, location);
+            return;
         }
+        if let Some(edges) = self.moving_function_edges(*func_def_id, args, destination) {
+            self.add_call_abstraction(*func_def_id, substs, edges, location);
+            return;
+        }
+        let summary = self.function_summaries.get(self.tcx, callee);
+        let args: Vec<_> = args
+            .iter()
+            .map(|arg| arg.place().map(utils::Place::from))
+            .collect();
+        let edges = summary.instantiate(
+            &args,
+            destination,
+            callee_args,
+            self.tcx.param_env(self.body.source.def_id()),
+            |place| self.state.after.get_latest(place),
+            self.repacker(),
+        );
+        self.add_call_abstraction(*func_def_id, substs, edges, location);
+    }
 
+    fn add_call_abstraction(
+        &mut self,
+        func_def_id: DefId,
+        substs: ty::GenericArgsRef<'tcx>,
+        edges: Vec<(usize, AbstractionBlockEdge<'tcx>)>,
+        location: Location,
+    ) {
         // No edges may be added e.g. if the inputs do not contain any (possibly
         // nested) mutable references
         if !edges.is_empty() {
+            let callee = self.tcx.def_path_str(func_def_id);
             self.state.after.add_region_abstraction(
                 AbstractionEdge::new(AbstractionType::FunctionCall(FunctionCallAbstraction::new(
                     location,
-                    *func_def_id,
+                    func_def_id,
                     substs,
                     edges,
                 ))),
                 location.block,
+                EdgeProvenance::at(format!("call abstraction for {callee}"), location),
             );
         }
     }
 
-    fn matches_for_input_lifetime(
+    /// Precise abstraction edges for the functions in [`MovingFunction`],
+    /// which only move values between the given places. The summary of their
+    /// signature would lose this, since it cannot tell which of the values of
+    /// their generic parameter end up where. Each region projection of the old value flows
+    /// into the corresponding projection of the place the value ends up in.
+    fn moving_function_edges(
         &self,
-        input_lifetime: ty::Region<'tcx>,
-        param_env: ty::ParamEnv<'tcx>,
-        _substs: ty::GenericArgsRef<'tcx>,
-        output_ty: ty::Ty<'tcx>,
-        output_place: utils::Place<'tcx>,
-    ) -> Vec<AbstractionOutputTarget<'tcx>> {
-        let mut result = vec![];
-        let output_ty = match output_ty.kind() {
-            ty::TyKind::Ref(output_lifetime, ty, Mutability::Mut) => {
-                if outlives_in_param_env(input_lifetime, *output_lifetime, param_env) {
-                    result.push(AbstractionTarget::Place(
-                        output_place.project_deref(self.repacker()).into(),
-                    ));
-                }
-                *ty
+        func_def_id: DefId,
+        args: &[&Operand<'tcx>],
+        destination: Place<'tcx>,
+    ) -> Option<Vec<(usize, AbstractionBlockEdge<'tcx>)>> {
+        let repacker = self.repacker();
+        let old_value = |idx: usize| -> Option<MaybeOldPlace<'tcx>> {
+            let place: utils::Place<'tcx> = args[idx].place()?.into();
+            Some(MaybeOldPlace::OldPlace(PlaceSnapshot::new(
+                place,
+                self.state.after.get_latest(place),
+            )))
+        };
+        let pointee = |idx: usize| -> Option<utils::Place<'tcx>> {
+            let place: utils::Place<'tcx> = args[idx].place()?.into();
+            Some(place.project_deref(repacker))
+        };
+        // (argument index, old value, place it flows into)
+        let flows = match self.moving_function(func_def_id)? {
+            MovingFunction::MemSwap => vec![
+                (0, old_value(0)?.project_deref(repacker), pointee(1)?),
+                (1, old_value(1)?.project_deref(repacker), pointee(0)?),
+            ],
+            MovingFunction::MemReplace => vec![
+                (0, old_value(0)?.project_deref(repacker), destination.into()),
+                (1, old_value(1)?, pointee(0)?),
+            ],
+            MovingFunction::MemTake | MovingFunction::PtrRead => {
+                vec![(0, old_value(0)?.project_deref(repacker), destination.into())]
+            }
+            MovingFunction::PtrWrite => vec![(1, old_value(1)?, pointee(0)?)],
+            MovingFunction::CopyNonoverlapping => {
+                vec![(0, old_value(0)?.project_deref(repacker), pointee(1)?)]
+            }
+            MovingFunction::ManuallyDropNew | MovingFunction::ManuallyDropIntoInner => {
+                vec![(0, old_value(0)?, destination.into())]
             }
-            _ => output_ty,
         };
-        for (output_lifetime_idx, output_lifetime) in
-            extract_lifetimes(output_ty).into_iter().enumerate()
-        {
-            if outlives_in_param_env(input_lifetime, output_lifetime, param_env) {
-                result.push(AbstractionTarget::RegionProjection(
-                    output_place.region_projection(output_lifetime_idx, self.repacker()),
+        let mut edges = vec![];
+        for (idx, from, to) in flows {
+            for (input, output) in from
+                .region_projections(repacker)
+                .into_iter()
+                .zip(to.region_projections(repacker))
+            {
+                edges.push((
+                    idx,
+                    AbstractionBlockEdge::new(
+                        vec![AbstractionTarget::RegionProjection(input)]
+                            .into_iter()
+                            .collect(),
+                        vec![AbstractionTarget::RegionProjection(output)]
+                            .into_iter()
+                            .collect(),
+                    ),
                 ));
             }
         }
-        result
+        Some(edges)
     }
 
-    fn minimize(&mut self, location: Location) {
-        let repacker = PlaceRepacker::new(self.body, self.tcx);
-        self.state.after.minimize(repacker, location);
+    fn moving_function(&self, def_id: DefId) -> Option<MovingFunction> {
+        match self.tcx.get_diagnostic_name(def_id) {
+            Some(sym::mem_swap) => Some(MovingFunction::MemSwap),
+            Some(sym::mem_replace) => Some(MovingFunction::MemReplace),
+            Some(sym::ptr_read) => Some(MovingFunction::PtrRead),
+            Some(sym::ptr_write) => Some(MovingFunction::PtrWrite),
+            Some(sym::ptr_copy_nonoverlapping) => Some(MovingFunction::CopyNonoverlapping),
+            Some(_) => None,
+            None => {
+                // `mem::take` has no diagnostic item, look for it next to `mem::swap`
+                let swap = self.tcx.get_diagnostic_item(sym::mem_swap)?;
+                if self.tcx.opt_parent(def_id) == self.tcx.opt_parent(swap)
+                    && self.tcx.opt_item_name(def_id) == Some(Symbol::intern("take"))
+                {
+                    return Some(MovingFunction::MemTake);
+                }
+                let (self_ty, name) = self.inherent_method(def_id)?;
+                match self_ty.ty_adt_def() {
+                    Some(adt) if adt.is_manually_drop() => match name.as_str() {
+                        "new" => Some(MovingFunction::ManuallyDropNew),
+                        "into_inner" => Some(MovingFunction::ManuallyDropIntoInner),
+                        _ => None,
+                    },
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    /// The type that `def_id` is an inherent method of, and its name
+    fn inherent_method(&self, def_id: DefId) -> Option<(ty::Ty<'tcx>, Symbol)> {
+        let impl_id = self.tcx.impl_of_method(def_id)?;
+        if self.tcx.trait_id_of_impl(impl_id).is_some() {
+            return None;
+        }
+        Some((
+            self.tcx.type_of(impl_id).instantiate_identity(),
+            self.tcx.item_name(def_id),
+        ))
     }
-}
 
-fn outlives_in_param_env<'tcx>(
-    input_lifetime: ty::Region<'tcx>,
-    output_lifetime: ty::Region<'tcx>,
-    param_env: ty::ParamEnv<'tcx>,
-) -> bool {
-    if input_lifetime == output_lifetime {
-        return true;
+    fn is_box_leak(&self, def_id: DefId) -> bool {
+        self.inherent_method(def_id)
+            .is_some_and(|(self_ty, name)| self_ty.is_box() && name.as_str() == "leak")
     }
-    for bound in param_env.caller_bounds() {
-        match bound.as_region_outlives_clause() {
-            Some(outlives) => {
-                let outlives = outlives.no_bound_vars().unwrap();
-                if outlives.0 == input_lifetime && outlives.1 == output_lifetime {
-                    return true;
-                }
+
+    /// `Box::leak(b)` returns a `'static` reborrow of the contents of `b`.
+    /// The allocation is no longer reachable from any place of the body, so
+    /// it is represented by the snapshot of `*b` just before the call.
+    fn add_box_leak_reborrow(
+        &mut self,
+        boxed: &Operand<'tcx>,
+        destination: Place<'tcx>,
+        location: Location,
+    ) {
+        let Some(boxed) = boxed.place() else {
+            return;
+        };
+        let boxed: utils::Place<'tcx> = boxed.into();
+        let contents = MaybeOldPlace::OldPlace(PlaceSnapshot::new(
+            boxed,
+            self.state.after.get_latest(boxed),
+        ))
+        .project_deref(self.repacker());
+        let destination: utils::Place<'tcx> = destination.into();
+        self.state.after.add_reborrow(
+            contents.into(),
+            destination.project_deref(self.repacker()),
+            Mutability::Mut,
+            location,
+            self.tcx.lifetimes.re_static,
+            EdgeProvenance::at("Box::leak", location),
+        );
+    }
+
+    /// Whether the borrow created at `location` is a two-phase borrow, i.e.
+    /// only reserved until it is activated by a later use.
+    fn is_two_phase_borrow(&self, location: Location) -> bool {
+        matches!(
+            self.borrow_set
+                .location_map
+                .get(&location)
+                .map(|borrow| &borrow.activation_location),
+            Some(TwoPhaseActivation::NotActivated | TwoPhaseActivation::ActivatedAt(_))
+        )
+    }
+
+    /// Activates the two-phase borrows whose activation point is `location`.
+    fn activate_two_phase_borrows(&mut self, location: Location) {
+        if let Some(activations) = self.borrow_set.activation_map.get(&location) {
+            for idx in activations {
+                let reserve_location = self.borrow_set[*idx].reserve_location;
+                self.state
+                    .after
+                    .set_reborrow_phase(reserve_location, ReborrowPhase::Active);
             }
-            _ => {}
         }
     }
-    false
+}
+
+/// Functions from `core::mem` and `core::ptr` (and `ManuallyDrop` methods)
+/// with a precise abstraction, see [`BorrowsVisitor::moving_function_edges`]
+#[derive(Clone, Copy)]
+enum MovingFunction {
+    /// `mem::swap(x, y)`: `*x` and `*y` are exchanged
+    MemSwap,
+    /// `mem::replace(dest, src)`: `*dest` is returned and replaced by `src`
+    MemReplace,
+    /// `mem::take(dest)`: `*dest` is returned and replaced by a default
+    MemTake,
+    /// `ptr::read(src)`: `*src` is moved out into the result
+    PtrRead,
+    /// `ptr::write(dst, src)`: `*dst` is initialized with `src`
+    PtrWrite,
+    /// `ptr::copy_nonoverlapping(src, dst, count)`: `*dst` is initialized
+    /// with a copy of `*src`
+    CopyNonoverlapping,
+    /// `ManuallyDrop::new(value)`: `value` is wrapped into the result
+    ManuallyDropNew,
+    /// `ManuallyDrop::into_inner(slot)`: the contents of `slot` are returned
+    ManuallyDropIntoInner,
 }
 
 pub fn get_vid(region: &Region) -> Option<RegionVid> {
@@ -330,40 +615,35 @@ impl<'tcx, 'mir, 'state> Visitor<'tcx> for BorrowsVisitor<'tcx, 'mir, 'state> {
     fn visit_operand(&mut self, operand: &Operand<'tcx>, location: Location) {
         self.super_operand(operand, location);
         if self.before && self.preparing {
-            match operand {
-                Operand::Copy(place) | Operand::Move(place) => {
-                    let place: utils::Place<'tcx> = (*place).into();
-                    self.ensure_expansion_to_exactly(place, location);
-                }
-                _ => {}
-            }
-            match operand {
-                Operand::Move(place) => {
-                    self.state.after.set_latest((*place).into(), location);
-                    self.state.after.make_place_old(
-                        (*place).into(),
-                        PlaceRepacker::new(self.body, self.tcx),
-                        None,
-                    );
-                }
-                _ => {}
+            if let Some(place) = self.prepare_operand(operand, location) {
+                self.state.after.make_place_old(
+                    place,
+                    PlaceRepacker::new(self.body, self.tcx),
+                    None,
+                );
             }
         }
     }
 
     fn visit_terminator(&mut self, terminator: &Terminator<'tcx>, location: Location) {
-        if self.preparing && self.before {
-            self.minimize(location);
-        }
         self.super_terminator(terminator, location);
+        if self.preparing && !self.before {
+            self.activate_two_phase_borrows(location);
+        }
         if !self.before && !self.preparing {
             match &terminator.kind {
                 TerminatorKind::Call {
                     func,
                     args,
                     destination,
+                    target: Some(_),
                     ..
                 } => {
+                    // Passing a `&mut` to a call implicitly reborrows it, but
+                    // in borrowck MIR the reborrow is an explicit statement
+                    // `_t = &mut (*x)` before the call, and only the temporary
+                    // `_t` is moved. Its reborrow edge thus blocks the call
+                    // abstraction's input, and `x` stays current.
                     self.state.after.set_latest((*destination).into(), location);
                     self.construct_region_abstraction_if_necessary(
                         func,
@@ -372,6 +652,16 @@ impl<'tcx, 'mir, 'state> Visitor<'tcx> for BorrowsVisitor<'tcx, 'mir, 'state> {
                         location,
                     );
                 }
+                // A diverging call never assigns its destination, and no
+                // borrows flow into it. Only the unwind edge (if any) leaves
+                // the block.
+                TerminatorKind::Call { target: None, .. } => {}
+                TerminatorKind::Return => {
+                    let repacker = PlaceRepacker::new(self.body, self.tcx);
+                    self.state
+                        .after
+                        .connect_return_place_to_remote_places(repacker, location);
+                }
                 _ => {}
             }
         }
@@ -379,9 +669,6 @@ impl<'tcx, 'mir, 'state> Visitor<'tcx> for BorrowsVisitor<'tcx, 'mir, 'state> {
 
     fn visit_statement(&mut self, statement: &Statement<'tcx>, location: Location) {
         self.debug_ctx = Some(DebugCtx::new(location));
-        if self.preparing && self.before {
-            self.minimize(location);
-        }
         self.super_statement(statement, location);
 
         // Will be included as start bridge ops
@@ -417,6 +704,7 @@ impl<'tcx, 'mir, 'state> Visitor<'tcx> for BorrowsVisitor<'tcx, 'mir, 'state> {
         // Stuff in this block will be included as the middle "bridge" ops that
         // are visible to Prusti
         if self.preparing && !self.before {
+            self.activate_two_phase_borrows(location);
             match &statement.kind {
                 StatementKind::StorageDead(local) => {
                     let place: utils::Place<'tcx> = (*local).into();
@@ -424,7 +712,9 @@ impl<'tcx, 'mir, 'state> Visitor<'tcx> for BorrowsVisitor<'tcx, 'mir, 'state> {
                     self.state
                         .after
                         .make_place_old(place, repacker, self.debug_ctx);
-                    self.state.after.trim_old_leaves(repacker, location);
+                    if self.old_leaf_trimming.trims_at(false) {
+                        self.state.after.trim_old_leaves(repacker, location);
+                    }
                 }
                 StatementKind::Assign(box (target, _)) => {
                     let target: utils::Place<'tcx> = (*target).into();
@@ -457,15 +747,16 @@ impl<'tcx, 'mir, 'state> Visitor<'tcx> for BorrowsVisitor<'tcx, 'mir, 'state> {
                                                     let operand_place = MaybeOldPlace::new(
                                                         operand_place
                                                             .project_deref(self.repacker()),
-                                                        Some(location),
+                                                        Some(SnapshotLocation::mid(location)),
                                                     );
                                                     self.state.after.add_region_projection_member(
                                                         RegionProjectionMember::new(
-                                                            operand_place.into(),
+                                                            vec![operand_place.into()],
                                                             proj,
                                                             location,
                                                             RegionProjectionMemberDirection::PlaceIsRegionInput,
                                                         ),
+                                                        EdgeProvenance::at("Rvalue::Aggregate", location),
                                                     );
                                                 }
                                             }
@@ -488,6 +779,7 @@ impl<'tcx, 'mir, 'state> Visitor<'tcx> for BorrowsVisitor<'tcx, 'mir, 'state> {
                                         Some(self.state.after.get_latest(from)),
                                     ),
                                     target.project_deref(repacker).into(),
+                                    repacker,
                                 );
                             }
                             let moved_place =
@@ -500,6 +792,7 @@ impl<'tcx, 'mir, 'state> Visitor<'tcx> for BorrowsVisitor<'tcx, 'mir, 'state> {
                                 self.state.after.change_pcs_elem(
                                     p,
                                     target.region_projection(idx, repacker).into(),
+                                    repacker,
                                 );
                             }
                             self.state.after.delete_descendants_of(
@@ -519,11 +812,18 @@ impl<'tcx, 'mir, 'state> Visitor<'tcx> for BorrowsVisitor<'tcx, 'mir, 'state> {
                                         Mutability::Not,
                                         location,
                                         *region, // TODO: This is the region for the place, not the loan, does that matter?
+                                        EdgeProvenance::at("copy of reference", location),
                                     );
                                 }
                                 _ => {}
                             }
                         }
+                        // Fake borrows only make borrowck check that match
+                        // guards do not modify the scrutinee. They are never
+                        // dereferenced, so they block nothing: the borrowed
+                        // place keeps its capability, as for a discriminant
+                        // read, and no reborrow edge is added.
+                        Rvalue::Ref(_, BorrowKind::Fake(_), _) => {}
                         Rvalue::Ref(region, kind, blocked_place) => {
                             let blocked_place: utils::Place<'tcx> = (*blocked_place).into();
                             let target: utils::Place<'tcx> = (*target).into();
@@ -540,7 +840,13 @@ impl<'tcx, 'mir, 'state> Visitor<'tcx> for BorrowsVisitor<'tcx, 'mir, 'state> {
                                 kind.mutability(),
                                 location,
                                 *region,
+                                EdgeProvenance::at("Rvalue::Ref", location),
                             );
+                            if self.is_two_phase_borrow(location) {
+                                self.state
+                                    .after
+                                    .set_reborrow_phase(location, ReborrowPhase::Reserved);
+                            }
                         }
                         _ => {}
                     }
@@ -548,11 +854,26 @@ impl<'tcx, 'mir, 'state> Visitor<'tcx> for BorrowsVisitor<'tcx, 'mir, 'state> {
                 _ => {}
             }
             let repacker = PlaceRepacker::new(self.body, self.tcx);
-            self.state.after.trim_old_leaves(repacker, location);
+            if self.old_leaf_trimming.trims_at(false) {
+                self.state.after.trim_old_leaves(repacker, location);
+            }
         }
     }
 
     fn visit_rvalue(&mut self, rvalue: &Rvalue<'tcx>, location: Location) {
+        if let Rvalue::Aggregate(_, fields) = rvalue
+            && self.before
+            && self.preparing
+        {
+            // All the operands are moved out of simultaneously, so the moved
+            // places are made old together rather than one operand at a time
+            let moved: FxHashSet<utils::Place<'tcx>> = fields
+                .iter()
+                .filter_map(|operand| self.prepare_operand(operand, location))
+                .collect();
+            self.state.after.make_places_old(&moved, None);
+            return;
+        }
         self.super_rvalue(rvalue, location);
         use Rvalue::*;
         match rvalue {
@@ -566,13 +887,16 @@ impl<'tcx, 'mir, 'state> Visitor<'tcx> for BorrowsVisitor<'tcx, 'mir, 'state> {
             | Aggregate(_, _)
             | ShallowInitBox(_, _) => {}
 
-            &Ref(_, _, place) | &Len(place) | &Discriminant(place) | &CopyForDeref(place) => {
+            &Ref(_, _, place)
+            | &RawPtr(_, place)
+            | &Len(place)
+            | &Discriminant(place)
+            | &CopyForDeref(place) => {
                 let place: utils::Place<'tcx> = place.into();
                 if self.before && self.preparing && !place.is_owned(self.body, self.tcx) {
                     self.ensure_expansion_to_exactly(place, location);
                 }
             }
-            _ => todo!(),
         }
     }
 }
@@ -587,6 +911,9 @@ impl<'tcx> TypeVisitor<ty::TyCtxt<'tcx>> for LifetimeExtractor<'tcx> {
     }
 }
 
+/// All regions in `ty`, in the order they appear. This walks every type
+/// constructor, so e.g. the regions of `[&'a T; N]`, `&[&'a T]` and
+/// `*mut &'a T` are included.
 pub fn extract_lifetimes<'tcx>(ty: ty::Ty<'tcx>) -> Vec<ty::Region<'tcx>> {
     let mut visitor = LifetimeExtractor { lifetimes: vec![] };
     ty.visit_with(&mut visitor);
@@ -4,6 +4,7 @@ use crate::{
     coupling,
     rustc_interface::{
         borrowck::consumers::{LocationTable, PoloniusOutput},
+        data_structures::fx::FxHashSet,
         middle::mir::{BasicBlock, Location},
     },
     utils::PlaceRepacker,
@@ -45,6 +46,9 @@ pub struct CouplingGraphConstructor<'polonius, 'mir, 'tcx> {
     block: BasicBlock,
     coupling_graph: coupling::Graph<CGNode<'tcx>>,
     location_table: &'mir LocationTable,
+    /// The pairs of nodes `add_edges_from` was called with, since the region
+    /// projection graph of a loop can be cyclic
+    visited: FxHashSet<(CGNode<'tcx>, CGNode<'tcx>)>,
 }
 
 impl<'polonius, 'mir, 'tcx> CouplingGraphConstructor<'polonius, 'mir, 'tcx> {
@@ -60,6 +64,7 @@ impl<'polonius, 'mir, 'tcx> CouplingGraphConstructor<'polonius, 'mir, 'tcx> {
             block,
             coupling_graph: coupling::Graph::new(),
             location_table,
+            visited: FxHashSet::default(),
         }
     }
 
@@ -69,6 +74,9 @@ impl<'polonius, 'mir, 'tcx> CouplingGraphConstructor<'polonius, 'mir, 'tcx> {
         bottom_connect: RegionProjection<'tcx>,
         upper_candidate: RegionProjection<'tcx>,
     ) {
+        if !self.visited.insert((bottom_connect, upper_candidate)) {
+            return;
+        }
         let nodes = bg.nodes_pointing_to(upper_candidate);
         if nodes.is_empty() {
             self.coupling_graph
@@ -95,9 +103,7 @@ impl<'polonius, 'mir, 'tcx> CouplingGraphConstructor<'polonius, 'mir, 'tcx> {
         bg: &BorrowsGraph<'tcx>,
     ) -> coupling::Graph<CGNode<'tcx>> {
         let full_graph = bg.region_projection_graph(self.repacker);
-        full_graph.render_with_imgcat().unwrap();
         for node in full_graph.leaf_nodes() {
-            eprintln!("leaf: {:?}", node);
             self.add_edges_from(&full_graph, node, node)
         }
         self.coupling_graph
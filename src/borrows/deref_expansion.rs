@@ -1,25 +1,31 @@
+#[cfg(feature = "json")]
 use serde_json::json;
 
 use crate::{
     rustc_interface::{
         data_structures::fx::FxHashSet,
-        middle::mir::{Location, PlaceElem},
+        middle::mir::{Location, PlaceElem, ProjectionElem},
+        target::abi::VariantIdx,
     },
     utils::{Place, PlaceRepacker, PlaceSnapshot, SnapshotLocation},
 };
 
+#[cfg(feature = "json")]
+use super::domain::ToJsonWithRepacker;
 use super::{
-    domain::{MaybeOldPlace, ToJsonWithRepacker},
-    has_pcs_elem::HasPcsElems,
-    latest::Latest,
+    domain::MaybeOldPlace, has_pcs_elem::HasPcsElems, latest::Latest,
     region_projection::RegionProjection,
 };
 
 /// An expansion of a place in the PCS
-#[derive(PartialEq, Eq, Clone, Debug, Hash)]
+#[derive(PartialEq, Eq, Clone, Debug, Hash, HasPcsElems)]
+#[pcs_elems(MaybeOldPlace<'tcx>)]
 pub struct BorrowDerefExpansion<'tcx> {
+    #[pcs_elem]
     base: MaybeOldPlace<'tcx>,
+    #[pcs_elems(skip)]
     expansion: Vec<PlaceElem<'tcx>>,
+    #[pcs_elems(skip)]
     pub location: Location,
 }
 
@@ -36,14 +42,10 @@ impl<'tcx> BorrowDerefExpansion<'tcx> {
     }
 }
 
-impl<'tcx> HasPcsElems<MaybeOldPlace<'tcx>> for BorrowDerefExpansion<'tcx> {
-    fn pcs_elems(&mut self) -> Vec<&mut MaybeOldPlace<'tcx>> {
-        vec![&mut self.base]
-    }
-}
-
-#[derive(PartialEq, Eq, Clone, Debug, Hash)]
+#[derive(PartialEq, Eq, Clone, Debug, Hash, HasPcsElems)]
+#[pcs_elems(MaybeOldPlace<'tcx>)]
 pub struct OwnedExpansion<'tcx> {
+    #[pcs_elem]
     base: MaybeOldPlace<'tcx>,
 }
 
@@ -68,27 +70,13 @@ impl<'tcx> OwnedExpansion<'tcx> {
     }
 }
 
-#[derive(PartialEq, Eq, Clone, Debug, Hash)]
+#[derive(PartialEq, Eq, Clone, Debug, Hash, HasPcsElems)]
+#[pcs_elems(MaybeOldPlace<'tcx>)]
 pub enum DerefExpansion<'tcx> {
     /// An expansion of a place in the FPCS
-    OwnedExpansion(OwnedExpansion<'tcx>),
+    OwnedExpansion(#[pcs_elems] OwnedExpansion<'tcx>),
     /// An expansion of a place in the PCS
-    BorrowExpansion(BorrowDerefExpansion<'tcx>),
-}
-
-impl<'tcx> HasPcsElems<MaybeOldPlace<'tcx>> for OwnedExpansion<'tcx> {
-    fn pcs_elems(&mut self) -> Vec<&mut MaybeOldPlace<'tcx>> {
-        vec![&mut self.base]
-    }
-}
-
-impl<'tcx> HasPcsElems<MaybeOldPlace<'tcx>> for DerefExpansion<'tcx> {
-    fn pcs_elems(&mut self) -> Vec<&mut MaybeOldPlace<'tcx>> {
-        match self {
-            DerefExpansion::OwnedExpansion(owned) => owned.pcs_elems(),
-            DerefExpansion::BorrowExpansion(e) => e.pcs_elems(),
-        }
-    }
+    BorrowExpansion(#[pcs_elems] BorrowDerefExpansion<'tcx>),
 }
 
 impl<'tcx> DerefExpansion<'tcx> {
@@ -172,6 +160,17 @@ impl<'tcx> DerefExpansion<'tcx> {
         }));
     }
 
+    /// The variant of an enum-typed base that this expansion is guarded by.
+    ///
+    /// Such an expansion only holds when the discriminant of the base selects
+    /// that variant: its only child is the base downcast to the variant.
+    pub fn downcast_variant(&self) -> Option<VariantIdx> {
+        match self.expansion_elems().as_slice() {
+            [ProjectionElem::Downcast(_, variant)] => Some(*variant),
+            _ => None,
+        }
+    }
+
     pub fn expansion_elems(&self) -> Vec<PlaceElem<'tcx>> {
         match self {
             DerefExpansion::OwnedExpansion { .. } => vec![PlaceElem::Deref],
@@ -187,26 +186,19 @@ impl<'tcx> DerefExpansion<'tcx> {
     }
 }
 
+#[cfg(feature = "json")]
 impl<'tcx> ToJsonWithRepacker<'tcx> for DerefExpansion<'tcx> {
     fn to_json(&self, repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value {
         json!({
             "base": self.base().to_json(repacker),
-            "expansion": self.expansion(repacker).iter().map(|p| p.to_json(repacker)).collect::<Vec<_>>(),
+            "expansion": self.expansion(repacker).to_json(repacker),
         })
     }
 }
 
-#[derive(PartialEq, Eq, Clone, Debug, Hash)]
+#[derive(PartialEq, Eq, Clone, Debug, Hash, HasPcsElems)]
+#[pcs_elems(MaybeOldPlace<'tcx>)]
 pub enum DerefSource<'tcx> {
-    Place(MaybeOldPlace<'tcx>),
-    RegionProjection(RegionProjection<'tcx>),
-}
-
-impl<'tcx> HasPcsElems<MaybeOldPlace<'tcx>> for DerefSource<'tcx> {
-    fn pcs_elems(&mut self) -> Vec<&mut MaybeOldPlace<'tcx>> {
-        match self {
-            DerefSource::Place(p) => vec![p],
-            DerefSource::RegionProjection(p) => p.pcs_elems(),
-        }
-    }
+    Place(#[pcs_elem] MaybeOldPlace<'tcx>),
+    RegionProjection(#[pcs_elems] RegionProjection<'tcx>),
 }
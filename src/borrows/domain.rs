@@ -1,21 +1,26 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, rc::Rc};
+
+use itertools::Either;
 
 use rustc_interface::{
     ast::Mutability,
     data_structures::fx::FxHashSet,
     hir::def_id::DefId,
-    middle::mir::{self, tcx::PlaceTy, BasicBlock, Location, PlaceElem, START_BLOCK},
+    middle::mir::{self, tcx::PlaceTy, BasicBlock, Location, PlaceElem},
     middle::ty::{self, GenericArgsRef, RegionVid, TyCtxt},
 };
 
 use crate::{
     rustc_interface,
-    utils::{Place, PlaceSnapshot, SnapshotLocation},
+    utils::{sorted_by_debug, Place, PlaceSnapshot, SmallSet, SnapshotLocation},
 };
 
-#[derive(PartialEq, Eq, Clone, Debug, Hash)]
+#[derive(PartialEq, Eq, Clone, Debug, Hash, HasPcsElems)]
+#[pcs_elems(MaybeOldPlace<'tcx>, RegionProjection<'tcx>)]
 pub struct LoopAbstraction<'tcx> {
+    #[pcs_elems]
     edge: AbstractionBlockEdge<'tcx>,
+    #[pcs_elems(skip)]
     block: BasicBlock,
 }
 
@@ -31,12 +36,12 @@ impl<'tcx> ToBorrowsEdge<'tcx> for LoopAbstraction<'tcx> {
 }
 
 impl<'tcx> LoopAbstraction<'tcx> {
-    pub fn inputs(&self) -> Vec<AbstractionInputTarget<'tcx>> {
-        self.edge.inputs().into_iter().collect()
+    pub fn inputs(&self) -> &[AbstractionInputTarget<'tcx>] {
+        self.edge.inputs()
     }
 
-    pub fn edges(&self) -> Vec<AbstractionBlockEdge<'tcx>> {
-        vec![self.edge.clone()]
+    pub fn edges(&self) -> &[AbstractionBlockEdge<'tcx>] {
+        std::slice::from_ref(&self.edge)
     }
     pub fn new(edge: AbstractionBlockEdge<'tcx>, block: BasicBlock) -> Self {
         Self { edge, block }
@@ -50,9 +55,119 @@ impl<'tcx> LoopAbstraction<'tcx> {
     }
 }
 
-impl<'tcx> HasPcsElems<MaybeOldPlace<'tcx>> for LoopAbstraction<'tcx> {
-    fn pcs_elems(&mut self) -> Vec<&mut MaybeOldPlace<'tcx>> {
-        self.edge.pcs_elems()
+/// A kind of abstraction defined outside of this crate, e.g. for effects of
+/// specifications or of unsafe blocks, see [`ExternalAbstraction`].
+pub trait ExternalAbstractionKind: std::fmt::Debug {
+    /// Identifies the kind: abstractions are only equal (and hash alike) if
+    /// the keys of their kinds are
+    fn key(&self) -> String;
+
+    /// A short description of the kind, e.g. `spec-derived`, for display
+    fn name(&self) -> String {
+        self.key()
+    }
+}
+
+/// An abstraction of a kind introduced by a downstream tool. Its edges are
+/// handled like those of the other abstractions, only the kind is opaque.
+#[derive(Clone, Debug, HasPcsElems)]
+#[pcs_elems(MaybeOldPlace<'tcx>, RegionProjection<'tcx>)]
+pub struct ExternalAbstraction<'tcx> {
+    #[pcs_elems(skip)]
+    kind: Rc<dyn ExternalAbstractionKind>,
+    #[pcs_elems(skip)]
+    location: Location,
+    #[pcs_elems(iter)]
+    edges: Vec<AbstractionBlockEdge<'tcx>>,
+}
+
+impl<'tcx> ExternalAbstraction<'tcx> {
+    pub fn new(
+        kind: Rc<dyn ExternalAbstractionKind>,
+        location: Location,
+        edges: Vec<AbstractionBlockEdge<'tcx>>,
+    ) -> Self {
+        assert!(!edges.is_empty());
+        Self {
+            kind,
+            location,
+            edges,
+        }
+    }
+
+    pub fn kind(&self) -> &dyn ExternalAbstractionKind {
+        self.kind.as_ref()
+    }
+
+    pub fn location(&self) -> Location {
+        self.location
+    }
+
+    pub fn edges(&self) -> &[AbstractionBlockEdge<'tcx>] {
+        &self.edges
+    }
+}
+
+impl<'tcx> PartialEq for ExternalAbstraction<'tcx> {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind.key() == other.kind.key()
+            && self.location == other.location
+            && self.edges == other.edges
+    }
+}
+
+impl<'tcx> Eq for ExternalAbstraction<'tcx> {}
+
+impl<'tcx> std::hash::Hash for ExternalAbstraction<'tcx> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.kind.key().hash(state);
+        self.location.hash(state);
+        self.edges.hash(state);
+    }
+}
+
+impl<'tcx> ToBorrowsEdge<'tcx> for ExternalAbstraction<'tcx> {
+    fn to_borrows_edge(self, path_conditions: PathConditions) -> BorrowsEdge<'tcx> {
+        BorrowsEdge::new(
+            super::borrows_edge::BorrowsEdgeKind::Abstraction(AbstractionEdge {
+                abstraction_type: AbstractionType::External(self),
+            }),
+            path_conditions,
+        )
+    }
+}
+
+/// How the effect of a call on the borrows graph is abstracted.
+///
+/// The abstraction of a call is, in order of precedence, the one chosen for
+/// the callee with
+/// [`crate::combined_pcs::PcsContext::with_call_abstraction_for`], the one
+/// given by the `#[pcs::havoc]` (or `#[pcs::trusted]`) or `#[pcs::precise]`
+/// attribute of the callee, where havoc wins if both are present, and
+/// otherwise [`crate::combined_pcs::PcsContext::call_abstraction`].
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum CallAbstraction {
+    /// Derived from the signature of the callee: an input blocks the parts
+    /// of the result its lifetimes may flow into
+    #[default]
+    Precise,
+    /// Maximally conservative: every part of every argument blocks every
+    /// part of the result and of the targets of the mutable references among
+    /// the arguments, which the callee may have overwritten. Useful to tell
+    /// whether a precision issue is caused by a call abstraction, for quick
+    /// coarse runs, and to work around imprecise abstractions of particular
+    /// functions. `trusted` is an alias.
+    Havoc,
+}
+
+impl std::str::FromStr for CallAbstraction {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "precise" => Ok(CallAbstraction::Precise),
+            "havoc" | "trusted" => Ok(CallAbstraction::Havoc),
+            other => Err(format!("Unknown call abstraction: {other}")),
+        }
     }
 }
 
@@ -74,6 +189,29 @@ impl<'tcx> HasPcsElems<MaybeOldPlace<'tcx>> for FunctionCallAbstraction<'tcx> {
             .flat_map(|(_, edge)| edge.pcs_elems())
             .collect()
     }
+
+    fn pcs_elems_ref(&self) -> Vec<&MaybeOldPlace<'tcx>> {
+        self.edges
+            .iter()
+            .flat_map(|(_, edge)| edge.pcs_elems_ref())
+            .collect()
+    }
+}
+
+impl<'tcx> HasPcsElems<RegionProjection<'tcx>> for FunctionCallAbstraction<'tcx> {
+    fn pcs_elems(&mut self) -> Vec<&mut RegionProjection<'tcx>> {
+        self.edges
+            .iter_mut()
+            .flat_map(|(_, edge)| edge.pcs_elems())
+            .collect()
+    }
+
+    fn pcs_elems_ref(&self) -> Vec<&RegionProjection<'tcx>> {
+        self.edges
+            .iter()
+            .flat_map(|(_, edge)| edge.pcs_elems_ref())
+            .collect()
+    }
 }
 
 impl<'tcx> FunctionCallAbstraction<'tcx> {
@@ -116,65 +254,42 @@ pub trait HasPlaces<'tcx> {
     }
 }
 
-#[derive(PartialEq, Eq, Clone, Debug, Hash)]
+#[derive(PartialEq, Eq, Clone, Debug, Hash, HasPcsElems)]
+#[pcs_elems(MaybeOldPlace<'tcx>, RegionProjection<'tcx>)]
 pub enum AbstractionType<'tcx> {
-    FunctionCall(FunctionCallAbstraction<'tcx>),
-    Loop(LoopAbstraction<'tcx>),
+    FunctionCall(#[pcs_elems] FunctionCallAbstraction<'tcx>),
+    Loop(#[pcs_elems] LoopAbstraction<'tcx>),
+    External(#[pcs_elems] ExternalAbstraction<'tcx>),
 }
 
-impl<'tcx> HasPcsElems<MaybeOldPlace<'tcx>> for AbstractionType<'tcx> {
-    fn pcs_elems(&mut self) -> Vec<&mut MaybeOldPlace<'tcx>> {
-        match self {
-            AbstractionType::FunctionCall(c) => c.pcs_elems(),
-            AbstractionType::Loop(c) => c.pcs_elems(),
-        }
-    }
-}
-
-#[derive(Clone, Debug, Hash)]
+/// The inputs and outputs are kept sorted and without duplicates, so that
+/// edges with the same sets of inputs and outputs are equal.
+#[derive(PartialEq, Eq, Clone, Debug, Hash, HasPcsElems)]
+#[pcs_elems(MaybeOldPlace<'tcx>, RegionProjection<'tcx>)]
 pub struct AbstractionBlockEdge<'tcx> {
+    #[pcs_elems(iter)]
     inputs: Vec<AbstractionInputTarget<'tcx>>,
+    #[pcs_elems(iter)]
     outputs: Vec<AbstractionOutputTarget<'tcx>>,
 }
 
-impl<'tcx> PartialEq for AbstractionBlockEdge<'tcx> {
-    fn eq(&self, other: &Self) -> bool {
-        self.inputs() == other.inputs() && self.outputs() == other.outputs()
-    }
-}
-
-impl<'tcx> Eq for AbstractionBlockEdge<'tcx> {}
-
 impl<'tcx> AbstractionBlockEdge<'tcx> {
     pub fn new(
         inputs: HashSet<AbstractionInputTarget<'tcx>>,
         outputs: HashSet<AbstractionOutputTarget<'tcx>>,
     ) -> Self {
         Self {
-            inputs: inputs.into_iter().collect(),
-            outputs: outputs.into_iter().collect(),
+            inputs: sorted_by_debug(inputs),
+            outputs: sorted_by_debug(outputs),
         }
     }
 
-    pub fn outputs(&self) -> HashSet<AbstractionOutputTarget<'tcx>> {
-        self.outputs.clone().into_iter().collect()
+    pub fn outputs(&self) -> &[AbstractionOutputTarget<'tcx>] {
+        &self.outputs
     }
 
-    pub fn inputs(&self) -> HashSet<AbstractionInputTarget<'tcx>> {
-        self.inputs.clone().into_iter().collect()
-    }
-}
-
-impl<'tcx> HasPcsElems<MaybeOldPlace<'tcx>> for AbstractionBlockEdge<'tcx> {
-    fn pcs_elems(&mut self) -> Vec<&mut MaybeOldPlace<'tcx>> {
-        let mut result = vec![];
-        for input in self.inputs.iter_mut() {
-            result.extend(input.pcs_elems());
-        }
-        for output in self.outputs.iter_mut() {
-            result.extend(output.pcs_elems());
-        }
-        result
+    pub fn inputs(&self) -> &[AbstractionInputTarget<'tcx>] {
+        &self.inputs
     }
 }
 
@@ -206,6 +321,13 @@ impl<'tcx> HasPcsElems<MaybeOldPlace<'tcx>> for AbstractionOutputTarget<'tcx> {
             AbstractionTarget::RegionProjection(p) => p.pcs_elems(),
         }
     }
+
+    fn pcs_elems_ref(&self) -> Vec<&MaybeOldPlace<'tcx>> {
+        match self {
+            AbstractionTarget::Place(p) => vec![p],
+            AbstractionTarget::RegionProjection(p) => p.pcs_elems_ref(),
+        }
+    }
 }
 
 impl<'tcx> HasPcsElems<MaybeOldPlace<'tcx>> for AbstractionInputTarget<'tcx> {
@@ -215,6 +337,29 @@ impl<'tcx> HasPcsElems<MaybeOldPlace<'tcx>> for AbstractionInputTarget<'tcx> {
             AbstractionTarget::RegionProjection(p) => p.pcs_elems(),
         }
     }
+
+    fn pcs_elems_ref(&self) -> Vec<&MaybeOldPlace<'tcx>> {
+        match self {
+            AbstractionTarget::Place(p) => p.pcs_elems_ref(),
+            AbstractionTarget::RegionProjection(p) => p.pcs_elems_ref(),
+        }
+    }
+}
+
+impl<'tcx, T> HasPcsElems<RegionProjection<'tcx>> for AbstractionTarget<'tcx, T> {
+    fn pcs_elems(&mut self) -> Vec<&mut RegionProjection<'tcx>> {
+        match self {
+            AbstractionTarget::Place(_) => vec![],
+            AbstractionTarget::RegionProjection(p) => vec![p],
+        }
+    }
+
+    fn pcs_elems_ref(&self) -> Vec<&RegionProjection<'tcx>> {
+        match self {
+            AbstractionTarget::Place(_) => vec![],
+            AbstractionTarget::RegionProjection(p) => vec![p],
+        }
+    }
 }
 
 impl<'tcx> AbstractionType<'tcx> {
@@ -222,49 +367,40 @@ impl<'tcx> AbstractionType<'tcx> {
         match self {
             AbstractionType::FunctionCall(c) => c.location,
             AbstractionType::Loop(c) => c.location(),
+            AbstractionType::External(c) => c.location(),
         }
     }
 
-    pub fn inputs(&self) -> Vec<AbstractionInputTarget<'tcx>> {
-        self.edges()
-            .into_iter()
-            .flat_map(|edge| edge.inputs())
-            .collect()
+    pub fn inputs(&self) -> impl Iterator<Item = &AbstractionInputTarget<'tcx>> + '_ {
+        self.edges().flat_map(|edge| edge.inputs())
     }
-    pub fn outputs(&self) -> Vec<AbstractionOutputTarget<'tcx>> {
-        self.edges()
-            .into_iter()
-            .flat_map(|edge| edge.outputs())
-            .collect()
+
+    pub fn outputs(&self) -> impl Iterator<Item = &AbstractionOutputTarget<'tcx>> + '_ {
+        self.edges().flat_map(|edge| edge.outputs())
     }
 
-    pub fn blocks_places(&self) -> FxHashSet<MaybeRemotePlace<'tcx>> {
-        self.edges()
-            .into_iter()
-            .flat_map(|edge| edge.inputs())
+    pub fn blocks_places(&self) -> SmallSet<MaybeRemotePlace<'tcx>> {
+        self.inputs()
             .flat_map(|input| match input {
-                AbstractionTarget::Place(p) => Some(p),
+                AbstractionTarget::Place(p) => Some(*p),
                 AbstractionTarget::RegionProjection(_) => None,
             })
             .collect()
     }
 
-    pub fn edges(&self) -> Vec<AbstractionBlockEdge<'tcx>> {
+    pub fn edges(&self) -> impl Iterator<Item = &AbstractionBlockEdge<'tcx>> + '_ {
         match self {
-            AbstractionType::FunctionCall(c) => {
-                c.edges.iter().map(|(_, edge)| edge).cloned().collect()
-            }
-            AbstractionType::Loop(c) => c.edges().clone(),
+            AbstractionType::FunctionCall(c) => Either::Left(c.edges.iter().map(|(_, edge)| edge)),
+            AbstractionType::Loop(c) => Either::Right(c.edges().iter()),
+            AbstractionType::External(c) => Either::Right(c.edges().iter()),
         }
     }
 
-    pub fn blocker_places(&self) -> FxHashSet<MaybeOldPlace<'tcx>> {
-        self.edges()
-            .into_iter()
-            .flat_map(|edge| edge.outputs())
-            .flat_map(|output| match output {
-                AbstractionTarget::Place(p) => Some(p),
-                AbstractionTarget::RegionProjection(p) => Some(p.place),
+    pub fn blocker_places(&self) -> SmallSet<MaybeOldPlace<'tcx>> {
+        self.outputs()
+            .map(|output| match output {
+                AbstractionTarget::Place(p) => *p,
+                AbstractionTarget::RegionProjection(p) => p.place,
             })
             .collect()
     }
@@ -432,6 +568,7 @@ impl<'tcx> MaybeOldPlace<'tcx> {
         }
     }
 
+    #[cfg(feature = "json")]
     pub fn to_json(&self, repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value {
         json!({
             "place": self.place().to_json(repacker),
@@ -453,15 +590,26 @@ impl<'tcx> MaybeOldPlace<'tcx> {
     }
     pub fn make_place_old(&mut self, place: Place<'tcx>, latest: &Latest<'tcx>) {
         if self.is_current() && place.is_prefix(self.place()) {
-            *self = MaybeOldPlace::OldPlace(PlaceSnapshot {
-                place: self.place(),
-                at: latest.get(self.place()),
-            });
+            self.make_old(latest);
+        }
+    }
+
+    pub fn make_places_old(&mut self, places: &FxHashSet<Place<'tcx>>, latest: &Latest<'tcx>) {
+        if self.is_current() && places.iter().any(|place| place.is_prefix(self.place())) {
+            self.make_old(latest);
         }
     }
+
+    fn make_old(&mut self, latest: &Latest<'tcx>) {
+        *self = MaybeOldPlace::OldPlace(PlaceSnapshot {
+            place: self.place(),
+            at: latest.get(self.place()),
+        });
+    }
 }
 
 use crate::utils::PlaceRepacker;
+#[cfg(feature = "json")]
 use serde_json::json;
 
 use super::{
@@ -490,18 +638,36 @@ pub struct RemotePlace {
 }
 
 impl RemotePlace {
+    /// The argument assigned from this place, as it was at the entry of the
+    /// function
+    pub fn entry_place<'tcx>(self) -> MaybeOldPlace<'tcx> {
+        MaybeOldPlace::new(self.local.into(), Some(SnapshotLocation::FunctionEntry))
+    }
+
     pub fn region_projections<'tcx>(
         &self,
         repacker: PlaceRepacker<'_, 'tcx>,
     ) -> Vec<RegionProjection<'tcx>> {
-        let maybe_old_place =
-            MaybeOldPlace::new(self.local.into(), Some(SnapshotLocation::Join(START_BLOCK)));
-        maybe_old_place.region_projections(repacker)
+        self.entry_place().region_projections(repacker)
     }
 
     pub fn assigned_local(self) -> mir::Local {
         self.local
     }
+
+    /// The place named after the argument assigned from it, e.g. `Remote(x)`
+    pub fn to_short_string(self, repacker: PlaceRepacker<'_, '_>) -> String {
+        let argument: Place<'_> = self.local.into();
+        format!("Remote({})", argument.to_short_string(repacker))
+    }
+
+    #[cfg(feature = "json")]
+    pub fn to_json(self, repacker: PlaceRepacker<'_, '_>) -> serde_json::Value {
+        json!({
+            "place": self.to_short_string(repacker),
+            "remote": true,
+        })
+    }
 }
 
 impl<'tcx> HasPcsElems<MaybeOldPlace<'tcx>> for MaybeRemotePlace<'tcx> {
@@ -511,6 +677,13 @@ impl<'tcx> HasPcsElems<MaybeOldPlace<'tcx>> for MaybeRemotePlace<'tcx> {
             MaybeRemotePlace::Remote(_) => vec![],
         }
     }
+
+    fn pcs_elems_ref(&self) -> Vec<&MaybeOldPlace<'tcx>> {
+        match self {
+            MaybeRemotePlace::Local(p) => vec![p],
+            MaybeRemotePlace::Remote(_) => vec![],
+        }
+    }
 }
 
 impl<'tcx> HasPcsElems<Place<'tcx>> for MaybeOldPlace<'tcx> {
@@ -520,13 +693,20 @@ impl<'tcx> HasPcsElems<Place<'tcx>> for MaybeOldPlace<'tcx> {
             MaybeOldPlace::OldPlace(snapshot) => snapshot.pcs_elems(),
         }
     }
+
+    fn pcs_elems_ref(&self) -> Vec<&Place<'tcx>> {
+        match self {
+            MaybeOldPlace::Current { place } => vec![place],
+            MaybeOldPlace::OldPlace(snapshot) => snapshot.pcs_elems_ref(),
+        }
+    }
 }
 
 impl<'tcx> std::fmt::Display for MaybeRemotePlace<'tcx> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             MaybeRemotePlace::Local(p) => write!(f, "{}", p),
-            MaybeRemotePlace::Remote(l) => write!(f, "Remote({:?})", l),
+            MaybeRemotePlace::Remote(l) => write!(f, "Remote({:?})", l.local),
         }
     }
 }
@@ -546,10 +726,18 @@ impl<'tcx> MaybeRemotePlace<'tcx> {
         }
     }
 
+    pub fn to_short_string(&self, repacker: PlaceRepacker<'_, 'tcx>) -> String {
+        match self {
+            MaybeRemotePlace::Local(place) => place.to_short_string(repacker),
+            MaybeRemotePlace::Remote(place) => place.to_short_string(repacker),
+        }
+    }
+
+    #[cfg(feature = "json")]
     pub fn to_json(&self, repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value {
         match self {
             MaybeRemotePlace::Local(p) => p.to_json(repacker),
-            MaybeRemotePlace::Remote(_) => todo!(),
+            MaybeRemotePlace::Remote(remote_place) => remote_place.to_json(repacker),
         }
     }
 
@@ -582,24 +770,95 @@ impl<'tcx> std::fmt::Display for Reborrow<'tcx> {
         )
     }
 }
-#[derive(PartialEq, Eq, Clone, Debug, Hash)]
+/// Whether a reborrow is usable yet.
+///
+/// Two-phase borrows (e.g. the `&mut v` in `v.push(v.len())`) are created in
+/// the `Reserved` phase: the blocked place may still be read until the
+/// borrow is activated at its first use. All other reborrows are `Active`
+/// immediately.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
+pub enum ReborrowPhase {
+    Reserved,
+    Active,
+}
+
+impl std::fmt::Display for ReborrowPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReborrowPhase::Reserved => write!(f, "reserved"),
+            ReborrowPhase::Active => write!(f, "active"),
+        }
+    }
+}
+
+/// When two reborrows are considered the same when joining borrows graphs.
+/// Equivalent reborrows from both sides of a join are merged into a single
+/// edge under the union of their conditions.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum ReborrowEquivalence {
+    /// Only identical reborrows, i.e. including where they were created
+    #[default]
+    Identical,
+    /// Reborrows between the same places with the same mutability, phase and
+    /// region, wherever they were created. This merges e.g. the copies of a
+    /// borrow that the compiler generates in each arm of a match.
+    SamePlacesAndRegion,
+    /// As [`ReborrowEquivalence::SamePlacesAndRegion`], but ignoring the
+    /// region too
+    SamePlaces,
+}
+
+impl std::str::FromStr for ReborrowEquivalence {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "identical" => Ok(ReborrowEquivalence::Identical),
+            "same_places_and_region" => Ok(ReborrowEquivalence::SamePlacesAndRegion),
+            "same_places" => Ok(ReborrowEquivalence::SamePlaces),
+            other => Err(format!("Unknown reborrow equivalence: {other}")),
+        }
+    }
+}
+
+impl ReborrowEquivalence {
+    pub fn equivalent<'tcx>(self, r1: &Reborrow<'tcx>, r2: &Reborrow<'tcx>) -> bool {
+        let same_places = r1.blocked_place == r2.blocked_place
+            && r1.assigned_place == r2.assigned_place
+            && r1.mutability == r2.mutability
+            && r1.phase == r2.phase;
+        match self {
+            ReborrowEquivalence::Identical => r1 == r2,
+            ReborrowEquivalence::SamePlacesAndRegion => same_places && r1.region == r2.region,
+            ReborrowEquivalence::SamePlaces => same_places,
+        }
+    }
+}
+
+/// A borrow of `blocked_place` whose target is `assigned_place`.
+///
+/// Reborrows are always deep: they block `blocked_place` together with every
+/// place inside it. Match ergonomics only produce ordinary borrows of the
+/// bound places; the only shallow borrows in MIR are the fake borrows of
+/// match guards, which do not create reborrows at all.
+#[derive(PartialEq, Eq, Clone, Debug, Hash, HasPcsElems)]
+#[pcs_elems(MaybeOldPlace<'tcx>)]
 pub struct Reborrow<'tcx> {
+    #[pcs_elems]
     pub blocked_place: MaybeRemotePlace<'tcx>,
+    #[pcs_elem]
     pub assigned_place: MaybeOldPlace<'tcx>,
+    #[pcs_elems(skip)]
     pub mutability: Mutability,
 
     /// The location when the reborrow was created
+    #[pcs_elems(skip)]
     reserve_location: Location,
 
+    #[pcs_elems(skip)]
     pub region: ty::Region<'tcx>,
-}
 
-impl<'tcx> HasPcsElems<MaybeOldPlace<'tcx>> for Reborrow<'tcx> {
-    fn pcs_elems(&mut self) -> Vec<&mut MaybeOldPlace<'tcx>> {
-        let mut vec = vec![&mut self.assigned_place];
-        vec.extend(self.blocked_place.pcs_elems());
-        vec
-    }
+    #[pcs_elems(skip)]
+    phase: ReborrowPhase,
 }
 
 impl<'tcx> Reborrow<'tcx> {
@@ -616,6 +875,7 @@ impl<'tcx> Reborrow<'tcx> {
             mutability,
             reserve_location: reservation_location,
             region,
+            phase: ReborrowPhase::Active,
         }
     }
 
@@ -623,6 +883,20 @@ impl<'tcx> Reborrow<'tcx> {
         self.reserve_location
     }
 
+    /// Treats this reborrow as if it were created at `location`, used to
+    /// identify it with an equivalent one, see [`ReborrowEquivalence`].
+    pub fn set_reserve_location(&mut self, location: Location) {
+        self.reserve_location = location;
+    }
+
+    pub fn phase(&self) -> ReborrowPhase {
+        self.phase
+    }
+
+    pub fn set_phase(&mut self, phase: ReborrowPhase) {
+        self.phase = phase;
+    }
+
     pub fn assiged_place_region_vid(&self, repacker: PlaceRepacker<'_, 'tcx>) -> Option<RegionVid> {
         match self
             .assigned_place
@@ -649,16 +923,161 @@ impl<'tcx> Reborrow<'tcx> {
     }
 }
 
+#[cfg(feature = "json")]
 pub trait ToJsonWithRepacker<'tcx> {
     fn to_json(&self, repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value;
 }
 
+#[cfg(feature = "json")]
 impl<'tcx> ToJsonWithRepacker<'tcx> for Reborrow<'tcx> {
     fn to_json(&self, repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value {
         json!({
             "blocked_place": self.blocked_place.to_json(repacker),
             "assigned_place": self.assigned_place.to_json(repacker),
-            "is_mut": self.mutability == Mutability::Mut
+            "is_mut": self.mutability == Mutability::Mut,
+            "phase": format!("{}", self.phase)
+        })
+    }
+}
+
+#[cfg(feature = "json")]
+impl<'tcx, T: ToJsonWithRepacker<'tcx> + ?Sized> ToJsonWithRepacker<'tcx> for &T {
+    fn to_json(&self, repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value {
+        (**self).to_json(repacker)
+    }
+}
+
+#[cfg(feature = "json")]
+impl<'tcx, T: ToJsonWithRepacker<'tcx>> ToJsonWithRepacker<'tcx> for [T] {
+    fn to_json(&self, repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value {
+        serde_json::Value::Array(self.iter().map(|elem| elem.to_json(repacker)).collect())
+    }
+}
+
+#[cfg(feature = "json")]
+impl<'tcx, T: ToJsonWithRepacker<'tcx>> ToJsonWithRepacker<'tcx> for Vec<T> {
+    fn to_json(&self, repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value {
+        self.as_slice().to_json(repacker)
+    }
+}
+
+#[cfg(feature = "json")]
+impl<'tcx, T: ToJsonWithRepacker<'tcx>> ToJsonWithRepacker<'tcx> for Option<T> {
+    fn to_json(&self, repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value {
+        match self {
+            Some(value) => value.to_json(repacker),
+            None => serde_json::Value::Null,
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl<'tcx, A: ToJsonWithRepacker<'tcx>, B: ToJsonWithRepacker<'tcx>> ToJsonWithRepacker<'tcx>
+    for (A, B)
+{
+    fn to_json(&self, repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value {
+        json!([self.0.to_json(repacker), self.1.to_json(repacker)])
+    }
+}
+
+#[cfg(feature = "json")]
+impl<'tcx, A, B, C> ToJsonWithRepacker<'tcx> for (A, B, C)
+where
+    A: ToJsonWithRepacker<'tcx>,
+    B: ToJsonWithRepacker<'tcx>,
+    C: ToJsonWithRepacker<'tcx>,
+{
+    fn to_json(&self, repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value {
+        json!([
+            self.0.to_json(repacker),
+            self.1.to_json(repacker),
+            self.2.to_json(repacker)
+        ])
+    }
+}
+
+#[cfg(feature = "json")]
+impl<'tcx> ToJsonWithRepacker<'tcx> for usize {
+    fn to_json(&self, _repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value {
+        json!(self)
+    }
+}
+
+#[cfg(feature = "json")]
+impl<'tcx> ToJsonWithRepacker<'tcx> for Place<'tcx> {
+    fn to_json(&self, repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value {
+        Place::to_json(self, repacker)
+    }
+}
+
+#[cfg(feature = "json")]
+impl<'tcx> ToJsonWithRepacker<'tcx> for MaybeOldPlace<'tcx> {
+    fn to_json(&self, repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value {
+        MaybeOldPlace::to_json(self, repacker)
+    }
+}
+
+#[cfg(feature = "json")]
+impl<'tcx> ToJsonWithRepacker<'tcx> for RemotePlace {
+    fn to_json(&self, repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value {
+        RemotePlace::to_json(*self, repacker)
+    }
+}
+
+#[cfg(feature = "json")]
+impl<'tcx> ToJsonWithRepacker<'tcx> for MaybeRemotePlace<'tcx> {
+    fn to_json(&self, repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value {
+        MaybeRemotePlace::to_json(self, repacker)
+    }
+}
+
+#[cfg(feature = "json")]
+impl<'tcx, T: ToJsonWithRepacker<'tcx>> ToJsonWithRepacker<'tcx> for AbstractionTarget<'tcx, T> {
+    fn to_json(&self, repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value {
+        match self {
+            AbstractionTarget::Place(place) => json!({
+                "type": "Place",
+                "place": place.to_json(repacker),
+            }),
+            AbstractionTarget::RegionProjection(projection) => json!({
+                "type": "RegionProjection",
+                "region_projection": projection.to_json(repacker),
+            }),
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl<'tcx> ToJsonWithRepacker<'tcx> for AbstractionBlockEdge<'tcx> {
+    fn to_json(&self, repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value {
+        json!({
+            "inputs": self.inputs.to_json(repacker),
+            "outputs": self.outputs.to_json(repacker),
         })
     }
 }
+
+#[cfg(feature = "json")]
+impl<'tcx> ToJsonWithRepacker<'tcx> for AbstractionType<'tcx> {
+    fn to_json(&self, repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value {
+        match self {
+            AbstractionType::FunctionCall(call) => json!({
+                "type": "FunctionCall",
+                "location": format!("{:?}", call.location()),
+                "function": repacker.tcx().def_path_str(call.def_id()),
+                "edges": call.edges().to_json(repacker),
+            }),
+            AbstractionType::Loop(abstraction) => json!({
+                "type": "Loop",
+                "location": format!("{:?}", abstraction.location()),
+                "edges": abstraction.edges().to_json(repacker),
+            }),
+            AbstractionType::External(abstraction) => json!({
+                "type": "External",
+                "kind": abstraction.kind().name(),
+                "location": format!("{:?}", abstraction.location()),
+                "edges": abstraction.edges().to_json(repacker),
+            }),
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::hash::{Hash, Hasher};
+
+use smallvec::SmallVec;
+
+use crate::rustc_interface::data_structures::fx::{FxHashMap, FxHasher};
+
+use super::borrows_edge::{BorrowsEdge, BorrowsEdgeKind};
+
+/// The edges of a [`super::borrows_graph::BorrowsGraph`], indexed by their
+/// kind, so that the edges that differ from a given one only in their
+/// conditions can be found without scanning the whole graph.
+///
+/// The edges are bucketed by the hash of their kind rather than by the kind
+/// itself, so that kinds are not stored twice. A bucket usually holds a
+/// single edge.
+#[derive(Clone, Debug, Default)]
+pub struct EdgeSet<'tcx> {
+    buckets: FxHashMap<u64, SmallVec<[BorrowsEdge<'tcx>; 1]>>,
+    len: usize,
+}
+
+fn kind_hash(kind: &BorrowsEdgeKind<'_>) -> u64 {
+    let mut hasher = FxHasher::default();
+    kind.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl<'tcx> EdgeSet<'tcx> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &BorrowsEdge<'tcx>> {
+        self.buckets.values().flatten()
+    }
+
+    /// The edges of kind `kind`, which differ only in their conditions
+    pub fn with_kind<'a>(
+        &'a self,
+        kind: &'a BorrowsEdgeKind<'tcx>,
+    ) -> impl Iterator<Item = &'a BorrowsEdge<'tcx>> {
+        self.buckets
+            .get(&kind_hash(kind))
+            .into_iter()
+            .flatten()
+            .filter(move |edge| edge.kind() == kind)
+    }
+
+    pub fn contains(&self, edge: &BorrowsEdge<'tcx>) -> bool {
+        self.with_kind(edge.kind()).any(|e| e == edge)
+    }
+
+    /// Inserts `edge`, returning `false` if an equal edge was present
+    pub fn insert(&mut self, edge: BorrowsEdge<'tcx>) -> bool {
+        let bucket = self.buckets.entry(kind_hash(edge.kind())).or_default();
+        if bucket.contains(&edge) {
+            return false;
+        }
+        bucket.push(edge);
+        self.len += 1;
+        true
+    }
+
+    /// Removes the edge equal to `edge` and returns it
+    pub fn take(&mut self, edge: &BorrowsEdge<'tcx>) -> Option<BorrowsEdge<'tcx>> {
+        let hash = kind_hash(edge.kind());
+        let bucket = self.buckets.get_mut(&hash)?;
+        let idx = bucket.iter().position(|e| e == edge)?;
+        let removed = bucket.swap_remove(idx);
+        if bucket.is_empty() {
+            self.buckets.remove(&hash);
+        }
+        self.len -= 1;
+        Some(removed)
+    }
+
+    pub fn remove(&mut self, edge: &BorrowsEdge<'tcx>) -> bool {
+        self.take(edge).is_some()
+    }
+}
+
+impl<'tcx> PartialEq for EdgeSet<'tcx> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().all(|edge| other.contains(edge))
+    }
+}
+
+impl<'tcx> Eq for EdgeSet<'tcx> {}
+
+impl<'tcx> IntoIterator for EdgeSet<'tcx> {
+    type Item = BorrowsEdge<'tcx>;
+    type IntoIter = std::iter::Flatten<
+        std::collections::hash_map::IntoValues<u64, SmallVec<[BorrowsEdge<'tcx>; 1]>>,
+    >;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.buckets.into_values().flatten()
+    }
+}
+
+impl<'tcx> FromIterator<BorrowsEdge<'tcx>> for EdgeSet<'tcx> {
+    fn from_iter<I: IntoIterator<Item = BorrowsEdge<'tcx>>>(iter: I) -> Self {
+        let mut set = Self::default();
+        for edge in iter {
+            set.insert(edge);
+        }
+        set
+    }
+}
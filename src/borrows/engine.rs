@@ -5,7 +5,9 @@ use rustc_interface::{
         borrow_set::BorrowSet,
         consumers::{LocationTable, PoloniusInput, PoloniusOutput, RegionInferenceContext},
     },
-    dataflow::{Analysis, AnalysisDomain, JoinSemiLattice},
+    data_structures::fx::FxHashMap,
+    dataflow::{JoinSemiLattice, PcsAnalysis},
+    hir::def_id::DefId,
     middle::{
         mir::{
             visit::Visitor, BasicBlock, Body, CallReturnPlaces, Location, Statement, Terminator,
@@ -14,21 +16,30 @@ use rustc_interface::{
         ty::{self, TyCtxt},
     },
 };
+#[cfg(feature = "json")]
 use serde_json::{json, Value};
 
+#[cfg(feature = "json")]
+use crate::borrows::domain::ToJsonWithRepacker;
 use crate::{
-    borrows::domain::ToJsonWithRepacker,
     rustc_interface,
-    utils::{self, Place, PlaceRepacker},
+    utils::{self, post_dominators::PostDominators, Place, PlaceRepacker},
 };
 
 use super::{
-    borrows_state::BorrowsState, borrows_visitor::BorrowsVisitor, domain::MaybeRemotePlace,
+    borrows_edge::EdgeProvenance,
+    borrows_graph::Conditioned,
+    borrows_state::{BorrowsState, ExpansionOp, OldLeafTrimming},
+    borrows_visitor::BorrowsVisitor,
+    domain::MaybeRemotePlace,
+    external_abstractions::ExternalAbstractions,
+    function_summary::FunctionSummaries,
     path_condition::PathCondition,
 };
 use super::{
     deref_expansion::DerefExpansion,
-    domain::{MaybeOldPlace, Reborrow},
+    domain::{AbstractionBlockEdge, CallAbstraction, MaybeOldPlace, Reborrow, ReborrowEquivalence},
+    invariants::{cycle_violation, InvariantCheckLevel},
 };
 
 pub struct BorrowsEngine<'mir, 'tcx> {
@@ -39,9 +50,76 @@ pub struct BorrowsEngine<'mir, 'tcx> {
     pub borrow_set: Rc<BorrowSet<'tcx>>,
     pub region_inference_context: Rc<RegionInferenceContext<'tcx>>,
     pub output_facts: &'mir PoloniusOutput,
+    pub invariant_check_level: Option<InvariantCheckLevel>,
+    pub old_leaf_trimming: OldLeafTrimming,
+    /// See [`crate::combined_pcs::PcsContext::projection_depth_limit`]
+    pub projection_depth_limit: Option<usize>,
+    /// See [`crate::combined_pcs::PcsContext::call_abstraction`]
+    pub call_abstraction: CallAbstraction,
+    /// See [`crate::combined_pcs::PcsContext::call_abstractions`]
+    pub call_abstractions: Rc<FxHashMap<DefId, CallAbstraction>>,
+    /// See [`crate::combined_pcs::PcsContext::function_summaries`]
+    pub function_summaries: Rc<FunctionSummaries<'tcx>>,
+    /// Supplies edges to insert after each location, see
+    /// [`crate::combined_pcs::PcsContext::with_external_abstractions`]
+    pub external_abstractions: Option<Rc<dyn ExternalAbstractions<'tcx> + 'mir>>,
 }
 
 impl<'mir, 'tcx> BorrowsEngine<'mir, 'tcx> {
+    /// In debug builds, checks the structural invariants of the graph after
+    /// each statement. If semantic checks were requested, also checks that
+    /// the graph is acyclic: a cycle would otherwise make unblocking diverge,
+    /// so it is reported immediately rather than collected after the
+    /// analysis, see [`crate::combined_pcs::check_invariants`].
+    fn check_invariants(&self, state: &BorrowsDomain<'mir, 'tcx>, location: Location) {
+        let repacker = PlaceRepacker::new(self.body, self.tcx);
+        if cfg!(debug_assertions) {
+            state.after.graph().assert_invariants_satisfied(
+                InvariantCheckLevel::Structural,
+                location,
+                repacker,
+            );
+        }
+        if self.invariant_check_level >= Some(InvariantCheckLevel::Semantic)
+            && let Some(cycle) = state.after.graph().find_cycle(repacker)
+        {
+            panic!(
+                "{}",
+                cycle_violation(cycle, location).to_short_string(repacker)
+            );
+        }
+    }
+
+    /// Inserts the edges supplied by the embedder after the effect of
+    /// `location`. An edge that would violate an invariant of the graph is
+    /// a bug of the embedder, and aborts the analysis.
+    fn insert_external_abstractions(
+        &self,
+        state: &mut BorrowsDomain<'mir, 'tcx>,
+        location: Location,
+    ) {
+        let Some(external) = &self.external_abstractions else {
+            return;
+        };
+        let repacker = PlaceRepacker::new(self.body, self.tcx);
+        for abstraction in external.edges_after(location, state.after.graph(), repacker) {
+            if let Err(violations) =
+                state
+                    .after
+                    .insert_external_abstraction(abstraction, location, repacker)
+            {
+                let report: Vec<_> = violations
+                    .iter()
+                    .map(|v| v.to_short_string(repacker))
+                    .collect();
+                panic!(
+                    "External abstraction violates borrows graph invariants:\n{}",
+                    report.join("\n")
+                );
+            }
+        }
+    }
+
     pub fn new(
         tcx: TyCtxt<'tcx>,
         body: &'mir Body<'tcx>,
@@ -59,6 +137,13 @@ impl<'mir, 'tcx> BorrowsEngine<'mir, 'tcx> {
             borrow_set,
             region_inference_context,
             output_facts,
+            invariant_check_level: None,
+            old_leaf_trimming: OldLeafTrimming::default(),
+            projection_depth_limit: None,
+            call_abstraction: CallAbstraction::default(),
+            call_abstractions: Rc::default(),
+            function_summaries: Rc::default(),
+            external_abstractions: None,
         }
     }
 }
@@ -72,6 +157,7 @@ pub enum ReborrowAction<'tcx> {
 }
 
 impl<'tcx> ReborrowAction<'tcx> {
+    #[cfg(feature = "json")]
     pub fn to_json(&self, repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value {
         match self {
             ReborrowAction::AddReborrow(reborrow) => json!({
@@ -103,19 +189,38 @@ impl<'mir, 'tcx> JoinSemiLattice for BorrowsDomain<'mir, 'tcx> {
         let pc = PathCondition::new(other.block(), self.block());
         other_after.add_path_condition(pc);
 
+        // Identify reborrows from the other side with equivalent ones here, so
+        // that they are merged below
+        other_after.align_reborrows_with(&self.after, self.reborrow_equivalence);
+
+        // The state at the head of a loop with a given abstraction does not
+        // depend on the iterations of the loop
+        if self.repacker.is_back_edge(other.block(), self.block())
+            && let Some(edges) = self.loop_abstractions.get(&self.block())
+        {
+            return self
+                .after
+                .apply_loop_abstraction(edges, self.block(), self.repacker);
+        }
+
         // Overlay both graphs
-        self.after.join(
+        let changed = self.after.join(
             &other_after,
             self.block(),
             other.block(),
             self.output_facts.as_ref(),
             self.location_table.as_ref(),
             self.repacker,
-        )
+        );
+        if changed {
+            self.after
+                .simplify_path_conditions(self.block(), &self.post_dominators, self.repacker);
+        }
+        changed
     }
 }
 
-impl<'tcx, 'a> AnalysisDomain<'tcx> for BorrowsEngine<'a, 'tcx> {
+impl<'a, 'tcx> PcsAnalysis<'tcx> for BorrowsEngine<'a, 'tcx> {
     type Domain = BorrowsDomain<'a, 'tcx>;
     const NAME: &'static str = "borrows";
 
@@ -126,15 +231,16 @@ impl<'tcx, 'a> AnalysisDomain<'tcx> for BorrowsEngine<'a, 'tcx> {
     fn initialize_start_block(&self, _body: &Body<'tcx>, _state: &mut Self::Domain) {
         todo!()
     }
-}
 
-impl<'a, 'tcx> Analysis<'tcx> for BorrowsEngine<'a, 'tcx> {
     fn apply_before_statement_effect(
         &mut self,
         state: &mut BorrowsDomain<'a, 'tcx>,
         statement: &Statement<'tcx>,
         location: Location,
     ) {
+        state.after.set_mutation_location(location.into());
+        state.start_expansion_ops.clear();
+        state.join_killed_reborrows.clear();
         BorrowsVisitor::preparing(self, state, true).visit_statement(statement, location);
         state.before_start = state.after.clone();
         BorrowsVisitor::applying(self, state, true).visit_statement(statement, location);
@@ -147,9 +253,13 @@ impl<'a, 'tcx> Analysis<'tcx> for BorrowsEngine<'a, 'tcx> {
         statement: &Statement<'tcx>,
         location: Location,
     ) {
+        state.after.set_mutation_location(location.into());
+        state.middle_expansion_ops.clear();
         BorrowsVisitor::preparing(self, state, false).visit_statement(statement, location);
         state.start = state.after.clone();
         BorrowsVisitor::applying(self, state, false).visit_statement(statement, location);
+        self.insert_external_abstractions(state, location);
+        self.check_invariants(state, location);
     }
 
     fn apply_before_terminator_effect(
@@ -158,6 +268,9 @@ impl<'a, 'tcx> Analysis<'tcx> for BorrowsEngine<'a, 'tcx> {
         terminator: &Terminator<'tcx>,
         location: Location,
     ) {
+        state.after.set_mutation_location(location.into());
+        state.start_expansion_ops.clear();
+        state.join_killed_reborrows.clear();
         BorrowsVisitor::preparing(self, state, true).visit_terminator(terminator, location);
         state.before_start = state.after.clone();
         BorrowsVisitor::applying(self, state, true).visit_terminator(terminator, location);
@@ -170,9 +283,13 @@ impl<'a, 'tcx> Analysis<'tcx> for BorrowsEngine<'a, 'tcx> {
         terminator: &'mir Terminator<'tcx>,
         location: Location,
     ) -> TerminatorEdges<'mir, 'tcx> {
+        state.after.set_mutation_location(location.into());
+        state.middle_expansion_ops.clear();
         BorrowsVisitor::preparing(self, state, false).visit_terminator(terminator, location);
         state.start = state.after.clone();
         BorrowsVisitor::applying(self, state, false).visit_terminator(terminator, location);
+        self.insert_external_abstractions(state, location);
+        self.check_invariants(state, location);
         terminator.edges()
     }
 
@@ -195,6 +312,20 @@ pub struct BorrowsDomain<'mir, 'tcx> {
     pub repacker: PlaceRepacker<'mir, 'tcx>,
     pub output_facts: Rc<PoloniusOutput>,
     pub location_table: Rc<LocationTable>,
+    pub post_dominators: Rc<PostDominators>,
+    pub reborrow_equivalence: ReborrowEquivalence,
+    /// Loops whose abstraction is given explicitly, see
+    /// [`crate::combined_pcs::PcsContext::with_loop_abstraction`]
+    pub loop_abstractions: Rc<FxHashMap<BasicBlock, Vec<AbstractionBlockEdge<'tcx>>>>,
+    /// The operations that expanded `after` to `before_start` at the current
+    /// statement, in the order they were applied
+    pub start_expansion_ops: Vec<ExpansionOp<'tcx>>,
+    /// The operations that expanded `before_after` to `start` at the current
+    /// statement, in the order they were applied
+    pub middle_expansion_ops: Vec<ExpansionOp<'tcx>>,
+    /// The reborrows killed when joining the states of the predecessors at
+    /// the start of the block, see [`crate::ReborrowBridge::killed_reborrows`]
+    pub join_killed_reborrows: Vec<Conditioned<Reborrow<'tcx>>>,
 }
 
 impl<'mir, 'tcx> PartialEq for BorrowsDomain<'mir, 'tcx> {
@@ -234,6 +365,7 @@ impl<'mir, 'tcx> BorrowsDomain<'mir, 'tcx> {
         self.block.unwrap()
     }
 
+    #[cfg(feature = "json")]
     pub fn to_json(&self, repacker: PlaceRepacker<'mir, 'tcx>) -> Value {
         json!({
             "before_start": self.before_start.to_json(repacker),
@@ -247,7 +379,10 @@ impl<'mir, 'tcx> BorrowsDomain<'mir, 'tcx> {
         repacker: PlaceRepacker<'mir, 'tcx>,
         output_facts: Rc<PoloniusOutput>,
         location_table: Rc<LocationTable>,
+        post_dominators: Rc<PostDominators>,
         block: Option<BasicBlock>,
+        reborrow_equivalence: ReborrowEquivalence,
+        loop_abstractions: Rc<FxHashMap<BasicBlock, Vec<AbstractionBlockEdge<'tcx>>>>,
     ) -> Self {
         Self {
             before_start: BorrowsState::new(),
@@ -258,6 +393,12 @@ impl<'mir, 'tcx> BorrowsDomain<'mir, 'tcx> {
             repacker,
             output_facts,
             location_table,
+            post_dominators,
+            reborrow_equivalence,
+            loop_abstractions,
+            start_expansion_ops: vec![],
+            middle_expansion_ops: vec![],
+            join_killed_reborrows: vec![],
         }
     }
 
@@ -273,6 +414,7 @@ impl<'mir, 'tcx> BorrowsDomain<'mir, 'tcx> {
                     *mutability,
                     Location::START,
                     *region,
+                    EdgeProvenance::new(format!("argument {:?}", arg), None),
                 );
             }
         }
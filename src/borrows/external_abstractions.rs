@@ -0,0 +1,86 @@
+// © 2023, ETH Zurich
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use rustc_interface::middle::mir::Location;
+
+use crate::{rustc_interface, utils::PlaceRepacker};
+
+use super::{
+    borrows_edge::{EdgeProvenance, ToBorrowsEdge},
+    borrows_graph::{BorrowsGraph, Conditioned},
+    invariants::{cycle_violation, edge_invariant_violations, InvariantViolation},
+    region_abstraction::AbstractionEdge,
+};
+
+/// Abstraction edges supplied by the embedder of the analysis.
+///
+/// These model effects that the analysis does not know about, e.g. those of
+/// specifications or ghost code. See
+/// [`crate::combined_pcs::PcsContext::with_external_abstractions`].
+///
+/// Closures taking the same arguments as [`Self::edges_after`] implement
+/// this trait.
+pub trait ExternalAbstractions<'tcx> {
+    /// The edges to insert into `graph`, the borrows graph after the effect
+    /// of `location`. Each edge only exists under its path conditions.
+    fn edges_after(
+        &self,
+        location: Location,
+        graph: &BorrowsGraph<'tcx>,
+        repacker: PlaceRepacker<'_, 'tcx>,
+    ) -> Vec<Conditioned<AbstractionEdge<'tcx>>>;
+}
+
+impl<'tcx, F> ExternalAbstractions<'tcx> for F
+where
+    F: Fn(
+        Location,
+        &BorrowsGraph<'tcx>,
+        PlaceRepacker<'_, 'tcx>,
+    ) -> Vec<Conditioned<AbstractionEdge<'tcx>>>,
+{
+    fn edges_after(
+        &self,
+        location: Location,
+        graph: &BorrowsGraph<'tcx>,
+        repacker: PlaceRepacker<'_, 'tcx>,
+    ) -> Vec<Conditioned<AbstractionEdge<'tcx>>> {
+        self(location, graph, repacker)
+    }
+}
+
+impl<'tcx> BorrowsGraph<'tcx> {
+    /// Inserts an edge supplied by [`ExternalAbstractions`] after the effect
+    /// of `location`, unless it would violate an invariant of the graph:
+    /// either a structural one concerning the edge itself, or acyclicity.
+    /// In that case the graph is left unchanged and the violations are
+    /// returned.
+    pub fn insert_external_abstraction(
+        &mut self,
+        abstraction: Conditioned<AbstractionEdge<'tcx>>,
+        location: Location,
+        repacker: PlaceRepacker<'_, 'tcx>,
+    ) -> Result<bool, Vec<InvariantViolation<'tcx>>> {
+        let edge = abstraction
+            .value
+            .to_borrows_edge(abstraction.conditions)
+            .with_provenance(EdgeProvenance::at("external abstraction", location));
+        let violations = edge_invariant_violations(&edge, location, repacker);
+        if !violations.is_empty() {
+            return Err(violations);
+        }
+        let mut graph = self.clone();
+        let changed = graph.insert(edge);
+        // Cycles that were already there are not caused by the new edge
+        if let Some(cycle) = graph.find_cycle(repacker)
+            && self.find_cycle(repacker).is_none()
+        {
+            return Err(vec![cycle_violation(cycle, location)]);
+        }
+        *self = graph;
+        Ok(changed)
+    }
+}
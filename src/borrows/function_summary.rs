@@ -0,0 +1,416 @@
+// © 2023, ETH Zurich
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Parametric summaries of the signatures of called functions, computed once
+//! per function and instantiated at each call, see [`FunctionSummary`].
+//!
+//! A summary describes the flows between the arguments and the result of a
+//! function in terms of its own generic parameters. A region parameter is a
+//! single region projection of a place, whereas a type parameter stands for
+//! all the region projections of the type it is instantiated with, which
+//! are only known at the call. The summary is therefore computed from the
+//! identity signature of the function, and the slots of its inputs and
+//! output are mapped to region projections by the generic arguments of each
+//! call.
+
+use std::{cell::RefCell, collections::HashSet, rc::Rc};
+
+use rustc_interface::{
+    ast::Mutability,
+    data_structures::fx::FxHashMap,
+    hir::{def_id::DefId, LangItem},
+    middle::{
+        mir::Place,
+        ty::{
+            self, EarlyBinder, GenericArgsRef, ParamEnv, TyCtxt, TypeSuperVisitable, TypeVisitable,
+            TypeVisitor,
+        },
+    },
+};
+
+use crate::{
+    rustc_interface,
+    utils::{self, PlaceRepacker, PlaceSnapshot, SnapshotLocation},
+};
+
+use super::{
+    borrows_visitor::extract_lifetimes,
+    domain::{AbstractionBlockEdge, AbstractionTarget, MaybeOldPlace},
+};
+
+/// A part of an input or the output of a function signature that borrows
+/// can flow from or into
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Slot<'tcx> {
+    /// A region of the signature, corresponding to one region projection
+    Region(ty::Region<'tcx>),
+    /// A type parameter, corresponding to the region projections of the type
+    /// it is instantiated with
+    Type(ty::ParamTy),
+    /// An associated or opaque type, corresponding to the region projections
+    /// of the type it normalizes to at the call
+    Opaque(ty::Ty<'tcx>),
+}
+
+/// The slots of an argument or result type, in the order of the region
+/// projections of the corresponding place
+#[derive(Clone, Debug)]
+pub struct SignatureSlots<'tcx> {
+    /// Whether the type is a `Pin`, which is transparent for borrows
+    pub pinned: bool,
+    /// The region and mutability of the type, if it is a reference. Its
+    /// target is tracked as a place rather than by region projections.
+    pub pointee: Option<(ty::Region<'tcx>, Mutability)>,
+    pub slots: Vec<Slot<'tcx>>,
+}
+
+/// A part of [`SignatureSlots`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SummaryTarget {
+    /// The target of the reference
+    Pointee,
+    /// The slot with the given index
+    Slot(usize),
+}
+
+/// The borrows in `from` of the input with index `input` may flow into `to`
+/// of the output
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SummaryEdge {
+    pub input: usize,
+    pub from: SummaryTarget,
+    pub to: SummaryTarget,
+}
+
+/// The flows between the arguments and the result of a function, in terms
+/// of its generic parameters
+#[derive(Clone, Debug)]
+pub struct FunctionSummary<'tcx> {
+    pub inputs: Vec<SignatureSlots<'tcx>>,
+    pub output: SignatureSlots<'tcx>,
+    pub edges: Vec<SummaryEdge>,
+}
+
+struct SlotCollector<'tcx> {
+    slots: Vec<Slot<'tcx>>,
+}
+
+impl<'tcx> TypeVisitor<TyCtxt<'tcx>> for SlotCollector<'tcx> {
+    fn visit_ty(&mut self, ty: ty::Ty<'tcx>) {
+        match ty.kind() {
+            ty::TyKind::Param(param) => self.slots.push(Slot::Type(*param)),
+            ty::TyKind::Alias(..) => self.slots.push(Slot::Opaque(ty)),
+            _ => ty.super_visit_with(self),
+        }
+    }
+
+    fn visit_region(&mut self, region: ty::Region<'tcx>) {
+        self.slots.push(Slot::Region(region));
+    }
+}
+
+impl<'tcx> SignatureSlots<'tcx> {
+    fn new(ty: ty::Ty<'tcx>, tcx: TyCtxt<'tcx>) -> Self {
+        let (pinned, ty) = match utils::pinned_pointer_ty(ty, tcx) {
+            Some(pointer_ty) => (true, pointer_ty),
+            None => (false, ty),
+        };
+        let (pointee, ty) = match ty.kind() {
+            ty::TyKind::Ref(region, ty, mutability) => (Some((*region, *mutability)), *ty),
+            _ => (None, ty),
+        };
+        let mut collector = SlotCollector { slots: vec![] };
+        ty.visit_with(&mut collector);
+        SignatureSlots {
+            pinned,
+            pointee,
+            slots: collector.slots,
+        }
+    }
+
+    /// The parts that borrows can flow from, with the slot standing for each
+    fn sources(&self) -> Vec<(SummaryTarget, Slot<'tcx>)> {
+        let pointee = match self.pointee {
+            Some((region, Mutability::Mut)) => Some((SummaryTarget::Pointee, Slot::Region(region))),
+            _ => None,
+        };
+        pointee.into_iter().chain(self.targets()).collect()
+    }
+
+    /// The parts that borrows can flow into, with the slot standing for each
+    fn sinks(&self) -> Vec<(SummaryTarget, Slot<'tcx>)> {
+        let pointee = match self.pointee {
+            Some((region, Mutability::Mut)) => Some((SummaryTarget::Pointee, Slot::Region(region))),
+            _ => None,
+        };
+        pointee.into_iter().chain(self.targets()).collect()
+    }
+
+    fn targets(&self) -> impl Iterator<Item = (SummaryTarget, Slot<'tcx>)> + '_ {
+        self.slots
+            .iter()
+            .enumerate()
+            .map(|(idx, slot)| (SummaryTarget::Slot(idx), *slot))
+    }
+
+    /// The place corresponding to this type, given the place of the argument
+    /// or result
+    fn place(
+        &self,
+        place: utils::Place<'tcx>,
+        repacker: PlaceRepacker<'_, 'tcx>,
+    ) -> utils::Place<'tcx> {
+        if self.pinned {
+            place.project_pin_pointer(repacker).unwrap_or(place)
+        } else {
+            place
+        }
+    }
+
+    /// The indices of the region projections of `place` corresponding to
+    /// each slot, given the generic arguments of the call. If they cannot be
+    /// determined, each slot corresponds to all of them.
+    fn ranges(
+        &self,
+        place: MaybeOldPlace<'tcx>,
+        callee_args: GenericArgsRef<'tcx>,
+        param_env: ParamEnv<'tcx>,
+        repacker: PlaceRepacker<'_, 'tcx>,
+    ) -> Vec<std::ops::Range<usize>> {
+        let tcx = repacker.tcx();
+        let len = place.region_projections(repacker).len();
+        let start = if self.pointee.is_some() { 1 } else { 0 };
+        let widths: Option<Vec<usize>> = self
+            .slots
+            .iter()
+            .map(|slot| match slot {
+                Slot::Region(_) => Some(1),
+                Slot::Type(param) => callee_args
+                    .get(param.index as usize)
+                    .and_then(|arg| arg.as_type())
+                    .map(|ty| extract_lifetimes(ty).len()),
+                Slot::Opaque(ty) => {
+                    let ty = EarlyBinder::bind(*ty).instantiate(tcx, callee_args);
+                    tcx.try_normalize_erasing_regions(param_env, ty)
+                        .ok()
+                        .map(|ty| extract_lifetimes(ty).len())
+                }
+            })
+            .collect();
+        match widths {
+            Some(widths) if start + widths.iter().sum::<usize>() == len => {
+                let mut next = start;
+                widths
+                    .into_iter()
+                    .map(|width| {
+                        next += width;
+                        next - width..next
+                    })
+                    .collect()
+            }
+            _ => vec![start.min(len)..len; self.slots.len()],
+        }
+    }
+}
+
+impl<'tcx> FunctionSummary<'tcx> {
+    /// The summary of `def_id`
+    pub fn compute(tcx: TyCtxt<'tcx>, def_id: DefId) -> Self {
+        let sig = EarlyBinder::instantiate_identity(tcx.fn_sig(def_id));
+        let sig = tcx.liberate_late_bound_regions(def_id, sig);
+        let param_env = tcx.param_env(def_id);
+        let inputs: Vec<_> = sig
+            .inputs()
+            .iter()
+            .map(|ty| SignatureSlots::new(*ty, tcx))
+            .collect();
+        let output = SignatureSlots::new(sig.output(), tcx);
+        let bounded = bounded_params(tcx, param_env);
+        let flows = |from: Slot<'tcx>, to: Slot<'tcx>| match (from, to) {
+            (Slot::Region(from), Slot::Region(to)) => outlives_in_param_env(from, to, param_env),
+            (Slot::Region(_), Slot::Type(to)) => bounded.contains(&to.index),
+            (Slot::Type(from), Slot::Region(_)) => bounded.contains(&from.index),
+            (Slot::Type(from), Slot::Type(to)) => {
+                from == to || bounded.contains(&from.index) || bounded.contains(&to.index)
+            }
+            (Slot::Opaque(_), _) | (_, Slot::Opaque(_)) => true,
+        };
+        let sinks = output.sinks();
+        let mut edges = vec![];
+        for (input, slots) in inputs.iter().enumerate() {
+            for (from, from_slot) in slots.sources() {
+                for (to, to_slot) in &sinks {
+                    if flows(from_slot, *to_slot) {
+                        edges.push(SummaryEdge {
+                            input,
+                            from,
+                            to: *to,
+                        });
+                    }
+                }
+            }
+        }
+        FunctionSummary {
+            inputs,
+            output,
+            edges,
+        }
+    }
+
+    /// The edges of the abstraction of a call with arguments `args` (`None`
+    /// for constants), result `destination` and generic arguments
+    /// `callee_args`, by argument index. The arguments are taken at their
+    /// latest snapshot, as given by `latest`.
+    pub fn instantiate(
+        &self,
+        args: &[Option<utils::Place<'tcx>>],
+        destination: Place<'tcx>,
+        callee_args: GenericArgsRef<'tcx>,
+        param_env: ParamEnv<'tcx>,
+        latest: impl Fn(utils::Place<'tcx>) -> SnapshotLocation,
+        repacker: PlaceRepacker<'_, 'tcx>,
+    ) -> Vec<(usize, AbstractionBlockEdge<'tcx>)> {
+        if self.edges.is_empty() {
+            return vec![];
+        }
+        let output_place: MaybeOldPlace<'tcx> =
+            self.output.place(destination.into(), repacker).into();
+        let output_ranges = self
+            .output
+            .ranges(output_place, callee_args, param_env, repacker);
+        let output_projections = output_place.region_projections(repacker);
+        let mut inputs = vec![];
+        for (slots, arg) in self.inputs.iter().zip(args) {
+            inputs.push(arg.map(|place| {
+                let snapshot = latest(place);
+                let place = MaybeOldPlace::OldPlace(PlaceSnapshot::new(
+                    slots.place(place, repacker),
+                    snapshot,
+                ));
+                let ranges = slots.ranges(place, callee_args, param_env, repacker);
+                (place, ranges)
+            }));
+        }
+        let mut edges = vec![];
+        for edge in &self.edges {
+            let Some(Some((input_place, input_ranges))) = inputs.get(edge.input) else {
+                continue;
+            };
+            let sources = match edge.from {
+                SummaryTarget::Pointee => {
+                    vec![AbstractionTarget::Place(
+                        input_place.project_deref(repacker).into(),
+                    )]
+                }
+                SummaryTarget::Slot(idx) => {
+                    let projections = input_place.region_projections(repacker);
+                    input_ranges[idx]
+                        .clone()
+                        .map(|rp_idx| AbstractionTarget::RegionProjection(projections[rp_idx]))
+                        .collect()
+                }
+            };
+            let sinks = match edge.to {
+                SummaryTarget::Pointee => {
+                    vec![AbstractionTarget::Place(
+                        output_place.project_deref(repacker),
+                    )]
+                }
+                SummaryTarget::Slot(idx) => output_ranges[idx]
+                    .clone()
+                    .map(|rp_idx| AbstractionTarget::RegionProjection(output_projections[rp_idx]))
+                    .collect(),
+            };
+            for source in &sources {
+                for sink in &sinks {
+                    let edge = (
+                        edge.input,
+                        AbstractionBlockEdge::new(
+                            HashSet::from([*source]),
+                            vec![*sink].into_iter().collect(),
+                        ),
+                    );
+                    if !edges.contains(&edge) {
+                        edges.push(edge);
+                    }
+                }
+            }
+        }
+        edges
+    }
+}
+
+/// The summaries computed so far, which can be shared between the analyses
+/// of the bodies of a crate
+#[derive(Default)]
+pub struct FunctionSummaries<'tcx> {
+    summaries: RefCell<FxHashMap<DefId, Rc<FunctionSummary<'tcx>>>>,
+}
+
+impl<'tcx> FunctionSummaries<'tcx> {
+    /// The summary of `def_id`, computed on first use
+    pub fn get(&self, tcx: TyCtxt<'tcx>, def_id: DefId) -> Rc<FunctionSummary<'tcx>> {
+        if let Some(summary) = self.summaries.borrow().get(&def_id) {
+            return summary.clone();
+        }
+        let summary = Rc::new(FunctionSummary::compute(tcx, def_id));
+        self.summaries.borrow_mut().insert(def_id, summary.clone());
+        summary
+    }
+}
+
+/// The indices of the type parameters that take part in a trait bound of
+/// `param_env` other than `Sized`, `Copy`, `Clone` or an auto trait. Borrows
+/// can only flow between the values of the other type parameters and the
+/// rest of the signature by moving the values themselves.
+fn bounded_params<'tcx>(tcx: TyCtxt<'tcx>, param_env: ParamEnv<'tcx>) -> Vec<u32> {
+    let mut result = vec![];
+    let mut add_params = |args: GenericArgsRef<'tcx>| {
+        for arg in args.iter().flat_map(|arg| arg.walk()) {
+            if let Some(ty) = arg.as_type() {
+                if let ty::TyKind::Param(param) = ty.kind() {
+                    if !result.contains(&param.index) {
+                        result.push(param.index);
+                    }
+                }
+            }
+        }
+    };
+    for clause in param_env.caller_bounds() {
+        if let Some(trait_clause) = clause.as_trait_clause() {
+            let trait_def_id = trait_clause.def_id();
+            let trivial = [LangItem::Sized, LangItem::Copy, LangItem::Clone]
+                .into_iter()
+                .any(|item| tcx.is_lang_item(trait_def_id, item))
+                || tcx.trait_is_auto(trait_def_id);
+            if !trivial {
+                add_params(trait_clause.skip_binder().trait_ref.args);
+            }
+        } else if let Some(projection_clause) = clause.as_projection_clause() {
+            add_params(projection_clause.skip_binder().projection_term.args);
+        }
+    }
+    result
+}
+
+fn outlives_in_param_env<'tcx>(
+    input_lifetime: ty::Region<'tcx>,
+    output_lifetime: ty::Region<'tcx>,
+    param_env: ParamEnv<'tcx>,
+) -> bool {
+    if input_lifetime == output_lifetime {
+        return true;
+    }
+    for bound in param_env.caller_bounds() {
+        if let Some(outlives) = bound.as_region_outlives_clause() {
+            let outlives = outlives.no_bound_vars().unwrap();
+            if outlives.0 == input_lifetime && outlives.1 == output_lifetime {
+                return true;
+            }
+        }
+    }
+    false
+}
@@ -1,10 +1,18 @@
-use crate::utils::Place;
+use crate::rustc_interface::data_structures::fx::FxHashSet;
+use crate::utils::{Place, PlaceRepacker};
 
 use super::{domain::MaybeOldPlace, latest::Latest};
 
+/// Derives [`HasPcsElems`] from annotations of the fields, see the
+/// documentation of the macro.
+pub use pcs_macros::HasPcsElems;
+
 pub trait HasPcsElems<T> {
     fn pcs_elems(&mut self) -> Vec<&mut T>;
 
+    /// Like [`HasPcsElems::pcs_elems`], but for reading the elements only.
+    fn pcs_elems_ref(&self) -> Vec<&T>;
+
     fn mut_pcs_elems(&mut self, mut f: impl FnMut(&mut T) -> bool) -> bool {
         let mut changed = false;
         for p in self.pcs_elems() {
@@ -14,11 +22,23 @@ pub trait HasPcsElems<T> {
         }
         changed
     }
+}
 
+/// Determines whether a value still refers to a PCS element `T`.
+///
+/// Unlike [`HasPcsElems`], this is computed from the structure of the value
+/// (e.g. the places it blocks), and is therefore used to check that renaming
+/// an element via [`HasPcsElems::pcs_elems`] did not miss any occurrences.
+pub trait ReferencesPcsElem<'tcx, T> {
+    fn references(&self, elem: &T, repacker: PlaceRepacker<'_, 'tcx>) -> bool;
 }
 
 pub trait MakePlaceOld<'tcx> {
     fn make_place_old(&mut self, place: Place<'tcx>, latest: &Latest<'tcx>);
+
+    /// Like [`MakePlaceOld::make_place_old`], but for every place in `places`
+    /// at once, visiting each element only a single time.
+    fn make_places_old(&mut self, places: &FxHashSet<Place<'tcx>>, latest: &Latest<'tcx>);
 }
 
 impl<'tcx, T> MakePlaceOld<'tcx> for T
@@ -30,5 +50,11 @@ where
             p.make_place_old(place, latest);
         }
     }
+
+    fn make_places_old(&mut self, places: &FxHashSet<Place<'tcx>>, latest: &Latest<'tcx>) {
+        for p in self.pcs_elems() {
+            p.make_places_old(places, latest);
+        }
+    }
 }
 
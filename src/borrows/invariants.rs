@@ -0,0 +1,228 @@
+use rustc_interface::{
+    data_structures::fx::{FxHashMap, FxHashSet},
+    middle::mir::{Location, ProjectionElem},
+};
+
+use crate::{rustc_interface, utils::PlaceRepacker};
+
+use super::{
+    borrows_edge::{BorrowsEdge, BorrowsEdgeKind},
+    borrows_graph::BorrowsGraph,
+    domain::{MaybeRemotePlace, ReborrowPhase},
+};
+
+/// How thoroughly [`BorrowsGraph::invariant_violations`] checks the graph.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum InvariantCheckLevel {
+    /// Checks of each edge in isolation. These are cheap enough to run after
+    /// every statement (and are, in debug builds).
+    Structural,
+    /// Additionally checks properties of the graph as a whole, e.g. that it
+    /// is acyclic.
+    Semantic,
+}
+
+impl std::str::FromStr for InvariantCheckLevel {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "structural" => Ok(InvariantCheckLevel::Structural),
+            "semantic" => Ok(InvariantCheckLevel::Semantic),
+            other => Err(format!("Unknown invariant check level: {other}")),
+        }
+    }
+}
+
+/// An invariant of the borrows graph that does not hold at `location`.
+#[derive(Clone, Debug)]
+pub struct InvariantViolation<'tcx> {
+    pub location: Location,
+    /// The offending edge, if the violation concerns a single edge
+    pub edge: Option<BorrowsEdge<'tcx>>,
+    /// The offending place, if there is one
+    pub place: Option<MaybeRemotePlace<'tcx>>,
+    pub description: String,
+}
+
+impl<'tcx> InvariantViolation<'tcx> {
+    pub fn to_short_string(&self, repacker: PlaceRepacker<'_, 'tcx>) -> String {
+        let mut result = format!("{:?}: {}", self.location, self.description);
+        if let Some(place) = self.place {
+            result.push_str(&format!(" (place {})", place_string(place, repacker)));
+        }
+        if let Some(edge) = &self.edge {
+            result.push_str(&format!(
+                " (edge {:?}, created by {})",
+                edge.kind(),
+                edge.provenance()
+            ));
+        }
+        result
+    }
+}
+
+fn place_string<'tcx>(place: MaybeRemotePlace<'tcx>, repacker: PlaceRepacker<'_, 'tcx>) -> String {
+    match place {
+        MaybeRemotePlace::Local(place) => place.place().to_short_string(repacker),
+        MaybeRemotePlace::Remote(_) => format!("{place}"),
+    }
+}
+
+/// Describes a cycle found by [`BorrowsGraph::find_cycle`], listing where
+/// each of its edges was created.
+pub fn cycle_violation(cycle: Vec<BorrowsEdge<'_>>, location: Location) -> InvariantViolation<'_> {
+    let edges: Vec<_> = cycle
+        .iter()
+        .map(|edge| format!("{:?} (created by {})", edge.kind(), edge.provenance()))
+        .collect();
+    InvariantViolation {
+        location,
+        edge: None,
+        place: None,
+        description: format!("cycle of blocking edges: {}", edges.join(" -> ")),
+    }
+}
+
+impl<'tcx> BorrowsGraph<'tcx> {
+    /// All violated invariants of the graph at `location`, checked up to
+    /// `level`.
+    pub fn invariant_violations(
+        &self,
+        level: InvariantCheckLevel,
+        location: Location,
+        repacker: PlaceRepacker<'_, 'tcx>,
+    ) -> Vec<InvariantViolation<'tcx>> {
+        let mut violations = vec![];
+        for edge in self.edges() {
+            violations.extend(edge_invariant_violations(edge, location, repacker));
+        }
+        if level >= InvariantCheckLevel::Semantic {
+            if let Some(cycle) = self.find_cycle(repacker) {
+                violations.push(cycle_violation(cycle, location));
+            }
+        }
+        violations
+    }
+
+    /// Panics, listing every violation, unless all invariants up to `level`
+    /// hold.
+    pub fn assert_invariants_satisfied(
+        &self,
+        level: InvariantCheckLevel,
+        location: Location,
+        repacker: PlaceRepacker<'_, 'tcx>,
+    ) {
+        let violations = self.invariant_violations(level, location, repacker);
+        if !violations.is_empty() {
+            let report: Vec<_> = violations
+                .iter()
+                .map(|v| v.to_short_string(repacker))
+                .collect();
+            panic!("Borrows graph invariants violated:\n{}", report.join("\n"));
+        }
+    }
+
+    /// The edges of a cycle of blocking edges, if there is one, in the order
+    /// in which each edge's blocker is blocked by the next edge.
+    pub fn find_cycle(&self, repacker: PlaceRepacker<'_, 'tcx>) -> Option<Vec<BorrowsEdge<'tcx>>> {
+        let mut blocks: BlocksMap<'_, 'tcx> = FxHashMap::default();
+        for edge in self.edges() {
+            for blocker in edge.blocked_by_places(repacker) {
+                blocks.entry(blocker.into()).or_default().extend(
+                    edge.blocked_places()
+                        .into_iter()
+                        .map(|blocked| (edge, blocked)),
+                );
+            }
+        }
+        let mut finished = FxHashSet::default();
+        for &start in blocks.keys() {
+            let mut path = vec![];
+            if let Some(cycle) = find_cycle_from(start, &blocks, &mut path, &mut finished) {
+                return Some(cycle);
+            }
+        }
+        None
+    }
+}
+
+/// The violated invariants of the graph that concern `edge` alone, see
+/// [`InvariantCheckLevel::Structural`].
+pub fn edge_invariant_violations<'tcx>(
+    edge: &BorrowsEdge<'tcx>,
+    location: Location,
+    repacker: PlaceRepacker<'_, 'tcx>,
+) -> Vec<InvariantViolation<'tcx>> {
+    let mut violations = vec![];
+    let mut violation = |place: Option<MaybeRemotePlace<'tcx>>, description: &str| {
+        violations.push(InvariantViolation {
+            location,
+            edge: Some(edge.clone()),
+            place,
+            description: description.to_string(),
+        })
+    };
+    if !edge.conditions().satisfiable_at(location.block) {
+        violation(None, "conditions are not satisfiable at this block");
+    }
+    let blocked = edge.blocked_places();
+    for place in edge.blocked_by_places(repacker) {
+        if blocked.contains(&place.into()) {
+            violation(Some(place.into()), "edge blocks itself");
+        }
+    }
+    match edge.kind() {
+        BorrowsEdgeKind::Reborrow(reborrow) => {
+            if reborrow.assigned_place.place().projection.last() != Some(&ProjectionElem::Deref) {
+                violation(
+                    Some(reborrow.assigned_place.into()),
+                    "reborrow is not assigned to a dereference",
+                );
+            }
+            if reborrow.phase() == ReborrowPhase::Reserved && reborrow.mutability.is_not() {
+                violation(None, "shared reborrow is reserved");
+            }
+        }
+        BorrowsEdgeKind::DerefExpansion(expansion) => {
+            let base = expansion.base();
+            for place in expansion.expansion(repacker) {
+                if !base.place().is_prefix_exact(place.place()) {
+                    violation(
+                        Some(place.into()),
+                        "expansion is not a direct child of its base",
+                    );
+                }
+            }
+        }
+        BorrowsEdgeKind::Abstraction(_) | BorrowsEdgeKind::RegionProjectionMember(_) => {}
+    }
+    violations
+}
+
+type BlocksMap<'a, 'tcx> =
+    FxHashMap<MaybeRemotePlace<'tcx>, Vec<(&'a BorrowsEdge<'tcx>, MaybeRemotePlace<'tcx>)>>;
+
+/// Depth-first search from `place`, where `path` holds the places and edges
+/// leading to it. Places in `finished` are known not to be on any cycle.
+fn find_cycle_from<'a, 'tcx>(
+    place: MaybeRemotePlace<'tcx>,
+    blocks: &BlocksMap<'a, 'tcx>,
+    path: &mut Vec<(MaybeRemotePlace<'tcx>, &'a BorrowsEdge<'tcx>)>,
+    finished: &mut FxHashSet<MaybeRemotePlace<'tcx>>,
+) -> Option<Vec<BorrowsEdge<'tcx>>> {
+    if finished.contains(&place) {
+        return None;
+    }
+    for &(edge, blocked) in blocks.get(&place).into_iter().flatten() {
+        path.push((place, edge));
+        if let Some(idx) = path.iter().position(|(p, _)| *p == blocked) {
+            return Some(path[idx..].iter().map(|(_, e)| (*e).clone()).collect());
+        }
+        if let Some(cycle) = find_cycle_from(blocked, blocks, path, finished) {
+            return Some(cycle);
+        }
+        path.pop();
+    }
+    finished.insert(place);
+    None
+}
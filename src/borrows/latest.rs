@@ -3,6 +3,13 @@ use std::collections::BTreeMap;
 use crate::rustc_interface::middle::mir::{BasicBlock, Local, Location};
 use crate::utils::{Place, SnapshotLocation};
 
+/// Tracks, for each place, the location at which it was last updated.
+///
+/// Updates are tracked at the granularity of places rather than locals:
+/// updating `x.f` does not affect the latest location of the disjoint place
+/// `x.g`, but does affect `x` (which contains `x.f`). Entries are kept in the
+/// order in which they were inserted, so the most recent update to a place is
+/// the last entry overlapping with it.
 #[derive(Clone, Debug)]
 pub struct Latest<'tcx>(Vec<(Place<'tcx>, SnapshotLocation)>);
 
@@ -11,46 +18,38 @@ impl<'tcx> Latest<'tcx> {
         Self(Vec::new())
     }
 
-    fn get_exact(&self, place: Place<'tcx>) -> Option<SnapshotLocation> {
-        self.0.iter().find(|(p, _)| *p == place).map(|(_, l)| *l)
-    }
     pub fn get_opt(&self, place: Place<'tcx>) -> Option<SnapshotLocation> {
-        if let Some(location) = self.get_exact(place) {
-            Some(location)
-        } else {
-            for (place, _) in place.iter_projections().rev() {
-                if let Some(location) = self.get_exact(place.into()) {
-                    return Some(location);
-                }
-            }
-            None
-        }
+        self.0
+            .iter()
+            .rev()
+            .find(|(p, _)| p.partial_cmp(place).is_some())
+            .map(|(_, l)| *l)
     }
 
+    /// The location of the latest update to `place`. A place that has not
+    /// been updated still holds its value from the entry of the function.
     pub fn get(&self, place: Place<'tcx>) -> SnapshotLocation {
-        self.get_opt(place).unwrap_or(SnapshotLocation::start())
+        self.get_opt(place)
+            .unwrap_or(SnapshotLocation::FunctionEntry)
     }
 
     pub fn insert(&mut self, place: Place<'tcx>, location: SnapshotLocation) {
+        // Updates to places contained in `place` are superseded by this one
         self.0.retain(|(p, _)| !place.is_prefix(*p));
-        for (p, loc) in self.0.iter_mut() {
-            if p.is_prefix(place) {
-                *loc = location;
-            }
-        }
         self.0.push((place, location));
     }
 
     pub fn join(&mut self, other: &Self, block: BasicBlock) -> bool {
         let mut changed = false;
-        for (place, other_loc) in other.0.iter() {
+        for (place, _) in other.0.iter() {
+            let other_loc = other.get(*place);
             if let Some(self_loc) = self.get_opt(*place) {
-                if self_loc != *other_loc {
+                if self_loc != other_loc {
                     self.insert(*place, SnapshotLocation::Join(block));
                     changed = true;
                 }
             } else {
-                self.insert(*place, *other_loc);
+                self.insert(*place, other_loc);
                 changed = true;
             }
         }
@@ -0,0 +1,132 @@
+// © 2023, ETH Zurich
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::HashSet;
+
+use rustc_interface::{
+    ast::NestedMetaItem,
+    data_structures::fx::FxHashMap,
+    middle::mir::{BasicBlock, VarDebugInfoContents},
+    span::Symbol,
+};
+
+use crate::{
+    rustc_interface,
+    utils::{Place, PlaceRepacker},
+};
+
+use super::domain::{AbstractionBlockEdge, AbstractionInputTarget, AbstractionOutputTarget};
+
+/// The loop abstractions given by the `#[pcs::loop_abstraction]` attributes
+/// of the body of `repacker`, by loop head.
+///
+/// These are meant to be passed to
+/// [`crate::combined_pcs::PcsContext::with_loop_abstraction`].
+///
+/// Each attribute adds one edge to the abstraction of the loop whose head is
+/// on the given line, in which the given inputs block the given outputs, e.g.
+/// `#[pcs::loop_abstraction(line = 4, inputs = "*x", outputs = "*r")]`.
+/// Places are written as the name of a variable, preceded by any number of
+/// dereferences, and separated by commas.
+pub fn annotated_loop_abstractions<'tcx>(
+    repacker: PlaceRepacker<'_, 'tcx>,
+) -> Result<FxHashMap<BasicBlock, Vec<AbstractionBlockEdge<'tcx>>>, String> {
+    let tcx = repacker.tcx();
+    let def_id = repacker.body().source.def_id();
+    let mut result: FxHashMap<BasicBlock, Vec<AbstractionBlockEdge<'tcx>>> = FxHashMap::default();
+    for attr in tcx.get_attrs_by_path(
+        def_id,
+        &[Symbol::intern("pcs"), Symbol::intern("loop_abstraction")],
+    ) {
+        let items = attr
+            .meta_item_list()
+            .ok_or("expected `loop_abstraction(line = .., inputs = \"..\", outputs = \"..\")`")?;
+        let value = |name: &str| {
+            items
+                .iter()
+                .find(|item| item.name_or_empty().as_str() == name)
+                .and_then(NestedMetaItem::meta_item)
+                .and_then(|item| item.name_value_literal())
+                .map(|lit| lit.symbol.to_string())
+                .ok_or(format!("missing `{name}` in loop abstraction"))
+        };
+        let line = value("line")?;
+        let line = line
+            .parse()
+            .map_err(|_| format!("invalid line `{line}` in loop abstraction"))?;
+        let loop_head = loop_head_on_line(repacker, line)?;
+        let inputs = parse_places(repacker, &value("inputs")?)?
+            .into_iter()
+            .map(|place| AbstractionInputTarget::Place(place.into()))
+            .collect();
+        let outputs = parse_places(repacker, &value("outputs")?)?
+            .into_iter()
+            .map(|place| AbstractionOutputTarget::Place(place.into()))
+            .collect::<HashSet<_>>();
+        result
+            .entry(loop_head)
+            .or_default()
+            .push(AbstractionBlockEdge::new(inputs, outputs));
+    }
+    Ok(result)
+}
+
+/// The head of the only loop of the body whose head is on `line`
+fn loop_head_on_line(repacker: PlaceRepacker<'_, '_>, line: usize) -> Result<BasicBlock, String> {
+    let body = repacker.body();
+    let source_map = repacker.tcx().sess.source_map();
+    let mut heads: Vec<BasicBlock> = body
+        .basic_blocks
+        .indices()
+        .filter(|&head| {
+            body.basic_blocks.predecessors()[head]
+                .iter()
+                .any(|&pred| repacker.is_back_edge(pred, head))
+                && source_map
+                    .lookup_char_pos(body[head].terminator().source_info.span.lo())
+                    .line
+                    == line
+        })
+        .collect();
+    match heads.len() {
+        1 => Ok(heads.pop().unwrap()),
+        0 => Err(format!("no loop head on line {line}")),
+        _ => Err(format!("several loop heads on line {line}: {heads:?}")),
+    }
+}
+
+/// Parses a comma-separated list of places, each the name of a variable
+/// preceded by any number of `*`
+fn parse_places<'tcx>(
+    repacker: PlaceRepacker<'_, 'tcx>,
+    places: &str,
+) -> Result<Vec<Place<'tcx>>, String> {
+    places
+        .split(',')
+        .map(|place| {
+            let place = place.trim();
+            let name = place.trim_start_matches('*');
+            let local = repacker
+                .body()
+                .var_debug_info
+                .iter()
+                .find_map(|info| match info.value {
+                    VarDebugInfoContents::Place(place)
+                        if info.name.as_str() == name && place.projection.is_empty() =>
+                    {
+                        Some(place.local)
+                    }
+                    _ => None,
+                })
+                .ok_or(format!("no variable `{name}` in loop abstraction"))?;
+            let mut result: Place<'tcx> = local.into();
+            for _ in 0..place.len() - name.len() {
+                result = result.project_deref(repacker);
+            }
+            Ok(result)
+        })
+        .collect()
+}
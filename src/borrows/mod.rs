@@ -5,9 +5,16 @@ pub mod borrows_visitor;
 pub mod coupling_graph_constructor;
 pub mod deref_expansion;
 pub mod domain;
+pub mod edge_set;
 pub mod engine;
+pub mod external_abstractions;
+pub mod function_summary;
+pub mod invariants;
 pub mod latest;
+pub mod loop_annotations;
+pub mod mutation_log;
 pub mod path_condition;
+pub mod projection_summary;
 pub mod region_abstraction;
 pub mod region_projection;
 pub mod region_projection_member;
@@ -0,0 +1,133 @@
+use std::rc::Rc;
+
+use crate::utils::SnapshotLocation;
+
+use super::borrows_edge::BorrowsEdge;
+
+/// A single change made to the edges of a [`super::borrows_graph::BorrowsGraph`].
+#[derive(Clone, Debug)]
+pub enum GraphMutation<'tcx> {
+    Insert(BorrowsEdge<'tcx>),
+    Remove(BorrowsEdge<'tcx>),
+    /// The edge `from` was replaced by `to`, e.g. because a place in it was
+    /// renamed or made old, or its path conditions changed
+    Update {
+        from: BorrowsEdge<'tcx>,
+        to: BorrowsEdge<'tcx>,
+    },
+}
+
+impl<'tcx> GraphMutation<'tcx> {
+    /// Whether this mutation added, removed or modified `edge`
+    pub fn affects(&self, edge: &BorrowsEdge<'tcx>) -> bool {
+        match self {
+            GraphMutation::Insert(e) | GraphMutation::Remove(e) => e == edge,
+            GraphMutation::Update { from, to } => from == edge || to == edge,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MutationLogEntry<'tcx> {
+    location: Option<SnapshotLocation>,
+    mutation: GraphMutation<'tcx>,
+}
+
+impl<'tcx> MutationLogEntry<'tcx> {
+    /// The point in the analysis at which the mutation was made, if known
+    pub fn location(&self) -> Option<SnapshotLocation> {
+        self.location
+    }
+
+    pub fn mutation(&self) -> &GraphMutation<'tcx> {
+        &self.mutation
+    }
+}
+
+struct Node<'tcx> {
+    entry: MutationLogEntry<'tcx>,
+    prev: Option<Rc<Node<'tcx>>>,
+}
+
+/// A record of the mutations made to a graph, in the order they were made.
+///
+/// Cloning a log is cheap: clones share the entries recorded before they were
+/// made. Thus, the log of a graph contains the history of the graph it was
+/// cloned from (e.g. the state of a predecessor block), followed by its own.
+#[derive(Clone, Default)]
+pub struct MutationLog<'tcx> {
+    last: Option<Rc<Node<'tcx>>>,
+    len: usize,
+    location: Option<SnapshotLocation>,
+}
+
+impl<'tcx> MutationLog<'tcx> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the location attached to subsequently recorded mutations
+    pub fn set_location(&mut self, location: SnapshotLocation) {
+        self.location = Some(location);
+    }
+
+    pub fn record(&mut self, mutation: GraphMutation<'tcx>) {
+        self.last = Some(Rc::new(Node {
+            entry: MutationLogEntry {
+                location: self.location,
+                mutation,
+            },
+            prev: self.last.take(),
+        }));
+        self.len += 1;
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The recorded entries, oldest first
+    pub fn entries(&self) -> Vec<&MutationLogEntry<'tcx>> {
+        let mut entries = Vec::with_capacity(self.len);
+        let mut node = self.last.as_deref();
+        while let Some(n) = node {
+            entries.push(&n.entry);
+            node = n.prev.as_deref();
+        }
+        entries.reverse();
+        entries
+    }
+
+    /// The entries that added, removed or modified `edge`, oldest first
+    pub fn history(&self, edge: &BorrowsEdge<'tcx>) -> Vec<&MutationLogEntry<'tcx>> {
+        self.entries()
+            .into_iter()
+            .filter(|entry| entry.mutation.affects(edge))
+            .collect()
+    }
+}
+
+impl<'tcx> std::fmt::Debug for MutationLog<'tcx> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MutationLog")
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl<'tcx> Drop for MutationLog<'tcx> {
+    // Drop the nodes iteratively to avoid overflowing the stack on long logs
+    fn drop(&mut self) {
+        let mut node = self.last.take();
+        while let Some(n) = node {
+            node = match Rc::try_unwrap(n) {
+                Ok(mut n) => n.prev.take(),
+                Err(_) => None,
+            };
+        }
+    }
+}
@@ -1,13 +1,17 @@
-use std::collections::BTreeSet;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+};
 
+#[cfg(feature = "json")]
 use serde_json::json;
 
-use crate::{
-    rustc_interface::middle::mir::{BasicBlock, BasicBlocks},
-    utils::PlaceRepacker,
-};
+use crate::rustc_interface::middle::mir::{BasicBlock, BasicBlocks};
 
+#[cfg(feature = "json")]
 use super::domain::ToJsonWithRepacker;
+#[cfg(feature = "json")]
+use crate::utils::PlaceRepacker;
 
 #[derive(Copy, PartialEq, Eq, Clone, Hash, PartialOrd, Ord, Debug)]
 pub struct PathCondition {
@@ -42,12 +46,24 @@ impl Path {
     }
 }
 
+/// A set of branch conditions.
+///
+/// The conditions are stored as an adjacency map from each block to the
+/// successors that must have been taken from it. The map is ordered, so two
+/// graphs containing the same conditions are always structurally equal.
+///
+/// The block in which the conditioned edge was created is stored explicitly
+/// as the root of the graph, since it cannot be recovered from the conditions
+/// once they contain cycles (e.g. when the edge lives across a loop).
 #[derive(PartialEq, Eq, Clone, Hash, PartialOrd, Ord, Debug)]
-pub struct PCGraph(BTreeSet<PathCondition>);
+pub struct PCGraph {
+    root: BasicBlock,
+    edges: BTreeMap<BasicBlock, BTreeSet<BasicBlock>>,
+}
 
 impl std::fmt::Display for PCGraph {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for pc in self.0.iter() {
+        for pc in self.conditions() {
             write!(f, "{:?} -> {:?},", pc.from, pc.to)?;
         }
         Ok(())
@@ -55,28 +71,53 @@ impl std::fmt::Display for PCGraph {
 }
 
 impl PCGraph {
-    pub fn root(&self) -> Option<BasicBlock> {
-        self.0
+    /// The conditions in the graph, ordered by source and then target block
+    pub fn conditions(&self) -> impl Iterator<Item = PathCondition> + '_ {
+        self.edges
             .iter()
-            .find(|pc| !self.has_path_to_block(pc.from))
-            .map(|pc| pc.from)
+            .flat_map(|(from, tos)| tos.iter().map(|to| PathCondition::new(*from, *to)))
+    }
+
+    pub fn contains(&self, pc: PathCondition) -> bool {
+        self.edges
+            .get(&pc.from)
+            .map_or(false, |tos| tos.contains(&pc.to))
+    }
+
+    /// Returns true iff every condition in `self` is also in `other`
+    pub fn is_subgraph_of(&self, other: &Self) -> bool {
+        self.edges.iter().all(|(from, tos)| {
+            other
+                .edges
+                .get(from)
+                .map_or(false, |other_tos| tos.is_subset(other_tos))
+        })
+    }
+
+    /// The block in which the conditions start
+    pub fn root(&self) -> BasicBlock {
+        self.root
     }
 
     pub fn end(&self) -> Option<BasicBlock> {
-        self.0
-            .iter()
+        self.conditions()
             .find(|pc| !self.has_path_from_block(pc.to))
             .map(|pc| pc.to)
     }
 
     pub fn singleton(pc: PathCondition) -> Self {
-        Self(BTreeSet::from([pc]))
+        let mut graph = Self {
+            root: pc.from,
+            edges: BTreeMap::new(),
+        };
+        graph.insert(pc);
+        graph
     }
 
     pub fn join(&mut self, other: &Self) -> bool {
         let mut changed = false;
-        for pc in other.0.iter() {
-            if self.insert(*pc) {
+        for pc in other.conditions() {
+            if self.insert(pc) {
                 changed = true;
             }
         }
@@ -84,43 +125,105 @@ impl PCGraph {
     }
 
     pub fn has_path_to_block(&self, block: BasicBlock) -> bool {
-        self.0.iter().any(|pc| pc.to == block)
+        self.edges.values().any(|tos| tos.contains(&block))
     }
 
     pub fn has_path_from_block(&self, block: BasicBlock) -> bool {
-        self.0.iter().any(|pc| pc.from == block)
+        self.edges.contains_key(&block)
     }
 
+    /// Returns true iff some suffix of `path` starting at the root consists
+    /// only of branches in this graph.
+    ///
+    /// The path may contain repeated blocks, e.g. when it iterates through a
+    /// loop several times. In that case the root may occur more than once, and
+    /// the conditions only need to hold after its last occurrence. If the root
+    /// does not occur on the path at all, every branch of the path must be in
+    /// the graph.
     pub fn has_suffix_of(&self, path: &[BasicBlock]) -> bool {
-        let path = if let Some(root) = self.root() {
-            let root_idx = path.iter().position(|b| *b == root).unwrap_or(0);
-            &path[root_idx..]
-        } else {
-            path
+        let Some(mut start) = path.len().checked_sub(1) else {
+            return true;
         };
-        let mut i = 0;
-        while i < path.len() - 1 {
-            let f = path[i];
-            let t = path[i + 1];
-            if !self.0.contains(&PathCondition::new(f, t)) {
+        while path[start] != self.root {
+            if start == 0 {
+                return true;
+            }
+            if !self.contains(PathCondition::new(path[start - 1], path[start])) {
                 return false;
             }
-            i += 1
+            start -= 1;
         }
         true
     }
 
     pub fn insert(&mut self, pc: PathCondition) -> bool {
-        self.0.insert(pc)
+        self.edges.entry(pc.from).or_default().insert(pc.to)
+    }
+
+    /// Returns true iff `block` is the root, or is reachable from the root
+    /// via branches in this graph.
+    pub fn reaches(&self, block: BasicBlock) -> bool {
+        let mut visited = BTreeSet::new();
+        let mut stack = vec![self.root];
+        while let Some(current) = stack.pop() {
+            if current == block {
+                return true;
+            }
+            if visited.insert(current) {
+                if let Some(tos) = self.edges.get(&current) {
+                    stack.extend(tos.iter().copied());
+                }
+            }
+        }
+        false
+    }
+
+    /// Returns true if every non-unwinding path from `from` eventually reaches
+    /// `to` only via branches in this graph.
+    pub fn covers_all_paths(
+        &self,
+        from: BasicBlock,
+        to: BasicBlock,
+        blocks: &BasicBlocks<'_>,
+    ) -> bool {
+        let mut visited = BTreeSet::new();
+        let mut stack = vec![from];
+        while let Some(block) = stack.pop() {
+            if block == to || !visited.insert(block) {
+                continue;
+            }
+            let mut succs = blocks[block]
+                .terminator()
+                .successors()
+                .filter(|succ| !blocks[*succ].is_cleanup)
+                .peekable();
+            if succs.peek().is_none() {
+                // The body can return without reaching `to`
+                return false;
+            }
+            for succ in succs {
+                if !self.contains(PathCondition::new(block, succ)) {
+                    return false;
+                }
+                stack.push(succ);
+            }
+        }
+        true
     }
 }
 
+/// The conditions under which an edge in the borrows graph is valid.
+///
+/// The graphs of `Paths` are shared between clones of the same conditions,
+/// and are only copied when they are modified. Both variants are kept in
+/// canonical form, so equal conditions are always structurally equal.
 #[derive(PartialEq, Eq, Clone, Hash, PartialOrd, Ord, Debug)]
 pub enum PathConditions {
     AtBlock(BasicBlock),
-    Paths(PCGraph),
+    Paths(Rc<PCGraph>),
 }
 
+#[cfg(feature = "json")]
 impl<'tcx> ToJsonWithRepacker<'tcx> for PathConditions {
     fn to_json(&self, _repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value {
         match self {
@@ -130,7 +233,7 @@ impl<'tcx> ToJsonWithRepacker<'tcx> for PathConditions {
             }),
             PathConditions::Paths(p) => json!({
                 "type": "Paths",
-                "paths": p.0.iter().map(|pc| format!("{:?} -> {:?}", pc.from, pc.to)).collect::<Vec<_>>()
+                "paths": p.conditions().map(|pc| format!("{:?} -> {:?}", pc.from, pc.to)).collect::<Vec<_>>()
             }),
         }
     }
@@ -153,7 +256,7 @@ impl PathConditions {
     pub fn root(&self) -> Option<BasicBlock> {
         match self {
             PathConditions::AtBlock(b) => Some(*b),
-            PathConditions::Paths(p) => p.root(),
+            PathConditions::Paths(p) => Some(p.root()),
         }
     }
 
@@ -178,13 +281,29 @@ impl PathConditions {
         }
     }
 
+    /// Whether [`PathConditions::join`] can represent the union of `self` and
+    /// `other`, i.e. they are conditions on paths from the same block
+    pub fn can_join(&self, other: &Self) -> bool {
+        match (self, other) {
+            (PathConditions::AtBlock(b1), PathConditions::AtBlock(b2)) => b1 == b2,
+            (PathConditions::Paths(p1), PathConditions::Paths(p2)) => p1.root() == p2.root(),
+            _ => false,
+        }
+    }
+
     pub fn join(&mut self, other: &Self) -> bool {
         match (self, other) {
             (PathConditions::AtBlock(b1), PathConditions::AtBlock(b2)) => {
                 assert!(*b1 == *b2);
                 false
             }
-            (PathConditions::Paths(p1), PathConditions::Paths(p2)) => p1.join(p2),
+            (PathConditions::Paths(p1), PathConditions::Paths(p2)) => {
+                if p2.is_subgraph_of(p1) {
+                    false
+                } else {
+                    Rc::make_mut(p1).join(p2)
+                }
+            }
             (PathConditions::AtBlock(_b), PathConditions::Paths(_p)) => false, // TODO: check
             (PathConditions::Paths(_p), PathConditions::AtBlock(_b)) => false, // TODO: check
         }
@@ -194,10 +313,28 @@ impl PathConditions {
         match self {
             PathConditions::AtBlock(b) => {
                 assert!(*b == pc.from);
-                *self = PathConditions::Paths(PCGraph::singleton(pc));
+                *self = PathConditions::Paths(Rc::new(PCGraph::singleton(pc)));
                 true
             }
-            PathConditions::Paths(p) => p.insert(pc),
+            PathConditions::Paths(p) => {
+                if p.contains(pc) {
+                    false
+                } else {
+                    Rc::make_mut(p).insert(pc)
+                }
+            }
+        }
+    }
+
+    /// Returns true if some path reaching `block` satisfies these conditions.
+    ///
+    /// The join extends the conditions of every edge along the branch it
+    /// takes, so the conditions of the edges in the state at a block always
+    /// reach it, see [`crate::borrows::invariants::edge_invariant_violations`].
+    pub fn satisfiable_at(&self, block: BasicBlock) -> bool {
+        match self {
+            PathConditions::AtBlock(b) => *b == block,
+            PathConditions::Paths(p) => p.reaches(block),
         }
     }
 
@@ -207,4 +344,39 @@ impl PathConditions {
             PathConditions::Paths(p) => p.has_suffix_of(path),
         }
     }
+
+    /// Returns true if every path in the body from `from` to `to` that does
+    /// not unwind satisfies these conditions, i.e. if the conditions include
+    /// every branch that can be taken between the two blocks.
+    pub fn covers_all_paths(
+        &self,
+        from: BasicBlock,
+        to: BasicBlock,
+        blocks: &BasicBlocks<'_>,
+    ) -> bool {
+        match self {
+            PathConditions::AtBlock(b) => *b == from && from == to,
+            PathConditions::Paths(p) => p.covers_all_paths(from, to, blocks),
+        }
+    }
+
+    /// Returns true if every path satisfying `self` also satisfies `other`.
+    ///
+    /// This check is conservative: it may return false for conditions that
+    /// are only equivalent under the control flow of the body.
+    pub fn implies(&self, other: &Self) -> bool {
+        match (self, other) {
+            (PathConditions::AtBlock(b1), PathConditions::AtBlock(b2)) => b1 == b2,
+            (PathConditions::Paths(p1), PathConditions::Paths(p2)) => {
+                Rc::ptr_eq(p1, p2) || (p1.root() == p2.root() && p1.is_subgraph_of(p2))
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns true if `self` and `other` are satisfied by the same paths,
+    /// even if they are represented differently.
+    pub fn equivalent(&self, other: &Self) -> bool {
+        self.implies(other) && other.implies(self)
+    }
 }
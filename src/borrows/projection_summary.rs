@@ -0,0 +1,185 @@
+// © 2023, ETH Zurich
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Bounds the expansion of the borrows graph through recursive data
+//! structures, see [`crate::combined_pcs::PcsContext::projection_depth_limit`].
+//!
+//! Instead of expanding a place whose number of dereferences exceeds the
+//! limit, the graph gets an abstraction edge of kind
+//! [`ProjectionDepthSummary`], by which the place blocks its ancestor at the
+//! limit. The intermediate places are not tracked, which is reported as a
+//! [`PrecisionLoss`].
+
+use std::{collections::HashSet, rc::Rc};
+
+use rustc_interface::middle::mir::{Location, ProjectionElem};
+
+use crate::{
+    rustc_interface,
+    utils::{Place, PlaceRepacker},
+    FpcsOutput,
+};
+
+use super::{
+    borrows_edge::{BorrowsEdgeKind, EdgeProvenance, ToBorrowsEdge},
+    borrows_graph::BorrowsGraph,
+    domain::{
+        AbstractionBlockEdge, AbstractionTarget, AbstractionType, ExternalAbstraction,
+        ExternalAbstractionKind, MaybeOldPlace,
+    },
+    path_condition::PathConditions,
+};
+
+/// The key of [`ProjectionDepthSummary`]
+pub const PROJECTION_DEPTH_SUMMARY: &str = "projection-depth-summary";
+
+/// The kind of the abstractions summarizing the dereferences beyond the
+/// projection depth limit
+#[derive(Debug)]
+pub struct ProjectionDepthSummary;
+
+impl ExternalAbstractionKind for ProjectionDepthSummary {
+    fn key(&self) -> String {
+        PROJECTION_DEPTH_SUMMARY.to_string()
+    }
+
+    fn name(&self) -> String {
+        "depth-summary".to_string()
+    }
+}
+
+/// The number of dereferences in the projection of `place`
+pub fn deref_depth(place: Place<'_>) -> usize {
+    place
+        .projection
+        .iter()
+        .filter(|elem| matches!(elem, ProjectionElem::Deref))
+        .count()
+}
+
+impl<'tcx> ExternalAbstraction<'tcx> {
+    /// Whether this abstraction summarizes the dereferences beyond the
+    /// projection depth limit
+    pub fn is_projection_summary(&self) -> bool {
+        self.kind().key() == PROJECTION_DEPTH_SUMMARY
+    }
+}
+
+impl<'tcx> BorrowsGraph<'tcx> {
+    /// Makes `place` block `summarized`, its ancestor at the projection depth
+    /// limit, unless it already does. Returns the inserted abstraction.
+    pub(crate) fn insert_projection_summary(
+        &mut self,
+        summarized: Place<'tcx>,
+        place: Place<'tcx>,
+        location: Location,
+        repacker: PlaceRepacker<'_, 'tcx>,
+    ) -> Option<ExternalAbstraction<'tcx>> {
+        let summarized: MaybeOldPlace<'tcx> = summarized.into();
+        let place: MaybeOldPlace<'tcx> = place.into();
+        let exists = self.edges().any(|edge| match edge.kind() {
+            BorrowsEdgeKind::Abstraction(abstraction) => match &abstraction.abstraction_type {
+                AbstractionType::External(external) => {
+                    external.is_projection_summary()
+                        && external.edges().iter().any(|edge| {
+                            edge.inputs() == [AbstractionTarget::Place(summarized.into())]
+                                && edge.outputs() == [AbstractionTarget::Place(place)]
+                        })
+                }
+                _ => false,
+            },
+            _ => false,
+        });
+        if exists {
+            return None;
+        }
+        let abstraction = ExternalAbstraction::new(
+            Rc::new(ProjectionDepthSummary),
+            location,
+            vec![AbstractionBlockEdge::new(
+                HashSet::from([AbstractionTarget::Place(summarized.into())]),
+                HashSet::from([AbstractionTarget::Place(place)]),
+            )],
+        );
+        self.insert(
+            abstraction
+                .clone()
+                .to_borrows_edge(PathConditions::new(location.block))
+                .with_provenance(EdgeProvenance::at(
+                    format!(
+                        "projection depth limit at {}",
+                        summarized.to_short_string(repacker)
+                    ),
+                    location,
+                )),
+        );
+        Some(abstraction)
+    }
+}
+
+/// The places between `summarized` and `place` are not tracked, because
+/// `summarized` is at the projection depth limit
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PrecisionLoss<'tcx> {
+    /// Where the summary was inserted
+    pub location: Location,
+    /// The place at the limit
+    pub summarized: MaybeOldPlace<'tcx>,
+    /// The place that was accessed
+    pub place: MaybeOldPlace<'tcx>,
+    /// The number of dereferences in `summarized`
+    pub depth: usize,
+}
+
+impl<'mir, 'tcx> FpcsOutput<'mir, 'tcx> {
+    /// The places that were summarized rather than expanded because of the
+    /// projection depth limit, each reported once, in the order of the
+    /// locations where they were summarized
+    pub fn precision_losses(&mut self) -> Vec<PrecisionLoss<'tcx>> {
+        let body = self.repacker().body();
+        let mut losses = vec![];
+        for block in body.basic_blocks.indices() {
+            for stmt in self.get_all_for_bb(block).statements {
+                for edge in stmt.extra.after.graph().sorted_edges() {
+                    let BorrowsEdgeKind::Abstraction(abstraction) = edge.kind() else {
+                        continue;
+                    };
+                    let AbstractionType::External(external) = &abstraction.abstraction_type else {
+                        continue;
+                    };
+                    if !external.is_projection_summary() {
+                        continue;
+                    }
+                    for edge in external.edges() {
+                        for (input, output) in edge.inputs().iter().zip(edge.outputs()) {
+                            let (
+                                AbstractionTarget::Place(summarized),
+                                AbstractionTarget::Place(place),
+                            ) = (input, output)
+                            else {
+                                continue;
+                            };
+                            let Some(summarized) = summarized.as_local_place() else {
+                                continue;
+                            };
+                            let loss = PrecisionLoss {
+                                location: external.location(),
+                                summarized,
+                                place: *place,
+                                depth: deref_depth(summarized.place()),
+                            };
+                            if !losses.contains(&loss) {
+                                losses.push(loss);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        losses.sort_by_key(|loss| loss.location);
+        losses
+    }
+}
@@ -1,26 +1,27 @@
-use rustc_interface::{data_structures::fx::FxHashSet, middle::mir::Location};
+use rustc_interface::middle::mir::Location;
 
-use crate::rustc_interface;
+#[cfg(feature = "json")]
+use crate::utils::PlaceRepacker;
+use crate::{rustc_interface, utils::SmallSet};
 
+#[cfg(feature = "json")]
+use super::domain::ToJsonWithRepacker;
 use super::{
     domain::{
         AbstractionBlockEdge, AbstractionInputTarget, AbstractionOutputTarget, AbstractionType,
         MaybeOldPlace, MaybeRemotePlace,
     },
     has_pcs_elem::HasPcsElems,
+    region_projection::RegionProjection,
 };
 
-#[derive(PartialEq, Eq, Clone, Debug, Hash)]
+#[derive(PartialEq, Eq, Clone, Debug, Hash, HasPcsElems)]
+#[pcs_elems(MaybeOldPlace<'tcx>, RegionProjection<'tcx>)]
 pub struct AbstractionEdge<'tcx> {
+    #[pcs_elems]
     pub abstraction_type: AbstractionType<'tcx>,
 }
 
-impl<'tcx> HasPcsElems<MaybeOldPlace<'tcx>> for AbstractionEdge<'tcx> {
-    fn pcs_elems(&mut self) -> Vec<&mut MaybeOldPlace<'tcx>> {
-        self.abstraction_type.pcs_elems()
-    }
-}
-
 impl<'tcx> AbstractionEdge<'tcx> {
     pub fn new(abstraction_type: AbstractionType<'tcx>) -> Self {
         Self { abstraction_type }
@@ -30,11 +31,11 @@ impl<'tcx> AbstractionEdge<'tcx> {
         self.abstraction_type.location()
     }
 
-    pub fn inputs(&self) -> Vec<AbstractionInputTarget<'tcx>> {
+    pub fn inputs(&self) -> impl Iterator<Item = &AbstractionInputTarget<'tcx>> + '_ {
         self.abstraction_type.inputs()
     }
 
-    pub fn outputs(&self) -> Vec<AbstractionOutputTarget<'tcx>> {
+    pub fn outputs(&self) -> impl Iterator<Item = &AbstractionOutputTarget<'tcx>> + '_ {
         self.abstraction_type.outputs()
     }
 
@@ -42,15 +43,22 @@ impl<'tcx> AbstractionEdge<'tcx> {
         self.abstraction_type.blocks(place)
     }
 
-    pub fn blocks_places(&self) -> FxHashSet<MaybeRemotePlace<'tcx>> {
+    pub fn blocks_places(&self) -> SmallSet<MaybeRemotePlace<'tcx>> {
         self.abstraction_type.blocks_places()
     }
 
-    pub fn blocked_by_places(&self) -> FxHashSet<MaybeOldPlace<'tcx>> {
+    pub fn blocked_by_places(&self) -> SmallSet<MaybeOldPlace<'tcx>> {
         self.abstraction_type.blocker_places()
     }
 
-    pub fn edges(&self) -> Vec<AbstractionBlockEdge<'tcx>> {
+    pub fn edges(&self) -> impl Iterator<Item = &AbstractionBlockEdge<'tcx>> + '_ {
         self.abstraction_type.edges()
     }
 }
+
+#[cfg(feature = "json")]
+impl<'tcx> ToJsonWithRepacker<'tcx> for AbstractionEdge<'tcx> {
+    fn to_json(&self, repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value {
+        self.abstraction_type.to_json(repacker)
+    }
+}
@@ -1,16 +1,58 @@
 use std::{backtrace, fmt};
 
-use crate::rustc_interface::{data_structures::fx::FxHashSet, middle::ty::RegionVid};
+#[cfg(feature = "json")]
+use serde_json::json;
+
+use crate::rustc_interface::{
+    ast::Mutability,
+    data_structures::fx::FxHashSet,
+    middle::{
+        mir::ProjectionElem,
+        ty::{self, GenericArgKind, RegionVid, TyCtxt, Variance},
+    },
+};
 
 use crate::utils::{Place, PlaceRepacker};
 
+#[cfg(feature = "json")]
+use super::domain::ToJsonWithRepacker;
 use super::has_pcs_elem::HasPcsElems;
-use super::{domain::MaybeOldPlace, latest::Latest};
+use super::{borrows_visitor::extract_lifetimes, domain::MaybeOldPlace, latest::Latest};
 
+/// Identifies a region projection independently of the place it is currently
+/// attached to.
+///
+/// The identity is fixed when the projection is created, and is preserved
+/// when the place of the projection is renamed or made old.
 #[derive(PartialEq, Eq, Clone, Debug, Hash, Copy)]
+pub struct RegionProjectionId<'tcx> {
+    pub region: RegionVid,
+    /// The place the projection was created for
+    pub origin: MaybeOldPlace<'tcx>,
+}
+
+#[derive(Clone, Debug, Copy)]
 pub struct RegionProjection<'tcx> {
     pub place: MaybeOldPlace<'tcx>,
     region: RegionVid,
+    origin: MaybeOldPlace<'tcx>,
+}
+
+// The origin is only used to track the projection across renames: projections
+// of the same region of the same place are equal.
+impl<'tcx> PartialEq for RegionProjection<'tcx> {
+    fn eq(&self, other: &Self) -> bool {
+        self.place == other.place && self.region == other.region
+    }
+}
+
+impl<'tcx> Eq for RegionProjection<'tcx> {}
+
+impl<'tcx> std::hash::Hash for RegionProjection<'tcx> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.place.hash(state);
+        self.region.hash(state);
+    }
 }
 
 impl<'tcx> fmt::Display for RegionProjection<'tcx> {
@@ -21,36 +63,110 @@ impl<'tcx> fmt::Display for RegionProjection<'tcx> {
 
 impl<'tcx> RegionProjection<'tcx> {
     pub fn new(region: RegionVid, place: MaybeOldPlace<'tcx>) -> Self {
-        Self { place, region }
+        Self {
+            place,
+            region,
+            origin: place,
+        }
+    }
+
+    pub fn id(&self) -> RegionProjectionId<'tcx> {
+        RegionProjectionId {
+            region: self.region,
+            origin: self.origin,
+        }
     }
 
     pub fn make_place_old(&mut self, place: Place<'tcx>, latest: &Latest<'tcx>) {
         self.place.make_place_old(place, latest);
     }
 
-    pub fn index(&self, repacker: PlaceRepacker<'_, 'tcx>) -> usize {
-        self.place
-            .place()
-            .projection_index(self.region, repacker)
-            .unwrap()
+    /// The projection of `place` that corresponds to this projection, e.g.
+    /// because the place of this projection was renamed to `place`. The
+    /// result has the same [`RegionProjectionId`] as `self`.
+    ///
+    /// If the type of `place` mentions the region of this projection, that
+    /// region is used. Otherwise (e.g. the type of `place` was normalized
+    /// differently) the region at the same position in the type is used.
+    pub fn with_place(
+        &self,
+        place: MaybeOldPlace<'tcx>,
+        repacker: PlaceRepacker<'_, 'tcx>,
+    ) -> Option<RegionProjection<'tcx>> {
+        let projections = place.region_projections(repacker);
+        let projection = match projections.iter().find(|rp| rp.region == self.region) {
+            Some(projection) => *projection,
+            None => {
+                let idx = self
+                    .place
+                    .region_projections(repacker)
+                    .iter()
+                    .position(|rp| rp.region == self.region)?;
+                *projections.get(idx)?
+            }
+        };
+        Some(RegionProjection {
+            origin: self.origin,
+            ..projection
+        })
     }
 
     pub fn region(&self) -> RegionVid {
         self.region
     }
 
+    /// Connects the region projections of `source` to those of `dest`, where
+    /// `dest` is a projection of `source` (e.g. `*x` for `x`).
+    ///
+    /// The type of `dest` is part of the type of `source`, so each region of
+    /// `dest` corresponds to the region at the same position in that part of
+    /// the type of `source`. For example, for `x: &'a mut &'b mut &'c mut T`
+    /// the projections of `*x` are connected to the `'b` and `'c` projections
+    /// of `x`. The part is found by matching regions, or is otherwise assumed
+    /// to be the innermost part of the type.
     pub fn connections_between_places(
         source: MaybeOldPlace<'tcx>,
         dest: MaybeOldPlace<'tcx>,
         repacker: PlaceRepacker<'_, 'tcx>,
     ) -> FxHashSet<(RegionProjection<'tcx>, RegionProjection<'tcx>)> {
-        let mut edges = FxHashSet::default();
-        for rp in source.region_projections(repacker) {
-            for erp in dest.region_projections(repacker) {
-                edges.insert((rp, erp));
-            }
-        }
-        edges
+        let sources = source.region_projections(repacker);
+        let dests = dest.region_projections(repacker);
+        let Some(max_offset) = sources.len().checked_sub(dests.len()) else {
+            // `dest` has regions that `source` does not, we cannot tell how
+            // they correspond
+            return sources
+                .iter()
+                .flat_map(|rp| dests.iter().map(move |erp| (*rp, *erp)))
+                .collect();
+        };
+        let offset = (0..=max_offset)
+            .find(|&offset| {
+                sources[offset..]
+                    .iter()
+                    .zip(dests.iter())
+                    .all(|(rp, erp)| rp.region == erp.region)
+            })
+            .unwrap_or(max_offset);
+        // Through a shared reference, the regions of the referent in covariant
+        // positions cannot be changed, so connecting them would only add
+        // spurious constraints
+        let non_covariant = if dest.place().projection.last() == Some(&ProjectionElem::Deref)
+            && let ty::TyKind::Ref(_, referent, Mutability::Not) =
+                source.place().ty(repacker).ty.kind()
+        {
+            Some(non_covariant_regions(*referent, repacker.tcx()))
+        } else {
+            None
+        };
+        sources[offset..]
+            .iter()
+            .copied()
+            .zip(dests.iter().copied())
+            .filter(|(rp, _)| match &non_covariant {
+                Some(regions) => regions.contains(&rp.region),
+                None => true,
+            })
+            .collect()
     }
 
     pub fn prefix_projection(
@@ -62,8 +178,84 @@ impl<'tcx> RegionProjection<'tcx> {
     }
 }
 
+/// The regions that occur in `ty` in a position that is not covariant. Regions
+/// in types whose variance we do not compute (e.g. trait objects) are
+/// conservatively considered invariant.
+fn non_covariant_regions<'tcx>(ty: ty::Ty<'tcx>, tcx: TyCtxt<'tcx>) -> FxHashSet<RegionVid> {
+    fn add_region(region: ty::Region<'_>, variance: Variance, result: &mut FxHashSet<RegionVid>) {
+        if variance != Variance::Covariant
+            && let ty::RegionKind::ReVar(vid) = region.kind()
+        {
+            result.insert(vid);
+        }
+    }
+    fn visit<'tcx>(
+        ty: ty::Ty<'tcx>,
+        variance: Variance,
+        tcx: TyCtxt<'tcx>,
+        result: &mut FxHashSet<RegionVid>,
+    ) {
+        match ty.kind() {
+            ty::TyKind::Ref(region, ty, mutability) => {
+                add_region(*region, variance, result);
+                let variance = match mutability {
+                    Mutability::Not => variance,
+                    Mutability::Mut => variance.xform(Variance::Invariant),
+                };
+                visit(*ty, variance, tcx, result);
+            }
+            ty::TyKind::RawPtr(ty, mutability) => {
+                let variance = match mutability {
+                    Mutability::Not => variance,
+                    Mutability::Mut => variance.xform(Variance::Invariant),
+                };
+                visit(*ty, variance, tcx, result);
+            }
+            ty::TyKind::Array(ty, _) | ty::TyKind::Slice(ty) => visit(*ty, variance, tcx, result),
+            ty::TyKind::Tuple(tys) => {
+                for ty in tys.iter() {
+                    visit(ty, variance, tcx, result);
+                }
+            }
+            ty::TyKind::Adt(def, args) => {
+                let variances = tcx.variances_of(def.did());
+                for (arg, arg_variance) in args.iter().zip(variances.iter()) {
+                    let variance = variance.xform(*arg_variance);
+                    match arg.unpack() {
+                        GenericArgKind::Lifetime(region) => add_region(region, variance, result),
+                        GenericArgKind::Type(ty) => visit(ty, variance, tcx, result),
+                        GenericArgKind::Const(_) => {}
+                    }
+                }
+            }
+            _ => {
+                for region in extract_lifetimes(ty) {
+                    add_region(region, Variance::Invariant, result);
+                }
+            }
+        }
+    }
+    let mut result = FxHashSet::default();
+    visit(ty, Variance::Covariant, tcx, &mut result);
+    result
+}
+
 impl<'tcx> HasPcsElems<MaybeOldPlace<'tcx>> for RegionProjection<'tcx> {
     fn pcs_elems(&mut self) -> Vec<&mut MaybeOldPlace<'tcx>> {
         vec![&mut self.place]
     }
+
+    fn pcs_elems_ref(&self) -> Vec<&MaybeOldPlace<'tcx>> {
+        vec![&self.place]
+    }
+}
+
+#[cfg(feature = "json")]
+impl<'tcx> ToJsonWithRepacker<'tcx> for RegionProjection<'tcx> {
+    fn to_json(&self, repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value {
+        json!({
+            "place": self.place.to_json(repacker),
+            "region": format!("{:?}", self.region),
+        })
+    }
 }
@@ -1,60 +1,84 @@
 use crate::rustc_interface::middle::mir::Location;
+#[cfg(feature = "json")]
 use crate::utils::PlaceRepacker;
+#[cfg(feature = "json")]
+use serde_json::json;
+
+#[cfg(feature = "json")]
+use super::domain::ToJsonWithRepacker;
 
 use super::{
     domain::{MaybeOldPlace, MaybeRemotePlace},
     has_pcs_elem::HasPcsElems,
     region_projection::RegionProjection,
 };
+
+/// How the places of a [`RegionProjectionMember`] relate to its region
+/// projection.
 #[derive(Clone, Debug, Hash, PartialEq, Eq, Copy)]
 pub enum RegionProjectionMemberDirection {
+    /// The places flow into the region, e.g. they are borrowed by a reference
+    /// that is stored in the place of the projection. The places are blocked
+    /// by the place of the projection.
     PlaceIsRegionInput,
+    /// The places are obtained through the region, e.g. by dereferencing a
+    /// reference stored in the place of the projection. The places block the
+    /// edge, which does not block any place itself.
     PlaceIsRegionOutput,
 }
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+/// An edge relating places to a region projection, in the direction given by
+/// `direction`.
+///
+/// A member may relate several places to the same projection at once, e.g.
+/// all places that flow into the region at the same location. The places are
+/// never empty.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, HasPcsElems)]
+#[pcs_elems(MaybeOldPlace<'tcx>, RegionProjection<'tcx>)]
 pub struct RegionProjectionMember<'tcx> {
-    pub place: MaybeRemotePlace<'tcx>,
+    #[pcs_elems(iter, MaybeOldPlace<'tcx>)]
+    pub places: Vec<MaybeRemotePlace<'tcx>>,
+    #[pcs_elems(MaybeOldPlace<'tcx>)]
+    #[pcs_elem(RegionProjection<'tcx>)]
     pub projection: RegionProjection<'tcx>,
+    #[pcs_elems(skip)]
     location: Location,
+    #[pcs_elems(skip)]
     pub direction: RegionProjectionMemberDirection,
 }
 
-impl<'tcx> HasPcsElems<RegionProjection<'tcx>> for RegionProjectionMember<'tcx> {
-    fn pcs_elems(&mut self) -> Vec<&mut RegionProjection<'tcx>> {
-        vec![&mut self.projection]
-    }
-}
-
-impl<'tcx> HasPcsElems<MaybeOldPlace<'tcx>> for RegionProjectionMember<'tcx> {
-    fn pcs_elems(&mut self) -> Vec<&mut MaybeOldPlace<'tcx>> {
-        let mut vec = self.place.pcs_elems();
-        vec.extend(self.projection.pcs_elems());
-        vec
-    }
-}
-
 impl<'tcx> RegionProjectionMember<'tcx> {
-
-    pub fn projection_index(&self, repacker: PlaceRepacker<'_, 'tcx>) -> usize {
-        self.projection.index(repacker)
-    }
-
     pub fn location(&self) -> Location {
         self.location
     }
 
     pub fn new(
-        place: MaybeRemotePlace<'tcx>,
+        places: Vec<MaybeRemotePlace<'tcx>>,
         projection: RegionProjection<'tcx>,
         location: Location,
         direction: RegionProjectionMemberDirection,
     ) -> Self {
+        assert!(
+            !places.is_empty(),
+            "A region projection member must have at least one place"
+        );
         Self {
-            place,
+            places,
             projection,
             location,
             direction,
         }
     }
 }
+
+#[cfg(feature = "json")]
+impl<'tcx> ToJsonWithRepacker<'tcx> for RegionProjectionMember<'tcx> {
+    fn to_json(&self, repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value {
+        json!({
+            "places": self.places.to_json(repacker),
+            "projection": self.projection.to_json(repacker),
+            "location": format!("{:?}", self.location),
+            "direction": format!("{:?}", self.direction),
+        })
+    }
+}
@@ -5,16 +5,17 @@ use rustc_interface::{
     middle::mir::{BasicBlock, Location},
 };
 
+#[cfg(feature = "viz")]
+use crate::visualization::generate_unblock_dot_graph;
 use crate::{
     borrows::{
         borrows_edge::ToBorrowsEdge,
         borrows_state::BorrowsState,
-        domain::{MaybeOldPlace, Reborrow},
+        domain::{MaybeOldPlace, Reborrow, ReborrowPhase},
     },
     combined_pcs::UnblockAction,
     rustc_interface,
-    utils::{PlaceRepacker, PlaceSnapshot},
-    visualization::generate_unblock_dot_graph,
+    utils::{sorted_by_debug, PlaceRepacker, PlaceSnapshot},
 };
 
 use super::{
@@ -76,9 +77,11 @@ impl<'tcx> UnblockHistory<'tcx> {
 }
 
 impl<'tcx> UnblockGraph<'tcx> {
+    /// The edges of the graph, in a deterministic order
     pub fn edges(&self) -> impl Iterator<Item = &UnblockEdge<'tcx>> {
-        self.edges.iter()
+        sorted_by_debug(self.edges.iter()).into_iter()
     }
+    #[cfg(feature = "viz")]
     pub fn to_json(&self, repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value {
         let dot_graph = generate_unblock_dot_graph(&repacker, self).unwrap();
         serde_json::json!({
@@ -146,7 +149,7 @@ impl<'tcx> UnblockGraph<'tcx> {
                 //     })
                 // })
             };
-            for edge in edges.iter() {
+            for edge in sorted_by_debug(edges.iter()) {
                 match edge.kind() {
                     UnblockEdgeType::Reborrow(reborrow) => {
                         if is_leaf(reborrow.assigned_place) {
@@ -234,6 +237,10 @@ impl<'tcx> UnblockGraph<'tcx> {
         }
         for edge in borrows.edges_blocking(place) {
             match edge.kind() {
+                // Borrowck only permits reads of a place while a two-phase
+                // borrow of it is reserved, so the reservation survives
+                BorrowsEdgeKind::Reborrow(reborrow)
+                    if reborrow.phase() == ReborrowPhase::Reserved => {}
                 BorrowsEdgeKind::Reborrow(reborrow) => self.kill_reborrow_internal(
                     Conditioned::new(reborrow.clone(), edge.conditions().clone()),
                     borrows,
@@ -270,7 +277,9 @@ impl<'tcx> UnblockGraph<'tcx> {
         match place {
             MaybeRemotePlace::Local(MaybeOldPlace::Current { place }) => {
                 for reborrow in borrows.reborrows_blocking_prefix_of(place) {
-                    self.kill_reborrow(reborrow, borrows, repacker);
+                    if reborrow.value.phase() != ReborrowPhase::Reserved {
+                        self.kill_reborrow(reborrow.cloned(), borrows, repacker);
+                    }
                 }
             }
             _ => {}
@@ -286,7 +295,7 @@ impl<'tcx> UnblockGraph<'tcx> {
         for edge in borrows.reborrow_edges_reserved_at(location) {
             if !edge.value.blocked_place.is_old() {
                 self.unblock_place(edge.value.assigned_place.into(), borrows, repacker);
-                self.add_dependency(edge.into());
+                self.add_dependency(edge.cloned().into());
             }
         }
     }
@@ -333,7 +342,7 @@ impl<'tcx> UnblockGraph<'tcx> {
                 }
                 _ => {}
             }
-            self.kill_reborrow(reborrow, borrows, repacker);
+            self.kill_reborrow(reborrow.cloned(), borrows, repacker);
         }
     }
 }
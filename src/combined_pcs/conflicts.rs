@@ -0,0 +1,154 @@
+// © 2023, ETH Zurich
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use rustc_interface::middle::mir::{visit::Visitor, Location};
+
+use crate::{
+    borrows::{
+        borrows_graph::Conditioned,
+        borrows_state::BorrowsState,
+        domain::{MaybeOldPlace, MaybeRemotePlace, Reborrow, ReborrowPhase},
+        invariants::{InvariantCheckLevel, InvariantViolation},
+    },
+    free_pcs::{
+        triple::{Condition, TripleWalker},
+        CapabilityKind,
+    },
+    rustc_interface,
+    utils::{Place, PlaceRepacker},
+    FpcsOutput,
+};
+
+/// A place that a statement requires [`CapabilityKind::Exclusive`] for, but
+/// that is (transitively) blocked by a mutable reborrow at that point.
+#[derive(Clone, Debug)]
+pub struct ExclusivityConflict<'tcx> {
+    pub location: Location,
+    pub place: Place<'tcx>,
+    /// The live mutable reborrows blocking `place`, or a prefix or
+    /// descendant of it
+    pub blocked_by: Vec<Conditioned<Reborrow<'tcx>>>,
+}
+
+impl<'tcx> ExclusivityConflict<'tcx> {
+    pub fn to_short_string(&self, repacker: PlaceRepacker<'_, 'tcx>) -> String {
+        let reborrows: Vec<_> = self
+            .blocked_by
+            .iter()
+            .map(|rb| {
+                format!(
+                    "{} (reserved at {:?})",
+                    rb.value,
+                    rb.value.reserve_location()
+                )
+            })
+            .collect();
+        format!(
+            "{:?}: {} is required to be exclusive, but is blocked by {}",
+            self.location,
+            self.place.to_short_string(repacker),
+            reborrows.join(", ")
+        )
+    }
+}
+
+/// The result of [`check_exclusivity`].
+#[derive(Clone, Debug, Default)]
+pub struct ConflictReport<'tcx> {
+    pub conflicts: Vec<ExclusivityConflict<'tcx>>,
+}
+
+impl<'tcx> ConflictReport<'tcx> {
+    pub fn is_empty(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+/// Checks that no place a statement requires exclusive access to is blocked
+/// by a (non-reserved) mutable reborrow once the borrows graph has been
+/// prepared for that statement.
+///
+/// The owned and borrowed parts of the analysis are computed independently,
+/// so a violation indicates that the two disagree, rather than an error in
+/// the analysed program.
+pub fn check_exclusivity<'tcx>(output: &mut FpcsOutput<'_, 'tcx>) -> ConflictReport<'tcx> {
+    let repacker = output.repacker();
+    let body = repacker.body();
+    let mut report = ConflictReport::default();
+    for block in body.basic_blocks.indices() {
+        for location in output.get_all_for_bb(block).statements {
+            let mut tw = TripleWalker::default();
+            match body.stmt_at(location.location).left() {
+                Some(statement) => tw.visit_statement(statement, location.location),
+                None => tw.visit_terminator(body[block].terminator(), location.location),
+            }
+            // Operands are evaluated once the graph is prepared for them, the
+            // statement itself after the middle bridge
+            let extra = &location.extra;
+            let requirements = tw
+                .operand_triples
+                .iter()
+                .map(|t| (t.pre(), &extra.before_start))
+                .chain(tw.main_triples.iter().map(|t| (t.pre(), &extra.start)));
+            for (pre, state) in requirements {
+                if let Condition::Capability(place, CapabilityKind::Exclusive) = pre {
+                    let blocked_by = mutable_reborrows_blocking(state, place);
+                    if !blocked_by.is_empty() {
+                        report.conflicts.push(ExclusivityConflict {
+                            location: location.location,
+                            place,
+                            blocked_by,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    report
+}
+
+/// Collects the violated invariants of the borrows graph after each statement.
+///
+/// The invariants are checked up to `level`. Unlike the structural checks
+/// during the analysis (in debug builds), this does not stop at the first
+/// violation.
+pub fn check_invariants<'tcx>(
+    output: &mut FpcsOutput<'_, 'tcx>,
+    level: InvariantCheckLevel,
+) -> Vec<InvariantViolation<'tcx>> {
+    let repacker = output.repacker();
+    let mut violations = vec![];
+    for block in repacker.body().basic_blocks.indices() {
+        for location in output.get_all_for_bb(block).statements {
+            violations.extend(location.extra.after.graph().invariant_violations(
+                level,
+                location.location,
+                repacker,
+            ));
+        }
+    }
+    violations
+}
+
+fn mutable_reborrows_blocking<'tcx>(
+    state: &BorrowsState<'tcx>,
+    place: Place<'tcx>,
+) -> Vec<Conditioned<Reborrow<'tcx>>> {
+    state
+        .reborrow_edges()
+        .filter(|rb| {
+            rb.value.mutability.is_mut()
+                && rb.value.phase() == ReborrowPhase::Active
+                && match rb.value.blocked_place {
+                    MaybeRemotePlace::Local(MaybeOldPlace::Current { place: blocked }) => {
+                        blocked.is_prefix(place) || place.is_prefix(blocked)
+                    }
+                    _ => false,
+                }
+        })
+        .map(Conditioned::cloned)
+        .collect()
+}
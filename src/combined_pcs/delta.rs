@@ -0,0 +1,240 @@
+// © 2023, ETH Zurich
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use rustc_interface::{
+    data_structures::fx::FxHashMap,
+    middle::mir::{BasicBlock, Location},
+};
+
+use crate::{
+    borrows::{
+        borrows_edge::{BorrowsEdge, BorrowsEdgeKind},
+        borrows_state::BorrowsState,
+        borrows_visitor::DebugCtx,
+        domain::MaybeOldPlace,
+        has_pcs_elem::HasPcsElems,
+    },
+    free_pcs::{CapabilityDiff, CapabilitySummary},
+    rustc_interface,
+    utils::sorted_by_debug,
+    FpcsOutput, ReborrowBridge,
+};
+
+/// The changes to the PCS between the states after two locations on a path.
+///
+/// See [`pcs_delta`]. The `bridge` is computed in the same way as the bridges
+/// between consecutive statements.
+#[derive(Clone)]
+pub struct PcsDelta<'tcx> {
+    pub from: Location,
+    pub to: Location,
+    /// Edges of the borrows graph at `to` that are not at `from`
+    pub added_edges: Vec<BorrowsEdge<'tcx>>,
+    /// Edges of the borrows graph at `from` that are not at `to`
+    pub removed_edges: Vec<BorrowsEdge<'tcx>>,
+    /// Edges that only differ in which of their places are old, as the edge at
+    /// `from` and at `to`. These are in neither `added_edges` nor
+    /// `removed_edges`.
+    pub renamed_edges: Vec<(BorrowsEdge<'tcx>, BorrowsEdge<'tcx>)>,
+    /// The operations that transform the borrows graph at `from` into the one
+    /// at `to`
+    pub bridge: ReborrowBridge<'tcx>,
+    pub capability_changes: CapabilityDiff<'tcx>,
+}
+
+/// Why [`pcs_delta`] cannot compute the changes between two locations
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PcsDeltaError {
+    /// The path does not lead from the block of `from` to the block of `to`
+    PathEndpoints { from: Location, to: Location },
+    /// `to` precedes `from` in the same block, and the path does not loop
+    NotAfter { from: Location, to: Location },
+    /// The path contains a block that is not in the body
+    NoSuchBlock(BasicBlock),
+    /// The path contains a block that is not a successor of the block
+    /// before it
+    NotASuccessor {
+        block: BasicBlock,
+        successor: BasicBlock,
+    },
+    /// There is no analysis result after the location, because it is not a
+    /// statement of its block or the block is unreachable
+    NoStatement(Location),
+}
+
+impl std::fmt::Display for PcsDeltaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PcsDeltaError::PathEndpoints { from, to } => {
+                write!(f, "The path must lead from {from:?} to {to:?}")
+            }
+            PcsDeltaError::NotAfter { from, to } => write!(f, "{to:?} does not follow {from:?}"),
+            PcsDeltaError::NoSuchBlock(block) => write!(f, "No block {block:?}"),
+            PcsDeltaError::NotASuccessor { block, successor } => {
+                write!(f, "{successor:?} is not a successor of {block:?}")
+            }
+            PcsDeltaError::NoStatement(location) => {
+                write!(f, "No reachable statement at {location:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PcsDeltaError {}
+
+/// Computes the changes to the PCS from after `from` to after `to`, when
+/// control flows along `path`.
+///
+/// The path is given as the blocks from `from.block` to `to.block`
+/// (inclusive), each a successor of the one before it; edges of the borrows
+/// graphs whose path conditions do not hold on it are ignored.
+///
+/// Unlike the bridges reported for each statement, `from` and `to` need not
+/// be consecutive, which allows consumers to work at the granularity of
+/// blocks or regions.
+pub fn pcs_delta<'tcx>(
+    output: &mut FpcsOutput<'_, 'tcx>,
+    from: Location,
+    to: Location,
+    path: &[BasicBlock],
+) -> Result<PcsDelta<'tcx>, PcsDeltaError> {
+    if path.first() != Some(&from.block) || path.last() != Some(&to.block) {
+        return Err(PcsDeltaError::PathEndpoints { from, to });
+    }
+    if path.len() == 1 && from.statement_index > to.statement_index {
+        return Err(PcsDeltaError::NotAfter { from, to });
+    }
+    let repacker = output.repacker();
+    let blocks = &repacker.body().basic_blocks;
+    if let Some(block) = path.iter().find(|block| block.index() >= blocks.len()) {
+        return Err(PcsDeltaError::NoSuchBlock(*block));
+    }
+    for window in path.windows(2) {
+        if !blocks[window[0]]
+            .terminator()
+            .successors()
+            .any(|succ| succ == window[1])
+        {
+            return Err(PcsDeltaError::NotASuccessor {
+                block: window[0],
+                successor: window[1],
+            });
+        }
+    }
+    let (from_capabilities, mut from_borrows) = state_after(output, from)?;
+    let (to_capabilities, mut to_borrows) = state_after(output, to)?;
+    from_borrows.filter_for_path(&path[..1]);
+    to_borrows.filter_for_path(path);
+
+    // All remaining edges hold on the path, so edges are identified by their
+    // kind alone: their conditions merely describe different prefixes of it
+    let from_edges = sorted_by_debug(from_borrows.graph().edges().cloned());
+    let to_edges = sorted_by_debug(to_borrows.graph().edges().cloned());
+    let has_kind = |edges: &[BorrowsEdge<'tcx>], kind: &BorrowsEdgeKind<'tcx>| {
+        edges.iter().any(|edge| edge.kind() == kind)
+    };
+    let mut removed_edges: Vec<_> = from_edges
+        .iter()
+        .filter(|edge| !has_kind(&to_edges, edge.kind()))
+        .cloned()
+        .collect();
+    let mut added_edges = vec![];
+    let mut renamed_edges = vec![];
+    for edge in to_edges
+        .iter()
+        .filter(|edge| !has_kind(&from_edges, edge.kind()))
+    {
+        let current = with_current_places(edge);
+        match removed_edges
+            .iter()
+            .position(|removed| with_current_places(removed) == current)
+        {
+            Some(idx) => renamed_edges.push((removed_edges.remove(idx), edge.clone())),
+            None => added_edges.push(edge.clone()),
+        }
+    }
+
+    Ok(PcsDelta {
+        from,
+        to,
+        added_edges,
+        removed_edges,
+        renamed_edges,
+        bridge: from_borrows.bridge(&to_borrows, DebugCtx::new(to), repacker),
+        capability_changes: from_capabilities.diff(&to_capabilities),
+    })
+}
+
+/// The reborrows and abstractions that are removed from the borrows graph at
+/// each location, i.e. that are in the state before the location but not
+/// after it.
+///
+/// Edges that merely have some of their places made old are not considered
+/// removed, as in [`pcs_delta`]. Locations at which nothing expires are not
+/// in the result.
+pub fn borrow_expirations<'tcx>(
+    output: &mut FpcsOutput<'_, 'tcx>,
+) -> FxHashMap<Location, Vec<BorrowsEdge<'tcx>>> {
+    let body = output.repacker().body();
+    let mut result = FxHashMap::default();
+    for block in body.basic_blocks.indices() {
+        let mut before = output.extra_at_block_entry(block).after;
+        for stmt in output.get_all_for_bb(block).statements {
+            let after = stmt.extra.after;
+            let expired = edges_not_in(&before, &after);
+            if !expired.is_empty() {
+                result.insert(stmt.location, expired);
+            }
+            before = after;
+        }
+    }
+    result
+}
+
+/// The reborrows and abstractions of `state` that are not in `other`,
+/// ignoring which of their places are old
+pub(crate) fn edges_not_in<'tcx>(
+    state: &BorrowsState<'tcx>,
+    other: &BorrowsState<'tcx>,
+) -> Vec<BorrowsEdge<'tcx>> {
+    let remaining: Vec<_> = other.graph().edges().map(with_current_places).collect();
+    sorted_by_debug(
+        state
+            .graph()
+            .edges()
+            .filter(|edge| {
+                matches!(
+                    edge.kind(),
+                    BorrowsEdgeKind::Reborrow(_) | BorrowsEdgeKind::Abstraction(_)
+                ) && !remaining.contains(&with_current_places(edge))
+            })
+            .cloned(),
+    )
+}
+
+fn state_after<'tcx>(
+    output: &mut FpcsOutput<'_, 'tcx>,
+    location: Location,
+) -> Result<(CapabilitySummary<'tcx>, BorrowsState<'tcx>), PcsDeltaError> {
+    let stmt = output
+        .get_all_for_bb(location.block)
+        .statements
+        .into_iter()
+        .find(|stmt| stmt.location == location)
+        .ok_or(PcsDeltaError::NoStatement(location))?;
+    Ok((stmt.states.after, stmt.extra.after))
+}
+
+/// The kind of `edge` with each old place replaced by its current version
+fn with_current_places<'tcx>(edge: &BorrowsEdge<'tcx>) -> BorrowsEdgeKind<'tcx> {
+    let mut kind = edge.kind().clone();
+    for place in HasPcsElems::<MaybeOldPlace<'tcx>>::pcs_elems(&mut kind) {
+        *place = MaybeOldPlace::Current {
+            place: place.place(),
+        };
+    }
+    kind
+}
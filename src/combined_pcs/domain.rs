@@ -4,6 +4,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
+#[cfg(feature = "json")]
 use itertools::Itertools;
 use std::{
     cell::RefCell,
@@ -24,10 +25,10 @@ use crate::{
         unblock_graph::UnblockGraph,
     },
     free_pcs::{CapabilityLocal, FreePlaceCapabilitySummary},
-    rustc_interface,
-    visualization::generate_dot_graph,
-    RECORD_PCS,
+    rustc_interface, RECORD_PCS,
 };
+#[cfg(feature = "viz")]
+use crate::{utils::borrow_scopes::ProgramPoint, visualization::generate_dot_graph};
 
 use super::{PcsContext, PcsEngine};
 
@@ -116,6 +117,7 @@ impl DotGraphs {
         top.insert(phase, filename).is_none()
     }
 
+    #[cfg(feature = "json")]
     pub fn write_json_file(&self, filename: &str) {
         let iterations_json = self
             .0
@@ -162,6 +164,7 @@ impl<'a, 'tcx> PlaceCapabilitySummary<'a, 'tcx> {
         self.dot_graphs.clone().unwrap()
     }
 
+    #[cfg(feature = "viz")]
     fn dot_filename_for(
         &self,
         output_dir: &str,
@@ -183,23 +186,28 @@ impl<'a, 'tcx> PlaceCapabilitySummary<'a, 'tcx> {
         if self.block().as_usize() == 0 {
             assert!(!matches!(phase, DataflowStmtPhase::Join(_)));
         }
-        if let Some(output_dir) = &self.dot_output_dir {
-            if phase == DataflowStmtPhase::Initial {
-                self.dot_graphs()
-                    .borrow_mut()
-                    .register_new_iteration(statement_index);
-            }
-            let relative_filename =
-                self.dot_graphs()
-                    .borrow()
-                    .relative_filename(phase, self.block(), statement_index);
-            let filename = self.dot_filename_for(&output_dir, phase, statement_index);
-            assert!(self.dot_graphs().borrow_mut().insert(
-                statement_index,
-                phase,
-                relative_filename
-            ));
+        if self.dot_output_dir.is_none() {
+            return;
+        }
+        if phase == DataflowStmtPhase::Initial {
+            self.dot_graphs()
+                .borrow_mut()
+                .register_new_iteration(statement_index);
+        }
+        let relative_filename =
+            self.dot_graphs()
+                .borrow()
+                .relative_filename(phase, self.block(), statement_index);
+        assert!(self
+            .dot_graphs()
+            .borrow_mut()
+            .insert(statement_index, phase, relative_filename));
 
+        // Without the `viz` feature, only the filenames are recorded
+        #[cfg(feature = "viz")]
+        {
+            let output_dir = self.dot_output_dir.as_ref().unwrap();
+            let filename = self.dot_filename_for(output_dir, phase, statement_index);
             let (fpcs, borrows) = match phase {
                 DataflowStmtPhase::Initial | DataflowStmtPhase::BeforeStart => {
                     (&self.fpcs.pre_operands, &self.borrows.before_start)
@@ -213,7 +221,29 @@ impl<'a, 'tcx> PlaceCapabilitySummary<'a, 'tcx> {
                 }
             };
 
-            generate_dot_graph(self.cgx.rp, fpcs, borrows, &filename).unwrap();
+            let location = mir::Location {
+                block: self.block(),
+                statement_index,
+            };
+            let point = match phase {
+                DataflowStmtPhase::Initial | DataflowStmtPhase::Join(_) => {
+                    ProgramPoint::Entry(location)
+                }
+                DataflowStmtPhase::BeforeStart
+                | DataflowStmtPhase::BeforeAfter
+                | DataflowStmtPhase::Start => ProgramPoint::Start(location),
+                DataflowStmtPhase::After => ProgramPoint::After(location),
+            };
+
+            generate_dot_graph(
+                &self.cgx,
+                fpcs,
+                borrows,
+                point,
+                &filename,
+                &self.cgx.dot_config,
+            )
+            .unwrap();
         }
     }
 
@@ -226,9 +256,12 @@ impl<'a, 'tcx> PlaceCapabilitySummary<'a, 'tcx> {
         let fpcs = FreePlaceCapabilitySummary::new(cgx.rp);
         let borrows = BorrowsDomain::new(
             cgx.rp,
-            cgx.mir.output_facts.clone().unwrap(),
+            cgx.polonius_output(),
             cgx.mir.location_table.clone().unwrap(),
+            cgx.post_dominators.clone(),
             block,
+            cgx.reborrow_equivalence,
+            cgx.loop_abstractions.clone(),
         );
         Self {
             cgx,
@@ -284,9 +317,14 @@ impl JoinSemiLattice for PlaceCapabilitySummary<'_, '_> {
                 statement_index: 0,
             },
         );
+        for reborrow in ub.killed_reborrows {
+            if !self.borrows.join_killed_reborrows.contains(&reborrow) {
+                self.borrows.join_killed_reborrows.push(reborrow);
+            }
+        }
         self.dot_graphs().borrow_mut().register_new_iteration(0);
         self.generate_dot_graph(DataflowStmtPhase::Join(other.block()), 0);
-        fpcs || borrows || ub
+        fpcs || borrows || ub.changed
     }
 }
 
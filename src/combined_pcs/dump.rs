@@ -0,0 +1,125 @@
+// © 2023, ETH Zurich
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use rustc_interface::middle::mir::Mutability;
+
+use crate::{
+    borrows::borrows_edge::{BorrowsEdge, BorrowsEdgeKind},
+    rustc_interface,
+    utils::PlaceRepacker,
+    FpcsOutput,
+};
+
+/// A canonical text rendering of the results of a body, see [`result_dump`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ResultDump {
+    pub lines: Vec<String>,
+}
+
+impl ResultDump {
+    /// A digest of the dump as 16 hexadecimal digits. Unlike the hashes of
+    /// the standard library, it does not change across runs, platforms or
+    /// compiler versions, so that it can be stored as an expectation.
+    pub fn digest(&self) -> String {
+        // 64-bit FNV-1a
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for line in &self.lines {
+            for byte in line.bytes().chain(std::iter::once(b'\n')) {
+                hash ^= u64::from(byte);
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+        format!("{hash:016x}")
+    }
+}
+
+fn edge_line<'tcx>(edge: &BorrowsEdge<'tcx>, repacker: PlaceRepacker<'_, 'tcx>) -> String {
+    let kind = match edge.kind() {
+        BorrowsEdgeKind::Reborrow(reborrow) if reborrow.mutability == Mutability::Mut => {
+            "reborrow mut"
+        }
+        BorrowsEdgeKind::Reborrow(_) => "reborrow shared",
+        BorrowsEdgeKind::DerefExpansion(_) => "deref-expansion",
+        BorrowsEdgeKind::Abstraction(_) => "abstraction",
+        BorrowsEdgeKind::RegionProjectionMember(_) => "region-projection-member",
+    };
+    let mut blocked: Vec<_> = edge
+        .blocked_places()
+        .iter()
+        .map(|place| place.to_short_string(repacker))
+        .collect();
+    blocked.sort();
+    let mut blocked_by: Vec<_> = edge
+        .blocked_by_places(repacker)
+        .iter()
+        .map(|place| place.to_short_string(repacker))
+        .collect();
+    blocked_by.sort();
+    format!(
+        "{kind}\t{} -> {}\t{}",
+        blocked.join(", "),
+        blocked_by.join(", "),
+        edge.conditions()
+    )
+}
+
+/// Renders the capabilities and the borrows graph after each statement of
+/// the body as text that only depends on the results, e.g. to detect changes
+/// in precision between two versions of the analysis.
+///
+/// Each statement of a reachable block contributes a line
+/// `bb1[2]\tstate\t...` with the capabilities as permission assertions (see
+/// [`crate::free_pcs::CapabilitySummary::to_permission_string`]), followed by
+/// one line `bb1[2]\texpansion\t...` per operation on the bridges to the
+/// statement (see [`crate::ReborrowBridge::expansion_ops`]) in the order they
+/// are applied, and one line `bb1[2]\tedge\t...` per edge of the graph, giving
+/// its kind, the places it blocks and is blocked by, and its path conditions.
+/// The edge lines of a statement are sorted. Finally, each reborrow of a block
+/// that is killed when joining the states at a successor contributes a line
+/// `bb1 -> bb3\tkill\t...`.
+pub fn result_dump(output: &mut FpcsOutput<'_, '_>) -> ResultDump {
+    let repacker = output.repacker();
+    let mut dump = ResultDump::default();
+    for block in repacker.body().basic_blocks.indices() {
+        let results = output.get_all_for_bb(block);
+        for stmt in results.statements {
+            let location = format!("{:?}", stmt.location);
+            dump.lines.push(format!(
+                "{location}\tstate\t{}",
+                stmt.states.after.to_permission_string(repacker)
+            ));
+            let ops = stmt.extra_start.expansion_ops.iter().chain(
+                stmt.extra_middle
+                    .iter()
+                    .flat_map(|bridge| &bridge.expansion_ops),
+            );
+            dump.lines.extend(
+                ops.map(|op| format!("{location}\texpansion\t{}", op.to_short_string(repacker))),
+            );
+            let mut edges: Vec<_> = stmt
+                .extra
+                .after
+                .graph_edges()
+                .map(|edge| format!("{location}\tedge\t{}", edge_line(edge, repacker)))
+                .collect();
+            edges.sort();
+            dump.lines.extend(edges);
+        }
+        for succ in results.terminator.succs {
+            let edge = format!("{block:?} -> {:?}", succ.location.block);
+            let kills = succ.extra_start.killed_reborrows.iter().map(|reborrow| {
+                format!(
+                    "{edge}\tkill\t{} -> {}\t{}",
+                    reborrow.value.blocked_place.to_short_string(repacker),
+                    reborrow.value.assigned_place.to_short_string(repacker),
+                    reborrow.conditions
+                )
+            });
+            dump.lines.extend(kills);
+        }
+    }
+    dump
+}
@@ -5,7 +5,8 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use std::{
-    cell::{Cell, RefCell},
+    cell::{Cell, OnceCell, RefCell},
+    collections::BTreeSet,
     fs::create_dir_all,
     rc::Rc,
 };
@@ -15,7 +16,9 @@ use rustc_interface::{
         borrow_set::BorrowSet,
         consumers::{self, LocationTable, PoloniusInput, PoloniusOutput, RegionInferenceContext},
     },
-    dataflow::{Analysis, AnalysisDomain},
+    data_structures::fx::FxHashMap,
+    dataflow::{impl_rustc_analysis, PcsAnalysis},
+    hir::def_id::DefId,
     index::{Idx, IndexVec},
     middle::{
         mir::{
@@ -28,14 +31,27 @@ use rustc_interface::{
 
 use crate::{
     borrows::{
-        domain::{AbstractionType, MaybeOldPlace, MaybeRemotePlace},
+        borrows_state::OldLeafTrimming,
+        domain::{
+            AbstractionBlockEdge, AbstractionType, CallAbstraction, MaybeOldPlace,
+            MaybeRemotePlace, ReborrowEquivalence,
+        },
         engine::BorrowsEngine,
+        external_abstractions::ExternalAbstractions,
+        function_summary::FunctionSummaries,
+        invariants::InvariantCheckLevel,
     },
     free_pcs::engine::FpcsEngine,
     rustc_interface,
-    utils::PlaceRepacker,
+    utils::{
+        borrow_scopes::BorrowScopes, origins::OriginFacts, post_dominators::PostDominators,
+        region_names::RegionNames, PlaceRepacker,
+    },
 };
 
+#[cfg(feature = "viz")]
+use crate::visualization::drawer::DotConfig;
+
 use super::{domain::PlaceCapabilitySummary, DataflowStmtPhase, DotGraphs};
 
 #[derive(Clone)]
@@ -92,12 +108,236 @@ impl<'tcx> From<consumers::BodyWithBorrowckFacts<'tcx>> for BodyWithBorrowckFact
 pub struct PcsContext<'a, 'tcx> {
     pub rp: PlaceRepacker<'a, 'tcx>,
     pub mir: &'a BodyWithBorrowckFacts<'tcx>,
+    pub post_dominators: Rc<PostDominators>,
+    /// Whether to record the mutations made to the borrows graph, see
+    /// [`crate::borrows::mutation_log::MutationLog`]
+    pub record_mutations: bool,
+    /// Invariants of the borrows graph to check after each transfer function,
+    /// in addition to the structural ones checked in debug builds. See
+    /// [`crate::borrows::invariants`]
+    pub invariant_check_level: Option<InvariantCheckLevel>,
+    /// Which reborrows are merged at join points
+    pub reborrow_equivalence: ReborrowEquivalence,
+    /// When old leaves are trimmed from the borrows graph
+    pub old_leaf_trimming: OldLeafTrimming,
+    /// The largest number of dereferences of the places in the borrows
+    /// graph. Accesses through more dereferences, e.g. deep into a recursive
+    /// data structure, block the place at the limit through a summarizing
+    /// abstraction, see [`crate::borrows::projection_summary`]
+    pub projection_depth_limit: Option<usize>,
+    /// How calls are abstracted, unless overridden for the callee
+    pub call_abstraction: CallAbstraction,
+    /// How calls to particular functions are abstracted, overriding both
+    /// [`Self::call_abstraction`] and the `#[pcs::precise]`, `#[pcs::havoc]`
+    /// and `#[pcs::trusted]` attributes of the callee
+    pub call_abstractions: Rc<FxHashMap<DefId, CallAbstraction>>,
+    /// The summaries of the called functions, instantiated at each call, see
+    /// [`crate::borrows::function_summary`]. Sharing them between the bodies
+    /// of a crate computes each summary only once.
+    pub function_summaries: Rc<FunctionSummaries<'tcx>>,
+    /// The abstractions of loops, by loop head, to use instead of the
+    /// inferred ones
+    pub loop_abstractions: Rc<FxHashMap<BasicBlock, Vec<AbstractionBlockEdge<'tcx>>>>,
+    /// Supplies abstraction edges to insert after each location
+    pub external_abstractions: Option<Rc<dyn ExternalAbstractions<'tcx> + 'a>>,
+    /// Whether the visualization renders, for each location, one graph per
+    /// acyclic path reaching it, see
+    /// [`crate::visualization::generate_path_dot_graphs`]
+    pub visualize_paths: bool,
+    /// The locations to render the per-path graphs of, all if `None`
+    pub path_visualization_locations: Option<BTreeSet<Location>>,
+    /// Whether the visualization also draws the whole function in a single
+    /// graph, see [`crate::visualization::generate_function_dot_graph`]
+    pub visualize_function: bool,
+    /// How the dot graphs of the visualization are drawn
+    #[cfg(feature = "viz")]
+    pub dot_config: DotConfig,
+    region_names: OnceCell<RegionNames>,
+    borrow_scopes: OnceCell<BorrowScopes>,
+    origin_facts: OnceCell<Option<OriginFacts<'a, 'tcx>>>,
 }
 
 impl<'a, 'tcx> PcsContext<'a, 'tcx> {
     pub fn new(tcx: TyCtxt<'tcx>, mir: &'a BodyWithBorrowckFacts<'tcx>) -> Self {
         let rp = PlaceRepacker::new(&mir.body, tcx);
-        Self { rp, mir }
+        let post_dominators = Rc::new(PostDominators::new(&mir.body.basic_blocks));
+        Self {
+            rp,
+            mir,
+            post_dominators,
+            record_mutations: false,
+            invariant_check_level: None,
+            reborrow_equivalence: ReborrowEquivalence::default(),
+            old_leaf_trimming: OldLeafTrimming::default(),
+            projection_depth_limit: None,
+            call_abstraction: CallAbstraction::default(),
+            call_abstractions: Rc::default(),
+            function_summaries: Rc::default(),
+            loop_abstractions: Rc::default(),
+            external_abstractions: None,
+            visualize_paths: false,
+            path_visualization_locations: None,
+            visualize_function: false,
+            #[cfg(feature = "viz")]
+            dot_config: DotConfig::default(),
+            region_names: OnceCell::new(),
+            borrow_scopes: OnceCell::new(),
+            origin_facts: OnceCell::new(),
+        }
+    }
+
+    /// Descriptions of the regions of the body, computed on first use
+    pub fn region_names(&self) -> &RegionNames {
+        self.region_names
+            .get_or_init(|| RegionNames::new(self.mir, self.rp.tcx()))
+    }
+
+    /// Where rustc considers the borrows of the body to be in scope, computed
+    /// on first use
+    pub fn borrow_scopes(&self) -> &BorrowScopes {
+        self.borrow_scopes
+            .get_or_init(|| BorrowScopes::new(self.mir))
+    }
+
+    /// The Polonius origins and subset relations of the body, computed on
+    /// first use. Returns `None` if the body has no Polonius input facts.
+    pub fn origin_facts(&self) -> Option<&OriginFacts<'a, 'tcx>> {
+        self.origin_facts
+            .get_or_init(|| OriginFacts::new(self.mir))
+            .as_ref()
+    }
+
+    /// The Polonius output for the borrows domain. Joins at loop heads need
+    /// the origins live there, which rustc only computes for debugging, so
+    /// for bodies with loops the output of [`Self::origin_facts`] is used.
+    pub fn polonius_output(&self) -> Rc<PoloniusOutput> {
+        if self.rp.body().basic_blocks.is_cfg_cyclic()
+            && let Some(facts) = self.origin_facts()
+        {
+            facts.output().clone()
+        } else {
+            self.mir.output_facts.clone().unwrap()
+        }
+    }
+
+    pub fn with_mutation_log(self) -> Self {
+        Self {
+            record_mutations: true,
+            ..self
+        }
+    }
+
+    pub fn with_invariant_checks(self, level: InvariantCheckLevel) -> Self {
+        Self {
+            invariant_check_level: Some(level),
+            ..self
+        }
+    }
+
+    pub fn with_reborrow_equivalence(self, reborrow_equivalence: ReborrowEquivalence) -> Self {
+        Self {
+            reborrow_equivalence,
+            ..self
+        }
+    }
+
+    pub fn with_old_leaf_trimming(self, old_leaf_trimming: OldLeafTrimming) -> Self {
+        Self {
+            old_leaf_trimming,
+            ..self
+        }
+    }
+
+    pub fn with_projection_depth_limit(self, limit: usize) -> Self {
+        Self {
+            projection_depth_limit: Some(limit),
+            ..self
+        }
+    }
+
+    pub fn with_call_abstraction(self, call_abstraction: CallAbstraction) -> Self {
+        Self {
+            call_abstraction,
+            ..self
+        }
+    }
+
+    /// Abstracts the calls to `def_id` by `call_abstraction`, regardless of
+    /// the global setting and of the attributes of the function
+    pub fn with_call_abstraction_for(
+        mut self,
+        def_id: DefId,
+        call_abstraction: CallAbstraction,
+    ) -> Self {
+        Rc::make_mut(&mut self.call_abstractions).insert(def_id, call_abstraction);
+        self
+    }
+
+    /// Uses `function_summaries`, e.g. shared with the analyses of other
+    /// bodies, instead of computing the summaries of the called functions
+    /// anew
+    pub fn with_function_summaries(self, function_summaries: Rc<FunctionSummaries<'tcx>>) -> Self {
+        Self {
+            function_summaries,
+            ..self
+        }
+    }
+
+    /// Abstracts the loop with head `loop_head` by `edges` rather than by
+    /// the abstraction inferred from the coupling of its borrows, e.g. to
+    /// match a loop invariant written by the user. The reborrows blocking
+    /// the inputs of the edges on entry to the loop are subsumed by them.
+    pub fn with_loop_abstraction(
+        mut self,
+        loop_head: BasicBlock,
+        edges: Vec<AbstractionBlockEdge<'tcx>>,
+    ) -> Self {
+        Rc::make_mut(&mut self.loop_abstractions).insert(loop_head, edges);
+        self
+    }
+
+    /// Inserts the abstraction edges supplied by `external_abstractions`
+    /// into the borrows graph after the effect of each location, to model
+    /// effects the analysis does not know about (e.g. of specifications or
+    /// ghost code). Edges that would violate an invariant of the graph abort
+    /// the analysis.
+    pub fn with_external_abstractions(
+        self,
+        external_abstractions: impl ExternalAbstractions<'tcx> + 'a,
+    ) -> Self {
+        Self {
+            external_abstractions: Some(Rc::new(external_abstractions)),
+            ..self
+        }
+    }
+
+    pub fn with_path_visualization(self) -> Self {
+        Self {
+            visualize_paths: true,
+            ..self
+        }
+    }
+
+    /// Renders the per-path graphs only of `locations`, which are typically
+    /// the few locations being debugged
+    pub fn with_path_visualization_at(self, locations: BTreeSet<Location>) -> Self {
+        Self {
+            visualize_paths: true,
+            path_visualization_locations: Some(locations),
+            ..self
+        }
+    }
+
+    pub fn with_function_visualization(self) -> Self {
+        Self {
+            visualize_function: true,
+            ..self
+        }
+    }
+
+    #[cfg(feature = "viz")]
+    pub fn with_dot_config(self, dot_config: DotConfig) -> Self {
+        Self { dot_config, ..self }
     }
 }
 
@@ -133,15 +373,24 @@ impl<'a, 'tcx> PcsEngine<'a, 'tcx> {
         );
         let cgx = Rc::new(cgx);
         let fpcs = FpcsEngine(cgx.rp);
-        let borrows = BorrowsEngine::new(
-            cgx.rp.tcx(),
-            cgx.rp.body(),
-            cgx.mir.location_table.as_ref().unwrap(),
-            cgx.mir.input_facts.as_ref().unwrap(),
-            cgx.mir.borrow_set.clone(),
-            cgx.mir.region_inference_context.clone(),
-            cgx.mir.output_facts.as_ref().unwrap(),
-        );
+        let borrows = BorrowsEngine {
+            invariant_check_level: cgx.invariant_check_level,
+            old_leaf_trimming: cgx.old_leaf_trimming,
+            projection_depth_limit: cgx.projection_depth_limit,
+            call_abstraction: cgx.call_abstraction,
+            call_abstractions: cgx.call_abstractions.clone(),
+            function_summaries: cgx.function_summaries.clone(),
+            external_abstractions: cgx.external_abstractions.clone(),
+            ..BorrowsEngine::new(
+                cgx.rp.tcx(),
+                cgx.rp.body(),
+                cgx.mir.location_table.as_ref().unwrap(),
+                cgx.mir.input_facts.as_ref().unwrap(),
+                cgx.mir.borrow_set.clone(),
+                cgx.mir.region_inference_context.clone(),
+                cgx.mir.output_facts.as_ref().unwrap(),
+            )
+        };
         Self {
             cgx,
             dot_graphs,
@@ -152,6 +401,25 @@ impl<'a, 'tcx> PcsEngine<'a, 'tcx> {
         }
     }
 
+    /// Collapses the borrows graph before the statement (or terminator, if
+    /// `at_terminator` is set) at `location`. This is done here rather than by
+    /// the borrows engine, since edges that the owned capabilities depend on
+    /// must be preserved.
+    fn minimize_borrows(
+        &self,
+        state: &mut PlaceCapabilitySummary<'a, 'tcx>,
+        location: Location,
+        at_terminator: bool,
+    ) {
+        state.borrows.after.set_mutation_location(location.into());
+        state.borrows.after.minimize(
+            self.cgx.rp,
+            &state.fpcs.post_main,
+            self.cgx.old_leaf_trimming.trims_at(at_terminator),
+            location,
+        );
+    }
+
     fn generate_dot_graph(
         &self,
         state: &mut PlaceCapabilitySummary<'a, 'tcx>,
@@ -162,34 +430,6 @@ impl<'a, 'tcx> PcsEngine<'a, 'tcx> {
     }
 }
 
-impl<'a, 'tcx> AnalysisDomain<'tcx> for PcsEngine<'a, 'tcx> {
-    type Domain = PlaceCapabilitySummary<'a, 'tcx>;
-    const NAME: &'static str = "pcs";
-
-    fn bottom_value(&self, body: &Body<'tcx>) -> Self::Domain {
-        let block = self.curr_block.get();
-        let (block, dot_graphs) = if block.as_usize() < body.basic_blocks.len() {
-            self.curr_block.set(block.plus(1));
-            (Some(block), Some(self.dot_graphs[block].clone()))
-        } else {
-            // For results cursor, don't set block
-            (None, None)
-        };
-        PlaceCapabilitySummary::new(
-            self.cgx.clone(),
-            block,
-            self.debug_output_dir.clone(),
-            dot_graphs,
-        )
-    }
-
-    fn initialize_start_block(&self, _body: &Body<'tcx>, state: &mut Self::Domain) {
-        self.curr_block.set(START_BLOCK);
-        state.fpcs.initialize_as_start_block();
-        state.borrows.initialize_as_start_block();
-    }
-}
-
 #[derive(Eq, PartialEq, Hash, Clone, Debug)]
 pub struct ProjectionEdge<'tcx> {
     pub blockers: Vec<PlaceElem<'tcx>>,
@@ -208,7 +448,7 @@ impl<'tcx> ProjectionEdge<'tcx> {
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum UnblockAction<'tcx> {
     TerminateAbstraction(Location, AbstractionType<'tcx>),
     TerminateReborrow {
@@ -220,7 +460,36 @@ pub enum UnblockAction<'tcx> {
     Collapse(MaybeOldPlace<'tcx>, Vec<MaybeOldPlace<'tcx>>),
 }
 
-impl<'a, 'tcx> Analysis<'tcx> for PcsEngine<'a, 'tcx> {
+impl<'a, 'tcx> PcsAnalysis<'tcx> for PcsEngine<'a, 'tcx> {
+    type Domain = PlaceCapabilitySummary<'a, 'tcx>;
+    const NAME: &'static str = "pcs";
+
+    fn bottom_value(&self, body: &Body<'tcx>) -> Self::Domain {
+        let block = self.curr_block.get();
+        let (block, dot_graphs) = if block.as_usize() < body.basic_blocks.len() {
+            self.curr_block.set(block.plus(1));
+            (Some(block), Some(self.dot_graphs[block].clone()))
+        } else {
+            // For results cursor, don't set block
+            (None, None)
+        };
+        PlaceCapabilitySummary::new(
+            self.cgx.clone(),
+            block,
+            self.debug_output_dir.clone(),
+            dot_graphs,
+        )
+    }
+
+    fn initialize_start_block(&self, _body: &Body<'tcx>, state: &mut Self::Domain) {
+        self.curr_block.set(START_BLOCK);
+        state.fpcs.initialize_as_start_block();
+        if self.cgx.record_mutations {
+            state.borrows.after.enable_mutation_log();
+        }
+        state.borrows.initialize_as_start_block();
+    }
+
     fn apply_before_statement_effect(
         &mut self,
         state: &mut Self::Domain,
@@ -236,7 +505,9 @@ impl<'a, 'tcx> Analysis<'tcx> for PcsEngine<'a, 'tcx> {
             self.cgx.rp.body(),
             &state.fpcs.post_main,
             location,
+            self.cgx.projection_depth_limit,
         );
+        self.minimize_borrows(state, location, false);
         self.borrows
             .apply_before_statement_effect(&mut state.borrows, statement, location);
         self.generate_dot_graph(
@@ -263,6 +534,7 @@ impl<'a, 'tcx> Analysis<'tcx> for PcsEngine<'a, 'tcx> {
             self.cgx.rp.body(),
             &state.fpcs.post_main,
             location,
+            self.cgx.projection_depth_limit,
         );
         self.borrows
             .apply_statement_effect(&mut state.borrows, statement, location);
@@ -277,6 +549,7 @@ impl<'a, 'tcx> Analysis<'tcx> for PcsEngine<'a, 'tcx> {
     ) {
         self.initialize(state, location.block);
         self.generate_dot_graph(state, DataflowStmtPhase::Initial, location.statement_index);
+        self.minimize_borrows(state, location, true);
         self.borrows
             .apply_before_terminator_effect(&mut state.borrows, terminator, location);
         self.fpcs
@@ -315,4 +588,16 @@ impl<'a, 'tcx> Analysis<'tcx> for PcsEngine<'a, 'tcx> {
     ) {
         // Nothing to do here
     }
+
+    fn apply_call_unwind_effect(
+        &mut self,
+        state: &mut Self::Domain,
+        block: BasicBlock,
+        return_places: CallReturnPlaces<'_, 'tcx>,
+    ) {
+        self.fpcs
+            .apply_call_unwind_effect(&mut state.fpcs, block, return_places);
+    }
 }
+
+impl_rustc_analysis!(PcsEngine<'a, 'tcx>);
@@ -0,0 +1,160 @@
+// © 2023, ETH Zurich
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use rustc_interface::middle::mir::{BasicBlock, Location, Mutability};
+
+use crate::{
+    borrows::{
+        borrows_edge::{BorrowsEdge, BorrowsEdgeKind},
+        domain::MaybeRemotePlace,
+    },
+    rustc_interface,
+    utils::PlaceRepacker,
+    FpcsOutput,
+};
+
+use super::delta::edges_not_in;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExpiryEvent {
+    /// The borrow is added to the borrows graph
+    Create,
+    /// The borrow is removed from the borrows graph
+    Expire,
+}
+
+/// A reborrow or abstraction that is created or expires at a location.
+#[derive(Clone, Debug)]
+pub struct ExpiryTraceEntry<'tcx> {
+    pub location: Location,
+    /// If set, the event happens on the control-flow edge from the terminator
+    /// at `location` to this block, rather than at `location` itself
+    pub successor: Option<BasicBlock>,
+    pub event: ExpiryEvent,
+    pub edge: BorrowsEdge<'tcx>,
+}
+
+impl<'tcx> ExpiryTraceEntry<'tcx> {
+    /// The entry as a line of tab-separated fields, see
+    /// [`ExpiryTrace::to_lines`]
+    pub fn to_line(&self, repacker: PlaceRepacker<'_, 'tcx>) -> String {
+        let point = match self.successor {
+            Some(succ) => format!("{:?}->{:?}", self.location, succ),
+            None => format!("{:?}", self.location),
+        };
+        let event = match self.event {
+            ExpiryEvent::Create => "create",
+            ExpiryEvent::Expire => "expire",
+        };
+        let borrow = match self.edge.kind() {
+            BorrowsEdgeKind::Reborrow(reborrow) => {
+                let blocked = match reborrow.blocked_place {
+                    MaybeRemotePlace::Local(place) => place.to_short_string(repacker),
+                    MaybeRemotePlace::Remote(place) => place.to_short_string(repacker),
+                };
+                format!(
+                    "reborrow\t{:?}\t{}\t{}\t{}",
+                    reborrow.reserve_location(),
+                    if reborrow.mutability == Mutability::Mut {
+                        "mut"
+                    } else {
+                        "shared"
+                    },
+                    blocked,
+                    reborrow.assigned_place.to_short_string(repacker)
+                )
+            }
+            BorrowsEdgeKind::Abstraction(abstraction) => {
+                format!("abstraction\t{:?}", abstraction.location())
+            }
+            BorrowsEdgeKind::DerefExpansion(_) | BorrowsEdgeKind::RegionProjectionMember(_) => {
+                unreachable!("only reborrows and abstractions are traced")
+            }
+        };
+        format!("{point}\t{event}\t{borrow}")
+    }
+}
+
+/// The predicted creation and expiry of every reborrow and abstraction of a
+/// body, see [`expiry_trace`].
+#[derive(Clone, Debug, Default)]
+pub struct ExpiryTrace<'tcx> {
+    pub entries: Vec<ExpiryTraceEntry<'tcx>>,
+}
+
+impl<'tcx> ExpiryTrace<'tcx> {
+    /// The trace in a line-based format for external checkers, one entry per
+    /// line with the tab-separated fields
+    ///
+    /// - the location (`bb1[2]`), or the control-flow edge (`bb1[4]->bb3`),
+    ///   at which the event happens
+    /// - the event, `create` or `expire`
+    /// - `reborrow` followed by the reserve location identifying the
+    ///   reborrow, `mut` or `shared`, the blocked and the assigned place; or
+    ///   `abstraction` followed by the location identifying the abstraction
+    ///
+    /// Lines are ordered by block and then by location within the block, so
+    /// that a checker can replay the events of an execution along its path.
+    /// Events at the same point are unordered.
+    pub fn to_lines(&self, repacker: PlaceRepacker<'_, 'tcx>) -> Vec<String> {
+        self.entries
+            .iter()
+            .map(|entry| entry.to_line(repacker))
+            .collect()
+    }
+}
+
+/// Predicts where each reborrow and abstraction is created and where it
+/// expires.
+///
+/// The trace can be checked against an instrumented execution of the
+/// function: on any execution, a borrow that the trace says has expired must
+/// not be used again.
+///
+/// As in [`borrow_expirations`](super::borrow_expirations), edges that merely
+/// have some of their places made old are neither created nor expired.
+pub fn expiry_trace<'tcx>(output: &mut FpcsOutput<'_, 'tcx>) -> ExpiryTrace<'tcx> {
+    let body = output.repacker().body();
+    let mut trace = ExpiryTrace::default();
+    let mut push = |location, successor, before: &_, after: &_| {
+        for (event, from, to) in [
+            (ExpiryEvent::Create, after, before),
+            (ExpiryEvent::Expire, before, after),
+        ] {
+            trace.entries.extend(
+                edges_not_in(from, to)
+                    .into_iter()
+                    .map(|edge| ExpiryTraceEntry {
+                        location,
+                        successor,
+                        event,
+                        edge,
+                    }),
+            );
+        }
+    };
+    for block in body.basic_blocks.indices() {
+        if !output.is_reachable(block) {
+            continue;
+        }
+        let mut before = output.extra_at_block_entry(block).after;
+        let results = output.get_all_for_bb(block);
+        for stmt in results.statements {
+            push(stmt.location, None, &before, &stmt.extra.after);
+            before = stmt.extra.after;
+        }
+        let terminator = body.terminator_loc(block);
+        for succ in results.terminator.succs {
+            push(
+                terminator,
+                Some(succ.location.block),
+                &before,
+                &succ.extra.after,
+            );
+        }
+    }
+    trace
+}
@@ -4,9 +4,19 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
+mod conflicts;
+mod delta;
+mod dump;
 mod engine;
+mod expiry_trace;
 mod domain;
+mod regain;
 mod remove;
 
+pub use conflicts::*;
+pub use delta::*;
+pub use dump::*;
 pub use engine::*;
+pub use expiry_trace::*;
 pub use domain::*;
+pub use regain::*;
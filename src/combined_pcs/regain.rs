@@ -0,0 +1,174 @@
+// © 2023, ETH Zurich
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::VecDeque;
+
+use rustc_interface::{
+    data_structures::fx::{FxHashMap, FxHashSet},
+    middle::mir::{BasicBlock, Location},
+};
+
+use crate::{
+    borrows::{
+        borrows_edge::{BorrowsEdge, BorrowsEdgeKind},
+        borrows_state::BorrowsState,
+        domain::{MaybeOldPlace, MaybeRemotePlace},
+    },
+    free_pcs::{CapabilityKind, CapabilitySummary},
+    rustc_interface,
+    utils::{sorted_by_debug, Place, PlaceRepacker},
+    FpcsOutput,
+};
+
+/// A location at which a blocked place regains [`CapabilityKind::Exclusive`],
+/// see [`earliest_exclusive_locations`].
+#[derive(Clone, Debug)]
+pub struct ExclusiveRegained<'tcx> {
+    pub location: Location,
+    /// The blocks from the block of the blocked location to that of
+    /// `location` (inclusive) along which the place stays blocked
+    pub path: Vec<BasicBlock>,
+    /// The edges that blocked the place before `location`, but no longer
+    /// exist after it
+    pub expiring_edges: Vec<BorrowsEdge<'tcx>>,
+}
+
+/// Searches forward from the state after `blocked_at` for the earliest
+/// locations on each path at which `place` is exclusive again.
+///
+/// The place is exclusive when neither it, nor a prefix or descendant of it,
+/// is blocked by a borrow or call, and every such place that is in the
+/// capability summary has [`CapabilityKind::Exclusive`].
+///
+/// Each returned location is the first on some path where the place is
+/// exclusive; paths that reach the end of the body first contribute nothing.
+/// Locations are reported once, along the shortest path that reaches them.
+/// If `place` is not blocked after `blocked_at`, the result is empty.
+pub fn earliest_exclusive_locations<'tcx>(
+    output: &mut FpcsOutput<'_, 'tcx>,
+    place: Place<'tcx>,
+    blocked_at: Location,
+) -> Vec<ExclusiveRegained<'tcx>> {
+    let repacker = output.repacker();
+    let mut states = BlockStates::default();
+    let start = states.after(output, blocked_at, &[blocked_at.block]);
+    if is_exclusive(place, &start.0, &start.1, repacker) {
+        return vec![];
+    }
+
+    let mut result = vec![];
+    let mut visited = FxHashSet::default();
+    let mut worklist = VecDeque::from([(blocked_at, vec![blocked_at.block], start.1)]);
+    while let Some((location, path, borrows)) = worklist.pop_front() {
+        let successors = if location == repacker.body().terminator_loc(location.block) {
+            let body = repacker.body();
+            body[location.block]
+                .terminator()
+                .successors()
+                .filter(|succ| !body[*succ].is_cleanup && visited.insert(*succ))
+                .map(|succ| (succ.start_location(), [path.as_slice(), &[succ]].concat()))
+                .collect()
+        } else {
+            vec![(location.successor_within_block(), path)]
+        };
+        for (next, path) in successors {
+            let (capabilities, next_borrows) = states.after(output, next, &path);
+            if is_exclusive(place, &capabilities, &next_borrows, repacker) {
+                let remaining: FxHashSet<_> =
+                    next_borrows.graph().edges().map(|e| e.kind()).collect();
+                let expiring_edges = sorted_by_debug(
+                    blocking_edges(place, &borrows)
+                        .filter(|edge| !remaining.contains(edge.kind()))
+                        .cloned(),
+                );
+                result.push(ExclusiveRegained {
+                    location: next,
+                    path,
+                    expiring_edges,
+                });
+            } else {
+                worklist.push_back((next, path, next_borrows));
+            }
+        }
+    }
+    result
+}
+
+/// The states after each location, computed one block at a time
+#[derive(Default)]
+struct BlockStates<'tcx> {
+    blocks: FxHashMap<BasicBlock, Vec<(CapabilitySummary<'tcx>, BorrowsState<'tcx>)>>,
+}
+
+impl<'tcx> BlockStates<'tcx> {
+    /// The state after `location`, restricted to the edges whose conditions
+    /// hold on `path`
+    fn after(
+        &mut self,
+        output: &mut FpcsOutput<'_, 'tcx>,
+        location: Location,
+        path: &[BasicBlock],
+    ) -> (CapabilitySummary<'tcx>, BorrowsState<'tcx>) {
+        let states = self.blocks.entry(location.block).or_insert_with(|| {
+            output
+                .get_all_for_bb(location.block)
+                .statements
+                .into_iter()
+                .map(|stmt| (stmt.states.after, stmt.extra.after))
+                .collect()
+        });
+        let (capabilities, mut borrows) = states[location.statement_index].clone();
+        borrows.filter_for_path(path);
+        (capabilities, borrows)
+    }
+}
+
+fn is_exclusive<'tcx>(
+    place: Place<'tcx>,
+    capabilities: &CapabilitySummary<'tcx>,
+    borrows: &BorrowsState<'tcx>,
+    repacker: PlaceRepacker<'_, 'tcx>,
+) -> bool {
+    if blocking_edges(place, borrows).next().is_some() {
+        return false;
+    }
+    let related: Vec<_> = capabilities
+        .capabilities()
+        .into_iter()
+        .filter(|(other, _)| related(place, *other))
+        .collect();
+    if related.is_empty() {
+        // Places behind references are not in the summary
+        return !place.is_owned(repacker.body(), repacker.tcx());
+    }
+    related
+        .iter()
+        .all(|(_, kind)| *kind == CapabilityKind::Exclusive)
+}
+
+/// The edges other than deref expansions that block `place`, a prefix of it
+/// or one of its descendants
+fn blocking_edges<'a, 'tcx>(
+    place: Place<'tcx>,
+    borrows: &'a BorrowsState<'tcx>,
+) -> impl Iterator<Item = &'a BorrowsEdge<'tcx>> {
+    borrows.graph().edges().filter(move |edge| {
+        !matches!(edge.kind(), BorrowsEdgeKind::DerefExpansion(_))
+            && edge
+                .blocked_places()
+                .into_iter()
+                .any(|blocked| match blocked {
+                    MaybeRemotePlace::Local(MaybeOldPlace::Current { place: blocked }) => {
+                        related(place, blocked)
+                    }
+                    _ => false,
+                })
+    })
+}
+
+fn related<'tcx>(place: Place<'tcx>, other: Place<'tcx>) -> bool {
+    place.is_prefix(other) || other.is_prefix(place)
+}
@@ -24,7 +24,7 @@ where
     /// Converts the custom Graph into a petgraph DiGraphMap
     fn to_petgraph(&self) -> DiGraphMap<N, ()> {
         let mut graph = DiGraphMap::<N, ()>::new();
-        for (from, to) in &self.edges {
+        for (from, to) in self.edges.iter().collect::<BTreeSet<_>>() {
             graph.add_edge(*from, *to, ());
         }
         graph
@@ -0,0 +1,79 @@
+// © 2023, ETH Zurich
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Errors of the analysis of a single body, so that the other bodies of a
+//! crate can still be analyzed.
+
+use std::{
+    backtrace::Backtrace,
+    cell::{Cell, RefCell},
+    panic::UnwindSafe,
+    sync::Once,
+};
+
+/// Why the analysis of a body failed.
+#[derive(Clone, Debug)]
+pub enum PcsError {
+    /// The analysis panicked, e.g. on a construct it does not support yet
+    InternalError { message: String, backtrace: String },
+}
+
+impl std::fmt::Display for PcsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PcsError::InternalError { message, .. } => write!(f, "internal error: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for PcsError {}
+
+thread_local! {
+    /// Whether a panic on this thread is caught by [`catch_internal_errors`]
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+    /// The backtrace of the last panic caught by [`catch_internal_errors`]
+    static BACKTRACE: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Installs a panic hook that records the backtrace of panics caught by
+/// [`catch_internal_errors`] instead of printing them, and otherwise defers
+/// to the hook installed before.
+fn install_panic_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if CATCHING.get() {
+                let backtrace = Backtrace::force_capture().to_string();
+                BACKTRACE.with(|last| *last.borrow_mut() = Some(backtrace));
+            } else {
+                previous(info);
+            }
+        }));
+    });
+}
+
+/// Runs `f`, turning a panic into a [`PcsError::InternalError`] with the
+/// panic message and the backtrace at the panic.
+pub fn catch_internal_errors<R>(f: impl FnOnce() -> R + UnwindSafe) -> Result<R, PcsError> {
+    install_panic_hook();
+    let was_catching = CATCHING.replace(true);
+    let result = std::panic::catch_unwind(f);
+    CATCHING.set(was_catching);
+    result.map_err(|payload| {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => match payload.downcast::<&'static str>() {
+                Ok(message) => message.to_string(),
+                Err(_) => "panicked with a non-string payload".to_string(),
+            },
+        };
+        let backtrace = BACKTRACE
+            .with(|last| last.borrow_mut().take())
+            .unwrap_or_default();
+        PcsError::InternalError { message, backtrace }
+    })
+}
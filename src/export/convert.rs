@@ -0,0 +1,578 @@
+use rustc_interface::{
+    dataflow::PcsAnalysis,
+    middle::{
+        mir::{Location, RetagKind},
+        ty::TyCtxt,
+    },
+};
+
+use crate::{
+    borrows::{
+        borrows_edge::{BorrowsEdge, BorrowsEdgeKind, ToBorrowsEdge},
+        borrows_state::ExpansionOp,
+        domain::{MaybeOldPlace, MaybeRemotePlace, Reborrow, ReborrowPhase},
+        engine::BorrowsDomain,
+        path_condition::PathConditions,
+        projection_summary::PrecisionLoss,
+    },
+    combined_pcs::{PcsDelta, PcsEngine, UnblockAction},
+    free_pcs::{
+        engine::FpcsEngine, CapabilityChange, CapabilityKind, CapabilityLocal, CapabilitySummary,
+        FreePcsAnalysis, FreePcsLocation, HasExtra, HasFpcs, RepackOp, Retag, RetagPermission,
+    },
+    rustc_interface,
+    utils::{
+        borrow_scopes::ProgramPoint, display::PlaceDisplay, origins::Origins,
+        region_names::RegionNames, sorted_by_debug, Place, PlaceRepacker,
+    },
+    FpcsOutput, ReborrowBridge,
+};
+
+use super::model::*;
+
+/// Conversion of an analysis result to its exported, session-independent
+/// counterpart.
+pub trait Export<'tcx> {
+    type Exported;
+    fn export(&self, repacker: PlaceRepacker<'_, 'tcx>) -> Self::Exported;
+}
+
+impl<'tcx> Export<'tcx> for () {
+    type Exported = ();
+    fn export(&self, _repacker: PlaceRepacker<'_, 'tcx>) {}
+}
+
+/// What an analysis knows about the body beyond its results at each
+/// location, added by [`FreePcsAnalysis::export`] to the export of the
+/// results.
+pub trait ExportBodyFacts<'tcx, T: Export<'tcx>, B: Export<'tcx>> {
+    /// The regions of the body that have a description
+    fn export_regions(&self) -> Vec<ExportedRegion> {
+        vec![]
+    }
+
+    /// Adds to `exported`, the export of `results`, what the analysis knows
+    /// about the statement
+    fn annotate_location(
+        &self,
+        _results: &FreePcsLocation<'tcx, T, B>,
+        _exported: &mut ExportedPcsLocation<T::Exported, B::Exported>,
+    ) {
+    }
+}
+
+impl<'tcx> ExportBodyFacts<'tcx, (), ()> for FpcsEngine<'_, 'tcx> {}
+
+impl<'mir, 'tcx> ExportBodyFacts<'tcx, BorrowsDomain<'mir, 'tcx>, ReborrowBridge<'tcx>>
+    for PcsEngine<'mir, 'tcx>
+{
+    fn export_regions(&self) -> Vec<ExportedRegion> {
+        self.cgx.region_names().export()
+    }
+
+    /// Annotates the borrows edges after the statement with the origins
+    /// justifying them
+    fn annotate_location(
+        &self,
+        results: &FreePcsLocation<'tcx, BorrowsDomain<'mir, 'tcx>, ReborrowBridge<'tcx>>,
+        exported: &mut ExportedPcsLocation<ExportedBorrowsState, ExportedReborrowBridge>,
+    ) {
+        let Some(facts) = self.cgx.origin_facts() else {
+            return;
+        };
+        let origins = facts.at(ProgramPoint::After(results.location));
+        for (exported_edge, edge) in exported
+            .extra
+            .edges
+            .iter_mut()
+            .zip(results.extra.after.graph().sorted_edges())
+        {
+            exported_edge.origins = match edge.kind() {
+                BorrowsEdgeKind::Reborrow(reborrow) => origins
+                    .of_borrow(reborrow.reserve_location())
+                    .map(|origins| origins.export()),
+                BorrowsEdgeKind::RegionProjectionMember(member) => {
+                    Some(origins.of_region(member.projection.region()).export())
+                }
+                BorrowsEdgeKind::DerefExpansion(_) | BorrowsEdgeKind::Abstraction(_) => None,
+            };
+        }
+    }
+}
+
+impl<'tcx> Export<'tcx> for Location {
+    type Exported = ExportedLocation;
+    fn export(&self, _repacker: PlaceRepacker<'_, 'tcx>) -> ExportedLocation {
+        ExportedLocation {
+            block: self.block.as_usize(),
+            statement_index: self.statement_index,
+        }
+    }
+}
+
+impl<'tcx> Export<'tcx> for Place<'tcx> {
+    type Exported = ExportedPlace;
+    fn export(&self, repacker: PlaceRepacker<'_, 'tcx>) -> ExportedPlace {
+        let name = match self.to_string(repacker) {
+            PlaceDisplay::Temporary(_) => None,
+            PlaceDisplay::User(_, name) => Some(name),
+        };
+        ExportedPlace {
+            local: self.local.as_usize(),
+            mir: format!("{self:?}"),
+            name,
+            ty: format!("{}", self.ty(repacker).ty),
+            pinned: self.is_pinned(repacker),
+        }
+    }
+}
+
+impl<'tcx> Export<'tcx> for MaybeOldPlace<'tcx> {
+    type Exported = ExportedMaybeOldPlace;
+    fn export(&self, repacker: PlaceRepacker<'_, 'tcx>) -> ExportedMaybeOldPlace {
+        ExportedMaybeOldPlace {
+            place: self.place().export(repacker),
+            at: self.location().map(|at| format!("{at:?}")),
+        }
+    }
+}
+
+impl<'tcx> Export<'tcx> for MaybeRemotePlace<'tcx> {
+    type Exported = ExportedMaybeRemotePlace;
+    fn export(&self, repacker: PlaceRepacker<'_, 'tcx>) -> ExportedMaybeRemotePlace {
+        match self {
+            MaybeRemotePlace::Local(place) => {
+                ExportedMaybeRemotePlace::Local(place.export(repacker))
+            }
+            MaybeRemotePlace::Remote(remote) => ExportedMaybeRemotePlace::Remote {
+                local: remote.assigned_local().as_usize(),
+            },
+        }
+    }
+}
+
+impl<'tcx> Export<'tcx> for CapabilityKind {
+    type Exported = ExportedCapability;
+    fn export(&self, _repacker: PlaceRepacker<'_, 'tcx>) -> ExportedCapability {
+        match self {
+            CapabilityKind::Write => ExportedCapability::Write,
+            CapabilityKind::Exclusive => ExportedCapability::Exclusive,
+            CapabilityKind::ShallowExclusive => ExportedCapability::ShallowExclusive,
+        }
+    }
+}
+
+impl<'tcx> Export<'tcx> for CapabilitySummary<'tcx> {
+    type Exported = ExportedCapabilitySummary;
+    fn export(&self, repacker: PlaceRepacker<'_, 'tcx>) -> ExportedCapabilitySummary {
+        let mut capabilities: Vec<_> = self
+            .iter()
+            .filter_map(|local| match local {
+                CapabilityLocal::Unallocated => None,
+                CapabilityLocal::Allocated(projections) => Some(projections.iter()),
+            })
+            .flatten()
+            .map(|(place, capability)| ExportedPlaceCapability {
+                place: place.export(repacker),
+                capability: capability.export(repacker),
+            })
+            .collect();
+        capabilities.sort_by(|a, b| a.place.cmp(&b.place));
+        ExportedCapabilitySummary(capabilities)
+    }
+}
+
+impl<'tcx> Export<'tcx> for PrecisionLoss<'tcx> {
+    type Exported = ExportedPrecisionLoss;
+    fn export(&self, repacker: PlaceRepacker<'_, 'tcx>) -> ExportedPrecisionLoss {
+        ExportedPrecisionLoss {
+            location: self.location.export(repacker),
+            summarized: self.summarized.export(repacker),
+            place: self.place.export(repacker),
+            depth: self.depth,
+        }
+    }
+}
+
+impl<'tcx> Export<'tcx> for Retag<'tcx> {
+    type Exported = ExportedRetag;
+    fn export(&self, repacker: PlaceRepacker<'_, 'tcx>) -> ExportedRetag {
+        ExportedRetag {
+            location: self.location.export(repacker),
+            kind: match self.kind {
+                RetagKind::FnEntry => ExportedRetagKind::FnEntry,
+                RetagKind::TwoPhase => ExportedRetagKind::TwoPhase,
+                RetagKind::Raw => ExportedRetagKind::Raw,
+                RetagKind::Default => ExportedRetagKind::Default,
+            },
+            implicit: self.implicit,
+            place: self.place.export(repacker),
+            capability: self.capability.map(|c| c.export(repacker)),
+            permission: self.permission.map(|permission| match permission {
+                RetagPermission::Unique => ExportedRetagPermission::Unique,
+                RetagPermission::SharedReadWrite => ExportedRetagPermission::SharedReadWrite,
+                RetagPermission::SharedReadOnly => ExportedRetagPermission::SharedReadOnly,
+            }),
+            parent: self.parent,
+        }
+    }
+}
+
+impl<'tcx> Export<'tcx> for RepackOp<'tcx> {
+    type Exported = ExportedRepackOp;
+    fn export(&self, repacker: PlaceRepacker<'_, 'tcx>) -> ExportedRepackOp {
+        match *self {
+            RepackOp::StorageDead(local) => ExportedRepackOp::StorageDead {
+                local: local.as_usize(),
+            },
+            RepackOp::IgnoreStorageDead(local) => ExportedRepackOp::IgnoreStorageDead {
+                local: local.as_usize(),
+            },
+            RepackOp::Weaken(place, from, to) => ExportedRepackOp::Weaken {
+                place: place.export(repacker),
+                from: from.export(repacker),
+                to: to.export(repacker),
+            },
+            RepackOp::Expand(from, guide, capability) => ExportedRepackOp::Expand {
+                from: from.export(repacker),
+                guide: guide.export(repacker),
+                capability: capability.export(repacker),
+            },
+            RepackOp::Collapse(to, guide, capability) => ExportedRepackOp::Collapse {
+                to: to.export(repacker),
+                guide: guide.export(repacker),
+                capability: capability.export(repacker),
+            },
+            RepackOp::DerefShallowInit(from, to) => ExportedRepackOp::DerefShallowInit {
+                from: from.export(repacker),
+                to: to.export(repacker),
+            },
+        }
+    }
+}
+
+impl<'tcx> Export<'tcx> for Reborrow<'tcx> {
+    type Exported = ExportedReborrow;
+    fn export(&self, repacker: PlaceRepacker<'_, 'tcx>) -> ExportedReborrow {
+        ExportedReborrow {
+            blocked_place: self.blocked_place.export(repacker),
+            assigned_place: self.assigned_place.export(repacker),
+            mutable: self.mutability.is_mut(),
+            reserve_location: self.reserve_location().export(repacker),
+            region: format!("{:?}", self.region),
+            phase: match self.phase() {
+                ReborrowPhase::Reserved => ExportedReborrowPhase::Reserved,
+                ReborrowPhase::Active => ExportedReborrowPhase::Active,
+            },
+        }
+    }
+}
+
+impl<'tcx> Export<'tcx> for BorrowsEdge<'tcx> {
+    type Exported = ExportedBorrowsEdge;
+    fn export(&self, repacker: PlaceRepacker<'_, 'tcx>) -> ExportedBorrowsEdge {
+        let kind = match self.kind() {
+            BorrowsEdgeKind::Reborrow(reborrow) => {
+                ExportedBorrowsEdgeKind::Reborrow(Box::new(reborrow.export(repacker)))
+            }
+            BorrowsEdgeKind::DerefExpansion(_) => ExportedBorrowsEdgeKind::DerefExpansion,
+            BorrowsEdgeKind::Abstraction(_) => ExportedBorrowsEdgeKind::Abstraction,
+            BorrowsEdgeKind::RegionProjectionMember(_) => {
+                ExportedBorrowsEdgeKind::RegionProjectionMember
+            }
+        };
+        let mut blocked_places: Vec<_> = self
+            .blocked_places()
+            .iter()
+            .map(|place| place.export(repacker))
+            .collect();
+        blocked_places.sort();
+        let mut blocked_by_places: Vec<_> = self
+            .blocked_by_places(repacker)
+            .iter()
+            .map(|place| place.export(repacker))
+            .collect();
+        blocked_by_places.sort();
+        ExportedBorrowsEdge {
+            kind,
+            blocked_places,
+            blocked_by_places,
+            region_projections: self
+                .kind()
+                .region_projections()
+                .iter()
+                .map(|projection| projection.to_string())
+                .collect(),
+            conditions: self.conditions().to_string(),
+            provenance: self.provenance().to_string(),
+            origins: None,
+        }
+    }
+}
+
+impl<'mir, 'tcx> Export<'tcx> for BorrowsDomain<'mir, 'tcx> {
+    type Exported = ExportedBorrowsState;
+    fn export(&self, repacker: PlaceRepacker<'_, 'tcx>) -> ExportedBorrowsState {
+        ExportedBorrowsState {
+            edges: self
+                .after
+                .graph()
+                .sorted_edges()
+                .into_iter()
+                .map(|edge| edge.export(repacker))
+                .collect(),
+        }
+    }
+}
+
+impl<'tcx> Export<'tcx> for ReborrowBridge<'tcx> {
+    type Exported = ExportedReborrowBridge;
+    fn export(&self, repacker: PlaceRepacker<'_, 'tcx>) -> ExportedReborrowBridge {
+        let actions = self.ug.clone().actions(repacker);
+        ExportedReborrowBridge {
+            expands: sorted_by_debug(&self.expands)
+                .into_iter()
+                .map(|expansion| expansion.value.base().export(repacker))
+                .collect(),
+            added_reborrows: sorted_by_debug(&self.added_reborrows)
+                .into_iter()
+                .map(|reborrow| reborrow.value.export(repacker))
+                .collect(),
+            activated_reborrows: sorted_by_debug(&self.activated_reborrows)
+                .into_iter()
+                .map(|reborrow| reborrow.value.export(repacker))
+                .collect(),
+            expired_reborrows: actions
+                .iter()
+                .filter_map(|action| match action {
+                    UnblockAction::TerminateReborrow {
+                        reserve_location, ..
+                    } => Some(reserve_location.export(repacker)),
+                    _ => None,
+                })
+                .collect(),
+            unblock_actions: actions.iter().map(|action| format!("{action:?}")).collect(),
+            expansion_ops: self
+                .expansion_ops
+                .iter()
+                .map(|op| op.export(repacker))
+                .collect(),
+            killed_reborrows: self
+                .killed_reborrows
+                .iter()
+                .map(|reborrow| reborrow.value.export(repacker))
+                .collect(),
+        }
+    }
+}
+
+impl<'tcx> Export<'tcx> for ExpansionOp<'tcx> {
+    type Exported = ExportedExpansionOp;
+    fn export(&self, repacker: PlaceRepacker<'_, 'tcx>) -> ExportedExpansionOp {
+        match self {
+            ExpansionOp::AddRegionProjectionMember(member) => {
+                ExportedExpansionOp::AddRegionProjectionMember {
+                    places: member
+                        .places
+                        .iter()
+                        .map(|place| place.export(repacker))
+                        .collect(),
+                    projection: member.projection.place.export(repacker),
+                }
+            }
+            ExpansionOp::Unblock(action) => ExportedExpansionOp::Unblock(format!("{action:?}")),
+            ExpansionOp::KillReborrow(reborrow) => {
+                ExportedExpansionOp::KillReborrow(reborrow.value.export(repacker))
+            }
+            ExpansionOp::Expand(expansion) => ExportedExpansionOp::Expand {
+                base: expansion.base().export(repacker),
+                expansion: expansion
+                    .expansion(repacker)
+                    .iter()
+                    .map(|place| place.export(repacker))
+                    .collect(),
+            },
+            ExpansionOp::Summarize(summary) => ExportedExpansionOp::Summarize(
+                summary
+                    .clone()
+                    .to_borrows_edge(PathConditions::new(summary.location().block))
+                    .export(repacker),
+            ),
+        }
+    }
+}
+
+impl<'tcx> Export<'tcx> for CapabilityChange<'tcx> {
+    type Exported = ExportedCapabilityChange;
+    fn export(&self, repacker: PlaceRepacker<'_, 'tcx>) -> ExportedCapabilityChange {
+        ExportedCapabilityChange {
+            place: self.place.export(repacker),
+            before: self.before.map(|kind| kind.export(repacker)),
+            after: self.after.map(|kind| kind.export(repacker)),
+        }
+    }
+}
+
+impl<'tcx> Export<'tcx> for PcsDelta<'tcx> {
+    type Exported = ExportedPcsDelta;
+    fn export(&self, repacker: PlaceRepacker<'_, 'tcx>) -> ExportedPcsDelta {
+        let export_edges =
+            |edges: &[BorrowsEdge<'tcx>]| edges.iter().map(|edge| edge.export(repacker)).collect();
+        ExportedPcsDelta {
+            from: self.from.export(repacker),
+            to: self.to.export(repacker),
+            added_edges: export_edges(&self.added_edges),
+            removed_edges: export_edges(&self.removed_edges),
+            renamed_edges: self
+                .renamed_edges
+                .iter()
+                .map(|(before, after)| (before.export(repacker), after.export(repacker)))
+                .collect(),
+            bridge: self.bridge.export(repacker),
+            capability_changes: self
+                .capability_changes
+                .iter()
+                .map(|change| change.export(repacker))
+                .collect(),
+        }
+    }
+}
+
+impl<'tcx, T: Export<'tcx>, A: Export<'tcx>> Export<'tcx> for FreePcsLocation<'tcx, T, A> {
+    type Exported = ExportedPcsLocation<T::Exported, A::Exported>;
+    fn export(&self, repacker: PlaceRepacker<'_, 'tcx>) -> Self::Exported {
+        let body = repacker.body();
+        let mir = body
+            .stmt_at(self.location)
+            .either(|s| format!("{s:?}"), |t| format!("{:?}", t.kind));
+        let span = body.source_info(self.location).span;
+        let export_repacks =
+            |repacks: &[RepackOp<'tcx>]| repacks.iter().map(|op| op.export(repacker)).collect();
+        ExportedPcsLocation {
+            location: self.location.export(repacker),
+            mir,
+            span: repacker
+                .tcx()
+                .sess
+                .source_map()
+                .span_to_diagnostic_string(span),
+            repacks_start: export_repacks(&self.repacks_start),
+            repacks_middle: export_repacks(&self.repacks_middle),
+            states: ExportedCapabilitySummaries {
+                before_start: self.states.before_start.export(repacker),
+                before_after: self.states.before_after.export(repacker),
+                start: self.states.start.export(repacker),
+                after: self.states.after.export(repacker),
+            },
+            extra_start: self.extra_start.export(repacker),
+            extra_middle: self
+                .extra_middle
+                .as_ref()
+                .map(|extra| extra.export(repacker)),
+            extra: self.extra.export(repacker),
+        }
+    }
+}
+
+impl<'mir, 'tcx, T, D, E> FreePcsAnalysis<'mir, 'tcx, T, D, E>
+where
+    T: Export<'tcx>,
+    D: HasFpcs<'mir, 'tcx> + HasExtra<T, BridgeCtx = TyCtxt<'tcx>>,
+    D::ExtraBridge: Export<'tcx>,
+    E: PcsAnalysis<'tcx, Domain = D> + ExportBodyFacts<'tcx, T, D::ExtraBridge>,
+{
+    /// Exports the results for every basic block of the body, with the facts
+    /// about the body known to the analysis, see [`crate::export`]
+    pub fn export(
+        &mut self,
+    ) -> ExportedBody<T::Exported, <D::ExtraBridge as Export<'tcx>>::Exported> {
+        let repacker = self.repacker();
+        let body = repacker.body();
+        let tcx = repacker.tcx();
+        let blocks = body
+            .basic_blocks
+            .indices()
+            .map(|block| {
+                let results = self.get_all_for_bb(block);
+                let analysis = self.cursor.analysis();
+                ExportedBasicBlock {
+                    block: block.as_usize(),
+                    statements: results
+                        .statements
+                        .iter()
+                        .map(|stmt| {
+                            let mut exported = stmt.export(repacker);
+                            analysis.annotate_location(stmt, &mut exported);
+                            exported
+                        })
+                        .collect(),
+                    successors: results
+                        .terminator
+                        .succs
+                        .iter()
+                        .map(|succ| succ.export(repacker))
+                        .collect(),
+                }
+            })
+            .collect();
+        ExportedBody {
+            name: tcx.def_path_str(body.source.def_id()),
+            span: tcx.sess.source_map().span_to_diagnostic_string(body.span),
+            blocks,
+            regions: self.cursor.analysis().export_regions(),
+            retags: vec![],
+            permission_ops: vec![],
+            precision_losses: vec![],
+        }
+    }
+
+    /// Exports the retags of the body, see
+    /// [`FreePcsAnalysis::retags`]
+    pub fn export_retags(&mut self) -> Vec<ExportedRetag> {
+        let repacker = self.repacker();
+        self.retags()
+            .iter()
+            .map(|retag| retag.export(repacker))
+            .collect()
+    }
+}
+
+impl RegionNames {
+    pub fn export(&self) -> Vec<ExportedRegion> {
+        self.iter()
+            .map(|(region, name)| ExportedRegion {
+                region: format!("{:?}", region),
+                name: name.to_string(),
+            })
+            .collect()
+    }
+}
+
+impl Origins {
+    pub fn export(&self) -> ExportedOrigins {
+        ExportedOrigins {
+            origins: self
+                .origins
+                .iter()
+                .map(|origin| format!("{:?}", origin))
+                .collect(),
+            subsets: self
+                .subsets
+                .iter()
+                .map(|(sub, sup)| (format!("{:?}", sub), format!("{:?}", sup)))
+                .collect(),
+        }
+    }
+}
+
+impl<'mir, 'tcx> FpcsOutput<'mir, 'tcx> {
+    /// Exports the places summarized because of the projection depth limit,
+    /// see [`FpcsOutput::precision_losses`]
+    pub fn export_precision_losses(&mut self) -> Vec<ExportedPrecisionLoss> {
+        let repacker = self.repacker();
+        self.precision_losses()
+            .iter()
+            .map(|loss| loss.export(repacker))
+            .collect()
+    }
+}
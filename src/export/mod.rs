@@ -0,0 +1,19 @@
+//! A representation of the analysis results that does not depend on the
+//! compiler session.
+//!
+//! The types in [`model`] contain only plain data: places, regions, types and
+//! spans are stored as the strings rustc would print for them. They can thus
+//! outlive the [`TyCtxt`](crate::rustc_interface::middle::ty::TyCtxt) they were
+//! computed with, be serialized with `serde` and sent to other processes, or
+//! be consumed by programs that do not link against rustc. Use
+//! [`FreePcsAnalysis::export`](crate::free_pcs::FreePcsAnalysis::export) to
+//! export the results of an analysis.
+
+mod convert;
+pub mod model;
+mod permission;
+pub mod query;
+pub mod smt;
+
+pub use convert::Export;
+pub use model::*;
@@ -0,0 +1,384 @@
+//! The exported result types. None of these reference rustc types.
+
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct ExportedLocation {
+    pub block: usize,
+    pub statement_index: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct ExportedPlace {
+    pub local: usize,
+    /// The place as printed in MIR, e.g. `(*_1).0`
+    pub mir: String,
+    /// The place in terms of source-level variable names, if it has one, e.g.
+    /// `x.f`
+    pub name: Option<String>,
+    pub ty: String,
+    /// Whether the place is behind the pointer of a `Pin`, e.g. `*(p.0)` for
+    /// `p: Pin<&mut T>`
+    pub pinned: bool,
+}
+
+/// A place, possibly referring to its value at an earlier point
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct ExportedMaybeOldPlace {
+    pub place: ExportedPlace,
+    /// Where the snapshot of the place was taken, if it is old
+    pub at: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum ExportedMaybeRemotePlace {
+    Local(ExportedMaybeOldPlace),
+    /// The place a reference argument refers to in the caller
+    Remote {
+        local: usize,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum ExportedCapability {
+    Write,
+    Exclusive,
+    ShallowExclusive,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedPlaceCapability {
+    pub place: ExportedPlace,
+    pub capability: ExportedCapability,
+}
+
+/// The capabilities of all places, ordered by place
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedCapabilitySummary(pub Vec<ExportedPlaceCapability>);
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedCapabilitySummaries {
+    pub before_start: ExportedCapabilitySummary,
+    pub before_after: ExportedCapabilitySummary,
+    pub start: ExportedCapabilitySummary,
+    pub after: ExportedCapabilitySummary,
+}
+
+/// See [`RepackOp`](crate::free_pcs::RepackOp) for the meaning of each operation
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportedRepackOp {
+    StorageDead {
+        local: usize,
+    },
+    IgnoreStorageDead {
+        local: usize,
+    },
+    Weaken {
+        place: ExportedPlace,
+        from: ExportedCapability,
+        to: ExportedCapability,
+    },
+    Expand {
+        from: ExportedPlace,
+        guide: ExportedPlace,
+        capability: ExportedCapability,
+    },
+    Collapse {
+        to: ExportedPlace,
+        guide: ExportedPlace,
+        capability: ExportedCapability,
+    },
+    DerefShallowInit {
+        from: ExportedPlace,
+        to: ExportedPlace,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedReborrow {
+    pub blocked_place: ExportedMaybeRemotePlace,
+    pub assigned_place: ExportedMaybeOldPlace,
+    pub mutable: bool,
+    pub reserve_location: ExportedLocation,
+    pub region: String,
+    pub phase: ExportedReborrowPhase,
+}
+
+/// Whether a (two-phase) reborrow has been activated yet
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportedReborrowPhase {
+    Reserved,
+    Active,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportedBorrowsEdgeKind {
+    Reborrow(Box<ExportedReborrow>),
+    DerefExpansion,
+    Abstraction,
+    RegionProjectionMember,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedBorrowsEdge {
+    pub kind: ExportedBorrowsEdgeKind,
+    pub blocked_places: Vec<ExportedMaybeRemotePlace>,
+    pub blocked_by_places: Vec<ExportedMaybeOldPlace>,
+    pub region_projections: Vec<String>,
+    /// The path conditions under which the edge holds
+    pub conditions: String,
+    /// Why the edge was created
+    pub provenance: String,
+    /// The Polonius origins justifying reborrow and region projection member
+    /// edges. Only available for analyses with borrow-checker facts, see
+    /// [`crate::utils::origins::OriginFacts`].
+    #[serde(default)]
+    pub origins: Option<ExportedOrigins>,
+}
+
+/// The origins containing the loan of a reborrow (or the region of a region
+/// projection), and the subset relations from them
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedOrigins {
+    pub origins: Vec<String>,
+    /// Pairs of origins where the loans of the first flow into the second
+    pub subsets: Vec<(String, String)>,
+}
+
+/// The borrows graph after a statement
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedBorrowsState {
+    pub edges: Vec<ExportedBorrowsEdge>,
+}
+
+/// The changes to the borrows graph between two points
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedReborrowBridge {
+    pub expands: Vec<ExportedMaybeOldPlace>,
+    pub added_reborrows: Vec<ExportedReborrow>,
+    /// Two-phase reborrows activated at this point
+    pub activated_reborrows: Vec<ExportedReborrow>,
+    /// Reserve locations of the reborrows that expire at this point
+    pub expired_reborrows: Vec<ExportedLocation>,
+    pub unblock_actions: Vec<String>,
+    /// The operations expanding the graph to the places accessed by the
+    /// statement, in the order they are applied
+    #[serde(default)]
+    pub expansion_ops: Vec<ExportedExpansionOp>,
+    /// The reborrows killed at this point, including those killed when
+    /// joining the states at the start of a block
+    #[serde(default)]
+    pub killed_reborrows: Vec<ExportedReborrow>,
+}
+
+/// See [`ExpansionOp`](crate::borrows::borrows_state::ExpansionOp) for the
+/// meaning of each operation
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportedExpansionOp {
+    AddRegionProjectionMember {
+        places: Vec<ExportedMaybeRemotePlace>,
+        projection: ExportedMaybeOldPlace,
+    },
+    Unblock(String),
+    KillReborrow(ExportedReborrow),
+    Expand {
+        base: ExportedMaybeOldPlace,
+        expansion: Vec<ExportedMaybeOldPlace>,
+    },
+    Summarize(ExportedBorrowsEdge),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedCapabilityChange {
+    pub place: ExportedPlace,
+    pub before: Option<ExportedCapability>,
+    pub after: Option<ExportedCapability>,
+}
+
+/// The changes between the states after two locations, see
+/// [`PcsDelta`](crate::combined_pcs::PcsDelta)
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedPcsDelta {
+    pub from: ExportedLocation,
+    pub to: ExportedLocation,
+    pub added_edges: Vec<ExportedBorrowsEdge>,
+    pub removed_edges: Vec<ExportedBorrowsEdge>,
+    /// Each edge before and after some of its places were made old
+    pub renamed_edges: Vec<(ExportedBorrowsEdge, ExportedBorrowsEdge)>,
+    pub bridge: ExportedReborrowBridge,
+    pub capability_changes: Vec<ExportedCapabilityChange>,
+}
+
+/// The exported counterpart of
+/// [`FreePcsLocation`](crate::free_pcs::FreePcsLocation).
+///
+/// `E` is the exported extra state (e.g. [`ExportedBorrowsState`]) and `B` the exported bridge
+/// between extra states (e.g. [`ExportedReborrowBridge`]).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedPcsLocation<E, B> {
+    pub location: ExportedLocation,
+    /// The MIR statement or terminator at the location
+    pub mir: String,
+    pub span: String,
+    pub repacks_start: Vec<ExportedRepackOp>,
+    pub repacks_middle: Vec<ExportedRepackOp>,
+    pub states: ExportedCapabilitySummaries,
+    pub extra_start: B,
+    pub extra_middle: Option<B>,
+    pub extra: E,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedBasicBlock<E, B> {
+    pub block: usize,
+    pub statements: Vec<ExportedPcsLocation<E, B>>,
+    /// The state at the start of each successor, with the repacks required on
+    /// the edge to it
+    pub successors: Vec<ExportedPcsLocation<E, B>>,
+}
+
+/// A region variable of the body, e.g. `'?12`, with a description of it in
+/// terms of the source code, e.g. `'a` or `r on line 4`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedRegion {
+    pub region: String,
+    pub name: String,
+}
+
+/// See [`RetagKind`](https://doc.rust-lang.org/nightly/nightly-rustc/rustc_middle/mir/enum.RetagKind.html)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportedRetagKind {
+    FnEntry,
+    TwoPhase,
+    Raw,
+    Default,
+}
+
+/// See [`RetagPermission`](crate::free_pcs::RetagPermission)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportedRetagPermission {
+    Unique,
+    SharedReadWrite,
+    SharedReadOnly,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedRetag {
+    pub location: ExportedLocation,
+    pub kind: ExportedRetagKind,
+    /// Whether this is the creation of a reference or raw pointer rather than
+    /// a `Retag` statement
+    pub implicit: bool,
+    pub place: ExportedPlace,
+    pub capability: Option<ExportedCapability>,
+    pub permission: Option<ExportedRetagPermission>,
+    /// The index of the retag of the pointer this one was derived from
+    pub parent: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedBody<E, B> {
+    /// The path of the function, e.g. `crate::module::f`
+    pub name: String,
+    pub span: String,
+    pub blocks: Vec<ExportedBasicBlock<E, B>>,
+    /// The regions occurring in the results that have a description. Only
+    /// available for analyses with borrow-checker facts, see
+    /// [`crate::utils::region_names::RegionNames`].
+    #[serde(default)]
+    pub regions: Vec<ExportedRegion>,
+    /// The retags of the body, only recorded when requested, see
+    /// [`Retag`](crate::free_pcs::Retag)
+    #[serde(default)]
+    pub retags: Vec<ExportedRetag>,
+    /// The repacks and expansions as permission-logic operations, only
+    /// recorded when requested, see
+    /// [`FpcsOutput::export_permission_ops`](crate::FpcsOutput::export_permission_ops)
+    #[serde(default)]
+    pub permission_ops: Vec<ExportedPermissionBlock>,
+    /// The places that were summarized rather than expanded because of the
+    /// projection depth limit, see
+    /// [`FpcsOutput::precision_losses`](crate::FpcsOutput::precision_losses)
+    #[serde(default)]
+    pub precision_losses: Vec<ExportedPrecisionLoss>,
+}
+
+/// See [`PrecisionLoss`](crate::borrows::projection_summary::PrecisionLoss)
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedPrecisionLoss {
+    pub location: ExportedLocation,
+    /// The place at the limit
+    pub summarized: ExportedMaybeOldPlace,
+    /// The place that was accessed
+    pub place: ExportedMaybeOldPlace,
+    pub depth: usize,
+}
+
+/// A fold-like operation of a permission logic, see
+/// [`FpcsOutput::export_permission_ops`](crate::FpcsOutput::export_permission_ops)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportedPermissionOpKind {
+    /// Exchange the predicate of a place for those of its fields (or of the
+    /// fields of an enum variant)
+    Unfold,
+    /// The inverse of [`ExportedPermissionOpKind::Unfold`]
+    Fold,
+    /// Exchange the predicate of a `Box` or reference for that of its target
+    Unpack,
+    /// The inverse of [`ExportedPermissionOpKind::Unpack`]
+    Pack,
+}
+
+/// The predicate a permission is held for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportedPredicate {
+    /// The place is initialized, as is everything it owns
+    Owned,
+    /// The place is a `Box` whose allocation is initialized but whose target
+    /// may not be
+    Shallow,
+    /// Only the memory of the place is available, its value may be
+    /// uninitialized
+    MemoryBlock,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportedPermissionAmount {
+    Write,
+    /// A fraction of the permission, e.g. behind a shared reference
+    Read,
+}
+
+/// The permission to a predicate of a place, e.g. `acc(Owned(x.f), write)`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedPermission {
+    pub place: ExportedMaybeOldPlace,
+    pub predicate: ExportedPredicate,
+    pub amount: ExportedPermissionAmount,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedPermissionOp {
+    pub kind: ExportedPermissionOpKind,
+    /// The permission folded into (or unfolded from)
+    pub permission: ExportedPermission,
+    /// A place below `permission.place` that selects what to unfold to, e.g.
+    /// the enum variant
+    pub guide: ExportedMaybeOldPlace,
+}
+
+/// The permission operations required before a statement, or on a
+/// control-flow edge
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedPermissionLocation {
+    pub location: ExportedLocation,
+    pub ops: Vec<ExportedPermissionOp>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedPermissionBlock {
+    pub block: usize,
+    pub statements: Vec<ExportedPermissionLocation>,
+    /// The operations on the edge to each successor
+    pub successors: Vec<ExportedPermissionLocation>,
+}
@@ -0,0 +1,162 @@
+//! Translation of repack operations and deref expansions to the fold and
+//! unfold operations of permission logics such as Viper's.
+
+use rustc_interface::middle::mir::ProjectionElem;
+
+use crate::{
+    borrows::deref_expansion::DerefExpansion,
+    free_pcs::{CapabilityKind, FreePcsLocation, RepackOp},
+    rustc_interface,
+    utils::{sorted_by_debug, Place, PlaceRepacker},
+    FpcsOutput, ReborrowBridge,
+};
+
+use super::{model::*, Export};
+
+fn current<'tcx>(place: Place<'tcx>, repacker: PlaceRepacker<'_, 'tcx>) -> ExportedMaybeOldPlace {
+    ExportedMaybeOldPlace {
+        place: place.export(repacker),
+        at: None,
+    }
+}
+
+fn predicate(capability: CapabilityKind) -> ExportedPredicate {
+    match capability {
+        CapabilityKind::Exclusive => ExportedPredicate::Owned,
+        CapabilityKind::ShallowExclusive => ExportedPredicate::Shallow,
+        CapabilityKind::Write => ExportedPredicate::MemoryBlock,
+    }
+}
+
+/// The operation of an expansion of `from` towards `guide` (or of a collapse,
+/// if `fold` is set): unpacking if it dereferences `from`, unfolding otherwise
+fn expansion_kind<'tcx>(
+    from: Place<'tcx>,
+    guide: Place<'tcx>,
+    fold: bool,
+) -> ExportedPermissionOpKind {
+    match (guide.projection[from.projection.len()], fold) {
+        (ProjectionElem::Deref, false) => ExportedPermissionOpKind::Unpack,
+        (ProjectionElem::Deref, true) => ExportedPermissionOpKind::Pack,
+        (_, false) => ExportedPermissionOpKind::Unfold,
+        (_, true) => ExportedPermissionOpKind::Fold,
+    }
+}
+
+impl<'tcx> RepackOp<'tcx> {
+    /// The permission operation corresponding to this repack, if any. Owned
+    /// places always have the full permission.
+    pub fn permission_op(&self, repacker: PlaceRepacker<'_, 'tcx>) -> Option<ExportedPermissionOp> {
+        let (place, guide, capability, fold) = match *self {
+            RepackOp::Expand(from, guide, capability) => (from, guide, capability, false),
+            RepackOp::Collapse(to, guide, capability) => (to, guide, capability, true),
+            RepackOp::DerefShallowInit(from, to) => {
+                return Some(ExportedPermissionOp {
+                    kind: ExportedPermissionOpKind::Unpack,
+                    permission: ExportedPermission {
+                        place: current(from, repacker),
+                        predicate: ExportedPredicate::Shallow,
+                        amount: ExportedPermissionAmount::Write,
+                    },
+                    guide: current(to, repacker),
+                })
+            }
+            RepackOp::StorageDead(_) | RepackOp::IgnoreStorageDead(_) | RepackOp::Weaken(..) => {
+                return None
+            }
+        };
+        Some(ExportedPermissionOp {
+            kind: expansion_kind(place, guide, fold),
+            permission: ExportedPermission {
+                place: current(place, repacker),
+                predicate: predicate(capability),
+                amount: ExportedPermissionAmount::Write,
+            },
+            guide: current(guide, repacker),
+        })
+    }
+}
+
+impl<'tcx> DerefExpansion<'tcx> {
+    /// The permission operation corresponding to this expansion: unpacking
+    /// the target of a reference, or unfolding a place behind one.
+    pub fn permission_op(&self, repacker: PlaceRepacker<'_, 'tcx>) -> ExportedPermissionOp {
+        let base = self.base();
+        let expansion = match self {
+            DerefExpansion::OwnedExpansion(owned) => owned.expansion(repacker),
+            DerefExpansion::BorrowExpansion(borrow) => borrow.expansion(repacker)[0],
+        };
+        let amount = if expansion.place().is_behind_shared_ref(repacker) {
+            ExportedPermissionAmount::Read
+        } else {
+            ExportedPermissionAmount::Write
+        };
+        ExportedPermissionOp {
+            kind: expansion_kind(base.place(), expansion.place(), false),
+            permission: ExportedPermission {
+                place: base.export(repacker),
+                predicate: ExportedPredicate::Owned,
+                amount,
+            },
+            guide: expansion.export(repacker),
+        }
+    }
+}
+
+impl<'mir, 'tcx> FpcsOutput<'mir, 'tcx> {
+    /// The fold, unfold, pack and unpack operations of a permission logic
+    /// corresponding to the repacks of the owned places and the expansions of
+    /// the borrowed ones, for every basic block of the body.
+    ///
+    /// Weakening a capability and killing a local have no counterpart and are
+    /// left out, as are the collapses of borrowed places: the borrows graph
+    /// does not record where expansions are packed up again.
+    pub fn export_permission_ops(&mut self) -> Vec<ExportedPermissionBlock> {
+        let repacker = self.repacker();
+        let export_location = |location: &FreePcsLocation<'tcx, _, ReborrowBridge<'tcx>>| {
+            let expansions = |bridge: &ReborrowBridge<'tcx>| {
+                sorted_by_debug(&bridge.expands)
+                    .into_iter()
+                    .map(|expansion| expansion.value.permission_op(repacker))
+                    .collect::<Vec<_>>()
+            };
+            let mut ops: Vec<_> = location
+                .repacks_start
+                .iter()
+                .filter_map(|op| op.permission_op(repacker))
+                .collect();
+            ops.extend(expansions(&location.extra_start));
+            ops.extend(
+                location
+                    .repacks_middle
+                    .iter()
+                    .filter_map(|op| op.permission_op(repacker)),
+            );
+            if let Some(bridge) = &location.extra_middle {
+                ops.extend(expansions(bridge));
+            }
+            ExportedPermissionLocation {
+                location: location.location.export(repacker),
+                ops,
+            }
+        };
+        repacker
+            .body()
+            .basic_blocks
+            .indices()
+            .map(|block| {
+                let results = self.get_all_for_bb(block);
+                ExportedPermissionBlock {
+                    block: block.as_usize(),
+                    statements: results.statements.iter().map(export_location).collect(),
+                    successors: results
+                        .terminator
+                        .succs
+                        .iter()
+                        .map(export_location)
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,309 @@
+//! A small s-expression query language over exported results, so that
+//! questions about the results can be scripted without linking against
+//! rustc.
+//!
+//! A query is a list whose head names the query, followed by positional
+//! string arguments and `key: value` (or `key value`) arguments:
+//!
+//! - `(edges kind: reborrow blocking: "_2" blocked-by: "x" at: "bb3[2]")`
+//!   lists the edges of the borrows graph after each statement, restricted to
+//!   the given kind (`reborrow`, `deref-expansion`, `abstraction` or
+//!   `region-projection-member`), to edges blocking or blocked by a place, and
+//!   to a location. All arguments are optional.
+//! - `(blocked-by "_1.f" at "bb3[2]")` lists the places blocking `_1.f` after
+//!   the statement at `bb3[2]`.
+//! - `(blocking "x" at "bb3[2]")` lists the places that `x` blocks.
+//! - `(capability "x" at "bb3[2]")` is the capability of `x`, if it has one.
+//!
+//! Places are matched against their MIR representation (`(*_1).0`) and their
+//! source-level name (`x.f`), regardless of whether they are old. A remote
+//! place is written `Remote(_1)`.
+
+use serde_derive::Serialize;
+
+use super::model::*;
+
+/// The exported results of the combined analysis, which queries run on
+pub type QueryBody = ExportedBody<ExportedBorrowsState, ExportedReborrowBridge>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum SExpr {
+    Atom(String),
+    Str(String),
+    List(Vec<SExpr>),
+}
+
+fn tokenize(input: &str) -> Result<Vec<SExpr>, String> {
+    let mut stack: Vec<Vec<SExpr>> = vec![vec![]];
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => stack.push(vec![]),
+            ')' => {
+                let list = stack.pop().unwrap();
+                stack
+                    .last_mut()
+                    .ok_or("Unbalanced `)`")?
+                    .push(SExpr::List(list));
+            }
+            '"' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => s.push(c),
+                        None => return Err("Unterminated string".to_string()),
+                    }
+                }
+                stack.last_mut().unwrap().push(SExpr::Str(s));
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut atom = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' || c == '"' {
+                        break;
+                    }
+                    atom.push(c);
+                    chars.next();
+                }
+                stack.last_mut().unwrap().push(SExpr::Atom(atom));
+            }
+        }
+    }
+    if stack.len() != 1 {
+        return Err("Unbalanced `(`".to_string());
+    }
+    Ok(stack.pop().unwrap())
+}
+
+/// Parses a location as printed in MIR, e.g. `bb3[2]`
+pub fn parse_location(s: &str) -> Result<ExportedLocation, String> {
+    let err = || format!("Invalid location `{s}`, expected e.g. `bb3[2]`");
+    let (block, index) = s
+        .strip_prefix("bb")
+        .and_then(|s| s.strip_suffix(']'))
+        .and_then(|s| s.split_once('['))
+        .ok_or_else(err)?;
+    Ok(ExportedLocation {
+        block: block.parse().map_err(|_| err())?,
+        statement_index: index.parse().map_err(|_| err())?,
+    })
+}
+
+/// A query, see the [module documentation](self)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Query {
+    Edges {
+        kind: Option<String>,
+        blocking: Option<String>,
+        blocked_by: Option<String>,
+        at: Option<ExportedLocation>,
+    },
+    BlockedBy {
+        place: String,
+        at: ExportedLocation,
+    },
+    Blocking {
+        place: String,
+        at: ExportedLocation,
+    },
+    Capability {
+        place: String,
+        at: ExportedLocation,
+    },
+}
+
+impl std::str::FromStr for Query {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let [SExpr::List(list)] = &tokenize(s)?[..] else {
+            return Err("Expected a single query in parentheses".to_string());
+        };
+        let Some((SExpr::Atom(head), args)) = list.split_first() else {
+            return Err("Expected the name of the query".to_string());
+        };
+        let mut positional = vec![];
+        let mut named = vec![];
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg {
+                SExpr::Str(s) => positional.push(s.clone()),
+                SExpr::Atom(key) => {
+                    let key = key.strip_suffix(':').unwrap_or(key).to_string();
+                    match args.next() {
+                        Some(SExpr::Str(value) | SExpr::Atom(value)) => {
+                            named.push((key, value.clone()))
+                        }
+                        _ => return Err(format!("Expected a value for `{key}`")),
+                    }
+                }
+                SExpr::List(_) => return Err("Nested queries are not supported".to_string()),
+            }
+        }
+        let mut take = |key: &str| {
+            named
+                .iter()
+                .position(|(k, _)| k == key)
+                .map(|i| named.remove(i).1)
+        };
+        let at = take("at").map(|at| parse_location(&at)).transpose()?;
+        let query = match head.as_str() {
+            "edges" => Query::Edges {
+                kind: take("kind"),
+                blocking: take("blocking"),
+                blocked_by: take("blocked-by"),
+                at,
+            },
+            "blocked-by" | "blocking" | "capability" => {
+                let [place] = &positional[..] else {
+                    return Err(format!("`{head}` expects exactly one place"));
+                };
+                let place = place.clone();
+                let at = at.ok_or_else(|| format!("`{head}` expects a location `at`"))?;
+                positional.clear();
+                match head.as_str() {
+                    "blocked-by" => Query::BlockedBy { place, at },
+                    "blocking" => Query::Blocking { place, at },
+                    _ => Query::Capability { place, at },
+                }
+            }
+            other => return Err(format!("Unknown query `{other}`")),
+        };
+        if let Some((key, _)) = named.first() {
+            return Err(format!("Unknown argument `{key}` of `{head}`"));
+        }
+        if !positional.is_empty() {
+            return Err(format!("Unexpected arguments to `{head}`"));
+        }
+        Ok(query)
+    }
+}
+
+/// An edge of the borrows graph after the statement at `location`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct QueryEdge {
+    pub location: ExportedLocation,
+    pub edge: ExportedBorrowsEdge,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum QueryResult {
+    Edges(Vec<QueryEdge>),
+    Places(Vec<ExportedMaybeRemotePlace>),
+    Capability(Option<ExportedCapability>),
+}
+
+fn place_matches(place: &ExportedPlace, pattern: &str) -> bool {
+    place.mir == pattern || place.name.as_deref() == Some(pattern)
+}
+
+fn remote_place_matches(place: &ExportedMaybeRemotePlace, pattern: &str) -> bool {
+    match place {
+        ExportedMaybeRemotePlace::Local(place) => place_matches(&place.place, pattern),
+        ExportedMaybeRemotePlace::Remote { local } => pattern == format!("Remote(_{local})"),
+    }
+}
+
+fn kind_name(kind: &ExportedBorrowsEdgeKind) -> &'static str {
+    match kind {
+        ExportedBorrowsEdgeKind::Reborrow(_) => "reborrow",
+        ExportedBorrowsEdgeKind::DerefExpansion => "deref-expansion",
+        ExportedBorrowsEdgeKind::Abstraction => "abstraction",
+        ExportedBorrowsEdgeKind::RegionProjectionMember => "region-projection-member",
+    }
+}
+
+impl Query {
+    /// Evaluates the query on `body`. Fails if the query refers to a location
+    /// that is not a statement of the body.
+    pub fn eval(&self, body: &QueryBody) -> Result<QueryResult, String> {
+        let statements = || body.blocks.iter().flat_map(|block| block.statements.iter());
+        let statement_at = |at: ExportedLocation| {
+            statements()
+                .find(|stmt| stmt.location == at)
+                .ok_or_else(|| format!("No statement at bb{}[{}]", at.block, at.statement_index))
+        };
+        Ok(match self {
+            Query::Edges {
+                kind,
+                blocking,
+                blocked_by,
+                at,
+            } => {
+                if let Some(at) = at {
+                    statement_at(*at)?;
+                }
+                let edges = statements()
+                    .filter(|stmt| at.map_or(true, |at| stmt.location == at))
+                    .flat_map(|stmt| {
+                        stmt.extra.edges.iter().map(|edge| QueryEdge {
+                            location: stmt.location,
+                            edge: edge.clone(),
+                        })
+                    })
+                    .filter(|e| kind.as_ref().map_or(true, |k| kind_name(&e.edge.kind) == k))
+                    .filter(|e| {
+                        blocking.as_ref().map_or(true, |place| {
+                            e.edge
+                                .blocked_places
+                                .iter()
+                                .any(|p| remote_place_matches(p, place))
+                        })
+                    })
+                    .filter(|e| {
+                        blocked_by.as_ref().map_or(true, |place| {
+                            e.edge
+                                .blocked_by_places
+                                .iter()
+                                .any(|p| place_matches(&p.place, place))
+                        })
+                    })
+                    .collect();
+                QueryResult::Edges(edges)
+            }
+            Query::BlockedBy { place, at } => {
+                let mut places: Vec<_> = statement_at(*at)?
+                    .extra
+                    .edges
+                    .iter()
+                    .filter(|edge| {
+                        edge.blocked_places
+                            .iter()
+                            .any(|p| remote_place_matches(p, place))
+                    })
+                    .flat_map(|edge| edge.blocked_by_places.iter().cloned())
+                    .map(ExportedMaybeRemotePlace::Local)
+                    .collect();
+                places.sort();
+                places.dedup();
+                QueryResult::Places(places)
+            }
+            Query::Blocking { place, at } => {
+                let mut places: Vec<_> = statement_at(*at)?
+                    .extra
+                    .edges
+                    .iter()
+                    .filter(|edge| {
+                        edge.blocked_by_places
+                            .iter()
+                            .any(|p| place_matches(&p.place, place))
+                    })
+                    .flat_map(|edge| edge.blocked_places.iter().cloned())
+                    .collect();
+                places.sort();
+                places.dedup();
+                QueryResult::Places(places)
+            }
+            Query::Capability { place, at } => QueryResult::Capability(
+                statement_at(*at)?
+                    .states
+                    .after
+                    .0
+                    .iter()
+                    .find(|capability| place_matches(&capability.place, place))
+                    .map(|capability| capability.capability),
+            ),
+        })
+    }
+}
@@ -0,0 +1,153 @@
+//! Encoding of the borrows graph as SMT-LIB assertions.
+//!
+//! Each branch `bbX -> bbY` of the body is a boolean constant `|bbX->bbY|`,
+//! of which at most one per source block holds. Each edge `i` of the graph is
+//! a constant `|edge i|` that holds exactly when its path conditions do, and
+//! each place blocked by some edge is a constant `|blocked p|` that holds
+//! exactly when one of those edges does. The order in which edges may expire
+//! is given by integer constants `|expiry i|`: an edge that blocks a place
+//! another edge is blocked by must expire first.
+//!
+//! The path conditions are over-approximated: whether the blocks they are
+//! conditions on are reached at all is not encoded.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
+
+use crate::{
+    borrows::{
+        borrows_edge::BorrowsEdge, borrows_state::BorrowsState, domain::MaybeRemotePlace,
+        path_condition::PathConditions,
+    },
+    rustc_interface::middle::mir::Location,
+    utils::PlaceRepacker,
+    FpcsOutput,
+};
+
+fn blocked_place_name<'tcx>(
+    place: MaybeRemotePlace<'tcx>,
+    repacker: PlaceRepacker<'_, 'tcx>,
+) -> String {
+    match place {
+        MaybeRemotePlace::Local(place) => place.to_short_string(repacker),
+        MaybeRemotePlace::Remote(place) => place.to_short_string(repacker),
+    }
+}
+
+/// The formula that holds on the paths satisfying `conditions`, collecting
+/// the branches it mentions in `branches`
+fn condition_formula(
+    conditions: &PathConditions,
+    branches: &mut BTreeMap<String, BTreeSet<String>>,
+) -> String {
+    let PathConditions::Paths(graph) = conditions else {
+        return "true".to_string();
+    };
+    let mut conjuncts = vec![];
+    let mut last_from = None;
+    let mut disjuncts = vec![];
+    for pc in graph.conditions() {
+        let branch = format!("|{:?}->{:?}|", pc.from, pc.to);
+        branches
+            .entry(format!("{:?}", pc.from))
+            .or_default()
+            .insert(branch.clone());
+        if last_from.is_some_and(|from| from != pc.from) {
+            conjuncts.push(format!("(or {})", disjuncts.join(" ")));
+            disjuncts.clear();
+        }
+        last_from = Some(pc.from);
+        disjuncts.push(branch);
+    }
+    if !disjuncts.is_empty() {
+        conjuncts.push(format!("(or {})", disjuncts.join(" ")));
+    }
+    match conjuncts.len() {
+        0 => "true".to_string(),
+        _ => format!("(and {})", conjuncts.join(" ")),
+    }
+}
+
+impl<'tcx> BorrowsState<'tcx> {
+    /// The borrows graph as an SMT-LIB script, see [`crate::export::smt`] for
+    /// the encoding. The script declares and constrains the constants but
+    /// contains no query, so that e.g. whether `x` and `y` can be blocked at
+    /// the same time can be checked by appending
+    /// `(check-sat-assuming (|blocked x| |blocked y|))`.
+    pub fn to_smtlib(&self, repacker: PlaceRepacker<'_, 'tcx>) -> String {
+        let edges: Vec<&BorrowsEdge<'tcx>> = self.graph().sorted_edges();
+        let mut branches = BTreeMap::new();
+        let mut blocked: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        let mut edge_assertions = String::new();
+        for (i, edge) in edges.iter().enumerate() {
+            let condition = condition_formula(edge.conditions(), &mut branches);
+            writeln!(edge_assertions, "; {:?}", edge.kind()).unwrap();
+            writeln!(edge_assertions, "(declare-const |edge {i}| Bool)").unwrap();
+            writeln!(edge_assertions, "(declare-const |expiry {i}| Int)").unwrap();
+            writeln!(edge_assertions, "(assert (= |edge {i}| {condition}))").unwrap();
+            for place in edge.blocked_places().iter() {
+                blocked
+                    .entry(blocked_place_name(*place, repacker))
+                    .or_default()
+                    .push(i);
+            }
+        }
+        let mut script = String::new();
+        for (from, tos) in &branches {
+            writeln!(script, "; branches from {from}").unwrap();
+            for branch in tos {
+                writeln!(script, "(declare-const {branch} Bool)").unwrap();
+            }
+            let tos: Vec<_> = tos.iter().collect();
+            for (j, a) in tos.iter().enumerate() {
+                for b in &tos[j + 1..] {
+                    writeln!(script, "(assert (not (and {a} {b})))").unwrap();
+                }
+            }
+        }
+        script.push_str(&edge_assertions);
+        for (place, blocking) in &blocked {
+            let edges: Vec<_> = blocking.iter().map(|i| format!("|edge {i}|")).collect();
+            writeln!(script, "(declare-const |blocked {place}| Bool)").unwrap();
+            writeln!(
+                script,
+                "(assert (= |blocked {place}| (or {})))",
+                edges.join(" ")
+            )
+            .unwrap();
+        }
+        for (i, edge) in edges.iter().enumerate() {
+            for blocker in edge.blocked_by_places(repacker).iter() {
+                let blocker = MaybeRemotePlace::Local(*blocker);
+                let Some(blocking) = blocked.get(&blocked_place_name(blocker, repacker)) else {
+                    continue;
+                };
+                for j in blocking.iter().filter(|j| **j != i) {
+                    writeln!(
+                        script,
+                        "(assert (=> (and |edge {i}| |edge {j}|) (< |expiry {j}| |expiry {i}|)))"
+                    )
+                    .unwrap();
+                }
+            }
+        }
+        script
+    }
+}
+
+impl<'mir, 'tcx> FpcsOutput<'mir, 'tcx> {
+    /// The borrows graph after each statement of the body as an SMT-LIB
+    /// script, see [`BorrowsState::to_smtlib`]
+    pub fn export_smtlib(&mut self) -> Vec<(Location, String)> {
+        let repacker = self.repacker();
+        let mut scripts = vec![];
+        for block in repacker.body().basic_blocks.indices() {
+            for stmt in self.get_all_for_bb(block).statements {
+                scripts.push((stmt.location, stmt.extra.after.to_smtlib(repacker)));
+            }
+        }
+        scripts
+    }
+}
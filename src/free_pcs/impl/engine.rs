@@ -5,7 +5,7 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use rustc_interface::{
-    dataflow::{Analysis, AnalysisDomain},
+    dataflow::{impl_rustc_analysis, PcsAnalysis},
     middle::mir::{
         visit::Visitor, BasicBlock, Body, CallReturnPlaces, Location,
         Statement, Terminator, TerminatorEdges,
@@ -16,12 +16,15 @@ use crate::{
     rustc_interface, utils::PlaceRepacker
 };
 
-use super::{triple::TripleWalker, FreePlaceCapabilitySummary};
+use super::{
+    triple::{Triple, TripleWalker},
+    FreePlaceCapabilitySummary,
+};
 
 #[derive(Clone, Copy)]
 pub struct FpcsEngine<'a, 'tcx>(pub PlaceRepacker<'a, 'tcx>);
 
-impl<'a, 'tcx> AnalysisDomain<'tcx> for FpcsEngine<'a, 'tcx> {
+impl<'a, 'tcx> PcsAnalysis<'tcx> for FpcsEngine<'a, 'tcx> {
     type Domain = FreePlaceCapabilitySummary<'a, 'tcx>;
     const NAME: &'static str = "free_pcs";
 
@@ -32,9 +35,7 @@ impl<'a, 'tcx> AnalysisDomain<'tcx> for FpcsEngine<'a, 'tcx> {
     fn initialize_start_block(&self, _body: &Body<'tcx>, state: &mut Self::Domain) {
         state.initialize_as_start_block();
     }
-}
 
-impl<'a, 'tcx> Analysis<'tcx> for FpcsEngine<'a, 'tcx> {
     fn apply_before_statement_effect(
         &mut self,
         state: &mut Self::Domain,
@@ -74,6 +75,7 @@ impl<'a, 'tcx> Analysis<'tcx> for FpcsEngine<'a, 'tcx> {
     ) -> TerminatorEdges<'mir, 'tcx> {
         let mut tw = TripleWalker::default();
         tw.visit_terminator(terminator, location);
+        tw.visit_raw_memory_call(terminator, self.0);
         self.apply_main(state, tw, location);
         terminator.edges()
     }
@@ -86,8 +88,24 @@ impl<'a, 'tcx> Analysis<'tcx> for FpcsEngine<'a, 'tcx> {
     ) {
         // Nothing to do here
     }
+
+    fn apply_call_unwind_effect(
+        &mut self,
+        state: &mut Self::Domain,
+        _block: BasicBlock,
+        return_places: CallReturnPlaces<'_, 'tcx>,
+    ) {
+        // The destination was made exclusive by the `Call` triple, but is
+        // only assigned if the call returns
+        return_places.for_each(|place| {
+            let triple = Triple::call_unwind(place.into()).replace_place(self.0);
+            state.post_main.ensures(triple, self.0);
+        });
+    }
 }
 
+impl_rustc_analysis!(FpcsEngine<'a, 'tcx>);
+
 impl<'a, 'tcx> FpcsEngine<'a, 'tcx> {
     fn apply_before(self, state: &mut FreePlaceCapabilitySummary<'a, 'tcx>, tw: TripleWalker<'tcx>, _location: Location) {
         // Repack for operands
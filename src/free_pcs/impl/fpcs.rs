@@ -8,6 +8,7 @@ use std::fmt::{Debug, Formatter, Result};
 
 use derive_more::{Deref, DerefMut};
 use rustc_interface::{
+    data_structures::fx::FxHashMap,
     index::Idx,
     dataflow::fmt::DebugWithContext, index::IndexVec, middle::mir::{Local, RETURN_PLACE},
 };
@@ -15,7 +16,7 @@ use rustc_interface::{
 use crate::{
     free_pcs::{
         CapabilityLocal, CapabilityProjections, RepackOp,
-    }, rustc_interface, utils::PlaceRepacker
+    }, rustc_interface, utils::{sorted_by_debug, Place, PlaceRepacker}
 };
 
 use super::{CapabilityKind, RepackingBridgeSemiLattice, engine::FpcsEngine};
@@ -130,6 +131,107 @@ impl<'tcx> CapabilitySummary<'tcx> {
     pub fn empty() -> Self {
         Self(IndexVec::new())
     }
+
+    /// The capability of each place in the summary
+    pub fn capabilities(&self) -> FxHashMap<Place<'tcx>, CapabilityKind> {
+        self.iter()
+            .filter_map(|local| match local {
+                CapabilityLocal::Allocated(projections) => Some(projections.iter()),
+                CapabilityLocal::Unallocated => None,
+            })
+            .flatten()
+            .map(|(&place, &kind)| (place, kind))
+            .collect()
+    }
+
+    /// The summary as a conjunction of permission assertions, e.g.
+    /// `acc(x.f, write) && acc(*y, read) && acc(uninit(_0), write)`.
+    ///
+    /// Places with [`CapabilityKind::Write`] are wrapped in `uninit` and those
+    /// with [`CapabilityKind::ShallowExclusive`] in `shallow`. Places behind a
+    /// shared reference only have `read` permission. The assertions are
+    /// ordered by place, and an empty summary is rendered as `true`.
+    pub fn to_permission_string(&self, repacker: PlaceRepacker<'_, 'tcx>) -> String {
+        let capabilities = self.capabilities();
+        let assertions: Vec<_> = sorted_by_debug(capabilities.iter())
+            .into_iter()
+            .map(|(place, kind)| {
+                let name = place.to_short_string(repacker);
+                let target = match kind {
+                    CapabilityKind::Exclusive => name,
+                    CapabilityKind::ShallowExclusive => format!("shallow({name})"),
+                    CapabilityKind::Write => format!("uninit({name})"),
+                };
+                let amount = if place.is_behind_shared_ref(repacker) {
+                    "read"
+                } else {
+                    "write"
+                };
+                format!("acc({target}, {amount})")
+            })
+            .collect();
+        if assertions.is_empty() {
+            "true".to_string()
+        } else {
+            assertions.join(" && ")
+        }
+    }
+
+    /// The places whose capability differs between `self` and `other`,
+    /// including places that are only in one of them.
+    pub fn diff(&self, other: &Self) -> CapabilityDiff<'tcx> {
+        let before = self.capabilities();
+        let after = other.capabilities();
+        let places = before
+            .keys()
+            .chain(after.keys().filter(|place| !before.contains_key(place)))
+            .filter(|place| before.get(place) != after.get(place));
+        CapabilityDiff(
+            sorted_by_debug(places)
+                .into_iter()
+                .map(|&place| CapabilityChange {
+                    place,
+                    before: before.get(&place).copied(),
+                    after: after.get(&place).copied(),
+                })
+                .collect(),
+        )
+    }
+}
+
+/// A place whose capability differs between two [`CapabilitySummary`]s.
+///
+/// A capability of `None` means that the place is not in the summary, e.g.
+/// because it is unallocated or only its children are.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CapabilityChange<'tcx> {
+    pub place: Place<'tcx>,
+    pub before: Option<CapabilityKind>,
+    pub after: Option<CapabilityKind>,
+}
+
+/// The changes between two [`CapabilitySummary`]s, see
+/// [`CapabilitySummary::diff`]. The changes are ordered by place.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deref)]
+pub struct CapabilityDiff<'tcx>(Vec<CapabilityChange<'tcx>>);
+
+impl<'tcx> CapabilityDiff<'tcx> {
+    /// Places in both summaries, but with different capabilities
+    pub fn changed(&self) -> impl Iterator<Item = &CapabilityChange<'tcx>> {
+        self.0
+            .iter()
+            .filter(|change| change.before.is_some() && change.after.is_some())
+    }
+
+    /// Places only in the second summary
+    pub fn appeared(&self) -> impl Iterator<Item = &CapabilityChange<'tcx>> {
+        self.0.iter().filter(|change| change.before.is_none())
+    }
+
+    /// Places only in the first summary
+    pub fn disappeared(&self) -> impl Iterator<Item = &CapabilityChange<'tcx>> {
+        self.0.iter().filter(|change| change.after.is_none())
+    }
 }
 
 struct CapabilitySummaryCompare<'a, 'tcx>(&'a CapabilitySummary<'tcx>, &'a CapabilitySummary<'tcx>, &'a str);
@@ -15,7 +15,7 @@ use rustc_interface::{
 use crate::{
     free_pcs::{CapabilityKind, RelatedSet, RepackOp},
     rustc_interface,
-    utils::{Place, PlaceOrdering, PlaceRepacker},
+    utils::{sorted_by_debug, Place, PlaceOrdering, PlaceRepacker},
 };
 
 #[derive(Clone, PartialEq, Eq)]
@@ -69,7 +69,9 @@ pub struct CapabilityProjections<'tcx>(FxHashMap<Place<'tcx>, CapabilityKind>);
 
 impl<'tcx> Debug for CapabilityProjections<'tcx> {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
-        self.0.fmt(f)
+        f.debug_map()
+            .entries(sorted_by_debug(self.0.iter()))
+            .finish()
     }
 }
 
@@ -206,7 +208,7 @@ impl<'tcx> CapabilityProjections<'tcx> {
         }
         let mut ops = Vec::new();
         for (to, from, _) in collapsed {
-            let removed_perms: Vec<_> = old_caps.extract_if(|old, _| to.is_prefix(*old)).collect();
+            let removed_perms = sorted_by_debug(old_caps.extract_if(|old, _| to.is_prefix(*old)));
             let perm = removed_perms
                 .iter()
                 .fold(CapabilityKind::Exclusive, |acc, (_, p)| {
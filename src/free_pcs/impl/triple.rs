@@ -4,9 +4,13 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use rustc_interface::middle::mir::{
-    visit::Visitor, Local, Location, Operand, ProjectionElem, Rvalue, Statement, StatementKind,
-    Terminator, TerminatorKind, RETURN_PLACE,
+use rustc_interface::{
+    data_structures::fx::FxHashSet,
+    middle::mir::{
+        self, visit::Visitor, BasicBlock, Local, Location, Operand, ProjectionElem, Rvalue,
+        Statement, StatementKind, Terminator, TerminatorKind, RETURN_PLACE,
+    },
+    span::{sym, Symbol},
 };
 
 use crate::{
@@ -29,6 +33,14 @@ impl<'tcx> Triple<'tcx> {
         self.post
     }
 
+    /// Unwinding out of a call leaves its `destination` uninitialized.
+    pub fn call_unwind(destination: Place<'tcx>) -> Self {
+        Self {
+            pre: Condition::exclusive(destination),
+            post: Some(Condition::write(destination)),
+        }
+    }
+
     /// Replace all places in the `Condition` with ones that are just above the
     /// first dereference of a ref.
     pub fn replace_place<'b>(self, repacker: PlaceRepacker<'b, 'tcx>) -> Self {
@@ -45,6 +57,10 @@ pub(crate) enum Condition<'tcx> {
     AllocateOrDeallocate(Local),
     Unalloc(Local),
     Return,
+    /// Unwinding out of the function: all owned data must have been dropped
+    /// by the cleanup chain. Locals need not be deallocated, since cleanup
+    /// blocks do not contain `StorageDead`s.
+    Resume,
 }
 
 impl<'tcx> Condition<'tcx> {
@@ -128,14 +144,17 @@ impl<'tcx> Visitor<'tcx> for TripleWalker<'tcx> {
             | Aggregate(_, _)
             | ShallowInitBox(_, _) => {}
 
-            &Ref(_, _, place) | &Len(place) | &Discriminant(place) | &CopyForDeref(place) => {
+            &Ref(_, _, place)
+            | &RawPtr(_, place)
+            | &Len(place)
+            | &Discriminant(place)
+            | &CopyForDeref(place) => {
                 let triple = Triple {
                     pre: Condition::exclusive(place),
                     post: None,
                 };
                 self.operand_triples.push(triple);
             }
-            _ => todo!(),
         }
     }
 
@@ -184,7 +203,6 @@ impl<'tcx> Visitor<'tcx> for TripleWalker<'tcx> {
         let t = match &terminator.kind {
             Goto { .. }
             | SwitchInt { .. }
-            | UnwindResume
             | UnwindTerminate(_)
             | Unreachable
             | Assert { .. }
@@ -194,10 +212,16 @@ impl<'tcx> Visitor<'tcx> for TripleWalker<'tcx> {
                 pre: Condition::Return,
                 post: Some(Condition::write(RETURN_PLACE)),
             },
+            UnwindResume => Triple {
+                pre: Condition::Resume,
+                post: None,
+            },
             &Drop { place, .. } => Triple {
                 pre: Condition::write(place),
                 post: None,
             },
+            // A diverging call never assigns its destination
+            Call { target: None, .. } => return,
             &Call { destination, .. } => Triple {
                 pre: Condition::write(destination),
                 post: Some(Condition::exclusive(destination)),
@@ -214,6 +238,197 @@ impl<'tcx> Visitor<'tcx> for TripleWalker<'tcx> {
     }
 }
 
+impl<'tcx> TripleWalker<'tcx> {
+    /// The effects of a call to `ptr::read`, `ptr::write` or
+    /// `ptr::copy_nonoverlapping` on the places its pointer arguments point
+    /// to: `read` moves out of its source, while `write` and
+    /// `copy_nonoverlapping` initialize their destination. The pointee is only
+    /// known if the pointer is a raw borrow (possibly of a reference, or cast)
+    /// of an owned place, see [`raw_pointee`]; otherwise the call has no
+    /// effect on it.
+    pub(crate) fn visit_raw_memory_call(
+        &mut self,
+        terminator: &Terminator<'tcx>,
+        repacker: PlaceRepacker<'_, 'tcx>,
+    ) {
+        let Some((name, pointee, target)) = raw_memory_call(terminator, repacker) else {
+            return;
+        };
+        let triple = if name == sym::ptr_read {
+            // Reading a `Copy` value leaves the source initialized, as does
+            // reading one whose source is moved afterwards, e.g. by the
+            // `mem::forget(src)` that usually follows the read
+            if is_copy(pointee, repacker) || is_moved_before_init(pointee, target, repacker) {
+                return;
+            }
+            Triple {
+                pre: Condition::exclusive(pointee),
+                post: Some(Condition::write(pointee)),
+            }
+        } else {
+            Triple {
+                pre: Condition::write(pointee),
+                post: Some(Condition::exclusive(pointee)),
+            }
+        };
+        self.main_triples.push(triple);
+    }
+}
+
+/// The name of the function, if `terminator` is a call to `ptr::read`,
+/// `ptr::write` or `ptr::copy_nonoverlapping`, together with the place its
+/// source (for `read`) or destination pointer points to and the block it
+/// returns to.
+fn raw_memory_call<'tcx>(
+    terminator: &Terminator<'tcx>,
+    repacker: PlaceRepacker<'_, 'tcx>,
+) -> Option<(Symbol, Place<'tcx>, BasicBlock)> {
+    let TerminatorKind::Call {
+        func,
+        args,
+        target: Some(target),
+        ..
+    } = &terminator.kind
+    else {
+        return None;
+    };
+    let (def_id, _) = func.const_fn_def()?;
+    let name = repacker.tcx().get_diagnostic_name(def_id)?;
+    let idx = match name {
+        sym::ptr_read | sym::ptr_write => 0,
+        sym::ptr_copy_nonoverlapping => 1,
+        _ => return None,
+    };
+    Some((name, raw_pointee(&args[idx].node, repacker)?, *target))
+}
+
+/// The owned place that the pointer `operand` points to, if the pointer is a
+/// local assigned only once, from a raw borrow of the place (e.g. `&raw const
+/// x.f`), from a raw borrow of the target of a reference to it (e.g. `&raw
+/// mut *r` after `r = &mut x.f`, as for `&mut x.f as *mut _`) or from a cast
+/// of such a pointer.
+fn raw_pointee<'tcx>(
+    operand: &Operand<'tcx>,
+    repacker: PlaceRepacker<'_, 'tcx>,
+) -> Option<Place<'tcx>> {
+    let mut local = operand.place()?.as_local()?;
+    loop {
+        let place = match only_assignment(local, repacker)? {
+            Rvalue::Cast(_, Operand::Copy(pointer) | Operand::Move(pointer), _) => {
+                local = pointer.as_local()?;
+                continue;
+            }
+            &Rvalue::RawPtr(_, place) | &Rvalue::Ref(_, _, place) => place,
+            _ => return None,
+        };
+        if let [ProjectionElem::Deref] = place.projection[..]
+            && repacker.body().local_decls[place.local].ty.is_ref()
+        {
+            local = place.local;
+            continue;
+        }
+        let place: Place<'tcx> = place.into();
+        return place
+            .is_owned(repacker.body(), repacker.tcx())
+            .then_some(place);
+    }
+}
+
+/// Whether the type of `place` is `Copy`
+fn is_copy<'tcx>(place: Place<'tcx>, repacker: PlaceRepacker<'_, 'tcx>) -> bool {
+    let param_env = repacker.tcx().param_env(repacker.body().source.def_id());
+    place
+        .ty(repacker)
+        .ty
+        .is_copy_modulo_regions(repacker.tcx(), param_env)
+}
+
+/// Whether a place related to `place` is moved out of on some path from the
+/// start of `block` before `place` is initialized again
+fn is_moved_before_init<'tcx>(
+    place: Place<'tcx>,
+    block: BasicBlock,
+    repacker: PlaceRepacker<'_, 'tcx>,
+) -> bool {
+    struct MoveFinder<'tcx> {
+        place: Place<'tcx>,
+        found: bool,
+    }
+    impl<'tcx> Visitor<'tcx> for MoveFinder<'tcx> {
+        fn visit_operand(&mut self, operand: &Operand<'tcx>, _location: Location) {
+            if let Operand::Move(moved) = operand {
+                self.found |= self.place.related_to((*moved).into());
+            }
+        }
+    }
+    let initializes = |assigned: mir::Place<'tcx>| Place::from(assigned).is_prefix(place);
+    let mut finder = MoveFinder {
+        place,
+        found: false,
+    };
+    let mut visited = FxHashSet::default();
+    let mut blocks = vec![block];
+    'blocks: while let Some(block) = blocks.pop() {
+        if !visited.insert(block) {
+            continue;
+        }
+        let data = &repacker.body().basic_blocks[block];
+        for (statement_index, statement) in data.statements.iter().enumerate() {
+            finder.visit_statement(
+                statement,
+                Location {
+                    block,
+                    statement_index,
+                },
+            );
+            if finder.found {
+                return true;
+            }
+            if let StatementKind::Assign(box (assigned, _)) = &statement.kind
+                && initializes(*assigned)
+            {
+                continue 'blocks;
+            }
+        }
+        let terminator = data.terminator();
+        finder.visit_terminator(terminator, repacker.body().terminator_loc(block));
+        if finder.found {
+            return true;
+        }
+        let written = match raw_memory_call(terminator, repacker) {
+            Some((name, pointee, _)) if name != sym::ptr_read => Some(pointee),
+            _ => match &terminator.kind {
+                TerminatorKind::Call { destination, .. } => Some((*destination).into()),
+                _ => None,
+            },
+        };
+        if !written.is_some_and(|written| written.is_prefix(place)) {
+            blocks.extend(terminator.successors());
+        }
+    }
+    false
+}
+
+/// The value assigned to `local`, if it is assigned exactly once in the body
+fn only_assignment<'mir, 'tcx>(
+    local: Local,
+    repacker: PlaceRepacker<'mir, 'tcx>,
+) -> Option<&'mir Rvalue<'tcx>> {
+    let mut assignments = repacker
+        .body()
+        .basic_blocks
+        .iter()
+        .flat_map(|data| &data.statements)
+        .filter_map(|statement| match &statement.kind {
+            StatementKind::Assign(box (place, rvalue)) if place.as_local() == Some(local) => {
+                Some(rvalue)
+            }
+            _ => None,
+        });
+    let rvalue = assignments.next()?;
+    assignments.next().is_none().then_some(rvalue)
+}
+
 trait ProducesCapability {
     fn capability(&self) -> CapabilityKind;
 }
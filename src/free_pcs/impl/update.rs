@@ -56,6 +56,18 @@ impl<'tcx> CapabilitySummary<'tcx> {
                     self.requires(pre, repacker);
                 }
             }
+            Condition::Resume => {
+                for local in 0..repacker.local_count() {
+                    let local = Local::from_usize(local);
+                    if self[local].is_unallocated() {
+                        continue;
+                    }
+                    self.requires(
+                        Condition::Capability(local.into(), CapabilityKind::Write),
+                        repacker,
+                    );
+                }
+            }
         }
     }
 
@@ -99,12 +111,27 @@ impl<'tcx> CapabilitySummary<'tcx> {
                         Condition::Capability(RETURN_PLACE.into(), CapabilityKind::Exclusive)
                     } else if always_live.contains(local) {
                         Condition::Capability(local.into(), CapabilityKind::Write)
+                    } else if repacker.is_const_item_body() {
+                        // Lifetime-extended temporaries are never deallocated
+                        continue;
                     } else {
                         Condition::Unalloc(local)
                     };
                     self.check_pre_satisfied(pre, repacker);
                 }
             }
+            Condition::Resume => {
+                for local in 0..repacker.local_count() {
+                    let local = Local::from_usize(local);
+                    if let CapabilityLocal::Allocated(cp) = &self[local] {
+                        assert_eq!(
+                            cp[&local.into()],
+                            CapabilityKind::Write,
+                            "local: {local:?}, fpcs: {self:?}\n"
+                        );
+                    }
+                }
+            }
         }
     }
     pub(crate) fn ensures(&mut self, t: Triple<'tcx>, repacker: PlaceRepacker<'_, 'tcx>) {
@@ -113,7 +140,7 @@ impl<'tcx> CapabilitySummary<'tcx> {
             return;
         };
         match post {
-            Condition::Return => unreachable!(),
+            Condition::Return | Condition::Resume => unreachable!(),
             Condition::Unalloc(local) => {
                 self[local] = CapabilityLocal::Unallocated;
             }
@@ -5,8 +5,7 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use rustc_interface::{
-    dataflow::Analysis,
-    dataflow::ResultsCursor,
+    dataflow::{DataflowStats, PcsAnalysis, PcsResultsCursor},
     middle::{
         mir::{BasicBlock, Body, Location},
         ty::TyCtxt,
@@ -48,7 +47,7 @@ impl<'mir, 'tcx> HasCgContext<'mir, 'tcx> for PcsEngine<'mir, 'tcx> {
     }
 }
 
-type Cursor<'mir, 'tcx, E> = ResultsCursor<'mir, 'tcx, E>;
+type Cursor<'mir, 'tcx, E> = PcsResultsCursor<'mir, 'tcx, E>;
 
 pub trait HasExtra<T> {
     type ExtraBridge;
@@ -67,12 +66,23 @@ pub trait HasExtra<T> {
     ) -> Self::ExtraBridge;
 }
 
+/// The free PCS on its own carries no extra state.
+impl<'mir, 'tcx> HasExtra<()> for FreePlaceCapabilitySummary<'mir, 'tcx> {
+    type ExtraBridge = ();
+    type BridgeCtx = TyCtxt<'tcx>;
+    fn get_extra(&self) {}
+    fn bridge_between_stmts(_lhs: (), _rhs: (), _debug_ctx: DebugCtx) -> ((), ()) {
+        ((), ())
+    }
+    fn bridge_terminator(_lhs: &(), _rhs: (), _block: BasicBlock, _tcx: TyCtxt<'tcx>) {}
+}
+
 pub struct FreePcsAnalysis<
     'mir,
     'tcx,
     T,
     D: HasFpcs<'mir, 'tcx> + HasExtra<T>,
-    E: Analysis<'tcx, Domain = D>,
+    E: PcsAnalysis<'tcx, Domain = D>,
 > {
     pub cursor: Cursor<'mir, 'tcx, E>,
     curr_stmt: Option<Location>,
@@ -85,7 +95,7 @@ impl<
         'tcx,
         T,
         D: HasFpcs<'mir, 'tcx> + HasExtra<T, BridgeCtx = TyCtxt<'tcx>>,
-        E: Analysis<'tcx, Domain = D>,
+        E: PcsAnalysis<'tcx, Domain = D>,
     > FreePcsAnalysis<'mir, 'tcx, T, D, E>
 {
     pub(crate) fn new(cursor: Cursor<'mir, 'tcx, E>) -> Self {
@@ -123,6 +133,16 @@ impl<
         &self.cursor.get().get_curr_fpcs().post_main
     }
 
+    /// How many times each block was visited while computing the fixpoint
+    pub fn stats(&self) -> &DataflowStats {
+        self.cursor.results().stats()
+    }
+
+    /// The extra state at the start of `block`, before its first statement
+    pub fn extra_at_block_entry(&self, block: BasicBlock) -> T {
+        self.cursor.results().entry_set_for_block(block).get_extra()
+    }
+
     /// Returns the free pcs for the location `exp_loc` and iterates the cursor
     /// to the *end* of that location.
     pub fn next(&mut self, exp_loc: Location) -> FreePcsLocation<'tcx, T, D::ExtraBridge> {
@@ -204,12 +224,45 @@ impl<
         FreePcsTerminator { succs }
     }
 
+    /// The capabilities after each statement of the body as permission
+    /// assertions, one line per statement prefixed with its location (e.g.
+    /// `bb0[1]: acc(x, write)`), see [`CapabilitySummary::to_permission_string`]
+    pub fn permission_lines(&mut self) -> Vec<String> {
+        let repacker = self.repacker();
+        let mut lines = Vec::new();
+        for block in repacker.body().basic_blocks.indices() {
+            for stmt in self.get_all_for_bb(block).statements {
+                lines.push(format!(
+                    "{:?}: {}",
+                    stmt.location,
+                    stmt.states.after.to_permission_string(repacker)
+                ));
+            }
+        }
+        lines
+    }
+
+    /// Whether `block` is reachable from the start block, see
+    /// [`get_all_for_bb`](Self::get_all_for_bb)
+    pub fn is_reachable(&self, block: BasicBlock) -> bool {
+        self.cursor.results().is_reachable(block)
+    }
+
     /// Recommended interface.
     /// Does *not* require that one calls `analysis_for_bb` first
+    ///
+    /// Blocks that are not reachable from the start block were never analysed,
+    /// so the result for them has no statements and no successors.
     pub fn get_all_for_bb(
         &mut self,
         block: BasicBlock,
     ) -> FreePcsBasicBlock<'tcx, T, D::ExtraBridge> {
+        if !self.is_reachable(block) {
+            return FreePcsBasicBlock {
+                statements: Vec::new(),
+                terminator: FreePcsTerminator { succs: Vec::new() },
+            };
+        }
         self.analysis_for_bb(block);
         let mut statements = Vec::new();
         while self.curr_stmt.unwrap() != self.end_stmt.unwrap() {
@@ -0,0 +1,175 @@
+// © 2023, ETH Zurich
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use rustc_interface::{
+    dataflow::PcsAnalysis,
+    middle::{
+        mir::{self, Local, Location, Operand, RetagKind, Rvalue, StatementKind},
+        ty::{self, TyCtxt},
+    },
+};
+
+use crate::{
+    free_pcs::{CapabilityKind, FreePcsAnalysis, HasExtra, HasFpcs},
+    rustc_interface,
+    utils::{Place, PlaceRepacker},
+};
+
+/// The permission a retag is predicted to grant its pointer.
+///
+/// The permissions are named after those of Stacked Borrows. For Tree
+/// Borrows, `Unique` corresponds to a `Reserved` pointer that becomes `Active`
+/// on its first write, and `SharedReadOnly` to `Frozen`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RetagPermission {
+    /// A `&mut` or `Box`, which may be written to and excludes all other
+    /// pointers to its referent
+    Unique,
+    /// A two-phase `&mut`, a raw pointer, or a shared reference to data with
+    /// interior mutability
+    SharedReadWrite,
+    /// A shared reference to data without interior mutability
+    SharedReadOnly,
+}
+
+/// A retag, i.e. a point at which Miri's Stacked and Tree Borrows give a
+/// pointer a new tag.
+///
+/// These are the MIR `Retag` statements, which are only emitted when compiling
+/// with `-Zmir-emit-retag` and only exist in runtime MIR (i.e. at
+/// [`MirStage::Optimized`](crate::MirStage)), as well as the creation of
+/// references and raw pointers, which Miri retags implicitly.
+#[derive(Clone, Debug)]
+pub struct Retag<'tcx> {
+    pub location: Location,
+    pub kind: RetagKind,
+    /// Whether this is the creation of a reference or raw pointer rather than
+    /// a `Retag` statement
+    pub implicit: bool,
+    pub place: Place<'tcx>,
+    /// The capability of `place` after it is retagged, if the free PCS tracks
+    /// it directly
+    pub capability: Option<CapabilityKind>,
+    /// `None` if `place` is not itself a pointer, in which case the pointers
+    /// in its fields are retagged
+    pub permission: Option<RetagPermission>,
+    /// The index (in the result of [`FreePcsAnalysis::retags`]) of the retag
+    /// that created the pointer `place` was derived from, if any
+    pub parent: Option<usize>,
+}
+
+impl<'mir, 'tcx, T, D, E> FreePcsAnalysis<'mir, 'tcx, T, D, E>
+where
+    D: HasFpcs<'mir, 'tcx> + HasExtra<T, BridgeCtx = TyCtxt<'tcx>>,
+    E: PcsAnalysis<'tcx, Domain = D>,
+{
+    /// The retags of the body in block order, each linked to the retag of the
+    /// pointer it was derived from. This predicts the aliasing structure that
+    /// Miri's Stacked and Tree Borrows track dynamically.
+    ///
+    /// A reference or raw pointer is derived from the pointer local it
+    /// reborrows through, and the pointer retagged by a `Retag` statement
+    /// from the pointer local copied into it immediately before. The parent
+    /// is the last retag of that local preceding it in block order.
+    pub fn retags(&mut self) -> Vec<Retag<'tcx>> {
+        let repacker = self.repacker();
+        let body = repacker.body();
+        let mut retags: Vec<Retag<'tcx>> = vec![];
+        for block in body.basic_blocks.indices() {
+            let statements = self.get_all_for_bb(block).statements;
+            for (index, location) in statements.iter().enumerate() {
+                let Some(statement) = body.stmt_at(location.location).left() else {
+                    continue;
+                };
+                let (kind, implicit, place, source) = match &statement.kind {
+                    &StatementKind::Retag(kind, box place) => {
+                        let source = index.checked_sub(1).and_then(|prev| {
+                            copied_from(&body[block].statements[prev].kind, place)
+                        });
+                        (kind, false, place, source)
+                    }
+                    StatementKind::Assign(box (place, Rvalue::Ref(_, borrow_kind, borrowed))) => {
+                        let kind = if borrow_kind.allows_two_phase_borrow() {
+                            RetagKind::TwoPhase
+                        } else {
+                            RetagKind::Default
+                        };
+                        (kind, true, *place, reborrowed_through(*borrowed))
+                    }
+                    StatementKind::Assign(box (place, Rvalue::RawPtr(_, borrowed))) => {
+                        (RetagKind::Raw, true, *place, reborrowed_through(*borrowed))
+                    }
+                    _ => continue,
+                };
+                let parent = source.and_then(|local| {
+                    retags
+                        .iter()
+                        .rposition(|retag| retag.place.as_local() == Some(local))
+                });
+                let place: Place<'tcx> = place.into();
+                retags.push(Retag {
+                    location: location.location,
+                    kind,
+                    implicit,
+                    place,
+                    capability: location.states.after.capabilities().get(&place).copied(),
+                    permission: retag_permission(place, kind, repacker),
+                    parent,
+                });
+            }
+        }
+        retags
+    }
+}
+
+/// The pointer local that `borrowed` is behind, if any
+fn reborrowed_through(borrowed: mir::Place<'_>) -> Option<Local> {
+    borrowed
+        .iter_projections()
+        .any(|(_, elem)| elem == mir::ProjectionElem::Deref)
+        .then_some(borrowed.local)
+}
+
+/// The local copied or moved into `place` by `statement`, if any
+fn copied_from<'tcx>(statement: &StatementKind<'tcx>, place: mir::Place<'tcx>) -> Option<Local> {
+    let StatementKind::Assign(box (target, rvalue)) = statement else {
+        return None;
+    };
+    if *target != place {
+        return None;
+    }
+    match rvalue {
+        Rvalue::Use(Operand::Copy(source) | Operand::Move(source))
+        | Rvalue::Cast(_, Operand::Copy(source) | Operand::Move(source), _) => Some(source.local),
+        _ => None,
+    }
+}
+
+fn retag_permission<'tcx>(
+    place: Place<'tcx>,
+    kind: RetagKind,
+    repacker: PlaceRepacker<'_, 'tcx>,
+) -> Option<RetagPermission> {
+    let ty = place.ty(repacker).ty;
+    match ty.kind() {
+        ty::Ref(_, _, mir::Mutability::Mut) if kind == RetagKind::TwoPhase => {
+            Some(RetagPermission::SharedReadWrite)
+        }
+        ty::Ref(_, _, mir::Mutability::Mut) => Some(RetagPermission::Unique),
+        ty::Ref(_, pointee, mir::Mutability::Not) => {
+            let tcx = repacker.tcx();
+            let param_env = tcx.param_env(repacker.body().source.def_id());
+            if pointee.is_freeze(tcx, param_env) {
+                Some(RetagPermission::SharedReadOnly)
+            } else {
+                Some(RetagPermission::SharedReadWrite)
+            }
+        }
+        ty::RawPtr(..) => Some(RetagPermission::SharedReadWrite),
+        _ if ty.is_box() => Some(RetagPermission::Unique),
+        _ => None,
+    }
+}
@@ -8,33 +8,62 @@
 #![feature(box_patterns, hash_extract_if, extract_if)]
 #![feature(if_let_guard, let_chains)]
 
+//! The place capability summaries of Rust functions. Tools consuming the
+//! results should use the [`api`] module; the internal modules, which change
+//! often, are public only for the binaries of this workspace and are hidden
+//! from the documentation.
+
+pub mod api;
+#[doc(hidden)]
 pub mod borrows;
+#[doc(hidden)]
 pub mod combined_pcs;
+#[doc(hidden)]
 pub mod coupling;
+pub mod error;
+#[doc(hidden)]
+pub mod export;
+#[doc(hidden)]
 pub mod free_pcs;
+#[doc(hidden)]
 pub mod r#loop;
 pub mod rustc_interface;
+#[cfg(feature = "json")]
+pub mod server;
+#[doc(hidden)]
 pub mod utils;
+#[cfg(feature = "viz")]
+#[doc(hidden)]
 pub mod visualization;
 
 
 use borrows::{
-    borrows_graph::Conditioned, borrows_visitor::DebugCtx, deref_expansion::DerefExpansion,
-    domain::Reborrow, engine::BorrowsDomain, unblock_graph::UnblockGraph,
+    borrows_graph::Conditioned, borrows_state::ExpansionOp, borrows_visitor::DebugCtx,
+    deref_expansion::DerefExpansion, domain::Reborrow, engine::BorrowsDomain,
+    unblock_graph::UnblockGraph,
 };
 use combined_pcs::{BodyWithBorrowckFacts, PcsContext, PcsEngine, PlaceCapabilitySummary};
-use free_pcs::HasExtra;
+use free_pcs::{engine::FpcsEngine, FreePlaceCapabilitySummary, HasExtra};
 use rustc_interface::{
     data_structures::fx::FxHashSet,
-    dataflow::Analysis,
-    middle::{mir::BasicBlock, ty::TyCtxt},
+    dataflow,
+    middle::{
+        mir::{BasicBlock, Body},
+        ty::TyCtxt,
+    },
 };
+#[cfg(feature = "viz")]
 use serde_json::json;
+#[cfg(feature = "viz")]
+use utils::sorted_by_debug;
 use utils::PlaceRepacker;
+#[cfg(feature = "viz")]
 use visualization::mir_graph::generate_json_from_mir;
 
+#[cfg(feature = "viz")]
 use crate::borrows::domain::ToJsonWithRepacker;
 
+#[doc(hidden)]
 pub type FpcsOutput<'mir, 'tcx> = free_pcs::FreePcsAnalysis<
     'mir,
     'tcx,
@@ -43,11 +72,58 @@ pub type FpcsOutput<'mir, 'tcx> = free_pcs::FreePcsAnalysis<
     PcsEngine<'mir, 'tcx>,
 >;
 
+/// The result of [`run_free_pcs`]: owned place capabilities only.
+#[doc(hidden)]
+pub type FreePcsOutput<'mir, 'tcx> = free_pcs::FreePcsAnalysis<
+    'mir,
+    'tcx,
+    (),
+    FreePlaceCapabilitySummary<'mir, 'tcx>,
+    FpcsEngine<'mir, 'tcx>,
+>;
+
+/// The MIR body an analysis is run on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[doc(hidden)]
+pub enum MirStage {
+    /// The body checked by the borrow checker. This is the only stage for
+    /// which borrow-checker facts exist, and hence the only one on which
+    /// borrows are tracked (see [`run_combined_pcs`]).
+    Borrowck,
+    /// The body as built from THIR, before promotion and drop elaboration.
+    /// Still contains e.g. `FakeRead`s and `FalseEdge`s.
+    Built,
+    /// The optimized body. Regions are erased and fake reads, fake borrows and
+    /// false edges have been removed.
+    Optimized,
+}
+
+impl std::str::FromStr for MirStage {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "borrowck" => Ok(MirStage::Borrowck),
+            "built" => Ok(MirStage::Built),
+            "optimized" => Ok(MirStage::Optimized),
+            other => Err(format!("Unknown MIR stage: {other}")),
+        }
+    }
+}
+
 #[derive(Clone)]
+#[doc(hidden)]
 pub struct ReborrowBridge<'tcx> {
     pub expands: FxHashSet<Conditioned<DerefExpansion<'tcx>>>,
     pub added_reborrows: FxHashSet<Conditioned<Reborrow<'tcx>>>,
+    /// Two-phase reborrows that move from reserved to active across the bridge
+    pub activated_reborrows: FxHashSet<Conditioned<Reborrow<'tcx>>>,
     pub ug: UnblockGraph<'tcx>,
+    /// The operations that expand the graph to the places accessed by the
+    /// statement, in the order they are applied
+    pub expansion_ops: Vec<ExpansionOp<'tcx>>,
+    /// The reborrows that are killed across the bridge, with the path
+    /// conditions under which they held
+    pub killed_reborrows: Vec<Conditioned<Reborrow<'tcx>>>,
 }
 
 impl<'tcx> ReborrowBridge<'tcx> {
@@ -55,14 +131,34 @@ impl<'tcx> ReborrowBridge<'tcx> {
         ReborrowBridge {
             expands: FxHashSet::default(),
             added_reborrows: FxHashSet::default(),
+            activated_reborrows: FxHashSet::default(),
             ug: UnblockGraph::new(),
+            expansion_ops: vec![],
+            killed_reborrows: vec![],
         }
     }
+    /// Sets the expansion operations of the bridge, which also determine the
+    /// reborrows it kills
+    fn set_expansion_ops(&mut self, ops: Vec<ExpansionOp<'tcx>>) {
+        self.killed_reborrows = ops
+            .iter()
+            .filter_map(|op| match op {
+                ExpansionOp::KillReborrow(reborrow) => Some(reborrow.clone()),
+                _ => None,
+            })
+            .collect();
+        self.expansion_ops = ops;
+    }
+
+    #[cfg(feature = "viz")]
     pub fn to_json(&self, repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value {
         json!({
-            "expands": self.expands.iter().map(|e| e.to_json(repacker)).collect::<Vec<_>>(),
-            "added_reborrows": self.added_reborrows.iter().map(|r| r.to_json(repacker)).collect::<Vec<_>>(),
-            "ug": self.ug.to_json(repacker)
+            "expands": sorted_by_debug(&self.expands).into_iter().map(|e| e.to_json(repacker)).collect::<Vec<_>>(),
+            "added_reborrows": sorted_by_debug(&self.added_reborrows).into_iter().map(|r| r.to_json(repacker)).collect::<Vec<_>>(),
+            "activated_reborrows": sorted_by_debug(&self.activated_reborrows).into_iter().map(|r| r.to_json(repacker)).collect::<Vec<_>>(),
+            "ug": self.ug.to_json(repacker),
+            "expansion_ops": self.expansion_ops.iter().map(|op| op.to_short_string(repacker)).collect::<Vec<_>>(),
+            "killed_reborrows": self.killed_reborrows.iter().map(|r| r.to_json(repacker)).collect::<Vec<_>>()
         })
     }
 }
@@ -79,18 +175,28 @@ impl<'mir, 'tcx> HasExtra<BorrowsDomain<'mir, 'tcx>> for PlaceCapabilitySummary<
         rhs: BorrowsDomain<'mir, 'tcx>,
         ctx: DebugCtx,
     ) -> (Self::ExtraBridge, Self::ExtraBridge) {
-        let start = lhs.after.bridge(&rhs.before_start, ctx, lhs.repacker);
-        let middle = rhs.before_after.bridge(&rhs.start, ctx, rhs.repacker);
+        let mut start = lhs.after.bridge(&rhs.before_start, ctx, lhs.repacker);
+        start.set_expansion_ops(rhs.start_expansion_ops);
+        let mut middle = rhs.before_after.bridge(&rhs.start, ctx, rhs.repacker);
+        middle.set_expansion_ops(rhs.middle_expansion_ops);
         (start, middle)
     }
 
     fn bridge_terminator(
-        _lhs: &BorrowsDomain<'mir, 'tcx>,
-        _rhs: BorrowsDomain<'mir, 'tcx>,
-        _block: BasicBlock,
+        lhs: &BorrowsDomain<'mir, 'tcx>,
+        rhs: BorrowsDomain<'mir, 'tcx>,
+        block: BasicBlock,
         _tcx: TyCtxt<'tcx>,
     ) -> Self::ExtraBridge {
-        ReborrowBridge::new()
+        let mut bridge = ReborrowBridge::new();
+        // The reborrows that the join at the successor kills and that held
+        // along the edge from this predecessor
+        bridge.killed_reborrows = rhs
+            .join_killed_reborrows
+            .into_iter()
+            .filter(|reborrow| reborrow.conditions.valid_for_path(&[lhs.block(), block]))
+            .collect();
+        bridge
     }
 }
 
@@ -100,25 +206,72 @@ lazy_static::lazy_static! {
     static ref RECORD_PCS: Mutex<bool> = Mutex::new(false);
 }
 
+/// Runs only the owned part of the analysis on `body`. Unlike
+/// [`run_combined_pcs`] this does not require borrow-checker facts, and can
+/// thus be used on MIR from any [`MirStage`].
+///
+/// The `visualization_output_path` is ignored unless the `viz` feature is
+/// enabled.
+#[cfg_attr(not(feature = "viz"), allow(unused_variables))]
+#[doc(hidden)]
+pub fn run_free_pcs<'mir, 'tcx>(
+    body: &'mir Body<'tcx>,
+    tcx: TyCtxt<'tcx>,
+    visualization_output_path: Option<String>,
+) -> FreePcsOutput<'mir, 'tcx> {
+    let fpcs = FpcsEngine(PlaceRepacker::new(body, tcx));
+    let analysis = dataflow::iterate_to_fixpoint(fpcs, body);
+    #[cfg(feature = "viz")]
+    if let Some(dir_path) = visualization_output_path {
+        std::fs::create_dir_all(&dir_path).expect("Failed to create visualization directory");
+        generate_json_from_mir(
+            &format!("{}/mir.json", dir_path),
+            tcx,
+            body,
+            &Default::default(),
+        )
+        .expect("Failed to generate JSON from MIR");
+    }
+    free_pcs::FreePcsAnalysis::new(analysis.into_results_cursor(body))
+}
+
+#[doc(hidden)]
 pub fn run_combined_pcs<'mir, 'tcx>(
     mir: &'mir BodyWithBorrowckFacts<'tcx>,
     tcx: TyCtxt<'tcx>,
     visualization_output_path: Option<String>,
 ) -> FpcsOutput<'mir, 'tcx> {
-    let cgx = PcsContext::new(tcx, mir);
+    run_combined_pcs_with_context(PcsContext::new(tcx, mir), visualization_output_path)
+}
+
+/// Like [`run_combined_pcs`], but with a context configured e.g. by
+/// [`PcsContext::with_invariant_checks`].
+#[doc(hidden)]
+pub fn run_combined_pcs_with_context<'mir, 'tcx>(
+    cgx: PcsContext<'mir, 'tcx>,
+    visualization_output_path: Option<String>,
+) -> FpcsOutput<'mir, 'tcx> {
+    let tcx = cgx.rp.tcx();
+    let mir = cgx.mir;
+    #[cfg_attr(not(feature = "viz"), allow(unused_variables))]
+    let visualize_paths = cgx.visualize_paths;
+    #[cfg_attr(not(feature = "viz"), allow(unused_variables))]
+    let path_visualization_locations = cgx.path_visualization_locations.clone();
+    #[cfg_attr(not(feature = "viz"), allow(unused_variables))]
+    let visualize_function = cgx.visualize_function;
+    #[cfg(feature = "viz")]
+    let dot_config = cgx.dot_config.clone();
     let fpcs = PcsEngine::new(cgx, visualization_output_path.clone());
     {
         let mut record_pcs = RECORD_PCS.lock().unwrap();
         *record_pcs = true;
     }
-    let analysis = fpcs
-        .into_engine(tcx, &mir.body)
-        .pass_name("free_pcs")
-        .iterate_to_fixpoint();
+    let analysis = dataflow::iterate_to_fixpoint(fpcs, &mir.body);
     {
         let mut record_pcs = RECORD_PCS.lock().unwrap();
         *record_pcs = false;
     }
+    #[cfg(feature = "viz")]
     if let Some(dir_path) = &visualization_output_path {
         for block in mir.body.basic_blocks.indices() {
             let state = analysis.entry_set_for_block(block);
@@ -131,11 +284,24 @@ pub fn run_combined_pcs<'mir, 'tcx>(
                 .write_json_file(&block_iterations_json_file);
         }
     }
+    #[cfg_attr(not(feature = "viz"), allow(unused_mut))]
     let mut fpcs_analysis = free_pcs::FreePcsAnalysis::new(analysis.into_results_cursor(&mir.body));
 
+    #[cfg(feature = "viz")]
     if let Some(dir_path) = visualization_output_path {
-        generate_json_from_mir(&format!("{}/mir.json", dir_path), tcx, &mir.body)
-            .expect("Failed to generate JSON from MIR");
+        let expirations = combined_pcs::borrow_expirations(&mut fpcs_analysis);
+        generate_json_from_mir(
+            &format!("{}/mir.json", dir_path),
+            tcx,
+            &mir.body,
+            &expirations,
+        )
+        .expect("Failed to generate JSON from MIR");
+        visualization::report::generate_source_report(
+            &mut fpcs_analysis,
+            &format!("{}/report.html", dir_path),
+        )
+        .expect("Failed to generate the source report");
 
         let rp = PcsContext::new(tcx, mir).rp;
 
@@ -155,6 +321,24 @@ pub fn run_combined_pcs<'mir, 'tcx>(
                     .expect("Failed to write borrows to JSON file");
             }
         }
+
+        if visualize_paths {
+            visualization::generate_path_dot_graphs(
+                &mut fpcs_analysis,
+                &dir_path,
+                path_visualization_locations.as_ref(),
+                &dot_config,
+            )
+            .expect("Failed to generate the per-path dot graphs");
+        }
+        if visualize_function {
+            visualization::generate_function_dot_graph(
+                &mut fpcs_analysis,
+                &format!("{}/function.dot", dir_path),
+                &dot_config,
+            )
+            .expect("Failed to generate the dot graph of the function");
+        }
     }
 
     fpcs_analysis
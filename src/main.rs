@@ -1,34 +1,351 @@
 #![feature(rustc_private)]
 
+#[cfg(feature = "json")]
 use std::io::Write;
+#[cfg(feature = "json")]
 use std::fs::File;
 
-use std::cell::RefCell;
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeSet,
+    panic::AssertUnwindSafe,
+    path::PathBuf,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, SystemTime},
+};
 
-use pcs::{combined_pcs::BodyWithBorrowckFacts, run_combined_pcs, rustc_interface};
+#[cfg(feature = "json")]
+use pcs::server::AnalysisServer;
+use pcs::{
+    borrows::{
+        borrows_state::OldLeafTrimming,
+        domain::{CallAbstraction, ReborrowEquivalence},
+        function_summary::FunctionSummaries,
+        invariants::InvariantCheckLevel,
+        loop_annotations::annotated_loop_abstractions,
+    },
+    combined_pcs::{
+        check_exclusivity, check_invariants, expiry_trace, BodyWithBorrowckFacts, PcsContext,
+    },
+    error::{catch_internal_errors, PcsError},
+    export::{query::parse_location, ExportedLocation},
+    run_combined_pcs_with_context, run_free_pcs, rustc_interface, MirStage,
+};
 use rustc_interface::{
     borrowck::consumers,
-    data_structures::fx::FxHashMap,
+    data_structures::fx::{FxHashMap, FxHashSet},
     driver::{self, Compilation},
-    hir::{self, def_id::LocalDefId},
+    hir::{
+        self,
+        def_id::{DefId, LocalDefId, LOCAL_CRATE},
+    },
     interface::{interface::Compiler, Config, Queries},
     middle::{
-        query::queries::mir_borrowck::ProvidedValue as MirBorrowck,
-        ty::TyCtxt,
+        mir::{BasicBlock, Body, Location},
+        query::queries::{
+            mir_borrowck::ProvidedValue as MirBorrowck, mir_built::ProvidedValue as MirBuilt,
+        },
+        ty::{self, TyCtxt},
         util::Providers,
     },
     session::Session,
+    span::{FileName, Symbol},
 };
 
-struct PcsCallbacks;
+#[derive(Default)]
+struct PcsCallbacks {
+    /// The local source files of the crate, recorded for watch mode
+    source_files: Vec<PathBuf>,
+}
+
+/// In watch mode, the source files that changed since the last successful
+/// run. Only bodies depending on these files are analyzed again, see
+/// [`is_changed`]; `None` analyzes all.
+/// The compiler runs on its own thread, hence this is not thread-local.
+static CHANGED_FILES: Mutex<Option<FxHashSet<PathBuf>>> = Mutex::new(None);
+
+/// Whether the analysis of some body failed, see `report_errors`
+static FAILED: AtomicBool = AtomicBool::new(false);
 
 thread_local! {
     pub static BODIES:
         RefCell<FxHashMap<LocalDefId, BodyWithBorrowckFacts<'static>>> =
         RefCell::new(FxHashMap::default());
+    pub static BUILT_BODIES: RefCell<FxHashMap<LocalDefId, Body<'static>>> =
+        RefCell::new(FxHashMap::default());
+    static ORIGINAL_MIR_BUILT: Cell<Option<for<'tcx> fn(TyCtxt<'tcx>, LocalDefId) -> MirBuilt<'tcx>>> =
+        const { Cell::new(None) };
+    static HAS_ANALYZE_ATTRS: Cell<Option<bool>> = const { Cell::new(None) };
+}
+
+/// The value of the environment variable `var`, if it is set. Panics with a
+/// message naming the variable if the value is invalid.
+fn parse_env_var<T: std::str::FromStr>(var: &str) -> Option<T>
+where
+    T::Err: std::fmt::Display,
+{
+    let value = std::env::var(var).ok()?;
+    Some(
+        value
+            .parse()
+            .unwrap_or_else(|err| panic!("invalid {var}: {err}")),
+    )
+}
+
+/// The MIR stage to analyze, given by `PCS_MIR_STAGE` (`borrowck` by default).
+fn mir_stage() -> MirStage {
+    parse_env_var("PCS_MIR_STAGE").unwrap_or(MirStage::Borrowck)
+}
+
+/// Whether `PCS_RECORD_RETAGS` asks for MIR `Retag` statements to be emitted
+/// and for the retags to be recorded.
+fn record_retags() -> bool {
+    std::env::var("PCS_RECORD_RETAGS").unwrap_or_default() == "true"
+}
+
+/// Whether `PCS_VISUALIZE_PATHS` asks for one graph per acyclic path to each
+/// location: `true` for every location, or a comma-separated list of
+/// locations, e.g. `bb1[2],bb3[0]`, for only those.
+fn path_visualization() -> Option<Option<BTreeSet<Location>>> {
+    let var = std::env::var("PCS_VISUALIZE_PATHS").ok()?;
+    let parse = |location: &str| {
+        let ExportedLocation {
+            block,
+            statement_index,
+        } = parse_location(location.trim())
+            .unwrap_or_else(|err| panic!("invalid PCS_VISUALIZE_PATHS: {err}"));
+        Location {
+            block: BasicBlock::from_usize(block),
+            statement_index,
+        }
+    };
+    match var.as_str() {
+        "" | "false" => None,
+        "true" => Some(None),
+        locations => Some(Some(locations.split(',').map(parse).collect())),
+    }
+}
+
+/// Whether `PCS_WATCH` asks for the analysis to be run again whenever a
+/// source file of the crate changes.
+fn watch() -> bool {
+    std::env::var("PCS_WATCH").unwrap_or_default() == "true"
+}
+
+/// Returns the function filter patterns given in `PCS_FUNCTION_FILTER`, if
+/// any. Multiple patterns are separated by commas.
+fn function_filter() -> Option<Vec<String>> {
+    path_patterns("PCS_FUNCTION_FILTER")
+}
+
+/// Returns the comma-separated path patterns given in the environment
+/// variable `var`, if any.
+fn path_patterns(var: &str) -> Option<Vec<String>> {
+    let filter = std::env::var(var).ok()?;
+    let patterns: Vec<_> = filter
+        .split(',')
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty())
+        .collect();
+    if patterns.is_empty() {
+        None
+    } else {
+        Some(patterns)
+    }
+}
+
+/// Matches `path` against `pattern`, where `*` matches any (possibly empty)
+/// sequence of characters. A pattern without `*` must match exactly.
+fn matches_pattern(pattern: &str, path: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap();
+    let Some(mut rest) = path.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<_> = parts.collect();
+    let Some(last) = parts.pop() else {
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(idx) => rest = &rest[idx + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// Whether `def_id` is annotated with `#[pcs::<name>]`.
+fn has_pcs_attr(tcx: TyCtxt<'_>, def_id: DefId, name: &str) -> bool {
+    tcx.has_attrs_with_path(def_id, &[Symbol::intern("pcs"), Symbol::intern(name)])
+}
+
+/// Whether any body in the crate is annotated with `#[pcs::analyze]`, in
+/// which case only annotated bodies are analyzed.
+fn has_analyze_attrs(tcx: TyCtxt<'_>) -> bool {
+    HAS_ANALYZE_ATTRS.with(|cached| {
+        if let Some(result) = cached.get() {
+            return result;
+        }
+        let result = tcx
+            .hir()
+            .body_owners()
+            .any(|def_id| has_pcs_attr(tcx, def_id.to_def_id(), "analyze"));
+        cached.set(Some(result));
+        result
+    })
+}
+
+/// Whether the body of `def_id` should be analyzed. Bodies marked with
+/// `#[pcs::skip]` never are; if some body is marked with `#[pcs::analyze]`,
+/// only the marked ones are. Closures are selected whenever the function they
+/// are (transitively) nested in is. Filter patterns may be given either with
+/// or without the crate name as the first segment.
+fn is_selected(tcx: TyCtxt<'_>, def_id: LocalDefId) -> bool {
+    let root = tcx.typeck_root_def_id(def_id.to_def_id());
+    if has_pcs_attr(tcx, root, "skip") {
+        return false;
+    }
+    if has_analyze_attrs(tcx) && !has_pcs_attr(tcx, root, "analyze") {
+        return false;
+    }
+    let Some(patterns) = function_filter() else {
+        return true;
+    };
+    matches_any_pattern(tcx, root, &patterns)
+}
+
+/// Whether the body of `def_id` depends on a file that changed since the last
+/// run in watch mode, see [`depends_on_files`]. Always holds outside of watch
+/// mode and on the first run.
+fn is_changed(tcx: TyCtxt<'_>, def_id: LocalDefId) -> bool {
+    let changed = CHANGED_FILES.lock().unwrap();
+    let Some(changed) = &*changed else {
+        return true;
+    };
+    depends_on_files(tcx, def_id.to_def_id(), changed, &RefCell::default())
+}
+
+/// Whether `def_id` is defined in one of `files`, or refers to a local item
+/// that (transitively) is. A body refers to the functions, closures and types
+/// in the types of its expressions and to the methods it calls, and a type to
+/// the types of its fields.
+///
+/// This is a heuristic: e.g. a new trait impl in another file may change
+/// which method a body calls without the body referring to anything in that
+/// file.
+fn depends_on_files(
+    tcx: TyCtxt<'_>,
+    def_id: DefId,
+    files: &FxHashSet<PathBuf>,
+    visited: &RefCell<FxHashSet<DefId>>,
+) -> bool {
+    let Some(local_def_id) = def_id.as_local() else {
+        return false;
+    };
+    if !visited.borrow_mut().insert(def_id) {
+        return false;
+    }
+    let in_files = match tcx.sess.source_map().span_to_filename(tcx.def_span(def_id)) {
+        FileName::Real(name) => name.local_path().is_some_and(|path| files.contains(path)),
+        _ => true,
+    };
+    if in_files {
+        return true;
+    }
+    let depends_on_type = |ty: ty::Ty<'_>| {
+        ty.walk()
+            .any(|arg| match arg.as_type().map(|ty| *ty.kind()) {
+                Some(ty::TyKind::Adt(adt, _)) => depends_on_files(tcx, adt.did(), files, visited),
+                Some(
+                    ty::TyKind::FnDef(def_id, _)
+                    | ty::TyKind::Closure(def_id, _)
+                    | ty::TyKind::Coroutine(def_id, _),
+                ) => depends_on_files(tcx, def_id, files, visited),
+                _ => false,
+            })
+    };
+    match tcx.def_kind(def_id) {
+        hir::def::DefKind::Struct | hir::def::DefKind::Enum | hir::def::DefKind::Union => tcx
+            .adt_def(def_id)
+            .all_fields()
+            .any(|field| depends_on_type(tcx.type_of(field.did).instantiate_identity())),
+        _ if tcx.hir().maybe_body_owned_by(local_def_id).is_some() => {
+            let results = tcx.typeck(local_def_id);
+            results
+                .node_types()
+                .items()
+                .any(|(_, ty)| depends_on_type(*ty))
+                || results.type_dependent_defs().items().any(|(_, def)| {
+                    def.is_ok_and(|(_, def_id)| depends_on_files(tcx, def_id, files, visited))
+                })
+        }
+        _ => false,
+    }
+}
+
+/// Whether the path of `def_id`, with or without the crate name as the first
+/// segment, matches one of `patterns`.
+fn matches_any_pattern(tcx: TyCtxt<'_>, def_id: DefId, patterns: &[String]) -> bool {
+    let path = tcx.def_path_str(def_id);
+    let full_path = format!("{}::{}", tcx.crate_name(LOCAL_CRATE), path);
+    patterns
+        .iter()
+        .any(|p| matches_pattern(p, &path) || matches_pattern(p, &full_path))
+}
+
+/// How calls to the functions of the crate matching the patterns given in
+/// `PCS_PRECISE_FUNCTIONS` and `PCS_HAVOC_FUNCTIONS` (or its alias
+/// `PCS_TRUSTED_FUNCTIONS`) are abstracted, regardless of their attributes.
+/// Panics if a function matches patterns of both.
+fn call_abstractions(tcx: TyCtxt<'_>) -> FxHashMap<DefId, CallAbstraction> {
+    let precise = matching_functions(tcx, "PCS_PRECISE_FUNCTIONS");
+    let mut havoc = matching_functions(tcx, "PCS_HAVOC_FUNCTIONS");
+    havoc.extend(matching_functions(tcx, "PCS_TRUSTED_FUNCTIONS"));
+    if let Some(def_id) = precise.iter().find(|def_id| havoc.contains(def_id)) {
+        panic!(
+            "{} matches both PCS_PRECISE_FUNCTIONS and PCS_HAVOC_FUNCTIONS or PCS_TRUSTED_FUNCTIONS",
+            tcx.def_path_str(*def_id)
+        );
+    }
+    let precise = precise
+        .into_iter()
+        .map(|def_id| (def_id, CallAbstraction::Precise));
+    let havoc = havoc
+        .into_iter()
+        .map(|def_id| (def_id, CallAbstraction::Havoc));
+    precise.chain(havoc).collect()
+}
+
+/// The functions of the crate matching the patterns given in the environment
+/// variable `var`.
+fn matching_functions(tcx: TyCtxt<'_>, var: &str) -> FxHashSet<DefId> {
+    let Some(patterns) = path_patterns(var) else {
+        return FxHashSet::default();
+    };
+    tcx.hir()
+        .body_owners()
+        .map(LocalDefId::to_def_id)
+        .filter(|def_id| {
+            matches!(
+                tcx.def_kind(def_id),
+                hir::def::DefKind::Fn | hir::def::DefKind::AssocFn
+            ) && matches_any_pattern(tcx, *def_id, &patterns)
+        })
+        .collect()
 }
 
 fn mir_borrowck<'tcx>(tcx: TyCtxt<'tcx>, def_id: LocalDefId) -> MirBorrowck<'tcx> {
+    let mut providers = Providers::default();
+    rustc_interface::borrowck::provide(&mut providers);
+    let original_mir_borrowck = providers.mir_borrowck;
+    if mir_stage() != MirStage::Borrowck || !is_selected(tcx, def_id) || !is_changed(tcx, def_id) {
+        return original_mir_borrowck(tcx, def_id);
+    }
     let consumer_opts = consumers::ConsumerOptions::PoloniusOutputFacts;
     let body_with_facts = consumers::get_body_with_borrowck_facts(tcx, def_id, consumer_opts);
     unsafe {
@@ -39,23 +356,141 @@ fn mir_borrowck<'tcx>(tcx: TyCtxt<'tcx>, def_id: LocalDefId) -> MirBorrowck<'tcx
             assert!(map.insert(def_id, body).is_none());
         });
     }
-    let mut providers = Providers::default();
-    rustc_interface::borrowck::provide(&mut providers);
-    let original_mir_borrowck = providers.mir_borrowck;
     original_mir_borrowck(tcx, def_id)
 }
 
+/// Built MIR is stolen before the analysis runs, so a copy of it is kept here.
+fn mir_built<'tcx>(tcx: TyCtxt<'tcx>, def_id: LocalDefId) -> MirBuilt<'tcx> {
+    let original_mir_built = ORIGINAL_MIR_BUILT.get().unwrap();
+    let result = original_mir_built(tcx, def_id);
+    if mir_stage() == MirStage::Built && is_selected(tcx, def_id) && is_changed(tcx, def_id) {
+        let body: Body<'tcx> = result.borrow().clone();
+        unsafe {
+            let body: Body<'static> = std::mem::transmute(body);
+            BUILT_BODIES.with(|state| {
+                let mut map = state.borrow_mut();
+                assert!(map.insert(def_id, body).is_none());
+            });
+        }
+    }
+    result
+}
+
 fn run_pcs_on_all_fns<'tcx>(tcx: TyCtxt<'tcx>) {
     let mut item_names = vec![];
+    let mut errors = vec![];
 
     let vis_dir = if std::env::var("PCS_VISUALIZATION").unwrap_or_default() == "true" {
-        Some("visualization/data")
+        if cfg!(feature = "viz") {
+            Some("visualization/data")
+        } else {
+            eprintln!("PCS_VISUALIZATION is ignored: pcs was built without the `viz` feature");
+            None
+        }
     } else {
         None
     };
 
+    // Render one PCS graph per acyclic path to each location, or to the
+    // locations listed in `PCS_VISUALIZE_PATHS`, see
+    // `pcs::visualization::generate_path_dot_graphs`.
+    let path_visualization = path_visualization();
+
+    // Draw the states after all locations of a function in a single graph, see
+    // `pcs::visualization::generate_function_dot_graph`.
+    let visualize_function = std::env::var("PCS_VISUALIZE_FUNCTION").unwrap_or_default() == "true";
+
+    // How the dot graphs are drawn, given by `PCS_DOT_CONFIG` as e.g.
+    // `rankdir=LR,max_label=20`, see `pcs::visualization::drawer::DotConfig`.
+    #[cfg(feature = "viz")]
+    let dot_config: pcs::visualization::drawer::DotConfig =
+        parse_env_var("PCS_DOT_CONFIG").unwrap_or_default();
+
+    // Bodies of constants, statics and const expressions are only analyzed
+    // when explicitly requested.
+    let include_consts = std::env::var("PCS_INCLUDE_CONSTS").unwrap_or_default() == "true";
+
+    let stage = mir_stage();
+
+    // Record the retags of each function in the exported results, see
+    // `pcs::free_pcs::Retag`. `Retag` statements only exist in runtime MIR,
+    // i.e. with `PCS_MIR_STAGE=optimized`.
+    let record_retags = record_retags();
+
+    // Include the repacks and expansions of each function as fold and unfold
+    // operations in the exported results, see
+    // `pcs::FpcsOutput::export_permission_ops`.
+    let export_permission_ops =
+        std::env::var("PCS_EXPORT_PERMISSION_OPS").unwrap_or_default() == "true";
+
+    // Print the capabilities after each statement as permission assertions,
+    // see `pcs::free_pcs::CapabilitySummary::to_permission_string`.
+    let print_permissions = std::env::var("PCS_PRINT_PERMISSIONS").unwrap_or_default() == "true";
+
+    // Report places that are required to be exclusive while blocked by a
+    // mutable borrow, see `pcs::combined_pcs::check_exclusivity`.
+    let check_conflicts = std::env::var("PCS_CHECK_CONFLICTS").unwrap_or_default() == "true";
+
+    // Which reborrows to merge at join points, given by
+    // `PCS_REBORROW_EQUIVALENCE` (`identical` by default).
+    let reborrow_equivalence: ReborrowEquivalence =
+        parse_env_var("PCS_REBORROW_EQUIVALENCE").unwrap_or_default();
+
+    // When old leaves are trimmed from the borrows graph, given by
+    // `PCS_TRIM_OLD_LEAVES` (`eager` by default, `block_exit` or `never`).
+    let old_leaf_trimming: OldLeafTrimming =
+        parse_env_var("PCS_TRIM_OLD_LEAVES").unwrap_or_default();
+
+    // The largest number of dereferences of the places in the borrows graph,
+    // given by `PCS_PROJECTION_DEPTH_LIMIT` (unlimited by default). Deeper
+    // accesses are summarized, see `pcs::borrows::projection_summary`.
+    let projection_depth_limit: Option<usize> = parse_env_var("PCS_PROJECTION_DEPTH_LIMIT");
+
+    // How calls are abstracted, given by `PCS_CALL_ABSTRACTION` (`precise` by
+    // default, or `havoc`, also called `trusted`), unless overridden for the
+    // callee by an attribute or by `PCS_PRECISE_FUNCTIONS` and
+    // `PCS_HAVOC_FUNCTIONS`, which take precedence over the attributes.
+    let call_abstraction: CallAbstraction =
+        parse_env_var("PCS_CALL_ABSTRACTION").unwrap_or_default();
+    let call_abstractions = call_abstractions(tcx);
+
+    // The summaries of the called functions are computed once for the crate
+    // rather than for each body calling them.
+    let function_summaries: Rc<FunctionSummaries<'_>> = Rc::default();
+
+    // Report all violated invariants of the borrows graph, checked up to the
+    // level given in `PCS_CHECK_INVARIANTS` (`structural` or `semantic`). At
+    // the `semantic` level, cycles are reported as soon as they arise.
+    let check_invariants_level: Option<InvariantCheckLevel> = parse_env_var("PCS_CHECK_INVARIANTS");
+
+    // The exported results of each function are written to
+    // `PCS_EXPORT_DIR/<function>.json`, see `pcs::export`.
+    let export_dir = std::env::var("PCS_EXPORT_DIR").ok();
+    if export_dir.is_some() && !cfg!(feature = "json") {
+        eprintln!("PCS_EXPORT_DIR is ignored: pcs was built without the `json` feature");
+    }
+    #[cfg(feature = "json")]
+    if let Some(dir) = &export_dir {
+        std::fs::create_dir_all(dir).expect("Failed to create export directory");
+    }
+
+    // The predicted creation and expiry of the borrows of each function are
+    // written to `PCS_EXPIRY_TRACE_DIR/<function>.trace`, see
+    // `pcs::combined_pcs::expiry_trace`.
+    let expiry_trace_dir = std::env::var("PCS_EXPIRY_TRACE_DIR").ok();
+    if let Some(dir) = &expiry_trace_dir {
+        std::fs::create_dir_all(dir).expect("Failed to create expiry trace directory");
+    }
+
+    // The borrows graph after each statement of each function is written to
+    // `PCS_SMT_DIR/<function>/block_<b>_stmt_<i>.smt2`, see
+    // `pcs::export::smt`.
+    let smt_dir = std::env::var("PCS_SMT_DIR").ok();
+
+    // In watch mode, the results of unchanged bodies are kept
+    let full_run = CHANGED_FILES.lock().unwrap().is_none();
     if let Some(path) = &vis_dir {
-        if std::path::Path::new(path).exists() {
+        if full_run && std::path::Path::new(path).exists() {
             std::fs::remove_dir_all(path)
                 .expect("Failed to delete visualization directory contents");
         }
@@ -63,27 +498,184 @@ fn run_pcs_on_all_fns<'tcx>(tcx: TyCtxt<'tcx>) {
     }
 
     for def_id in tcx.hir().body_owners() {
+        if !is_selected(tcx, def_id) {
+            continue;
+        }
         let kind = tcx.def_kind(def_id);
         match kind {
-            hir::def::DefKind::Fn | hir::def::DefKind::AssocFn => {
-                let item_name = format!("{}", tcx.item_name(def_id.to_def_id()));
+            hir::def::DefKind::Fn | hir::def::DefKind::AssocFn | hir::def::DefKind::Closure => {}
+            hir::def::DefKind::Const
+            | hir::def::DefKind::AssocConst
+            | hir::def::DefKind::Static { .. }
+            | hir::def::DefKind::AnonConst
+            | hir::def::DefKind::InlineConst
+                if include_consts => {}
+            unsupported_item_kind => {
+                eprintln!("unsupported item: {unsupported_item_kind:?}");
+                continue;
+            }
+        }
+        let item_name = match kind {
+            hir::def::DefKind::Closure
+            | hir::def::DefKind::AnonConst
+            | hir::def::DefKind::InlineConst => tcx.def_path_str(def_id.to_def_id()),
+            _ => format!("{}", tcx.item_name(def_id.to_def_id())),
+        };
+        if !is_changed(tcx, def_id) {
+            item_names.push(item_name);
+            continue;
+        }
+        let output_path = vis_dir.map(|dir| format!("{}/{}", dir, item_name));
+        let result = catch_internal_errors(AssertUnwindSafe(|| match stage {
+            MirStage::Borrowck => {
                 let body = BODIES.with(|state| {
                     let mut map = state.borrow_mut();
                     unsafe { std::mem::transmute(map.remove(&def_id).unwrap()) }
                 });
-                run_combined_pcs(
-                    &body,
-                    tcx,
-                    vis_dir.map(|dir| format!("{}/{}", dir, item_name)),
-                );
-                item_names.push(item_name);
+                let mut cgx = PcsContext::new(tcx, &body)
+                    .with_reborrow_equivalence(reborrow_equivalence)
+                    .with_old_leaf_trimming(old_leaf_trimming)
+                    .with_call_abstraction(call_abstraction)
+                    .with_function_summaries(function_summaries.clone());
+                for (def_id, call_abstraction) in &call_abstractions {
+                    cgx = cgx.with_call_abstraction_for(*def_id, *call_abstraction);
+                }
+                match annotated_loop_abstractions(cgx.rp) {
+                    Ok(loops) => {
+                        for (loop_head, edges) in loops {
+                            cgx = cgx.with_loop_abstraction(loop_head, edges);
+                        }
+                    }
+                    Err(err) => eprintln!("ignoring loop abstractions of {item_name}: {err}"),
+                }
+                match &path_visualization {
+                    Some(Some(locations)) => {
+                        cgx = cgx.with_path_visualization_at(locations.clone());
+                    }
+                    Some(None) => cgx = cgx.with_path_visualization(),
+                    None => {}
+                }
+                if visualize_function {
+                    cgx = cgx.with_function_visualization();
+                }
+                #[cfg(feature = "viz")]
+                {
+                    cgx = cgx.with_dot_config(dot_config.clone());
+                }
+                if let Some(level) = check_invariants_level {
+                    cgx = cgx.with_invariant_checks(level);
+                }
+                if let Some(limit) = projection_depth_limit {
+                    cgx = cgx.with_projection_depth_limit(limit);
+                }
+                let mut output = run_combined_pcs_with_context(cgx, output_path);
+                if print_permissions {
+                    for line in output.permission_lines() {
+                        println!("{item_name} {line}");
+                    }
+                }
+                if check_conflicts {
+                    let repacker = output.repacker();
+                    for conflict in check_exclusivity(&mut output).conflicts {
+                        eprintln!(
+                            "exclusivity conflict in {item_name}: {}",
+                            conflict.to_short_string(repacker)
+                        );
+                    }
+                }
+                if let Some(level) = check_invariants_level {
+                    let repacker = output.repacker();
+                    for violation in check_invariants(&mut output, level) {
+                        eprintln!(
+                            "invariant violation in {item_name}: {}",
+                            violation.to_short_string(repacker)
+                        );
+                    }
+                }
+                if let Some(dir) = &expiry_trace_dir {
+                    let repacker = output.repacker();
+                    let mut lines = expiry_trace(&mut output).to_lines(repacker);
+                    lines.push(String::new());
+                    std::fs::write(format!("{dir}/{item_name}.trace"), lines.join("\n"))
+                        .expect("Failed to write expiry trace");
+                }
+                if let Some(dir) = &smt_dir {
+                    let dir = format!("{dir}/{item_name}");
+                    std::fs::create_dir_all(&dir).expect("Failed to create SMT directory");
+                    for (location, script) in output.export_smtlib() {
+                        std::fs::write(
+                            format!(
+                                "{dir}/block_{}_stmt_{}.smt2",
+                                location.block.index(),
+                                location.statement_index
+                            ),
+                            script,
+                        )
+                        .expect("Failed to write SMT-LIB script");
+                    }
+                }
+                if let Some(dir) = &export_dir {
+                    let mut export = output.export();
+                    if record_retags {
+                        export.retags = output.export_retags();
+                    }
+                    if export_permission_ops {
+                        export.permission_ops = output.export_permission_ops();
+                    }
+                    export.precision_losses = output.export_precision_losses();
+                    write_export(dir, &item_name, &export);
+                }
             }
-            unsupported_item_kind => {
-                eprintln!("unsupported item: {unsupported_item_kind:?}");
+            MirStage::Built => {
+                let body: Body<'tcx> = BUILT_BODIES.with(|state| {
+                    let mut map = state.borrow_mut();
+                    unsafe { std::mem::transmute(map.remove(&def_id).unwrap()) }
+                });
+                let mut output = run_free_pcs(&body, tcx, output_path);
+                if print_permissions {
+                    for line in output.permission_lines() {
+                        println!("{item_name} {line}");
+                    }
+                }
+                if let Some(dir) = &export_dir {
+                    let mut export = output.export();
+                    if record_retags {
+                        export.retags = output.export_retags();
+                    }
+                    write_export(dir, &item_name, &export);
+                }
             }
+            MirStage::Optimized => {
+                let body = match kind {
+                    hir::def::DefKind::Fn
+                    | hir::def::DefKind::AssocFn
+                    | hir::def::DefKind::Closure => tcx.optimized_mir(def_id),
+                    _ => tcx.mir_for_ctfe(def_id),
+                };
+                let mut output = run_free_pcs(body, tcx, output_path);
+                if print_permissions {
+                    for line in output.permission_lines() {
+                        println!("{item_name} {line}");
+                    }
+                }
+                if let Some(dir) = &export_dir {
+                    let mut export = output.export();
+                    if record_retags {
+                        export.retags = output.export_retags();
+                    }
+                    write_export(dir, &item_name, &export);
+                }
+            }
+        }));
+        match result {
+            Ok(()) => item_names.push(item_name),
+            Err(err) => errors.push((item_name, err)),
         }
     }
 
+    report_errors(&errors);
+
+    #[cfg(feature = "viz")]
     if let Some(dir_path) = &vis_dir {
         let file_path = format!("{}/functions.json", dir_path);
 
@@ -91,7 +683,7 @@ fn run_pcs_on_all_fns<'tcx>(tcx: TyCtxt<'tcx>) {
             &item_names
                 .iter()
                 .map(|name| (name.clone(), name.clone()))
-                .collect::<std::collections::HashMap<_, _>>(),
+                .collect::<std::collections::BTreeMap<_, _>>(),
         )
         .expect("Failed to serialize item names to JSON");
         let mut file = File::create(file_path).expect("Failed to create JSON file");
@@ -100,8 +692,80 @@ fn run_pcs_on_all_fns<'tcx>(tcx: TyCtxt<'tcx>) {
     }
 }
 
+/// Reports the bodies whose analysis failed, with the backtrace of each
+/// failure if `RUST_BACKTRACE` is set. If there are any, the driver exits
+/// with an error once compilation stops.
+fn report_errors(errors: &[(String, PcsError)]) {
+    if errors.is_empty() {
+        return;
+    }
+    let show_backtraces = std::env::var("RUST_BACKTRACE").is_ok_and(|value| value != "0");
+    for (item_name, err) in errors {
+        eprintln!("failed to analyze {item_name}: {err}");
+        match err {
+            PcsError::InternalError { backtrace, .. } if show_backtraces => {
+                eprintln!("{backtrace}")
+            }
+            PcsError::InternalError { .. } => {}
+        }
+    }
+    eprintln!("failed to analyze {} bodies", errors.len());
+    FAILED.store(true, Ordering::Relaxed);
+}
+
+#[cfg(feature = "json")]
+fn write_export<T: serde::Serialize>(dir: &str, item_name: &str, exported: &T) {
+    let json_data = serde_json::to_string(exported).expect("Failed to serialize exported results");
+    let mut file =
+        File::create(format!("{dir}/{item_name}.json")).expect("Failed to create export file");
+    file.write_all(json_data.as_bytes())
+        .expect("Failed to write exported results");
+}
+
+#[cfg(not(feature = "json"))]
+fn write_export<T>(_dir: &str, _item_name: &str, _exported: &T) {}
+
+/// Answers JSON-RPC requests about the selected bodies on stdin, see
+/// `pcs::server`.
+#[cfg(feature = "json")]
+fn serve_requests<'tcx>(tcx: TyCtxt<'tcx>) {
+    assert_eq!(
+        mir_stage(),
+        MirStage::Borrowck,
+        "PCS_SERVER requires borrow-checker facts"
+    );
+    let bodies: Vec<(String, BodyWithBorrowckFacts<'tcx>)> = BODIES.with(|state| {
+        state
+            .borrow_mut()
+            .drain()
+            .map(|(def_id, body)| {
+                let body: BodyWithBorrowckFacts<'tcx> =
+                    unsafe { std::mem::transmute::<BodyWithBorrowckFacts<'static>, _>(body) };
+                (tcx.def_path_str(def_id.to_def_id()), body)
+            })
+            .collect()
+    });
+    let server = AnalysisServer::new(
+        tcx,
+        bodies
+            .iter()
+            .map(|(name, body)| (name.clone(), body))
+            .collect(),
+    );
+    server
+        .serve(std::io::stdin().lock(), std::io::stdout().lock())
+        .expect("Failed to answer requests");
+}
+
+#[cfg(not(feature = "json"))]
+fn serve_requests(_tcx: TyCtxt<'_>) {
+    eprintln!("PCS_SERVER is ignored: pcs was built without the `json` feature");
+}
+
 fn set_mir_borrowck(_session: &Session, providers: &mut Providers) {
     providers.mir_borrowck = mir_borrowck;
+    ORIGINAL_MIR_BUILT.set(Some(providers.mir_built));
+    providers.mir_built = mir_built;
 }
 
 impl driver::Callbacks for PcsCallbacks {
@@ -114,12 +778,89 @@ impl driver::Callbacks for PcsCallbacks {
         _compiler: &Compiler,
         queries: &'tcx Queries<'tcx>,
     ) -> Compilation {
-        queries.global_ctxt().unwrap().enter(run_pcs_on_all_fns);
+        if watch() {
+            queries.global_ctxt().unwrap().enter(|tcx| {
+                self.source_files = tcx
+                    .sess
+                    .source_map()
+                    .files()
+                    .iter()
+                    .filter_map(|file| match &file.name {
+                        FileName::Real(name) => name.local_path().map(PathBuf::from),
+                        _ => None,
+                    })
+                    .collect();
+            });
+        }
+        if std::env::var("PCS_SERVER").unwrap_or_default() == "true" {
+            queries.global_ctxt().unwrap().enter(serve_requests);
+        } else {
+            queries.global_ctxt().unwrap().enter(run_pcs_on_all_fns);
+        }
         Compilation::Stop
     }
 }
 
+/// The modification times of `files`, `None` for those that cannot be read
+fn modification_times(files: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    files
+        .iter()
+        .map(|file| std::fs::metadata(file).and_then(|m| m.modified()).ok())
+        .collect()
+}
 
+/// Blocks until at least one of `files` is modified, returning those that were
+fn wait_for_changes(files: &[PathBuf]) -> FxHashSet<PathBuf> {
+    let before = modification_times(files);
+    loop {
+        std::thread::sleep(Duration::from_millis(500));
+        let after = modification_times(files);
+        let changed: FxHashSet<_> = files
+            .iter()
+            .zip(before.iter().zip(&after))
+            .filter(|(_, (before, after))| before != after)
+            .map(|(file, _)| file.clone())
+            .collect();
+        if !changed.is_empty() {
+            return changed;
+        }
+    }
+}
+
+/// Runs the analysis, then runs it again on the bodies depending on changed files
+/// whenever a source file of the crate changes. A run that fails to compile
+/// is reported and retried on the next change, together with the changes it
+/// did not analyze.
+fn run_watching(rustc_args: &[String]) -> ! {
+    let mut callbacks = PcsCallbacks::default();
+    loop {
+        let succeeded = driver::catch_fatal_errors(|| {
+            driver::RunCompiler::new(rustc_args, &mut callbacks).run()
+        })
+        .is_ok_and(|result| result.is_ok());
+        if !succeeded {
+            eprintln!("compilation failed, waiting for changes");
+        }
+        if callbacks.source_files.is_empty() {
+            // Nothing was recorded yet, so watch the crate root
+            callbacks.source_files = rustc_args
+                .iter()
+                .filter(|arg| arg.ends_with(".rs"))
+                .map(PathBuf::from)
+                .collect();
+        }
+        let changed = wait_for_changes(&callbacks.source_files);
+        for file in &changed {
+            eprintln!("{} changed, analyzing again", file.display());
+        }
+        let mut changed_files = CHANGED_FILES.lock().unwrap();
+        match &mut *changed_files {
+            Some(pending) if !succeeded => pending.extend(changed),
+            None if !succeeded => {}
+            _ => *changed_files = Some(changed),
+        }
+    }
+}
 
 fn main() {
     let mut rustc_args = vec![
@@ -131,11 +872,21 @@ fn main() {
     ];
     rustc_args.push("-Zcrate-attr=feature(register_tool)".to_owned());
     rustc_args.push("-Zcrate-attr=register_tool(prusti)".to_owned());
+    rustc_args.push("-Zcrate-attr=register_tool(pcs)".to_owned());
     rustc_args.push("-Zcrate-attr=feature(stmt_expr_attributes)".to_owned());
+    if record_retags() {
+        rustc_args.push("-Zmir-emit-retag".to_owned());
+    }
 
     rustc_args.extend(std::env::args().skip(1));
-    let mut callbacks = PcsCallbacks;
+    if watch() {
+        run_watching(&rustc_args);
+    }
+    let mut callbacks = PcsCallbacks::default();
     driver::RunCompiler::new(&rustc_args, &mut callbacks)
         .run()
         .unwrap();
+    if FAILED.load(Ordering::Relaxed) {
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,8 @@
+pub use super::rustc_borrowck::*;
+
+pub mod consumers {
+    pub use crate::rustc_interface::rustc_borrowck::consumers::*;
+
+    #[cfg(pcs_polonius_location_table)]
+    pub use crate::rustc_interface::rustc_borrowck::consumers::PoloniusLocationTable as LocationTable;
+}
@@ -0,0 +1,454 @@
+//! Shims for the dataflow framework, whose traits have changed between the
+//! nightlies we support.
+//!
+//! Analyses implement [`PcsAnalysis`], which mirrors the API of the oldest
+//! supported nightly, and use [`impl_rustc_analysis`] to implement the
+//! framework's traits in terms of it. They are run with our own solver,
+//! [`iterate_to_fixpoint`], rather than the framework's engine so that the
+//! order in which blocks are visited does not depend on the nightly.
+
+pub use super::rustc_mir_dataflow::*;
+
+use std::collections::BTreeSet;
+
+use super::{
+    index::IndexVec,
+    middle::mir::{
+        BasicBlock, Body, CallReturnPlaces, Location, Statement, Terminator, TerminatorEdges,
+        START_BLOCK,
+    },
+};
+
+/// A forward dataflow analysis, see [`Analysis`] for the meaning of each item.
+pub trait PcsAnalysis<'tcx> {
+    type Domain: Clone + JoinSemiLattice;
+    const NAME: &'static str;
+
+    fn bottom_value(&self, body: &Body<'tcx>) -> Self::Domain;
+
+    fn initialize_start_block(&self, body: &Body<'tcx>, state: &mut Self::Domain);
+
+    fn apply_before_statement_effect(
+        &mut self,
+        _state: &mut Self::Domain,
+        _statement: &Statement<'tcx>,
+        _location: Location,
+    ) {
+    }
+
+    fn apply_statement_effect(
+        &mut self,
+        state: &mut Self::Domain,
+        statement: &Statement<'tcx>,
+        location: Location,
+    );
+
+    fn apply_before_terminator_effect(
+        &mut self,
+        _state: &mut Self::Domain,
+        _terminator: &Terminator<'tcx>,
+        _location: Location,
+    ) {
+    }
+
+    fn apply_terminator_effect<'mir>(
+        &mut self,
+        state: &mut Self::Domain,
+        terminator: &'mir Terminator<'tcx>,
+        location: Location,
+    ) -> TerminatorEdges<'mir, 'tcx>;
+
+    fn apply_call_return_effect(
+        &mut self,
+        state: &mut Self::Domain,
+        block: BasicBlock,
+        return_places: CallReturnPlaces<'_, 'tcx>,
+    );
+
+    /// Applied to the state flowing along the unwind edge of a call instead
+    /// of [`apply_call_return_effect`](Self::apply_call_return_effect). Needed
+    /// by analyses that assign the return places in
+    /// [`apply_terminator_effect`](Self::apply_terminator_effect).
+    fn apply_call_unwind_effect(
+        &mut self,
+        _state: &mut Self::Domain,
+        _block: BasicBlock,
+        _return_places: CallReturnPlaces<'_, 'tcx>,
+    ) {
+    }
+}
+
+/// Implements the dataflow framework's traits for a type implementing
+/// [`PcsAnalysis`]. The type must be generic over exactly `'a` and `'tcx`.
+macro_rules! impl_rustc_analysis {
+    ($ty:ty) => {
+        #[cfg(not(pcs_no_analysis_domain))]
+        impl<'a, 'tcx> $crate::rustc_interface::dataflow::AnalysisDomain<'tcx> for $ty {
+            type Domain = <Self as $crate::rustc_interface::dataflow::PcsAnalysis<'tcx>>::Domain;
+            const NAME: &'static str =
+                <Self as $crate::rustc_interface::dataflow::PcsAnalysis<'tcx>>::NAME;
+
+            fn bottom_value(
+                &self,
+                body: &$crate::rustc_interface::middle::mir::Body<'tcx>,
+            ) -> Self::Domain {
+                $crate::rustc_interface::dataflow::PcsAnalysis::bottom_value(self, body)
+            }
+
+            fn initialize_start_block(
+                &self,
+                body: &$crate::rustc_interface::middle::mir::Body<'tcx>,
+                state: &mut Self::Domain,
+            ) {
+                $crate::rustc_interface::dataflow::PcsAnalysis::initialize_start_block(
+                    self, body, state,
+                )
+            }
+        }
+
+        #[cfg(not(pcs_no_analysis_domain))]
+        impl<'a, 'tcx> $crate::rustc_interface::dataflow::Analysis<'tcx> for $ty {
+            $crate::rustc_interface::dataflow::impl_rustc_analysis!(@effects);
+        }
+
+        #[cfg(pcs_no_analysis_domain)]
+        impl<'a, 'tcx> $crate::rustc_interface::dataflow::Analysis<'tcx> for $ty {
+            type Domain = <Self as $crate::rustc_interface::dataflow::PcsAnalysis<'tcx>>::Domain;
+            const NAME: &'static str =
+                <Self as $crate::rustc_interface::dataflow::PcsAnalysis<'tcx>>::NAME;
+
+            fn bottom_value(
+                &self,
+                body: &$crate::rustc_interface::middle::mir::Body<'tcx>,
+            ) -> Self::Domain {
+                $crate::rustc_interface::dataflow::PcsAnalysis::bottom_value(self, body)
+            }
+
+            fn initialize_start_block(
+                &self,
+                body: &$crate::rustc_interface::middle::mir::Body<'tcx>,
+                state: &mut Self::Domain,
+            ) {
+                $crate::rustc_interface::dataflow::PcsAnalysis::initialize_start_block(
+                    self, body, state,
+                )
+            }
+
+            $crate::rustc_interface::dataflow::impl_rustc_analysis!(@effects);
+        }
+    };
+    (@effects) => {
+        #[cfg(not(pcs_early_effects))]
+        $crate::rustc_interface::dataflow::impl_rustc_analysis!(
+            @effects
+            apply_before_statement_effect,
+            apply_statement_effect,
+            apply_before_terminator_effect,
+            apply_terminator_effect
+        );
+        #[cfg(pcs_early_effects)]
+        $crate::rustc_interface::dataflow::impl_rustc_analysis!(
+            @effects
+            apply_early_statement_effect,
+            apply_primary_statement_effect,
+            apply_early_terminator_effect,
+            apply_primary_terminator_effect
+        );
+    };
+    (@effects $before_statement:ident, $statement:ident, $before_terminator:ident, $terminator:ident) => {
+        fn $before_statement(
+            &mut self,
+            state: &mut Self::Domain,
+            statement: &$crate::rustc_interface::middle::mir::Statement<'tcx>,
+            location: $crate::rustc_interface::middle::mir::Location,
+        ) {
+            $crate::rustc_interface::dataflow::PcsAnalysis::apply_before_statement_effect(
+                self, state, statement, location,
+            )
+        }
+
+        fn $statement(
+            &mut self,
+            state: &mut Self::Domain,
+            statement: &$crate::rustc_interface::middle::mir::Statement<'tcx>,
+            location: $crate::rustc_interface::middle::mir::Location,
+        ) {
+            $crate::rustc_interface::dataflow::PcsAnalysis::apply_statement_effect(
+                self, state, statement, location,
+            )
+        }
+
+        fn $before_terminator(
+            &mut self,
+            state: &mut Self::Domain,
+            terminator: &$crate::rustc_interface::middle::mir::Terminator<'tcx>,
+            location: $crate::rustc_interface::middle::mir::Location,
+        ) {
+            $crate::rustc_interface::dataflow::PcsAnalysis::apply_before_terminator_effect(
+                self, state, terminator, location,
+            )
+        }
+
+        fn $terminator<'mir>(
+            &mut self,
+            state: &mut Self::Domain,
+            terminator: &'mir $crate::rustc_interface::middle::mir::Terminator<'tcx>,
+            location: $crate::rustc_interface::middle::mir::Location,
+        ) -> $crate::rustc_interface::middle::mir::TerminatorEdges<'mir, 'tcx> {
+            $crate::rustc_interface::dataflow::PcsAnalysis::apply_terminator_effect(
+                self, state, terminator, location,
+            )
+        }
+
+        fn apply_call_return_effect(
+            &mut self,
+            state: &mut Self::Domain,
+            block: $crate::rustc_interface::middle::mir::BasicBlock,
+            return_places: $crate::rustc_interface::middle::mir::CallReturnPlaces<'_, 'tcx>,
+        ) {
+            $crate::rustc_interface::dataflow::PcsAnalysis::apply_call_return_effect(
+                self,
+                state,
+                block,
+                return_places,
+            )
+        }
+    };
+}
+pub(crate) use impl_rustc_analysis;
+
+/// Statistics about a run of [`iterate_to_fixpoint`].
+#[derive(Clone, Debug)]
+pub struct DataflowStats {
+    block_visits: IndexVec<BasicBlock, usize>,
+}
+
+impl DataflowStats {
+    /// The number of times the effects of `block` were applied before the
+    /// fixpoint was reached.
+    pub fn block_visits(&self, block: BasicBlock) -> usize {
+        self.block_visits[block]
+    }
+
+    /// The total number of block visits before the fixpoint was reached.
+    pub fn iterations(&self) -> usize {
+        self.block_visits.iter().sum()
+    }
+}
+
+/// The fixpoint of an analysis, i.e. the state at the entry of each block.
+pub struct PcsResults<'tcx, A: PcsAnalysis<'tcx>> {
+    pub analysis: A,
+    entry_sets: IndexVec<BasicBlock, A::Domain>,
+    stats: DataflowStats,
+}
+
+impl<'tcx, A: PcsAnalysis<'tcx>> PcsResults<'tcx, A> {
+    pub fn entry_set_for_block(&self, block: BasicBlock) -> &A::Domain {
+        &self.entry_sets[block]
+    }
+
+    pub fn stats(&self) -> &DataflowStats {
+        &self.stats
+    }
+
+    /// Whether `block` is reachable from the start block. The entry state of
+    /// an unreachable block is the bottom value, and its effects have never
+    /// been applied.
+    pub fn is_reachable(&self, block: BasicBlock) -> bool {
+        self.stats.block_visits[block] > 0
+    }
+
+    pub fn into_results_cursor<'mir>(
+        self,
+        body: &'mir Body<'tcx>,
+    ) -> PcsResultsCursor<'mir, 'tcx, A> {
+        PcsResultsCursor::new(body, self)
+    }
+}
+
+/// Computes the fixpoint of `analysis` on `body`.
+///
+/// Blocks are taken from a worklist in reverse post-order, the block earliest
+/// in that order first. A loop header re-queued by a back edge is thus only
+/// visited again once the blocks of the loop body that precede the back edge
+/// have been, and an inner loop stabilises before its outer loop continues.
+pub fn iterate_to_fixpoint<'tcx, A: PcsAnalysis<'tcx>>(
+    mut analysis: A,
+    body: &Body<'tcx>,
+) -> PcsResults<'tcx, A> {
+    let mut entry_sets =
+        IndexVec::from_fn_n(|_| analysis.bottom_value(body), body.basic_blocks.len());
+    // Bodies without MIR (e.g. of intrinsics) have no start block
+    let Some(start) = entry_sets.get_mut(START_BLOCK) else {
+        return PcsResults {
+            analysis,
+            entry_sets,
+            stats: DataflowStats {
+                block_visits: IndexVec::new(),
+            },
+        };
+    };
+    analysis.initialize_start_block(body, start);
+
+    let rpo = body.basic_blocks.reverse_postorder();
+    let mut rpo_index = IndexVec::from_elem_n(usize::MAX, body.basic_blocks.len());
+    for (idx, &block) in rpo.iter().enumerate() {
+        rpo_index[block] = idx;
+    }
+    let mut worklist: BTreeSet<usize> = (0..rpo.len()).collect();
+    let mut block_visits = IndexVec::from_elem_n(0, body.basic_blocks.len());
+
+    let mut state = analysis.bottom_value(body);
+    while let Some(idx) = worklist.pop_first() {
+        let block = rpo[idx];
+        block_visits[block] += 1;
+        state.clone_from(&entry_sets[block]);
+        let edges = apply_effects_in_block(&mut analysis, &mut state, body, block);
+        let mut propagate = |target: BasicBlock, state: &A::Domain| {
+            if entry_sets[target].join(state) {
+                worklist.insert(rpo_index[target]);
+            }
+        };
+        match edges {
+            TerminatorEdges::None => {}
+            TerminatorEdges::Single(target) => propagate(target, &state),
+            TerminatorEdges::Double(target, unwind) => {
+                propagate(target, &state);
+                propagate(unwind, &state);
+            }
+            TerminatorEdges::AssignOnReturn {
+                return_,
+                cleanup,
+                place,
+            } => {
+                // The unwind path must not see the assignment of the return place
+                if let Some(cleanup) = cleanup {
+                    let mut unwind_state = state.clone();
+                    analysis.apply_call_unwind_effect(&mut unwind_state, block, place);
+                    propagate(cleanup, &unwind_state);
+                }
+                if !return_.is_empty() {
+                    analysis.apply_call_return_effect(&mut state, block, place);
+                    for &target in return_ {
+                        propagate(target, &state);
+                    }
+                }
+            }
+            TerminatorEdges::SwitchInt { targets, .. } => {
+                for &target in targets.all_targets() {
+                    propagate(target, &state);
+                }
+            }
+        }
+    }
+
+    PcsResults {
+        analysis,
+        entry_sets,
+        stats: DataflowStats { block_visits },
+    }
+}
+
+/// Applies the effects of all statements and of the terminator of `block`,
+/// returning the edges to its successors.
+fn apply_effects_in_block<'mir, 'tcx, A: PcsAnalysis<'tcx>>(
+    analysis: &mut A,
+    state: &mut A::Domain,
+    body: &'mir Body<'tcx>,
+    block: BasicBlock,
+) -> TerminatorEdges<'mir, 'tcx> {
+    let data = &body[block];
+    for (statement_index, statement) in data.statements.iter().enumerate() {
+        let location = Location {
+            block,
+            statement_index,
+        };
+        analysis.apply_before_statement_effect(state, statement, location);
+        analysis.apply_statement_effect(state, statement, location);
+    }
+    let location = body.terminator_loc(block);
+    let terminator = data.terminator();
+    analysis.apply_before_terminator_effect(state, terminator, location);
+    analysis.apply_terminator_effect(state, terminator, location)
+}
+
+/// Allows inspecting the state of a [`PcsResults`] at any location, by
+/// re-applying the effects of the block from its entry state.
+pub struct PcsResultsCursor<'mir, 'tcx, A: PcsAnalysis<'tcx>> {
+    body: &'mir Body<'tcx>,
+    results: PcsResults<'tcx, A>,
+    state: A::Domain,
+    /// The block the cursor is in, and the index of the last statement (or
+    /// terminator) whose effects have been applied to `state`.
+    position: Option<(BasicBlock, Option<usize>)>,
+}
+
+impl<'mir, 'tcx, A: PcsAnalysis<'tcx>> PcsResultsCursor<'mir, 'tcx, A> {
+    fn new(body: &'mir Body<'tcx>, results: PcsResults<'tcx, A>) -> Self {
+        let state = results.analysis.bottom_value(body);
+        Self {
+            body,
+            results,
+            state,
+            position: None,
+        }
+    }
+
+    pub fn body(&self) -> &'mir Body<'tcx> {
+        self.body
+    }
+
+    pub fn results(&self) -> &PcsResults<'tcx, A> {
+        &self.results
+    }
+
+    pub fn analysis(&self) -> &A {
+        &self.results.analysis
+    }
+
+    /// The state at the current position of the cursor.
+    pub fn get(&self) -> &A::Domain {
+        &self.state
+    }
+
+    pub fn seek_to_block_start(&mut self, block: BasicBlock) {
+        self.state.clone_from(&self.results.entry_sets[block]);
+        self.position = Some((block, None));
+    }
+
+    /// Moves the cursor to just after the primary effect of the statement or
+    /// terminator at `location`.
+    pub fn seek_after_primary_effect(&mut self, location: Location) {
+        let target = location.statement_index;
+        let next = match self.position {
+            Some((block, applied))
+                if block == location.block && applied.map_or(true, |i| i <= target) =>
+            {
+                applied.map_or(0, |i| i + 1)
+            }
+            _ => {
+                self.seek_to_block_start(location.block);
+                0
+            }
+        };
+        let data = &self.body[location.block];
+        let analysis = &mut self.results.analysis;
+        for statement_index in next..=target {
+            let location = Location {
+                block: location.block,
+                statement_index,
+            };
+            if statement_index == data.statements.len() {
+                let terminator = data.terminator();
+                analysis.apply_before_terminator_effect(&mut self.state, terminator, location);
+                analysis.apply_terminator_effect(&mut self.state, terminator, location);
+            } else {
+                let statement = &data.statements[statement_index];
+                analysis.apply_before_statement_effect(&mut self.state, statement, location);
+                analysis.apply_statement_effect(&mut self.state, statement, location);
+            }
+        }
+        self.position = Some((location.block, Some(target)));
+    }
+}
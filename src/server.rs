@@ -0,0 +1,197 @@
+//! A JSON-RPC 2.0 server answering questions about the functions of a crate,
+//! so that interactive tools only need to compile the crate once.
+//!
+//! Requests and responses are read and written one per line. The supported
+//! methods are
+//!
+//! - `functions`: the paths of the functions that can be analyzed
+//! - `analyze` with `{"function": F}`: the exported results of `F`, see
+//!   [`crate::api::FunctionPcs`]
+//! - `state` with `{"function": F, "location": "bb1[2]"}`: the exported
+//!   results for the statement at the location, see
+//!   [`crate::api::LocationPcs`]
+//! - `bridge` with `{"function": F, "from": "bb1[2]", "to": "bb3[0]",
+//!   "path": [1, 2, 3]}`: the changes between the states after two
+//!   statements, see [`pcs_delta`]. The path defaults to the block of both
+//!   locations.
+//! - `shutdown`: stops the server after answering
+//!
+//! Invalid parameters are answered with an `INVALID_PARAMS` error and panics
+//! of the analysis with an `INTERNAL_ERROR`, so that one request cannot stop
+//! the server.
+//!
+//! Functions are given by their path as listed by `functions`, e.g.
+//! `module::f`, or with a `crate::` prefix, see [`function_path`]. Each request
+//! runs the analysis of the function again, which takes a fraction of the
+//! time needed to compile it.
+
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, Write},
+    panic::AssertUnwindSafe,
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    api::function_path,
+    combined_pcs::{pcs_delta, BodyWithBorrowckFacts},
+    error::catch_internal_errors,
+    export::{query::parse_location, Export, ExportedLocation},
+    run_combined_pcs,
+    rustc_interface::middle::{
+        mir::{BasicBlock, Location},
+        ty::TyCtxt,
+    },
+    FpcsOutput,
+};
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+type RpcError = (i64, String);
+
+fn invalid_params(message: String) -> RpcError {
+    (INVALID_PARAMS, message)
+}
+
+fn string_param<'p>(params: &'p Value, name: &str) -> Result<&'p str, RpcError> {
+    params
+        .get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| invalid_params(format!("Missing string parameter `{name}`")))
+}
+
+fn location_param(params: &Value, name: &str) -> Result<Location, RpcError> {
+    let ExportedLocation {
+        block,
+        statement_index,
+    } = parse_location(string_param(params, name)?).map_err(invalid_params)?;
+    Ok(Location {
+        block: BasicBlock::from_usize(block),
+        statement_index,
+    })
+}
+
+fn check_blocks(output: &FpcsOutput<'_, '_>, blocks: &[BasicBlock]) -> Result<(), RpcError> {
+    let count = output.repacker().body().basic_blocks.len();
+    match blocks.iter().find(|block| block.index() >= count) {
+        Some(block) => Err(invalid_params(format!("No block {block:?}"))),
+        None => Ok(()),
+    }
+}
+
+/// Answers requests about the bodies of a compiled crate, see the
+/// [module documentation](self)
+pub struct AnalysisServer<'a, 'tcx> {
+    tcx: TyCtxt<'tcx>,
+    bodies: BTreeMap<String, &'a BodyWithBorrowckFacts<'tcx>>,
+}
+
+impl<'a, 'tcx> AnalysisServer<'a, 'tcx> {
+    /// A server for `bodies`, by the path of the function they belong to
+    pub fn new(
+        tcx: TyCtxt<'tcx>,
+        bodies: BTreeMap<String, &'a BodyWithBorrowckFacts<'tcx>>,
+    ) -> Self {
+        Self { tcx, bodies }
+    }
+
+    /// Answers the requests read from `input` until it ends or a `shutdown`
+    /// request is received
+    pub fn serve(&self, input: impl BufRead, mut output: impl Write) -> std::io::Result<()> {
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (id, method, result) = match serde_json::from_str::<Value>(&line) {
+                Ok(request) => {
+                    let method = request["method"].as_str().unwrap_or_default().to_string();
+                    let result = catch_internal_errors(AssertUnwindSafe(|| {
+                        self.handle(&method, &request["params"])
+                    }))
+                    .unwrap_or_else(|err| Err((INTERNAL_ERROR, err.to_string())));
+                    (request["id"].clone(), method, result)
+                }
+                Err(err) => (
+                    Value::Null,
+                    String::new(),
+                    Err((PARSE_ERROR, err.to_string())),
+                ),
+            };
+            let response = match result {
+                Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+                Err((code, message)) => json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": {"code": code, "message": message},
+                }),
+            };
+            writeln!(output, "{response}")?;
+            output.flush()?;
+            if method == "shutdown" {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn handle(&self, method: &str, params: &Value) -> Result<Value, RpcError> {
+        match method {
+            "functions" => Ok(json!(self.bodies.keys().collect::<Vec<_>>())),
+            "shutdown" => Ok(Value::Null),
+            "analyze" => self.with_output(params, |output| {
+                Ok(serde_json::to_value(output.export()).unwrap())
+            }),
+            "state" => {
+                let location = location_param(params, "location")?;
+                self.with_output(params, |output| {
+                    check_blocks(output, &[location.block])?;
+                    let block = output.get_all_for_bb(location.block);
+                    let stmt = block
+                        .statements
+                        .iter()
+                        .find(|stmt| stmt.location == location)
+                        .ok_or_else(|| invalid_params(format!("No statement at {location:?}")))?;
+                    Ok(serde_json::to_value(stmt.export(output.repacker())).unwrap())
+                })
+            }
+            "bridge" => {
+                let from = location_param(params, "from")?;
+                let to = location_param(params, "to")?;
+                let path = match params.get("path") {
+                    Some(path) => serde_json::from_value::<Vec<usize>>(path.clone())
+                        .map_err(|err| invalid_params(format!("Invalid path: {err}")))?
+                        .into_iter()
+                        .map(BasicBlock::from_usize)
+                        .collect(),
+                    None => vec![from.block],
+                };
+                self.with_output(params, |output| {
+                    let delta = pcs_delta(output, from, to, &path)
+                        .map_err(|err| invalid_params(err.to_string()))?;
+                    Ok(serde_json::to_value(delta.export(output.repacker())).unwrap())
+                })
+            }
+            other => Err((METHOD_NOT_FOUND, format!("Unknown method `{other}`"))),
+        }
+    }
+
+    /// Analyzes the function given by the `function` parameter
+    fn with_output(
+        &self,
+        params: &Value,
+        f: impl FnOnce(&mut FpcsOutput<'_, 'tcx>) -> Result<Value, RpcError>,
+    ) -> Result<Value, RpcError> {
+        let function = string_param(params, "function")?;
+        let body = self
+            .bodies
+            .get(function_path(function))
+            .ok_or_else(|| invalid_params(format!("Unknown function `{function}`")))?;
+        let mut output = run_combined_pcs(body, self.tcx, None);
+        f(&mut output)
+    }
+}
@@ -0,0 +1,135 @@
+// © 2023, ETH Zurich
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use rustc_interface::{
+    borrowck::consumers::calculate_borrows_out_of_scope_at_location,
+    data_structures::fx::{FxHashMap, FxHashSet},
+    middle::mir::{Body, Location},
+};
+
+use crate::{combined_pcs::BodyWithBorrowckFacts, rustc_interface};
+
+/// A point in the execution of a location
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProgramPoint {
+    /// Before any effect of the location
+    Entry(Location),
+    /// After borrows going out of scope at the location have been killed,
+    /// but before the effect of the statement itself
+    Start(Location),
+    /// After the effect of the location
+    After(Location),
+}
+
+/// Where rustc considers each borrow of a body to be in scope.
+///
+/// A borrow is in scope at the locations reachable from it without passing
+/// through a location where `calculate_borrows_out_of_scope_at_location` says
+/// it goes out of scope.
+///
+/// Borrows are only killed when they go out of scope: rustc additionally kills
+/// borrows of places that are overwritten or whose storage dies, which is not
+/// reflected here.
+#[derive(Clone, Debug, Default)]
+pub struct BorrowScopes {
+    /// The locations on entry to which each borrow, identified by its reserve
+    /// location, is in scope
+    in_scope_on_entry: FxHashMap<Location, FxHashSet<Location>>,
+    /// The locations at which each borrow goes out of scope
+    out_of_scope_at: FxHashMap<Location, FxHashSet<Location>>,
+}
+
+impl BorrowScopes {
+    pub fn new(mir: &BodyWithBorrowckFacts<'_>) -> Self {
+        let body = &mir.body;
+        let borrow_set = &mir.borrow_set;
+        let mut out_of_scope_at: FxHashMap<Location, FxHashSet<Location>> = FxHashMap::default();
+        for (location, borrows) in calculate_borrows_out_of_scope_at_location(
+            body,
+            &mir.region_inference_context,
+            borrow_set,
+        ) {
+            for borrow in borrows {
+                out_of_scope_at
+                    .entry(borrow_set[borrow].reserve_location)
+                    .or_default()
+                    .insert(location);
+            }
+        }
+
+        let mut in_scope_on_entry = FxHashMap::default();
+        for &reserve_location in borrow_set.location_map.keys() {
+            let kills = out_of_scope_at.get(&reserve_location);
+            let mut visited = FxHashSet::default();
+            let mut worklist = successors(body, reserve_location);
+            while let Some(location) = worklist.pop() {
+                if !visited.insert(location) || kills.is_some_and(|k| k.contains(&location)) {
+                    continue;
+                }
+                worklist.extend(successors(body, location));
+            }
+            in_scope_on_entry.insert(reserve_location, visited);
+        }
+        Self {
+            in_scope_on_entry,
+            out_of_scope_at,
+        }
+    }
+
+    /// Whether the borrow created at `borrow` is in scope at `point`, or
+    /// `None` if rustc does not track a borrow created there
+    pub fn is_in_scope(&self, borrow: Location, point: ProgramPoint) -> Option<bool> {
+        let on_entry = self.in_scope_on_entry.get(&borrow)?;
+        let killed_at = |location| {
+            self.out_of_scope_at
+                .get(&borrow)
+                .is_some_and(|kills| kills.contains(&location))
+        };
+        let in_scope_at_start = |location| on_entry.contains(&location) && !killed_at(location);
+        Some(match point {
+            ProgramPoint::Entry(location) => on_entry.contains(&location),
+            ProgramPoint::Start(location) => in_scope_at_start(location),
+            ProgramPoint::After(location) => location == borrow || in_scope_at_start(location),
+        })
+    }
+
+    /// The scopes as seen from `point`
+    pub fn at(&self, point: ProgramPoint) -> BorrowScopesAt<'_> {
+        BorrowScopesAt {
+            scopes: self,
+            point,
+        }
+    }
+}
+
+/// [`BorrowScopes`] at a fixed program point
+#[derive(Clone, Copy, Debug)]
+pub struct BorrowScopesAt<'a> {
+    scopes: &'a BorrowScopes,
+    point: ProgramPoint,
+}
+
+impl BorrowScopesAt<'_> {
+    /// Whether rustc tracks the borrow created at `borrow`, and considers it
+    /// to be out of scope at this point
+    pub fn is_out_of_scope(&self, borrow: Location) -> bool {
+        self.scopes.is_in_scope(borrow, self.point) == Some(false)
+    }
+}
+
+/// The locations that may be executed right after `location`, including by
+/// unwinding
+fn successors(body: &Body<'_>, location: Location) -> Vec<Location> {
+    if location == body.terminator_loc(location.block) {
+        body[location.block]
+            .terminator()
+            .successors()
+            .map(|block| block.start_location())
+            .collect()
+    } else {
+        vec![location.successor_within_block()]
+    }
+}
@@ -0,0 +1,14 @@
+use std::fmt::Debug;
+
+/// Sorts `items` by their debug representation.
+///
+/// Many of our types contain interned rustc types and regions, whose hashes
+/// depend on their addresses, so iterating over hash-based collections of them
+/// yields a different order in every run. Such collections are sorted with
+/// this function wherever their order becomes observable, e.g. in dumps and
+/// visualizations.
+pub fn sorted_by_debug<T: Debug>(items: impl IntoIterator<Item = T>) -> Vec<T> {
+    let mut items: Vec<T> = items.into_iter().collect();
+    items.sort_by_cached_key(|item| format!("{:?}", item));
+    items
+}
@@ -12,9 +12,7 @@ use std::{
 
 use rustc_interface::{
     middle::{
-        mir::{
-            PlaceElem, PlaceRef, ProjectionElem, VarDebugInfo, VarDebugInfoContents, RETURN_PLACE,
-        },
+        mir::{PlaceElem, PlaceRef, ProjectionElem, VarDebugInfoContents, RETURN_PLACE},
         ty::{AdtKind, TyKind},
     },
     span::Span,
@@ -45,8 +43,27 @@ impl<'tcx> PlaceDisplay<'tcx> {
     }
 }
 
+/// The part of `span` that lies within `outer_span`, going to the callsite of
+/// macro expansions if necessary
+pub(crate) fn as_local(span: Span, outer_span: Span) -> Option<Span> {
+    // Before we call source_callsite, we check and see if the span is already local.
+    // This is important b/c in print!("{}", y) if the user selects `y`, the source_callsite
+    // of that span is the entire macro.
+    if outer_span.contains(span) {
+        return Some(span);
+    } else {
+        let sp = span.source_callsite();
+        if outer_span.contains(sp) {
+            return Some(sp);
+        }
+    }
+
+    None
+}
+
 impl<'tcx> Place<'tcx> {
 
+    #[cfg(feature = "json")]
     pub fn to_json(&self, repacker: PlaceRepacker<'_, 'tcx>) -> serde_json::Value {
         let place_str = match self.to_string(repacker) {
             crate::utils::display::PlaceDisplay::Temporary(p) => format!("{:?}", p),
@@ -63,38 +80,15 @@ impl<'tcx> Place<'tcx> {
     }
 
     pub fn to_string(&self, repacker: PlaceRepacker<'_, 'tcx>) -> PlaceDisplay<'tcx> {
-        // Get the local's debug name from the Body's VarDebugInfo
-        let local_name = if self.local == RETURN_PLACE {
-            Cow::Borrowed("RETURN")
+        // Get the name of the user variable at (a prefix of) the place from
+        // the Body's VarDebugInfo
+        let (local_name, named_elems) = if self.local == RETURN_PLACE {
+            (Cow::Borrowed("RETURN"), 0)
         } else {
-            fn as_local(span: Span, outer_span: Span) -> Option<Span> {
-                // Before we call source_callsite, we check and see if the span is already local.
-                // This is important b/c in print!("{}", y) if the user selects `y`, the source_callsite
-                // of that span is the entire macro.
-                if outer_span.contains(span) {
-                    return Some(span);
-                } else {
-                    let sp = span.source_callsite();
-                    if outer_span.contains(sp) {
-                        return Some(sp);
-                    }
-                }
-
-                None
-            }
-
-            let get_local_name = |info: &VarDebugInfo<'tcx>| match info.value {
-                VarDebugInfoContents::Place(place) if place.local == self.local => {
-                    as_local(info.source_info.span, repacker.mir.span)
-                        .map(|_| info.name.to_string())
-                }
-                _ => None,
-            };
-            let Some(local_name) = repacker.mir.var_debug_info.iter().find_map(get_local_name)
-            else {
+            let Some((name, named_elems)) = self.debug_name(repacker) else {
                 return PlaceDisplay::Temporary(*self);
             };
-            Cow::Owned(local_name)
+            (Cow::Owned(name), named_elems)
         };
 
         #[derive(Copy, Clone)]
@@ -136,7 +130,9 @@ impl<'tcx> Place<'tcx> {
 
                         TyKind::Tuple(_) => field.as_usize().to_string(),
 
-                        TyKind::Closure(def_id, _substs) => match def_id.as_local() {
+                        TyKind::Closure(def_id, _substs)
+                        | TyKind::Coroutine(def_id, _substs)
+                        | TyKind::CoroutineClosure(def_id, _substs) => match def_id.as_local() {
                             Some(local_def_id) => {
                                 let captures = repacker.tcx.closure_captures(local_def_id);
                                 captures[field.as_usize()].var_ident.to_string()
@@ -162,6 +158,7 @@ impl<'tcx> Place<'tcx> {
         let (positions, contents): (Vec<_>, Vec<_>) = self
             .iter_projections()
             .enumerate()
+            .skip(named_elems)
             .map(elem_to_string)
             .unzip();
 
@@ -183,4 +180,64 @@ impl<'tcx> Place<'tcx> {
         let full = parts.make_contiguous().join("");
         PlaceDisplay::User(*self, full)
     }
+
+    /// The name of the user variable stored at the longest prefix of `self`,
+    /// and the number of projection elements of `self` that prefix has.
+    ///
+    /// Variables of inlined functions are ignored, whereas the variables
+    /// captured by a closure are named in its body. Shadowed variables
+    /// of the same name are told apart by the order of their source scopes:
+    /// the first one is `x`, the ones declared after it `x#1`, `x#2` and so on.
+    fn debug_name(&self, repacker: PlaceRepacker<'_, 'tcx>) -> Option<(String, usize)> {
+        let body = repacker.mir;
+        let user_variables = || {
+            body.var_debug_info
+                .iter()
+                .filter_map(|info| match info.value {
+                    VarDebugInfoContents::Place(place)
+                        if info.composite.is_none()
+                            && body.source_scopes[info.source_info.scope]
+                                .inlined_parent_scope
+                                .is_none()
+                            && body.source_scopes[info.source_info.scope].inlined.is_none() =>
+                    {
+                        Some((info, place))
+                    }
+                    _ => None,
+                })
+        };
+        let (info, place) = user_variables()
+            .filter(|(_, place)| {
+                // The regions in the types of fields may differ between the
+                // debug info and the body
+                let same_elem =
+                    |(elem, other): (&PlaceElem<'tcx>, PlaceElem<'tcx>)| match (*elem, other) {
+                        (ProjectionElem::Field(field, _), ProjectionElem::Field(other, _)) => {
+                            field == other
+                        }
+                        (elem, other) => elem == other,
+                    };
+                place.local == self.local
+                    && self.projection.len() >= place.projection.len()
+                    && self
+                        .projection
+                        .iter()
+                        .zip(place.projection.iter())
+                        .all(same_elem)
+            })
+            .max_by_key(|(_, place)| place.projection.len())?;
+        let shadowed = user_variables()
+            .filter(|(other, other_place)| {
+                other.name == info.name
+                    && other.source_info.scope.as_usize() < info.source_info.scope.as_usize()
+                    && *other_place != place
+            })
+            .count();
+        let name = if shadowed == 0 {
+            info.name.to_string()
+        } else {
+            format!("{}#{shadowed}", info.name)
+        };
+        Some((name, place.projection.len()))
+    }
 }
@@ -6,15 +6,23 @@
 
 pub mod place;
 pub(crate) mod repacker;
+pub mod borrow_scopes;
 pub mod display;
 mod mutable;
+pub mod origins;
 pub mod place_snapshot;
+pub mod post_dominators;
+pub mod region_names;
 mod root_place;
 // pub mod ty;
 pub mod r#const;
 pub mod debug_info;
+mod deterministic;
+mod small_set;
 
+pub use deterministic::*;
 pub use mutable::*;
 pub use place::*;
 pub use place_snapshot::*;
 pub use repacker::*;
+pub use small_set::*;
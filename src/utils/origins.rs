@@ -0,0 +1,167 @@
+// © 2023, ETH Zurich
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::{collections::BTreeSet, fmt, rc::Rc};
+
+use itertools::Itertools;
+use rustc_interface::{
+    borrowck::consumers::{BorrowIndex, LocationTable, PoloniusOutput},
+    middle::{mir::Location, ty::RegionVid},
+};
+
+use crate::{combined_pcs::BodyWithBorrowckFacts, rustc_interface};
+
+use super::borrow_scopes::ProgramPoint;
+
+/// The origins (Polonius' regions) containing each loan, and the subset
+/// relations between origins, at each point of a body.
+///
+/// Rustc computes the Polonius output without these relations, as they are
+/// only meant for debugging, so they are recomputed from the input facts.
+pub struct OriginFacts<'mir, 'tcx> {
+    mir: &'mir BodyWithBorrowckFacts<'tcx>,
+    location_table: Rc<LocationTable>,
+    output: Rc<PoloniusOutput>,
+}
+
+impl<'mir, 'tcx> OriginFacts<'mir, 'tcx> {
+    /// Returns `None` if the body has no Polonius input facts
+    pub fn new(mir: &'mir BodyWithBorrowckFacts<'tcx>) -> Option<Self> {
+        let input_facts = mir.input_facts.as_ref()?;
+        let location_table = mir.location_table.clone()?;
+        // The `polonius_engine` of the sysroot is shadowed by our own
+        // dependency, so its `Algorithm` can only be obtained by parsing
+        let algorithm = "DatafrogOpt".parse().unwrap();
+        let output = Rc::new(PoloniusOutput::compute(input_facts, algorithm, true));
+        Some(Self {
+            mir,
+            location_table,
+            output,
+        })
+    }
+
+    /// The Polonius output including the relations only meant for debugging
+    pub fn output(&self) -> &Rc<PoloniusOutput> {
+        &self.output
+    }
+
+    /// The facts as seen from `point`
+    pub fn at(&self, point: ProgramPoint) -> OriginFactsAt<'_, 'mir, 'tcx> {
+        let (location, mid) = match point {
+            ProgramPoint::Entry(location) | ProgramPoint::Start(location) => (location, false),
+            ProgramPoint::After(location) => (location, true),
+        };
+        OriginFactsAt {
+            facts: self,
+            location,
+            mid,
+        }
+    }
+}
+
+/// [`OriginFacts`] at a fixed program point. Polonius has a point before the
+/// effect of each location and one after it, so [`ProgramPoint::Entry`] and
+/// [`ProgramPoint::Start`] are treated alike.
+#[derive(Clone, Copy)]
+pub struct OriginFactsAt<'a, 'mir, 'tcx> {
+    facts: &'a OriginFacts<'mir, 'tcx>,
+    location: Location,
+    /// Whether this is the point after the effect of `location`
+    mid: bool,
+}
+
+impl OriginFactsAt<'_, '_, '_> {
+    /// The origins containing the loan of the borrow created at `borrow`,
+    /// and the subset relations from them. Returns `None` if rustc does not
+    /// track a borrow created there.
+    pub fn of_borrow(&self, borrow: Location) -> Option<Origins> {
+        let loan = BorrowIndex::from_usize(
+            self.facts
+                .mir
+                .borrow_set
+                .location_map
+                .get_index_of(&borrow)?,
+        );
+        let table = &self.facts.location_table;
+        let point = if self.mid {
+            table.mid_index(self.location)
+        } else {
+            table.start_index(self.location)
+        };
+        let origins = self
+            .facts
+            .output
+            .origin_contains_loan_at
+            .get(&point)
+            .into_iter()
+            .flatten()
+            .filter(|(_, loans)| loans.contains(&loan))
+            .map(|(origin, _)| RegionVid::from(*origin))
+            .collect();
+        Some(self.with_subsets(origins))
+    }
+
+    /// The subset relations from `region`
+    pub fn of_region(&self, region: RegionVid) -> Origins {
+        self.with_subsets(BTreeSet::from([region]))
+    }
+
+    fn with_subsets(&self, origins: BTreeSet<RegionVid>) -> Origins {
+        let table = &self.facts.location_table;
+        let point = if self.mid {
+            table.mid_index(self.location)
+        } else {
+            table.start_index(self.location)
+        };
+        let subsets = self
+            .facts
+            .output
+            .subset
+            .get(&point)
+            .into_iter()
+            .flatten()
+            .map(|(sub, sups)| (RegionVid::from(*sub), sups))
+            .filter(|(sub, _)| origins.contains(sub))
+            .flat_map(|(sub, sups)| sups.iter().map(move |sup| (sub, RegionVid::from(*sup))))
+            .collect();
+        Origins { origins, subsets }
+    }
+}
+
+/// Origins justifying an edge of the borrows graph, see [`OriginFactsAt`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Origins {
+    pub origins: BTreeSet<RegionVid>,
+    /// Pairs `(o1, o2)` where the loans of `o1` flow into `o2`
+    pub subsets: BTreeSet<(RegionVid, RegionVid)>,
+}
+
+impl fmt::Display for Origins {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.origins.is_empty() {
+            return write!(f, "no origins");
+        }
+        write!(
+            f,
+            "origins: {}",
+            self.origins
+                .iter()
+                .map(|origin| format!("{origin:?}"))
+                .join(", ")
+        )?;
+        if !self.subsets.is_empty() {
+            write!(
+                f,
+                "; {}",
+                self.subsets
+                    .iter()
+                    .map(|(sub, sup)| format!("{sub:?} ⊆ {sup:?}"))
+                    .join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
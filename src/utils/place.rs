@@ -15,11 +15,12 @@ use derive_more::{Deref, DerefMut};
 
 use rustc_interface::{
     ast::Mutability,
+    hir::LangItem,
     middle::{
         mir::{Body, Local, Place as MirPlace, PlaceElem, PlaceRef, ProjectionElem},
         ty::{self, RegionVid, Ty, TyCtxt, TyKind},
     },
-    target::abi::VariantIdx,
+    target::abi::{FieldIdx, VariantIdx},
 };
 
 use crate::{
@@ -125,6 +126,30 @@ impl<'tcx> Place<'tcx> {
             .any(|(place, elem)| elem == ProjectionElem::Deref && !place.ty(body, tcx).ty.is_box())
     }
 
+    /// Whether the place is reached through the pointer of a `Pin`, e.g.
+    /// `(*(p.0)).f` for `p: Pin<&mut T>`. Pinned places take part in
+    /// expansion and reborrows like any other place, but must not be moved
+    /// out of.
+    pub fn is_pinned(&self, repacker: PlaceRepacker<'_, 'tcx>) -> bool {
+        let mut pin_pointer = false;
+        for (place, elem) in self.iter_projections() {
+            if pin_pointer && elem == ProjectionElem::Deref {
+                return true;
+            }
+            pin_pointer =
+                pinned_pointer_ty(place.ty(repacker.body(), repacker.tcx()).ty, repacker.tcx())
+                    .is_some();
+        }
+        false
+    }
+
+    /// The pointer of a `Pin`, i.e. `p.0` for `p: Pin<P>`. The `Pin` wrapper
+    /// is transparent for borrows, so the pinned place is `*(p.0)`.
+    pub fn project_pin_pointer(&self, repacker: PlaceRepacker<'_, 'tcx>) -> Option<Self> {
+        let pointer_ty = pinned_pointer_ty(self.ty(repacker).ty, repacker.tcx())?;
+        Some(self.mk_place_elem(PlaceElem::Field(FieldIdx::ZERO, pointer_ty), repacker))
+    }
+
     pub fn is_mut_ref(&self, body: &Body<'tcx>, tcx: TyCtxt<'tcx>) -> bool {
         matches!(
             self.0.ty(body, tcx).ty.kind(),
@@ -140,11 +165,22 @@ impl<'tcx> Place<'tcx> {
         self.0.ty(body, tcx).ty.ref_mutability()
     }
 
+    /// Whether the place is reached by dereferencing a shared reference, in
+    /// which case it can at most be read
+    pub fn is_behind_shared_ref(&self, repacker: PlaceRepacker<'_, 'tcx>) -> bool {
+        self.iter_projections().any(|(base, elem)| {
+            elem == ProjectionElem::Deref
+                && base.ty(repacker.body(), repacker.tcx()).ty.ref_mutability()
+                    == Some(Mutability::Not)
+        })
+    }
+
     pub fn project_deref(&self, repacker: PlaceRepacker<'_, 'tcx>) -> Self {
+        let ty = self.ty(repacker).ty;
         assert!(
-            self.ty(repacker).ty.is_ref() || self.ty(repacker).ty.is_box(),
-            "Expected ref or box, got {:?}",
-            self.ty(repacker).ty
+            ty.is_ref() || ty.is_box() || ty.is_unsafe_ptr(),
+            "Expected ref, box or raw pointer, got {:?}",
+            ty
         );
         Place::new(
             self.0.local,
@@ -548,3 +584,13 @@ impl From<PlaceOrdering> for Option<Ordering> {
         }
     }
 }
+
+/// The pointer type `P` if `ty` is `Pin<P>`
+pub fn pinned_pointer_ty<'tcx>(ty: Ty<'tcx>, tcx: TyCtxt<'tcx>) -> Option<Ty<'tcx>> {
+    match ty.kind() {
+        TyKind::Adt(adt, args) if tcx.is_lang_item(adt.did(), LangItem::Pin) => {
+            Some(args.type_at(0))
+        }
+        _ => None,
+    }
+}
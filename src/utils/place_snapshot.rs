@@ -5,21 +5,58 @@ use crate::{
 
 use super::{Place, PlaceRepacker};
 
-#[derive(PartialEq, Eq, Clone, Debug, Hash, Copy)]
+/// A position within a single MIR statement (or terminator).
+#[derive(PartialEq, Eq, Clone, Debug, Hash, Copy, PartialOrd, Ord)]
+pub enum StatementPhase {
+    /// After the right-hand side has been evaluated (and its operands moved
+    /// out of), but before the left-hand side is assigned
+    Mid,
+    /// After the statement has taken effect, including the assignment to its
+    /// left-hand side
+    End,
+}
+
+#[derive(PartialEq, Eq, Clone, Hash, Copy)]
 pub enum SnapshotLocation {
-    Location(Location),
+    Location(Location, StatementPhase),
     Join(BasicBlock),
+    /// The entry of the function, before its first statement. This is where
+    /// the targets of the function's reference arguments (see
+    /// [`crate::borrows::domain::RemotePlace`]) are taken from, and is
+    /// distinct from any snapshot taken while analyzing the start block.
+    FunctionEntry,
+}
+
+impl std::fmt::Debug for SnapshotLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotLocation::Location(location, StatementPhase::End) => {
+                write!(f, "Location({:?})", location)
+            }
+            SnapshotLocation::Location(location, StatementPhase::Mid) => {
+                write!(f, "Location({:?}, mid)", location)
+            }
+            SnapshotLocation::Join(block) => write!(f, "Join({:?})", block),
+            SnapshotLocation::FunctionEntry => write!(f, "FunctionEntry"),
+        }
+    }
 }
 
 impl SnapshotLocation {
     pub fn start() -> Self {
-        SnapshotLocation::Location(Location::START)
+        SnapshotLocation::Location(Location::START, StatementPhase::End)
+    }
+
+    /// The point in the statement at `location` after its right-hand side has
+    /// been evaluated, but before the result is assigned
+    pub fn mid(location: Location) -> Self {
+        SnapshotLocation::Location(location, StatementPhase::Mid)
     }
 }
 
 impl From<Location> for SnapshotLocation {
     fn from(loc: Location) -> Self {
-        SnapshotLocation::Location(loc)
+        SnapshotLocation::Location(loc, StatementPhase::End)
     }
 }
 
@@ -63,4 +100,8 @@ impl<'tcx> HasPcsElems<Place<'tcx>> for PlaceSnapshot<'tcx> {
     fn pcs_elems(&mut self) -> Vec<&mut Place<'tcx>> {
         vec![&mut self.place]
     }
+
+    fn pcs_elems_ref(&self) -> Vec<&Place<'tcx>> {
+        vec![&self.place]
+    }
 }
@@ -0,0 +1,113 @@
+use crate::rustc_interface::{
+    data_structures::graph::{
+        dominators::{dominators, Dominators},
+        DirectedGraph, Predecessors, StartNode, Successors,
+    },
+    index::Idx,
+    middle::mir::{BasicBlock, BasicBlocks},
+};
+
+/// The post-dominator tree of a MIR body.
+///
+/// Cleanup blocks are ignored, i.e. a block `a` post-dominates `b` if every
+/// path from `b` that does not unwind eventually reaches `a`.
+#[derive(Clone, Debug)]
+pub struct PostDominators {
+    dominators: Dominators<BasicBlock>,
+}
+
+impl PostDominators {
+    pub fn new(blocks: &BasicBlocks<'_>) -> Self {
+        Self {
+            dominators: dominators(&ReverseCfg { blocks }),
+        }
+    }
+
+    /// Returns true iff every (non-unwinding) path from `b` to the exit of
+    /// the body passes through `a`. Blocks from which the exit cannot be
+    /// reached (e.g. infinite loops) are not post-dominated by any block.
+    pub fn post_dominates(&self, a: BasicBlock, b: BasicBlock) -> bool {
+        self.dominators.is_reachable(b) && self.dominators.dominates(a, b)
+    }
+}
+
+/// The control flow graph of a body with its edges reversed, excluding
+/// cleanup blocks. An additional node (with index `blocks.len()`) is used as
+/// the single exit, and is the start node of the reversed graph.
+struct ReverseCfg<'a, 'tcx> {
+    blocks: &'a BasicBlocks<'tcx>,
+}
+
+impl<'a, 'tcx> ReverseCfg<'a, 'tcx> {
+    fn exit(&self) -> BasicBlock {
+        BasicBlock::new(self.blocks.len())
+    }
+
+    fn is_exit(&self, block: BasicBlock) -> bool {
+        block == self.exit()
+    }
+
+    fn is_cleanup(&self, block: BasicBlock) -> bool {
+        self.blocks[block].is_cleanup
+    }
+
+    /// The successors of `block` in the original control flow graph
+    fn cfg_successors(&self, block: BasicBlock) -> Vec<BasicBlock> {
+        self.blocks[block]
+            .terminator()
+            .successors()
+            .filter(|succ| !self.is_cleanup(*succ))
+            .collect()
+    }
+
+    fn is_cfg_exit(&self, block: BasicBlock) -> bool {
+        !self.is_cleanup(block) && self.cfg_successors(block).is_empty()
+    }
+}
+
+impl<'a, 'tcx> DirectedGraph for ReverseCfg<'a, 'tcx> {
+    type Node = BasicBlock;
+
+    fn num_nodes(&self) -> usize {
+        self.blocks.len() + 1
+    }
+}
+
+impl<'a, 'tcx> StartNode for ReverseCfg<'a, 'tcx> {
+    fn start_node(&self) -> BasicBlock {
+        self.exit()
+    }
+}
+
+impl<'a, 'tcx> Successors for ReverseCfg<'a, 'tcx> {
+    fn successors(&self, node: BasicBlock) -> impl Iterator<Item = BasicBlock> {
+        let preds: Vec<BasicBlock> = if self.is_exit(node) {
+            self.blocks
+                .indices()
+                .filter(|block| self.is_cfg_exit(*block))
+                .collect()
+        } else if self.is_cleanup(node) {
+            vec![]
+        } else {
+            self.blocks.predecessors()[node]
+                .iter()
+                .copied()
+                .filter(|pred| !self.is_cleanup(*pred))
+                .collect()
+        };
+        preds.into_iter()
+    }
+}
+
+impl<'a, 'tcx> Predecessors for ReverseCfg<'a, 'tcx> {
+    fn predecessors(&self, node: BasicBlock) -> impl Iterator<Item = BasicBlock> {
+        let succs: Vec<BasicBlock> = if self.is_exit(node) || self.is_cleanup(node) {
+            vec![]
+        } else if self.is_cfg_exit(node) {
+            vec![self.exit()]
+        } else {
+            self.cfg_successors(node)
+        };
+        succs.into_iter()
+    }
+}
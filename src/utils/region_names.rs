@@ -0,0 +1,136 @@
+// © 2023, ETH Zurich
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::iter;
+
+use rustc_interface::{
+    data_structures::fx::FxHashMap,
+    hir::def::DefKind,
+    middle::{
+        mir::RETURN_PLACE,
+        ty::{self, RegionVid, TyCtxt},
+    },
+};
+
+use crate::{combined_pcs::BodyWithBorrowckFacts, rustc_interface};
+
+use super::{Place, PlaceRepacker};
+
+/// Descriptions of the region variables of a body in terms of the source
+/// code, since users cannot relate a region like `'?12` to their program.
+///
+/// A region equal to a lifetime parameter of the function (or `'static`) is
+/// described by its name, e.g. `'a`. Other regions are described by the first
+/// local whose type contains them, e.g. `r on line 4`.
+#[derive(Clone, Debug, Default)]
+pub struct RegionNames {
+    names: FxHashMap<RegionVid, String>,
+}
+
+impl RegionNames {
+    pub fn new<'tcx>(mir: &BodyWithBorrowckFacts<'tcx>, tcx: TyCtxt<'tcx>) -> Self {
+        let body = &mir.body;
+        let regioncx = &mir.region_inference_context;
+        let mut names = FxHashMap::default();
+
+        // The regions in the types of the arguments and the return place are
+        // matched up with those in the declared signature
+        let def_id = body.source.def_id();
+        if matches!(tcx.def_kind(def_id), DefKind::Fn | DefKind::AssocFn) {
+            let sig = tcx.fn_sig(def_id).instantiate_identity();
+            let sig = tcx.liberate_late_bound_regions(def_id, sig);
+            let declared = body
+                .args_iter()
+                .zip(sig.inputs().iter().copied())
+                .chain(iter::once((RETURN_PLACE, sig.output())));
+            for (local, ty) in declared {
+                let in_body = regions_of(body.local_decls[local].ty, tcx);
+                let in_sig = regions_of(ty, tcx);
+                if in_body.len() != in_sig.len() {
+                    continue;
+                }
+                for (region, declared) in in_body.into_iter().zip(in_sig) {
+                    if let (ty::ReVar(vid), Some(name)) = (region.kind(), declared.get_name()) {
+                        names.entry(vid).or_insert_with(|| name.to_string());
+                    }
+                }
+            }
+        }
+        names.insert(
+            regioncx.to_region_vid(tcx.lifetimes.re_static),
+            "'static".to_string(),
+        );
+
+        let mut named: Vec<_> = names
+            .iter()
+            .map(|(vid, name)| (*vid, name.clone()))
+            .collect();
+        named.sort();
+        for vid in regioncx.regions() {
+            if names.contains_key(&vid) {
+                continue;
+            }
+            if let Some((_, name)) = named
+                .iter()
+                .find(|(named, _)| regioncx.eval_equal(vid, *named))
+            {
+                names.insert(vid, name.clone());
+            }
+        }
+
+        let repacker = PlaceRepacker::new(body, tcx);
+        let source_map = tcx.sess.source_map();
+        for (local, decl) in body.local_decls.iter_enumerated() {
+            for region in regions_of(decl.ty, tcx) {
+                if let ty::ReVar(vid) = region.kind() {
+                    names.entry(vid).or_insert_with(|| {
+                        let line = source_map.lookup_char_pos(decl.source_info.span.lo()).line;
+                        let local: Place<'tcx> = local.into();
+                        format!("{} on line {line}", local.to_short_string(repacker))
+                    });
+                }
+            }
+        }
+        Self { names }
+    }
+
+    pub fn get(&self, region: RegionVid) -> Option<&str> {
+        self.names.get(&region).map(String::as_str)
+    }
+
+    /// `region` followed by its description, if it has one, e.g. `'?12 ('a)`
+    pub fn describe(&self, region: RegionVid) -> String {
+        match self.get(region) {
+            Some(name) => format!("{region:?} ({name})"),
+            None => format!("{region:?}"),
+        }
+    }
+
+    /// Like [`Self::describe`], for regions that need not be region variables
+    pub fn describe_region(&self, region: ty::Region<'_>) -> String {
+        match region.kind() {
+            ty::ReVar(vid) => self.describe(vid),
+            _ => format!("{region:?}"),
+        }
+    }
+
+    /// The regions that have a description, ordered by region
+    pub fn iter(&self) -> impl Iterator<Item = (RegionVid, &str)> {
+        let mut names: Vec<_> = self
+            .names
+            .iter()
+            .map(|(vid, name)| (*vid, name.as_str()))
+            .collect();
+        names.sort();
+        names.into_iter()
+    }
+}
+
+fn regions_of<'tcx>(ty: ty::Ty<'tcx>, tcx: TyCtxt<'tcx>) -> Vec<ty::Region<'tcx>> {
+    let mut regions = vec![];
+    tcx.for_each_free_region(&ty, |region| regions.push(region));
+    regions
+}
@@ -9,13 +9,14 @@ use std::collections::HashSet;
 use rustc_interface::{
     data_structures::fx::FxHashSet,
     dataflow::storage,
+    hir::ConstContext,
     index::{bit_set::BitSet, Idx},
     middle::{
         mir::{
             self, tcx::PlaceTy, BasicBlock, Body, HasLocalDecls, Local, Mutability,
             Place as MirPlace, PlaceElem, ProjectionElem,
         },
-        ty::{Region, RegionVid, Ty, TyCtxt, TyKind},
+        ty::{List, Region, RegionVid, Ty, TyCtxt, TyKind},
     },
     target::abi::FieldIdx,
 };
@@ -118,7 +119,7 @@ impl<'a, 'tcx: 'a> PlaceRepacker<'a, 'tcx> {
                     unwind,
                     call_source,
                     fn_span,
-                } => vec![target.unwrap()],
+                } => target.iter().copied().collect(),
                 mir::TerminatorKind::FalseUnwind {
                     real_target,
                     unwind,
@@ -170,6 +171,16 @@ impl<'a, 'tcx: 'a> PlaceRepacker<'a, 'tcx> {
         self.mir.local_decls().len()
     }
 
+    /// Whether this is the body of a constant or static. Temporaries in such
+    /// bodies may be extended to outlive the body, and are then never
+    /// `StorageDead`.
+    pub fn is_const_item_body(self) -> bool {
+        matches!(
+            self.tcx.hir().body_const_context(self.mir.source.def_id()),
+            Some(ConstContext::Const { .. } | ConstContext::Static(_))
+        )
+    }
+
     pub fn always_live_locals(self) -> BitSet<Local> {
         storage::always_storage_live_locals(self.mir)
     }
@@ -389,8 +400,8 @@ impl<'tcx> Place<'tcx> {
                     }
                 }
             }
-            TyKind::Closure(_, substs) => {
-                for (index, subst_ty) in substs.as_closure().upvar_tys().iter().enumerate() {
+            TyKind::Closure(..) | TyKind::Coroutine(..) | TyKind::CoroutineClosure(..) => {
+                for (index, subst_ty) in upvar_tys(typ.ty).iter().enumerate() {
                     if Some(index) != without_field {
                         let field = FieldIdx::from_usize(index);
                         let field_place = repacker.tcx.mk_place_field(
@@ -402,19 +413,6 @@ impl<'tcx> Place<'tcx> {
                     }
                 }
             }
-            // TyKind::Generator(_, substs, _) => {
-            //     for (index, subst_ty) in substs.as_generator().upvar_tys().iter().enumerate() {
-            //         if Some(index) != without_field {
-            //             let field = FieldIdx::from_usize(index);
-            //             let field_place = repacker.tcx.mk_place_field(
-            //                 self.to_rust_place(repacker),
-            //                 field,
-            //                 subst_ty,
-            //             );
-            //             places.push(field_place.into());
-            //         }
-            //     }
-            // }
             TyKind::Ref(_, _, _) => {
                 places.push(
                     repacker
@@ -610,3 +608,14 @@ impl<'tcx> Place<'tcx> {
         }
     }
 }
+
+/// The types of the captured upvars of a closure, coroutine or
+/// coroutine-closure, in the order of its fields.
+fn upvar_tys(ty: Ty<'_>) -> &List<Ty<'_>> {
+    match ty.kind() {
+        TyKind::Closure(_, substs) => substs.as_closure().upvar_tys(),
+        TyKind::Coroutine(_, substs) => substs.as_coroutine().upvar_tys(),
+        TyKind::CoroutineClosure(_, substs) => substs.as_coroutine_closure().upvar_tys(),
+        ty => unreachable!("ty={:?}", ty),
+    }
+}
@@ -0,0 +1,92 @@
+use smallvec::SmallVec;
+
+/// A set that stores up to two elements inline, for the sets of places that
+/// an edge blocks or is blocked by, which rarely contain more.
+///
+/// Membership is checked by a linear scan, so this should only be used for
+/// sets that are expected to stay small. Elements are iterated in the order
+/// in which they were first inserted.
+#[derive(Clone, Debug)]
+pub struct SmallSet<T>(SmallVec<[T; 2]>);
+
+impl<T> SmallSet<T> {
+    pub fn new() -> Self {
+        Self(SmallVec::new())
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.0.iter()
+    }
+}
+
+impl<T: PartialEq> SmallSet<T> {
+    pub fn contains(&self, elem: &T) -> bool {
+        self.0.contains(elem)
+    }
+
+    /// Inserts `elem`, returning whether it was not already in the set.
+    pub fn insert(&mut self, elem: T) -> bool {
+        if self.contains(&elem) {
+            false
+        } else {
+            self.0.push(elem);
+            true
+        }
+    }
+}
+
+impl<T> Default for SmallSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: PartialEq> PartialEq for SmallSet<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|elem| other.contains(elem))
+    }
+}
+
+impl<T: Eq> Eq for SmallSet<T> {}
+
+impl<T: PartialEq> FromIterator<T> for SmallSet<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut set = Self::new();
+        set.extend(iter);
+        set
+    }
+}
+
+impl<T: PartialEq> Extend<T> for SmallSet<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            self.insert(elem);
+        }
+    }
+}
+
+impl<T> IntoIterator for SmallSet<T> {
+    type Item = T;
+    type IntoIter = smallvec::IntoIter<[T; 2]>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a SmallSet<T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
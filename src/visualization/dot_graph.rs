@@ -5,6 +5,9 @@ type NodeId = String;
 
 pub struct DotGraph {
     pub name: String,
+    pub rankdir: String,
+    pub font_name: Option<String>,
+    pub font_size: Option<f64>,
     pub nodes: Vec<DotNode>,
     pub edges: Vec<DotEdge>,
     pub subgraphs: Vec<DotSubgraph>,
@@ -14,6 +17,32 @@ impl DotGraph {
     pub fn write_to_file(&self, path: &str) -> std::io::Result<()> {
         std::fs::write(path, self.to_string())
     }
+
+    /// Turns this graph into a cluster labelled `label`, for drawing it as
+    /// part of a larger graph. The ids of all nodes and subgraphs are prefixed
+    /// with `prefix` to keep them distinct from those of other clusters. The
+    /// edges of the graph are returned separately, as edges are drawn at the
+    /// top level.
+    pub fn into_cluster(self, prefix: &str, label: String) -> (DotSubgraph, Vec<DotEdge>) {
+        let edges = self
+            .edges
+            .into_iter()
+            .map(|edge| DotEdge {
+                from: format!("{}{}", prefix, edge.from),
+                to: format!("{}{}", prefix, edge.to),
+                options: edge.options,
+            })
+            .collect();
+        let cluster = DotSubgraph {
+            id: format!("cluster_{}", prefix),
+            label,
+            nodes: self.nodes,
+            rank_annotations: vec![],
+            subgraphs: self.subgraphs,
+        }
+        .with_prefix(prefix);
+        (cluster, edges)
+    }
 }
 
 pub struct DotSubgraph {
@@ -21,6 +50,47 @@ pub struct DotSubgraph {
     pub label: String,
     pub nodes: Vec<DotNode>,
     pub rank_annotations: Vec<RankAnnotation>,
+    pub subgraphs: Vec<DotSubgraph>,
+}
+
+impl DotSubgraph {
+    /// Prefixes the ids of the nodes and subgraphs in this subgraph, but not
+    /// its own id
+    fn with_prefix(self, prefix: &str) -> Self {
+        Self {
+            id: self.id,
+            label: self.label,
+            nodes: self
+                .nodes
+                .into_iter()
+                .map(|node| DotNode {
+                    id: format!("{}{}", prefix, node.id),
+                    ..node
+                })
+                .collect(),
+            rank_annotations: self
+                .rank_annotations
+                .into_iter()
+                .map(|annotation| RankAnnotation {
+                    rank_type: annotation.rank_type,
+                    nodes: annotation
+                        .nodes
+                        .iter()
+                        .map(|node| format!("{}{}", prefix, node))
+                        .collect(),
+                })
+                .collect(),
+            subgraphs: self
+                .subgraphs
+                .into_iter()
+                .map(|subgraph| DotSubgraph {
+                    id: format!("cluster_{}{}", prefix, subgraph.id),
+                    ..subgraph
+                })
+                .map(|subgraph| subgraph.with_prefix(prefix))
+                .collect(),
+        }
+    }
 }
 
 pub struct RankAnnotation {
@@ -53,6 +123,9 @@ impl Display for DotSubgraph {
         for rank_annotation in &self.rank_annotations {
             writeln!(f, "{}", rank_annotation)?;
         }
+        for subgraph in &self.subgraphs {
+            writeln!(f, "{}", subgraph)?;
+        }
         writeln!(f, "}}")
     }
 }
@@ -60,7 +133,15 @@ impl Display for DotSubgraph {
 impl Display for DotGraph {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "digraph {} {{", self.name)?;
-        writeln!(f, "node [shape=rect]")?;
+        writeln!(f, "rankdir=\"{}\"", self.rankdir)?;
+        let mut node_attrs = vec!["shape=rect".to_string()];
+        if let Some(font_name) = &self.font_name {
+            node_attrs.push(format_attr("fontname", &DotStringAttr(font_name.clone())));
+        }
+        if let Some(font_size) = self.font_size {
+            node_attrs.push(format_attr("fontsize", &DotFloatAttr(font_size)));
+        }
+        writeln!(f, "node [{}]", node_attrs.join(", "))?;
         for node in &self.nodes {
             writeln!(f, "{}", node)?;
         }
@@ -98,6 +179,7 @@ pub struct DotNode {
     pub shape: DotStringAttr,
     pub style: Option<DotStringAttr>,
     pub penwidth: Option<DotFloatAttr>,
+    pub tooltip: Option<DotStringAttr>,
 }
 
 trait DotAttr: Display {}
@@ -141,6 +223,7 @@ impl Display for DotNode {
             format_attr("shape", &self.shape),
             format_optional("style", &self.style),
             format_optional("penwidth", &self.penwidth),
+            format_optional("tooltip", &self.tooltip),
         ]
         .into_iter()
         .filter(|s| !s.is_empty())
@@ -161,6 +244,7 @@ pub struct EdgeOptions {
     color: Option<String>,
     style: Option<String>,
     direction: Option<EdgeDirection>,
+    tooltip: Option<String>,
 }
 
 impl EdgeOptions {
@@ -170,6 +254,7 @@ impl EdgeOptions {
             color: None,
             style: None,
             direction: Some(direction),
+            tooltip: None,
         }
     }
 
@@ -179,6 +264,7 @@ impl EdgeOptions {
             color: None,
             style: None,
             direction: None,
+            tooltip: None,
         }
     }
 
@@ -196,6 +282,11 @@ impl EdgeOptions {
         self.style = Some(style);
         self
     }
+
+    pub fn with_tooltip(mut self, tooltip: String) -> Self {
+        self.tooltip = Some(tooltip);
+        self
+    }
 }
 
 #[derive(Eq, PartialEq, PartialOrd, Ord)]
@@ -220,11 +311,21 @@ impl Display for DotEdge {
             Some(color) => format!(", color=\"{}\"", color),
             None => "".to_string(),
         };
+        let tooltip_part = match &self.options.tooltip {
+            Some(tooltip) => format!(", tooltip=\"{}\"", tooltip),
+            None => "".to_string(),
+        };
 
         write!(
             f,
-            "    \"{}\" -> \"{}\" [label=\"{}\"{}{}{}]",
-            self.from, self.to, self.options.label, style_part, direction_part, color_part
+            "    \"{}\" -> \"{}\" [label=\"{}\"{}{}{}{}]",
+            self.from,
+            self.to,
+            self.options.label,
+            style_part,
+            direction_part,
+            color_part,
+            tooltip_part
         )
     }
 }
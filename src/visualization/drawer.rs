@@ -1,29 +1,162 @@
 use std::{
+    collections::BTreeSet,
     io::{self},
 };
 
-use crate::{visualization::dot_graph::DotGraph};
+use crate::visualization::dot_graph::DotGraph;
 
-use super::{
-    Graph, GraphDrawer,
-};
+use super::{Graph, GraphDrawer};
+
+/// The direction in which the ranks of a graph are laid out
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RankDir {
+    #[default]
+    TopToBottom,
+    LeftToRight,
+    BottomToTop,
+    RightToLeft,
+}
+
+impl std::fmt::Display for RankDir {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RankDir::TopToBottom => write!(f, "TB"),
+            RankDir::LeftToRight => write!(f, "LR"),
+            RankDir::BottomToTop => write!(f, "BT"),
+            RankDir::RightToLeft => write!(f, "RL"),
+        }
+    }
+}
+
+impl std::str::FromStr for RankDir {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "TB" => Ok(RankDir::TopToBottom),
+            "LR" => Ok(RankDir::LeftToRight),
+            "BT" => Ok(RankDir::BottomToTop),
+            "RL" => Ok(RankDir::RightToLeft),
+            other => Err(format!("Unknown rank direction: {other}")),
+        }
+    }
+}
+
+/// How the graphs of the visualization are laid out and labelled. The default
+/// matches graphviz's own defaults, with place names in courier.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DotConfig {
+    pub rankdir: RankDir,
+    /// The font of node labels, replacing courier for place names
+    pub font_name: Option<String>,
+    pub font_size: Option<f64>,
+    /// Node labels longer than this many characters are truncated
+    pub max_label_length: Option<usize>,
+    /// Whether the labels of place nodes include the type of the place
+    pub include_types: bool,
+    /// Whether places and region projections that are not (projections of
+    /// places) connected to any edge of the borrows graph are left out
+    pub hide_unborrowed: bool,
+    /// Whether old places are labelled with the source code they were
+    /// snapshotted at, rather than with the MIR location
+    pub source_snippets: bool,
+    /// Whether reborrow and region projection member edges are labelled with
+    /// the Polonius origins and subset relations justifying them
+    pub origins: bool,
+}
+
+impl DotConfig {
+    /// Truncates `label` to the maximum label length, if any
+    pub fn truncate(&self, label: &str) -> String {
+        match self.max_label_length {
+            Some(max) if label.chars().count() > max => {
+                let mut truncated: String = label.chars().take(max).collect();
+                truncated.push('…');
+                truncated
+            }
+            _ => label.to_string(),
+        }
+    }
+
+    pub fn label_font(&self) -> &str {
+        self.font_name.as_deref().unwrap_or("courier")
+    }
+}
+
+/// Parses a comma-separated list of `key=value` settings, e.g.
+/// `rankdir=LR,font=Helvetica,font_size=10,max_label=20,types=true,snippets=true`. Settings
+/// that are not given keep their default.
+impl std::str::FromStr for DotConfig {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = DotConfig::default();
+        for setting in s.split(',').filter(|setting| !setting.is_empty()) {
+            let (key, value) = setting
+                .split_once('=')
+                .ok_or_else(|| format!("Expected key=value, got {setting}"))?;
+            let invalid = || format!("Invalid value for {key}: {value}");
+            match key {
+                "rankdir" => config.rankdir = value.parse()?,
+                "font" => config.font_name = Some(value.to_string()),
+                "font_size" => {
+                    config.font_size = Some(value.parse().map_err(|_| invalid())?);
+                }
+                "max_label" => {
+                    config.max_label_length = Some(value.parse().map_err(|_| invalid())?);
+                }
+                "types" => config.include_types = value.parse().map_err(|_| invalid())?,
+                "snippets" => config.source_snippets = value.parse().map_err(|_| invalid())?,
+                "hide_unborrowed" => {
+                    config.hide_unborrowed = value.parse().map_err(|_| invalid())?;
+                }
+                "origins" => config.origins = value.parse().map_err(|_| invalid())?,
+                other => return Err(format!("Unknown dot setting: {other}")),
+            }
+        }
+        Ok(config)
+    }
+}
 
 impl<T: io::Write> GraphDrawer<T> {
-    pub fn new(out: T) -> Self {
-        Self { out }
+    pub fn new(out: T, config: DotConfig) -> Self {
+        Self { out, config }
     }
 
     pub fn draw(mut self, graph: Graph) -> io::Result<()> {
-        let dot_graph = DotGraph {
+        let dot_graph = graph.to_dot_graph(&self.config);
+        writeln!(self.out, "{}", dot_graph)
+    }
+}
+
+impl Graph {
+    pub(super) fn to_dot_graph(&self, config: &DotConfig) -> DotGraph {
+        let hidden = if config.hide_unborrowed {
+            self.unborrowed_nodes()
+        } else {
+            BTreeSet::new()
+        };
+        DotGraph {
             name: "CapabilitySummary".to_string(),
-            nodes: graph.nodes.iter().map(|g| g.to_dot_node()).collect(),
-            edges: graph.edges.into_iter().map(|e| e.to_dot_edge()).collect(),
-            subgraphs: graph
+            rankdir: config.rankdir.to_string(),
+            font_name: config.font_name.clone(),
+            font_size: config.font_size,
+            nodes: self
+                .nodes
+                .iter()
+                .filter(|g| !hidden.contains(&g.id))
+                .map(|g| g.to_dot_node(config, &self.provenance_at(g.id)))
+                .collect(),
+            edges: self
+                .edges
+                .iter()
+                .filter(|e| e.endpoints().iter().all(|node| !hidden.contains(node)))
+                .map(|e| e.to_dot_edge())
+                .collect(),
+            subgraphs: self
                 .clusters
                 .iter()
-                .map(|c| c.to_dot_subgraph(&graph.nodes))
+                .map(|c| c.to_dot_subgraph(self, config, &hidden))
+                .filter(|subgraph| !subgraph.nodes.is_empty())
                 .collect(),
-        };
-        writeln!(self.out, "{}", dot_graph)
+        }
     }
 }
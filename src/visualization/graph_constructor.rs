@@ -1,34 +1,36 @@
 use crate::{
     borrows::{
-        borrows_edge::{BorrowsEdge, BorrowsEdgeKind},
+        borrows_edge::{BorrowsEdge, BorrowsEdgeKind, EdgeProvenance},
         borrows_graph::BorrowsGraph,
         borrows_state::BorrowsState,
         deref_expansion::DerefExpansion,
         domain::{
-            AbstractionInputTarget, AbstractionOutputTarget, AbstractionTarget, MaybeOldPlace,
-            MaybeRemotePlace, RemotePlace,
+            AbstractionBlockEdge, AbstractionInputTarget, AbstractionOutputTarget,
+            AbstractionTarget, AbstractionType, MaybeOldPlace, MaybeRemotePlace, RemotePlace,
         },
         region_abstraction::AbstractionEdge,
         region_projection::RegionProjection,
         unblock_graph::UnblockGraph,
     },
     free_pcs::{CapabilityKind, CapabilityLocal, CapabilitySummary},
-    rustc_interface::{self, middle::mir::Local},
-    utils::{Place, PlaceRepacker, PlaceSnapshot, SnapshotLocation},
+    rustc_interface::{self, middle::mir::Location},
+    utils::{
+        borrow_scopes::BorrowScopesAt, origins::OriginFactsAt, region_names::RegionNames,
+        sorted_by_debug, Place, PlaceRepacker, PlaceSnapshot, SnapshotLocation,
+    },
     visualization::dot_graph::RankAnnotation,
 };
 
-use std::{
-    borrow::Borrow,
-    collections::{BTreeSet, HashSet},
-    ops::Deref,
-};
+use std::{borrow::Borrow, collections::BTreeSet, ops::Deref};
 
 use rustc_interface::middle::ty::{self, TyCtxt};
 
-use super::{dot_graph::DotSubgraph, Graph, GraphEdge, GraphNode, NodeId, NodeType};
+use super::{
+    dot_graph::DotSubgraph, drawer::DotConfig, snapshot_source_text, Graph, GraphEdge, GraphNode,
+    NodeId, NodeType,
+};
 
-#[derive(Eq, PartialEq, Hash)]
+#[derive(Eq, PartialEq, Hash, PartialOrd, Ord)]
 pub struct GraphCluster {
     label: String,
     id: String,
@@ -37,31 +39,48 @@ pub struct GraphCluster {
 }
 
 impl GraphCluster {
-    pub fn to_dot_subgraph(&self, nodes: &[GraphNode]) -> DotSubgraph {
+    /// The subgraph drawn for the cluster, leaving out the `hidden` nodes
+    pub(super) fn to_dot_subgraph(
+        &self,
+        graph: &Graph,
+        config: &DotConfig,
+        hidden: &BTreeSet<NodeId>,
+    ) -> DotSubgraph {
         DotSubgraph {
             id: format!("cluster_{}", self.id),
             label: self.label.clone(),
             nodes: self
                 .nodes
                 .iter()
+                .filter(|node_id| !hidden.contains(node_id))
                 .map(|node_id| {
-                    nodes
+                    graph
+                        .nodes
                         .iter()
                         .find(|n| n.id == *node_id)
                         .unwrap()
-                        .to_dot_node()
+                        .to_dot_node(config, &graph.provenance_at(*node_id))
                 })
                 .collect(),
             rank_annotations: self
                 .min_rank_nodes
                 .as_ref()
+                .map(|nodes| {
+                    nodes
+                        .iter()
+                        .filter(|n| !hidden.contains(n))
+                        .map(|n| n.to_string())
+                        .collect::<BTreeSet<_>>()
+                })
+                .filter(|nodes| !nodes.is_empty())
                 .map(|nodes| {
                     vec![RankAnnotation {
                         rank_type: "min".to_string(),
-                        nodes: nodes.iter().map(|n| n.to_string()).collect(),
+                        nodes,
                     }]
                 })
                 .unwrap_or_default(),
+            subgraphs: vec![],
         }
     }
 }
@@ -70,10 +89,18 @@ struct GraphConstructor<'mir, 'tcx> {
     remote_nodes: IdLookup<RemotePlace>,
     place_nodes: IdLookup<(Place<'tcx>, Option<SnapshotLocation>)>,
     region_projection_nodes: IdLookup<RegionProjection<'tcx>>,
-    region_clusters: HashSet<GraphCluster>,
+    junction_nodes: IdLookup<(Location, AbstractionBlockEdge<'tcx>)>,
+    region_clusters: BTreeSet<GraphCluster>,
     nodes: Vec<GraphNode>,
-    edges: HashSet<GraphEdge>,
+    edges: BTreeSet<GraphEdge>,
     repacker: PlaceRepacker<'mir, 'tcx>,
+    /// Used to describe regions in labels, if available
+    region_names: Option<&'mir RegionNames>,
+    /// Used to mark the reborrows that rustc considers out of scope, if
+    /// available
+    borrow_scopes: Option<BorrowScopesAt<'mir>>,
+    /// Used to label edges with the origins justifying them, if requested
+    origins: Option<OriginFactsAt<'mir, 'mir, 'tcx>>,
 }
 
 struct IdLookup<T>(char, Vec<T>);
@@ -101,15 +128,30 @@ impl<T: Eq + Clone> IdLookup<T> {
 }
 
 impl<'a, 'tcx> GraphConstructor<'a, 'tcx> {
-    fn new(repacker: PlaceRepacker<'a, 'tcx>) -> Self {
+    fn new(
+        repacker: PlaceRepacker<'a, 'tcx>,
+        region_names: Option<&'a RegionNames>,
+        borrow_scopes: Option<BorrowScopesAt<'a>>,
+    ) -> Self {
         Self {
             remote_nodes: IdLookup::new('a'),
             place_nodes: IdLookup::new('p'),
             region_projection_nodes: IdLookup::new('r'),
-            region_clusters: HashSet::new(),
+            junction_nodes: IdLookup::new('j'),
+            region_clusters: BTreeSet::new(),
             nodes: vec![],
-            edges: HashSet::new(),
+            edges: BTreeSet::new(),
             repacker,
+            region_names,
+            borrow_scopes,
+            origins: None,
+        }
+    }
+
+    fn describe_region(&self, region: ty::Region<'tcx>) -> String {
+        match self.region_names {
+            Some(names) => names.describe_region(region),
+            None => format!("{:?}", region),
         }
     }
 
@@ -183,9 +225,12 @@ impl<'a, 'tcx> GraphConstructor<'a, 'tcx> {
             id,
             node_type: NodeType::RegionProjectionNode {
                 label: format!(
-                    "{}↓{:?}",
+                    "{}↓{}",
                     projection.place.to_short_string(self.repacker),
-                    projection.region()
+                    match self.region_names {
+                        Some(names) => names.describe(projection.region()),
+                        None => format!("{:?}", projection.region()),
+                    }
                 ),
             },
         };
@@ -193,27 +238,55 @@ impl<'a, 'tcx> GraphConstructor<'a, 'tcx> {
         id
     }
 
-    fn insert_region_abstraction(&mut self, region_abstraction: &AbstractionEdge<'tcx>) {
+    fn insert_junction_node(
+        &mut self,
+        location: Location,
+        edge: &AbstractionBlockEdge<'tcx>,
+    ) -> NodeId {
+        let key = (location, edge.clone());
+        if let Some(id) = self.junction_nodes.existing_id(&key) {
+            return id;
+        }
+        let id = self.junction_nodes.node_id(&key);
+        self.insert_node(GraphNode {
+            id,
+            node_type: NodeType::JunctionNode,
+        });
+        id
+    }
+
+    /// Each edge of the abstraction is drawn as a hyper-edge: all of its inputs
+    /// and outputs are connected to a single junction node, since the outputs
+    /// block the inputs jointly rather than pairwise.
+    fn insert_region_abstraction(
+        &mut self,
+        region_abstraction: &AbstractionEdge<'tcx>,
+        provenance: &EdgeProvenance,
+    ) {
         let mut input_nodes = BTreeSet::new();
         let mut output_nodes = BTreeSet::new();
+        let mut junction_nodes = BTreeSet::new();
 
         for edge in region_abstraction.edges() {
+            let junction = self.insert_junction_node(region_abstraction.location(), edge);
+            junction_nodes.insert(junction);
             for input in edge.inputs() {
-                let input = self.insert_abstraction_input_target(input);
+                let input = self.insert_abstraction_input_target(*input);
                 input_nodes.insert(input);
+                self.edges.insert(GraphEdge::AbstractionInputEdge {
+                    input,
+                    junction,
+                    provenance: provenance.to_string(),
+                });
             }
             for output in edge.outputs() {
-                let output = self.insert_abstraction_output_target(output);
+                let output = self.insert_abstraction_output_target(*output);
                 output_nodes.insert(output);
-            }
-            for input in &input_nodes {
-                for output in &output_nodes {
-                    // TODO: Color or Label edges
-                    self.edges.insert(GraphEdge::AbstractEdge {
-                        blocked: *input,
-                        blocking: *output,
-                    });
-                }
+                self.edges.insert(GraphEdge::AbstractionOutputEdge {
+                    junction,
+                    output,
+                    provenance: provenance.to_string(),
+                });
             }
         }
 
@@ -224,9 +297,17 @@ impl<'a, 'tcx> GraphConstructor<'a, 'tcx> {
                 region_abstraction.location().block,
                 region_abstraction.location().statement_index
             ),
-            label: format!("{:?}", region_abstraction.location()),
+            label: match &region_abstraction.abstraction_type {
+                AbstractionType::External(abstraction) => format!(
+                    "{} at {:?}",
+                    abstraction.kind().name(),
+                    region_abstraction.location()
+                ),
+                _ => format!("{:?}", region_abstraction.location()),
+            },
             nodes: input_nodes
                 .iter()
+                .chain(junction_nodes.iter())
                 .chain(output_nodes.iter())
                 .cloned()
                 .collect(),
@@ -242,9 +323,8 @@ impl<'a, 'tcx> GraphConstructor<'a, 'tcx> {
         let id = self.remote_nodes.node_id(&remote_place);
         let node = GraphNode {
             id,
-            node_type: NodeType::ReborrowingDagNode {
-                label: format!("Target of input {:?}", remote_place.assigned_local()),
-                location: None,
+            node_type: NodeType::RemotePlaceNode {
+                label: remote_place.to_short_string(self.repacker),
             },
         };
         self.insert_node(node);
@@ -262,21 +342,33 @@ impl<'a, 'tcx> GraphConstructor<'a, 'tcx> {
         }
         let id = self.place_node_id(place, location);
         let label = format!("{:?}", place.to_string(self.repacker));
-        let region = match place.ty(self.repacker).ty.kind() {
-            ty::TyKind::Ref(region, _, _) => Some(format!("{:?}", region)),
+        let place_ty = place.ty(self.repacker).ty;
+        let region = match place_ty.kind() {
+            ty::TyKind::Ref(region, _, _) => Some(self.describe_region(*region)),
             _ => None,
         };
-        let node_type = if place.is_owned(self.repacker.body(), self.repacker.tcx()) {
-            NodeType::FPCSNode {
-                label,
-                capability,
-                location,
-                region,
-            }
-        } else {
-            assert!(capability.is_none());
-            NodeType::ReborrowingDagNode { label, location }
-        };
+        let ty = format!("{}", place_ty);
+        let source = location.and_then(|location| snapshot_source_text(location, self.repacker));
+        // Places behind raw pointers are not owned, but are still tracked by
+        // the free PCS
+        let node_type =
+            if capability.is_some() || place.is_owned(self.repacker.body(), self.repacker.tcx()) {
+                NodeType::FPCSNode {
+                    label,
+                    capability,
+                    location,
+                    source,
+                    region,
+                    ty,
+                }
+            } else {
+                NodeType::ReborrowingDagNode {
+                    label,
+                    location,
+                    source,
+                    ty: Some(ty),
+                }
+            };
         if place.is_owned(self.repacker.body(), self.repacker.tcx()) {
             for region_projection in place.region_projections(self.repacker) {
                 self.insert_region_projection_and_ancestors(region_projection);
@@ -297,7 +389,7 @@ impl<'a, 'tcx> UnblockGraphConstructor<'a, 'tcx> {
     pub fn new(unblock_graph: UnblockGraph<'tcx>, repacker: PlaceRepacker<'a, 'tcx>) -> Self {
         Self {
             unblock_graph,
-            constructor: GraphConstructor::new(repacker),
+            constructor: GraphConstructor::new(repacker, None, None),
         }
     }
 
@@ -337,6 +429,7 @@ trait PlaceGrapher<'mir, 'tcx: 'mir> {
     fn constructor(&mut self) -> &mut GraphConstructor<'mir, 'tcx>;
     fn repacker(&self) -> PlaceRepacker<'mir, 'tcx>;
     fn draw_borrows_edge(&mut self, edge: &BorrowsEdge<'tcx>, graph: Option<&BorrowsGraph<'tcx>>) {
+        let provenance = edge.provenance().to_string();
         match edge.kind() {
             BorrowsEdgeKind::DerefExpansion(deref_expansion) => {
                 let base_node = self.insert_maybe_old_place(deref_expansion.base());
@@ -348,6 +441,7 @@ trait PlaceGrapher<'mir, 'tcx: 'mir> {
                             .insert(GraphEdge::DerefExpansionEdge {
                                 source: base_node,
                                 target,
+                                provenance: provenance.clone(),
                             });
                         let base_rp = owned.base_region_projection(self.repacker());
                         let base_rp_node = self
@@ -368,6 +462,7 @@ trait PlaceGrapher<'mir, 'tcx: 'mir> {
                                 .insert(GraphEdge::DerefExpansionEdge {
                                     source: base_node,
                                     target: place,
+                                    provenance: provenance.clone(),
                                 });
                         }
                     }
@@ -376,12 +471,26 @@ trait PlaceGrapher<'mir, 'tcx: 'mir> {
             BorrowsEdgeKind::Reborrow(reborrow) => {
                 let borrowed_place = self.insert_maybe_remote_place(reborrow.blocked_place);
                 let assigned_place = self.insert_maybe_old_place(reborrow.assigned_place);
+                let region = self.constructor().describe_region(reborrow.region);
+                let location = reborrow.reserve_location();
+                let out_of_scope = self
+                    .constructor()
+                    .borrow_scopes
+                    .is_some_and(|scopes| scopes.is_out_of_scope(location));
+                let origins = self
+                    .constructor()
+                    .origins
+                    .and_then(|origins| origins.of_borrow(location))
+                    .map(|origins| origins.to_string());
                 self.constructor().edges.insert(GraphEdge::ReborrowEdge {
                     borrowed_place,
                     assigned_place,
-                    location: reborrow.reserve_location(),
-                    region: format!("{:?}", reborrow.region),
+                    location,
+                    region,
+                    out_of_scope,
                     path_conditions: format!("{}", edge.conditions()),
+                    provenance,
+                    origins,
                 });
                 if let Some(graph) = graph
                     && let Some((e1, e2)) = graph.region_projection_edge(reborrow, self.repacker())
@@ -397,19 +506,29 @@ trait PlaceGrapher<'mir, 'tcx: 'mir> {
                 }
             }
             BorrowsEdgeKind::Abstraction(abstraction) => {
-                let _r = self.constructor().insert_region_abstraction(abstraction);
+                let _r = self
+                    .constructor()
+                    .insert_region_abstraction(abstraction, edge.provenance());
             }
             BorrowsEdgeKind::RegionProjectionMember(member) => {
-                let place = self.insert_maybe_remote_place(member.place);
                 let region_projection = self
                     .constructor()
                     .insert_region_projection_node(member.projection);
-                self.constructor()
-                    .edges
-                    .insert(GraphEdge::RegionProjectionMemberEdge {
-                        place,
-                        region_projection,
-                    });
+                let origins = self
+                    .constructor()
+                    .origins
+                    .map(|origins| origins.of_region(member.projection.region()).to_string());
+                for &place in member.places.iter() {
+                    let place = self.insert_maybe_remote_place(place);
+                    self.constructor()
+                        .edges
+                        .insert(GraphEdge::RegionProjectionMemberEdge {
+                            place,
+                            region_projection,
+                            provenance: provenance.clone(),
+                            origins: origins.clone(),
+                        });
+                }
             }
         }
     }
@@ -454,15 +573,23 @@ impl<'a, 'tcx> PCSGraphConstructor<'a, 'tcx> {
         summary: &'a CapabilitySummary<'tcx>,
         repacker: PlaceRepacker<'a, 'tcx>,
         borrows_domain: &'a BorrowsState<'tcx>,
+        region_names: &'a RegionNames,
+        borrow_scopes: BorrowScopesAt<'a>,
     ) -> Self {
         Self {
             summary,
             borrows_domain,
-            constructor: GraphConstructor::new(repacker),
+            constructor: GraphConstructor::new(repacker, Some(region_names), Some(borrow_scopes)),
             repacker,
         }
     }
 
+    /// Labels the edges with the origins justifying them
+    pub fn with_origins(mut self, origins: OriginFactsAt<'a, 'a, 'tcx>) -> Self {
+        self.constructor.origins = Some(origins);
+        self
+    }
+
     fn insert_place_and_previous_projections(
         &mut self,
         place: Place<'tcx>,
@@ -525,13 +652,13 @@ impl<'a, 'tcx> PCSGraphConstructor<'a, 'tcx> {
             match capability {
                 CapabilityLocal::Unallocated => {}
                 CapabilityLocal::Allocated(projections) => {
-                    for (place, kind) in projections.iter() {
+                    for (place, kind) in sorted_by_debug(projections.iter()) {
                         self.insert_place_and_previous_projections(*place, None, Some(*kind));
                     }
                 }
             }
         }
-        for edge in self.borrows_domain.graph_edges() {
+        for edge in self.borrows_domain.graph().sorted_edges() {
             self.draw_borrows_edge(edge, Some(self.borrows_domain.graph()));
         }
 
@@ -1,4 +1,8 @@
 use crate::{
+    borrows::{
+        borrows_edge::{BorrowsEdge, BorrowsEdgeKind},
+        domain::{AbstractionType, MaybeRemotePlace},
+    },
     rustc_interface,
     utils::{Place, PlaceRepacker},
 };
@@ -8,9 +12,15 @@ use std::{
     io::{self},
 };
 
-use rustc_interface::middle::{
-    mir::{self, BinOp, Body, Local, Operand, Rvalue, Statement, TerminatorKind, UnwindAction},
-    ty::TyCtxt,
+use rustc_interface::{
+    data_structures::fx::FxHashMap,
+    middle::{
+        mir::{
+            self, BinOp, Body, Local, Location, Operand, Rvalue, Statement, TerminatorKind,
+            UnwindAction,
+        },
+        ty::TyCtxt,
+    },
 };
 
 #[derive(Serialize)]
@@ -25,6 +35,8 @@ struct MirNode {
     block: usize,
     stmts: Vec<String>,
     terminator: String,
+    /// For each statement and the terminator, the borrows that expire there
+    expirations: Vec<Vec<String>>,
 }
 
 #[derive(Serialize)]
@@ -41,12 +53,12 @@ fn format_bin_op(op: &BinOp) -> String {
         BinOp::Mul => "*".to_string(),
         BinOp::Div => "/".to_string(),
         BinOp::Rem => "%".to_string(),
-        BinOp::AddUnchecked => todo!(),
-        BinOp::SubUnchecked => todo!(),
-        BinOp::MulUnchecked => todo!(),
-        BinOp::BitXor => todo!(),
+        BinOp::AddUnchecked => "+".to_string(),
+        BinOp::SubUnchecked => "-".to_string(),
+        BinOp::MulUnchecked => "*".to_string(),
+        BinOp::BitXor => "^".to_string(),
         BinOp::BitAnd => "&".to_string(),
-        BinOp::BitOr => todo!(),
+        BinOp::BitOr => "|".to_string(),
         BinOp::Shl => "<<".to_string(),
         BinOp::ShlUnchecked => "<<".to_string(),
         BinOp::Shr => ">>".to_string(),
@@ -57,8 +69,8 @@ fn format_bin_op(op: &BinOp) -> String {
         BinOp::Ne => "!=".to_string(),
         BinOp::Ge => ">=".to_string(),
         BinOp::Gt => ">".to_string(),
-        BinOp::Offset => todo!(),
-        BinOp::Cmp => todo!(),
+        BinOp::Offset => "offset".to_string(),
+        BinOp::Cmp => "cmp".to_string(),
         BinOp::AddWithOverflow => "+".to_string(),
         BinOp::SubWithOverflow => "-".to_string(),
         BinOp::MulWithOverflow => "*".to_string(),
@@ -86,17 +98,18 @@ fn format_operand<'tcx>(operand: &Operand<'tcx>, repacker: PlaceRepacker<'_, 'tc
 fn format_rvalue<'tcx>(rvalue: &Rvalue<'tcx>, repacker: PlaceRepacker<'_, 'tcx>) -> String {
     match rvalue {
         Rvalue::Use(operand) => format_operand(operand, repacker),
-        Rvalue::Repeat(_, _) => todo!(),
+        Rvalue::Repeat(operand, count) => {
+            format!("[{}; {}]", format_operand(operand, repacker), count)
+        }
         Rvalue::Ref(_region, kind, place) => {
             let kind = match kind {
                 mir::BorrowKind::Shared => "",
                 mir::BorrowKind::Mut { .. } => "mut",
-                mir::BorrowKind::Fake(_) => todo!(),
+                mir::BorrowKind::Fake(_) => "fake",
             };
             format!("&{} {}", kind, format_place(place, repacker))
         }
-        Rvalue::ThreadLocalRef(_) => todo!(),
-        Rvalue::Len(_) => todo!(),
+        Rvalue::Len(place) => format!("Len({})", format_place(place, repacker)),
         Rvalue::Cast(_, operand, ty) => format!("{} as {}", format_operand(operand, repacker), ty),
         Rvalue::BinaryOp(op, box (lhs, rhs)) => {
             format!(
@@ -106,7 +119,6 @@ fn format_rvalue<'tcx>(rvalue: &Rvalue<'tcx>, repacker: PlaceRepacker<'_, 'tcx>)
                 format_operand(rhs, repacker)
             )
         }
-        Rvalue::NullaryOp(_, _) => todo!(),
         Rvalue::UnaryOp(op, val) => {
             format!("{:?} {}", op, format_operand(val, repacker))
         }
@@ -121,12 +133,11 @@ fn format_rvalue<'tcx>(rvalue: &Rvalue<'tcx>, repacker: PlaceRepacker<'_, 'tcx>)
                     .join(", ")
             )
         }
-        Rvalue::ShallowInitBox(_, _) => todo!(),
-        Rvalue::CopyForDeref(_) => todo!(),
-        _ => todo!(),
+        Rvalue::CopyForDeref(place) => format!("deref_copy {}", format_place(place, repacker)),
+        _ => format!("{:?}", rvalue),
     }
 }
-fn format_terminator<'tcx>(
+pub(super) fn format_terminator<'tcx>(
     terminator: &TerminatorKind<'tcx>,
     repacker: PlaceRepacker<'_, 'tcx>,
 ) -> String {
@@ -154,7 +165,54 @@ fn format_terminator<'tcx>(
     }
 }
 
-fn format_stmt<'tcx>(stmt: &Statement<'tcx>, repacker: PlaceRepacker<'_, 'tcx>) -> String {
+fn format_maybe_remote_place<'tcx>(
+    place: &MaybeRemotePlace<'tcx>,
+    repacker: PlaceRepacker<'_, 'tcx>,
+) -> String {
+    match place {
+        MaybeRemotePlace::Local(place) => place.to_short_string(repacker),
+        MaybeRemotePlace::Remote(remote_place) => remote_place.to_short_string(repacker),
+    }
+}
+
+pub(super) fn format_borrows_edge<'tcx>(
+    edge: &BorrowsEdge<'tcx>,
+    repacker: PlaceRepacker<'_, 'tcx>,
+) -> String {
+    match edge.kind() {
+        BorrowsEdgeKind::Reborrow(reborrow) => format!(
+            "&{}{} -> {} (from {:?})",
+            reborrow.mutability.prefix_str(),
+            format_maybe_remote_place(&reborrow.blocked_place, repacker),
+            reborrow.assigned_place.to_short_string(repacker),
+            reborrow.reserve_location()
+        ),
+        BorrowsEdgeKind::Abstraction(abstraction) => match &abstraction.abstraction_type {
+            AbstractionType::FunctionCall(call) => format!(
+                "call to {} (from {:?})",
+                repacker.tcx().def_path_str(call.def_id()),
+                call.location()
+            ),
+            AbstractionType::Loop(abstraction) => {
+                format!("loop at {:?}", abstraction.location().block)
+            }
+            AbstractionType::External(abstraction) => format!(
+                "{} (from {:?})",
+                abstraction.kind().name(),
+                abstraction.location()
+            ),
+        },
+        BorrowsEdgeKind::DerefExpansion(expansion) => {
+            format!("expansion of {}", expansion.base().to_short_string(repacker))
+        }
+        _ => format!("{:?}", edge.kind()),
+    }
+}
+
+pub(super) fn format_stmt<'tcx>(
+    stmt: &Statement<'tcx>,
+    repacker: PlaceRepacker<'_, 'tcx>,
+) -> String {
     match &stmt.kind {
         mir::StatementKind::Assign(box (place, rvalue)) => {
             format!(
@@ -166,32 +224,27 @@ fn format_stmt<'tcx>(stmt: &Statement<'tcx>, repacker: PlaceRepacker<'_, 'tcx>)
         mir::StatementKind::FakeRead(box (_, place)) => {
             format!("FakeRead({})", format_place(place, repacker))
         }
-        mir::StatementKind::SetDiscriminant {
-            place: _,
-            variant_index: _,
-        } => todo!(),
-        mir::StatementKind::Deinit(_) => todo!(),
         mir::StatementKind::StorageLive(local) => {
             format!("StorageLive({})", format_local(local, repacker))
         }
         mir::StatementKind::StorageDead(local) => {
             format!("StorageDead({})", format_local(local, repacker))
         }
-        mir::StatementKind::Retag(_, _) => todo!(),
         mir::StatementKind::PlaceMention(place) => {
             format!("PlaceMention({})", format_place(place, repacker))
         }
         mir::StatementKind::AscribeUserType(_, _) => {
             format!("AscribeUserType(...)")
         }
-        mir::StatementKind::Coverage(_) => todo!(),
-        mir::StatementKind::Intrinsic(_) => todo!(),
-        mir::StatementKind::ConstEvalCounter => todo!(),
-        mir::StatementKind::Nop => todo!(),
+        _ => format!("{:?}", stmt),
     }
 }
 
-fn mk_mir_graph<'mir, 'tcx>(tcx: TyCtxt<'tcx>, body: &'mir Body<'tcx>) -> MirGraph {
+fn mk_mir_graph<'mir, 'tcx>(
+    tcx: TyCtxt<'tcx>,
+    body: &'mir Body<'tcx>,
+    expirations: &FxHashMap<Location, Vec<BorrowsEdge<'tcx>>>,
+) -> MirGraph {
     let mut nodes = Vec::new();
     let mut edges = Vec::new();
 
@@ -205,11 +258,27 @@ fn mk_mir_graph<'mir, 'tcx>(tcx: TyCtxt<'tcx>, body: &'mir Body<'tcx>) -> MirGra
 
         let terminator = format_terminator(&data.terminator().kind, repacker);
 
+        let expirations = (0..=data.statements.len())
+            .map(|statement_index| {
+                let location = Location {
+                    block: bb,
+                    statement_index,
+                };
+                expirations
+                    .get(&location)
+                    .into_iter()
+                    .flatten()
+                    .map(|edge| format_borrows_edge(edge, repacker))
+                    .collect()
+            })
+            .collect();
+
         nodes.push(MirNode {
             id: format!("{:?}", bb),
             block: bb.as_usize(),
             stmts: stmts.collect(),
             terminator,
+            expirations,
         });
 
         match &data.terminator().kind {
@@ -235,7 +304,7 @@ fn mk_mir_graph<'mir, 'tcx>(tcx: TyCtxt<'tcx>, body: &'mir Body<'tcx>) -> MirGra
                 });
             }
             TerminatorKind::UnwindResume => {}
-            TerminatorKind::UnwindTerminate(_) => todo!(),
+            TerminatorKind::UnwindTerminate(_) => {}
             TerminatorKind::Return => {}
             TerminatorKind::Unreachable => {}
             TerminatorKind::Drop {
@@ -266,9 +335,9 @@ fn mk_mir_graph<'mir, 'tcx>(tcx: TyCtxt<'tcx>, body: &'mir Body<'tcx>) -> MirGra
                         label: "call".to_string(),
                     });
                     match unwind {
-                        UnwindAction::Continue => todo!(),
-                        UnwindAction::Unreachable => todo!(),
-                        UnwindAction::Terminate(_) => todo!(),
+                        UnwindAction::Continue
+                        | UnwindAction::Unreachable
+                        | UnwindAction::Terminate(_) => {}
                         UnwindAction::Cleanup(cleanup) => {
                             edges.push(MirEdge {
                                 source: format!("{:?}", bb),
@@ -287,9 +356,9 @@ fn mk_mir_graph<'mir, 'tcx>(tcx: TyCtxt<'tcx>, body: &'mir Body<'tcx>) -> MirGra
                 unwind,
             } => {
                 match unwind {
-                    UnwindAction::Continue => todo!(),
-                    UnwindAction::Unreachable => todo!(),
-                    UnwindAction::Terminate(_) => todo!(),
+                    UnwindAction::Continue
+                    | UnwindAction::Unreachable
+                    | UnwindAction::Terminate(_) => {}
                     UnwindAction::Cleanup(cleanup) => {
                         edges.push(MirEdge {
                             source: format!("{:?}", bb),
@@ -304,12 +373,6 @@ fn mk_mir_graph<'mir, 'tcx>(tcx: TyCtxt<'tcx>, body: &'mir Body<'tcx>) -> MirGra
                     label: format!("success"),
                 });
             }
-            TerminatorKind::Yield {
-                value: _,
-                resume: _,
-                resume_arg: _,
-                drop: _,
-            } => todo!(),
             TerminatorKind::FalseEdge {
                 real_target,
                 imaginary_target: _,
@@ -330,22 +393,30 @@ fn mk_mir_graph<'mir, 'tcx>(tcx: TyCtxt<'tcx>, body: &'mir Body<'tcx>) -> MirGra
                     label: "real".to_string(),
                 });
             }
-            TerminatorKind::InlineAsm {
-                ..
-            } => todo!(),
-            TerminatorKind::CoroutineDrop => todo!(),
-            _ => todo!(),
+            _ => {
+                for target in data.terminator().successors() {
+                    edges.push(MirEdge {
+                        source: format!("{:?}", bb),
+                        target: format!("{:?}", target),
+                        label: String::new(),
+                    });
+                }
+            }
         }
     }
 
     MirGraph { nodes, edges }
 }
+/// Writes the MIR graph of `body` to `path`, annotating each statement with
+/// the borrows that `expirations` says expire there (see
+/// [`crate::combined_pcs::borrow_expirations`]).
 pub fn generate_json_from_mir<'mir, 'tcx>(
     path: &str,
     tcx: TyCtxt<'tcx>,
     body: &'mir Body<'tcx>,
+    expirations: &FxHashMap<Location, Vec<BorrowsEdge<'tcx>>>,
 ) -> io::Result<()> {
-    let mir_graph = mk_mir_graph(tcx, body);
+    let mir_graph = mk_mir_graph(tcx, body, expirations);
     let mut file = File::create(path)?;
     serde_json::to_writer(&mut file, &mir_graph)?;
     Ok(())
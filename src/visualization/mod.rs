@@ -8,26 +8,37 @@ pub mod dot_graph;
 pub mod drawer;
 pub mod graph_constructor;
 pub mod mir_graph;
+pub mod report;
 
 use crate::{
     borrows::{borrows_state::BorrowsState, unblock_graph::UnblockGraph},
-    free_pcs::{CapabilityKind, CapabilitySummary},
+    combined_pcs::PcsContext,
+    free_pcs::{CapabilityKind, CapabilitySummary, HasCgContext},
     rustc_interface,
-    utils::{Place, PlaceRepacker, SnapshotLocation},
+    utils::{
+        borrow_scopes::ProgramPoint, display::as_local, Place, PlaceRepacker, SnapshotLocation,
+    },
+    FpcsOutput,
 };
 use std::{
-    collections::HashSet,
+    collections::BTreeSet,
     fs::File,
     io::{self},
 };
 
 use dot::escape_html;
-use rustc_interface::middle::mir::Location;
+use itertools::Itertools;
+use rustc_interface::{
+    index::IndexVec,
+    middle::mir::{BasicBlock, Body, Location, START_BLOCK},
+};
 
 use self::{
     dot_graph::{
-        DotEdge, DotFloatAttr, DotLabel, DotNode, DotStringAttr, EdgeDirection, EdgeOptions,
+        DotEdge, DotFloatAttr, DotGraph, DotLabel, DotNode, DotStringAttr, EdgeDirection,
+        EdgeOptions,
     },
+    drawer::DotConfig,
     graph_constructor::{GraphCluster, PCSGraphConstructor, UnblockGraphConstructor},
 };
 
@@ -37,6 +48,7 @@ pub fn place_id<'tcx>(place: &Place<'tcx>) -> String {
 
 struct GraphDrawer<T: io::Write> {
     out: T,
+    config: DotConfig,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Ord, PartialOrd)]
@@ -55,42 +67,53 @@ pub struct GraphNode {
 }
 
 impl GraphNode {
-    fn to_dot_node(&self) -> DotNode {
+    /// The node drawn for `self`, where `provenance` is that of the edges at
+    /// the node
+    fn to_dot_node(&self, config: &DotConfig, provenance: &[&str]) -> DotNode {
         match &self.node_type {
-            NodeType::ReborrowingDagNode { label, location } => {
-                let location_text = match location {
-                    Some(l) => escape_html(&format!(" at {:?}", l)),
-                    None => "".to_string(),
-                };
-                let label = format!(
-                    "<FONT FACE=\"courier\">{}</FONT>&nbsp;{}",
-                    escape_html(&label),
+            NodeType::ReborrowingDagNode {
+                label,
+                location,
+                source,
+                ty,
+            } => {
+                let location_text = location_text(*location, source.as_deref(), config);
+                let html_label = format!(
+                    "<FONT FACE=\"{}\">{}</FONT>&nbsp;{}",
+                    config.label_font(),
+                    escape_html(&place_label(label, ty.as_deref(), config)),
                     escape_html(&location_text)
                 );
                 DotNode {
                     id: self.id.to_string(),
-                    label: DotLabel::Html(label.clone()),
+                    label: DotLabel::Html(html_label),
                     color: DotStringAttr("darkgreen".to_string()),
                     font_color: DotStringAttr("darkgreen".to_string()),
                     shape: DotStringAttr("rect".to_string()),
                     style: Some(DotStringAttr("rounded".to_string())),
                     penwidth: Some(DotFloatAttr(1.5)),
+                    tooltip: Some(place_tooltip(
+                        label,
+                        ty.as_deref(),
+                        None,
+                        *location,
+                        provenance,
+                    )),
                 }
             }
             NodeType::FPCSNode {
                 capability,
                 location,
+                source,
                 label,
                 region,
+                ty,
             } => {
                 let capability_text = match capability {
                     Some(k) => format!("{:?}", k),
                     None => "".to_string(),
                 };
-                let location_text = match location {
-                    Some(l) => escape_html(&format!(" at {:?}", l)),
-                    None => "".to_string(),
-                };
+                let location_text = location_text(*location, source.as_deref(), config);
                 let color =
                     if location.is_some() || matches!(capability, Some(CapabilityKind::Write)) {
                         "gray"
@@ -101,43 +124,162 @@ impl GraphNode {
                     Some(r) => format!("<br/>{}", r),
                     None => "".to_string(),
                 };
-                let label = format!(
-                    "<FONT FACE=\"courier\">{}</FONT>&nbsp;{}{}{}",
-                    escape_html(&label),
+                let html_label = format!(
+                    "<FONT FACE=\"{}\">{}</FONT>&nbsp;{}{}{}",
+                    config.label_font(),
+                    escape_html(&place_label(label, Some(ty), config)),
                     escape_html(&capability_text),
                     escape_html(&location_text),
                     region_html
                 );
                 DotNode {
                     id: self.id.to_string(),
-                    label: DotLabel::Html(label),
+                    label: DotLabel::Html(html_label),
                     color: DotStringAttr(color.to_string()),
                     font_color: DotStringAttr(color.to_string()),
                     shape: DotStringAttr("rect".to_string()),
                     style: None,
                     penwidth: None,
+                    tooltip: Some(place_tooltip(
+                        label,
+                        Some(ty),
+                        *capability,
+                        *location,
+                        provenance,
+                    )),
                 }
             }
+            NodeType::JunctionNode => DotNode {
+                id: self.id.to_string(),
+                label: DotLabel::Text("".to_string()),
+                color: DotStringAttr("black".to_string()),
+                font_color: DotStringAttr("black".to_string()),
+                shape: DotStringAttr("point".to_string()),
+                style: None,
+                penwidth: None,
+                tooltip: None,
+            },
+            NodeType::RemotePlaceNode { label } => DotNode {
+                id: self.id.to_string(),
+                label: DotLabel::Html(format!(
+                    "<FONT FACE=\"{}\">{}</FONT>",
+                    config.label_font(),
+                    escape_html(&config.truncate(label))
+                )),
+                color: DotStringAttr("darkgreen".to_string()),
+                font_color: DotStringAttr("darkgreen".to_string()),
+                shape: DotStringAttr("rect".to_string()),
+                style: Some(DotStringAttr("dashed".to_string())),
+                penwidth: Some(DotFloatAttr(1.5)),
+                tooltip: Some(place_tooltip(label, None, None, None, provenance)),
+            },
             NodeType::RegionProjectionNode { label } => DotNode {
                 id: self.id.to_string(),
-                label: DotLabel::Text(label.clone()),
+                label: DotLabel::Text(config.truncate(label)),
                 color: DotStringAttr("blue".to_string()),
                 font_color: DotStringAttr("blue".to_string()),
                 shape: DotStringAttr("octagon".to_string()),
                 style: None,
                 penwidth: None,
+                tooltip: None,
             },
         }
     }
 }
 
+/// The tooltip of a place node, with the parts of its label that are not
+/// shown or truncated and the provenance of the edges at the node, one per
+/// line
+fn place_tooltip(
+    label: &str,
+    ty: Option<&str>,
+    capability: Option<CapabilityKind>,
+    location: Option<SnapshotLocation>,
+    provenance: &[&str],
+) -> DotStringAttr {
+    let mut lines = vec![label.to_string()];
+    lines.extend(ty.map(|ty| format!("type: {ty}")));
+    lines.extend(capability.map(|capability| format!("capability: {capability:?}")));
+    lines.extend(location.map(|location| format!("snapshot at {location:?}")));
+    lines.extend(
+        provenance
+            .iter()
+            .map(|provenance| format!("from: {provenance}")),
+    );
+    let escaped = lines
+        .iter()
+        .map(|line| line.replace('\\', "\\\\").replace('"', "\\\""))
+        .join("\\n");
+    DotStringAttr(escaped)
+}
+
+/// The text after the label of a place that was snapshotted at `location`
+fn location_text(
+    location: Option<SnapshotLocation>,
+    source: Option<&str>,
+    config: &DotConfig,
+) -> String {
+    match (location, source) {
+        (Some(_), Some(source)) if config.source_snippets => format!(" ({source})"),
+        (Some(location), _) => format!(" at {:?}", location),
+        (None, _) => "".to_string(),
+    }
+}
+
+/// The snippets in descriptions of snapshot locations are cut after this many
+/// characters
+const SNIPPET_LENGTH: usize = 24;
+
+/// Describes the snapshot `location` by the source code it was taken at, e.g.
+/// ``before `x = y + 1` on line 14``. Returns `None` if the location does not
+/// correspond to code of the function.
+fn snapshot_source_text(
+    location: SnapshotLocation,
+    repacker: PlaceRepacker<'_, '_>,
+) -> Option<String> {
+    let body = repacker.body();
+    let (mir_location, prefix) = match location {
+        SnapshotLocation::Location(location, _) => (location, "before"),
+        SnapshotLocation::Join(block) => (block.start_location(), "at the join before"),
+        SnapshotLocation::FunctionEntry => return Some("at function entry".to_string()),
+    };
+    let span = as_local(body.source_info(mir_location).span, body.span)?;
+    let source_map = repacker.tcx().sess.source_map();
+    let line = source_map.lookup_char_pos(span.lo()).line;
+    let snippet = source_map.span_to_snippet(span).ok()?;
+    let snippet = snippet.lines().next().unwrap_or_default().trim();
+    let snippet = if snippet.chars().count() > SNIPPET_LENGTH {
+        format!(
+            "{}...",
+            snippet.chars().take(SNIPPET_LENGTH).collect::<String>()
+        )
+    } else {
+        snippet.to_string()
+    };
+    Some(format!("{prefix} `{snippet}` on line {line}"))
+}
+
+/// The label of a place node, truncated and annotated with the type of the
+/// place as configured
+fn place_label(label: &str, ty: Option<&str>, config: &DotConfig) -> String {
+    let label = config.truncate(label);
+    match ty {
+        Some(ty) if config.include_types => format!("{}: {}", label, config.truncate(ty)),
+        _ => label,
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 enum NodeType {
     FPCSNode {
         label: String,
         capability: Option<CapabilityKind>,
         location: Option<SnapshotLocation>,
+        /// The source code `location` corresponds to, see
+        /// [`snapshot_source_text`]
+        source: Option<String>,
         region: Option<String>,
+        ty: String,
     },
     RegionProjectionNode {
         label: String,
@@ -145,21 +287,43 @@ enum NodeType {
     ReborrowingDagNode {
         label: String,
         location: Option<SnapshotLocation>,
+        source: Option<String>,
+        /// The type of the place, if the node is for a place
+        ty: Option<String>,
     },
+    /// The place a reference argument of the function points to, which is
+    /// borrowed by the caller rather than by the function itself
+    RemotePlaceNode {
+        label: String,
+    },
+    /// The point joining the inputs and outputs of an abstraction edge, which
+    /// is drawn as a hyper-edge through this node
+    JunctionNode,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 enum GraphEdge {
-    AbstractEdge {
-        blocked: NodeId,
-        blocking: NodeId,
+    AbstractionInputEdge {
+        input: NodeId,
+        junction: NodeId,
+        provenance: String,
+    },
+    AbstractionOutputEdge {
+        junction: NodeId,
+        output: NodeId,
+        provenance: String,
     },
     ReborrowEdge {
         borrowed_place: NodeId,
         assigned_place: NodeId,
         location: Location,
         region: String,
+        /// Whether rustc considers the borrow to be out of scope here
+        out_of_scope: bool,
         path_conditions: String,
+        provenance: String,
+        /// The Polonius origins justifying the edge, if requested
+        origins: Option<String>,
     },
     ProjectionEdge {
         source: NodeId,
@@ -168,10 +332,14 @@ enum GraphEdge {
     DerefExpansionEdge {
         source: NodeId,
         target: NodeId,
+        provenance: String,
     },
     RegionProjectionMemberEdge {
         place: NodeId,
         region_projection: NodeId,
+        provenance: String,
+        /// The Polonius origins justifying the edge, if requested
+        origins: Option<String>,
     },
     RegionProjectionToDerefExpansionEdge {
         region_projection: NodeId,
@@ -184,6 +352,50 @@ enum GraphEdge {
 }
 
 impl GraphEdge {
+    fn endpoints(&self) -> [NodeId; 2] {
+        match *self {
+            GraphEdge::AbstractionInputEdge {
+                input, junction, ..
+            } => [input, junction],
+            GraphEdge::AbstractionOutputEdge {
+                junction, output, ..
+            } => [junction, output],
+            GraphEdge::ReborrowEdge {
+                borrowed_place,
+                assigned_place,
+                ..
+            }
+            | GraphEdge::RegionProjectionBorrowEdge {
+                borrowed_place,
+                assigned_place,
+            } => [borrowed_place, assigned_place],
+            GraphEdge::ProjectionEdge { source, target }
+            | GraphEdge::DerefExpansionEdge { source, target, .. } => [source, target],
+            GraphEdge::RegionProjectionMemberEdge {
+                place,
+                region_projection,
+                ..
+            } => [place, region_projection],
+            GraphEdge::RegionProjectionToDerefExpansionEdge {
+                region_projection,
+                deref,
+            } => [region_projection, deref],
+        }
+    }
+
+    fn provenance(&self) -> Option<&str> {
+        match self {
+            GraphEdge::AbstractionInputEdge { provenance, .. }
+            | GraphEdge::AbstractionOutputEdge { provenance, .. }
+            | GraphEdge::ReborrowEdge { provenance, .. }
+            | GraphEdge::DerefExpansionEdge { provenance, .. }
+            | GraphEdge::RegionProjectionMemberEdge { provenance, .. } => Some(provenance),
+            GraphEdge::ProjectionEdge { .. }
+            | GraphEdge::RegionProjectionToDerefExpansionEdge { .. }
+            | GraphEdge::RegionProjectionBorrowEdge { .. } => None,
+        }
+    }
+
     fn to_dot_edge(&self) -> DotEdge {
         match self {
             GraphEdge::ProjectionEdge { source, target } => DotEdge {
@@ -196,14 +408,32 @@ impl GraphEdge {
                 assigned_place,
                 location: _,
                 region,
+                out_of_scope,
                 path_conditions,
-            } => DotEdge {
-                to: assigned_place.to_string(),
-                from: borrowed_place.to_string(),
-                options: EdgeOptions::directed(EdgeDirection::Forward)
-                    .with_color("orange".to_string())
-                    .with_label(format!("{} - {}", region, path_conditions)),
-            },
+                provenance,
+                origins,
+            } => {
+                let mut label = format!("{} - {}", region, path_conditions);
+                if *out_of_scope {
+                    label.push_str(" (out of scope for rustc)");
+                }
+                if let Some(origins) = origins {
+                    label.push_str(&format!("\\n{}", origins));
+                }
+                let options = EdgeOptions::directed(EdgeDirection::Forward).with_label(label);
+                let options = if *out_of_scope {
+                    options
+                        .with_color("gray".to_string())
+                        .with_style("dashed".to_string())
+                } else {
+                    options.with_color("orange".to_string())
+                };
+                DotEdge {
+                    to: assigned_place.to_string(),
+                    from: borrowed_place.to_string(),
+                    options: options.with_tooltip(provenance.clone()),
+                }
+            }
             GraphEdge::RegionProjectionBorrowEdge {
                 borrowed_place,
                 assigned_place,
@@ -222,24 +452,48 @@ impl GraphEdge {
                 options: EdgeOptions::directed(EdgeDirection::Forward)
                     .with_color("orange".to_string()),
             },
-            GraphEdge::DerefExpansionEdge { source, target } => DotEdge {
+            GraphEdge::DerefExpansionEdge {
+                source,
+                target,
+                provenance,
+            } => DotEdge {
                 from: source.to_string(),
                 to: target.to_string(),
-                options: EdgeOptions::undirected().with_color("green".to_string()),
+                options: EdgeOptions::undirected()
+                    .with_color("green".to_string())
+                    .with_tooltip(provenance.clone()),
+            },
+            GraphEdge::AbstractionInputEdge {
+                input,
+                junction,
+                provenance,
+            } => DotEdge {
+                from: input.to_string(),
+                to: junction.to_string(),
+                options: EdgeOptions::undirected().with_tooltip(provenance.clone()),
             },
-            GraphEdge::AbstractEdge { blocked, blocking } => DotEdge {
-                from: blocked.to_string(),
-                to: blocking.to_string(),
-                options: EdgeOptions::directed(EdgeDirection::Forward),
+            GraphEdge::AbstractionOutputEdge {
+                junction,
+                output,
+                provenance,
+            } => DotEdge {
+                from: junction.to_string(),
+                to: output.to_string(),
+                options: EdgeOptions::directed(EdgeDirection::Forward)
+                    .with_tooltip(provenance.clone()),
             },
             GraphEdge::RegionProjectionMemberEdge {
                 place: source,
                 region_projection: target,
+                provenance,
+                origins,
             } => DotEdge {
                 from: source.to_string(),
                 to: target.to_string(),
                 options: EdgeOptions::directed(EdgeDirection::Forward)
-                    .with_color("purple".to_string()),
+                    .with_color("purple".to_string())
+                    .with_label(origins.clone().unwrap_or_default())
+                    .with_tooltip(provenance.clone()),
             },
         }
     }
@@ -247,15 +501,56 @@ impl GraphEdge {
 
 pub struct Graph {
     nodes: Vec<GraphNode>,
-    edges: HashSet<GraphEdge>,
-    clusters: HashSet<GraphCluster>,
+    edges: BTreeSet<GraphEdge>,
+    clusters: BTreeSet<GraphCluster>,
 }
 
 impl Graph {
+    /// The provenance of the edges from or to `node`, without duplicates
+    fn provenance_at(&self, node: NodeId) -> Vec<&str> {
+        self.edges
+            .iter()
+            .filter(|edge| edge.endpoints().contains(&node))
+            .filter_map(|edge| edge.provenance())
+            .sorted()
+            .dedup()
+            .collect()
+    }
+
+    /// The nodes that are not connected to any edge of the borrows graph,
+    /// also via the edges between places and their projections
+    fn unborrowed_nodes(&self) -> BTreeSet<NodeId> {
+        let is_projection = |edge: &&GraphEdge| matches!(edge, GraphEdge::ProjectionEdge { .. });
+        let mut borrowed: BTreeSet<NodeId> = self
+            .edges
+            .iter()
+            .filter(|edge| !is_projection(edge))
+            .flat_map(|edge| edge.endpoints())
+            .collect();
+        let mut worklist: Vec<NodeId> = borrowed.iter().copied().collect();
+        while let Some(node) = worklist.pop() {
+            for edge in self.edges.iter().filter(is_projection) {
+                let endpoints = edge.endpoints();
+                if endpoints.contains(&node) {
+                    for other in endpoints {
+                        if borrowed.insert(other) {
+                            worklist.push(other);
+                        }
+                    }
+                }
+            }
+        }
+        self.nodes
+            .iter()
+            .map(|node| node.id)
+            .filter(|id| !borrowed.contains(id))
+            .collect()
+    }
+
     fn new(
         nodes: Vec<GraphNode>,
-        edges: HashSet<GraphEdge>,
-        clusters: HashSet<GraphCluster>,
+        edges: BTreeSet<GraphEdge>,
+        clusters: BTreeSet<GraphCluster>,
     ) -> Self {
         Self {
             nodes,
@@ -272,21 +567,216 @@ pub fn generate_unblock_dot_graph<'a, 'tcx: 'a>(
     let constructor = UnblockGraphConstructor::new(unblock_graph.clone(), *repacker);
     let graph = constructor.construct_graph();
     let mut buf = vec![];
-    let drawer = GraphDrawer::new(&mut buf);
+    let drawer = GraphDrawer::new(&mut buf, DotConfig::default());
     drawer.draw(graph)?;
     Ok(String::from_utf8(buf).unwrap())
 }
 
-pub fn generate_dot_graph<'a, 'tcx: 'a>(
-    repacker: PlaceRepacker<'a, 'tcx>,
+/// The graph of the state given by `summary` and `borrows_domain` at `point`
+/// in the body of `cgx`
+fn construct_pcs_graph<'a, 'tcx>(
+    cgx: &'a PcsContext<'_, 'tcx>,
+    summary: &'a CapabilitySummary<'tcx>,
+    borrows_domain: &'a BorrowsState<'tcx>,
+    point: ProgramPoint,
+    config: &DotConfig,
+) -> Graph {
+    let mut constructor = PCSGraphConstructor::new(
+        summary,
+        cgx.rp,
+        borrows_domain,
+        cgx.region_names(),
+        cgx.borrow_scopes().at(point),
+    );
+    if config.origins
+        && let Some(origins) = cgx.origin_facts()
+    {
+        constructor = constructor.with_origins(origins.at(point));
+    }
+    constructor.construct_graph()
+}
+
+pub fn generate_dot_graph<'tcx>(
+    cgx: &PcsContext<'_, 'tcx>,
     summary: &CapabilitySummary<'tcx>,
     borrows_domain: &BorrowsState<'tcx>,
+    point: ProgramPoint,
     file_path: &str,
+    config: &DotConfig,
 ) -> io::Result<()> {
-    let constructor = PCSGraphConstructor::new(summary, repacker, borrows_domain);
-    let graph = constructor.construct_graph();
-    let drawer = GraphDrawer::new(File::create(file_path).unwrap_or_else(|e| {
+    let graph = construct_pcs_graph(cgx, summary, borrows_domain, point, config);
+    let file = File::create(file_path).unwrap_or_else(|e| {
         panic!("Failed to create file at path: {}: {}", file_path, e);
-    }));
+    });
+    let drawer = GraphDrawer::new(file, config.clone());
     drawer.draw(graph)
 }
+
+/// Draws the states after all locations of the body in a single graph, for
+/// viewing how the graph evolves in small functions.
+///
+/// The graph of each location is drawn in its own cluster, which also contains
+/// a point node through which the clusters are connected in program order.
+pub fn generate_function_dot_graph(
+    output: &mut FpcsOutput<'_, '_>,
+    file_path: &str,
+    config: &DotConfig,
+) -> io::Result<()> {
+    let repacker = output.repacker();
+    let cgx = output.cursor.analysis().get_cgx();
+    let body = repacker.body();
+    let prefix = |location: Location| format!("{:?}_{}_", location.block, location.statement_index);
+    let anchor = |location: Location| format!("{}anchor", prefix(location));
+    let mut subgraphs = vec![];
+    let mut edges = vec![];
+    for block in body.basic_blocks.indices() {
+        for stmt in output.get_all_for_bb(block).statements {
+            let location = stmt.location;
+            let graph = construct_pcs_graph(
+                &cgx,
+                &stmt.states.after,
+                &stmt.extra.after,
+                ProgramPoint::After(location),
+                config,
+            );
+            let (mut cluster, cluster_edges) = graph
+                .to_dot_graph(config)
+                .into_cluster(&prefix(location), format!("{:?}", location));
+            cluster.nodes.push(DotNode {
+                id: anchor(location),
+                label: DotLabel::Text("".to_string()),
+                font_color: DotStringAttr("gray".to_string()),
+                color: DotStringAttr("gray".to_string()),
+                shape: DotStringAttr("point".to_string()),
+                style: None,
+                penwidth: None,
+                tooltip: None,
+            });
+            subgraphs.push(cluster);
+            edges.extend(cluster_edges);
+
+            let successors = if location == body.terminator_loc(block) {
+                body[block]
+                    .terminator()
+                    .successors()
+                    .map(|succ| succ.start_location())
+                    .collect()
+            } else {
+                vec![location.successor_within_block()]
+            };
+            for succ in successors {
+                edges.push(DotEdge {
+                    from: anchor(location),
+                    to: anchor(succ),
+                    options: EdgeOptions::directed(EdgeDirection::Forward)
+                        .with_color("gray".to_string())
+                        .with_style("dashed".to_string()),
+                });
+            }
+        }
+    }
+    let graph = DotGraph {
+        name: "Function".to_string(),
+        rankdir: config.rankdir.to_string(),
+        font_name: config.font_name.clone(),
+        font_size: config.font_size,
+        nodes: vec![],
+        edges,
+        subgraphs,
+    };
+    graph.write_to_file(file_path)
+}
+
+/// The maximum number of paths to each block rendered by
+/// [`generate_path_dot_graphs`]
+const MAX_PATHS_PER_BLOCK: usize = 16;
+
+/// Renders, for each location in `locations` (or each location of the body if
+/// it is `None`), one graph per acyclic path from the start block to it.
+///
+/// Each graph only contains the edges of the borrows graph whose conditions
+/// hold on its path (see [`BorrowsState::filter_for_path`]).
+///
+/// The graphs of the statements of block `N` are listed in
+/// `block_N_paths.json`, as pairs of the path (as block indices) and the file
+/// name of the graph, per statement; the list of a statement that is not
+/// rendered is empty. The graphs are numbered by path within each statement.
+/// Blocks that are reached by many paths only have the first
+/// [`MAX_PATHS_PER_BLOCK`] of them rendered.
+pub fn generate_path_dot_graphs(
+    output: &mut FpcsOutput<'_, '_>,
+    dir_path: &str,
+    locations: Option<&BTreeSet<Location>>,
+    config: &DotConfig,
+) -> io::Result<()> {
+    let repacker = output.repacker();
+    let cgx = output.cursor.analysis().get_cgx();
+    let selected = |location: Location| locations.map_or(true, |l| l.contains(&location));
+    for (block, paths) in acyclic_paths(repacker.body()).into_iter_enumerated() {
+        if paths.is_empty() {
+            continue;
+        }
+        let statements = output.get_all_for_bb(block).statements;
+        if !statements.iter().any(|stmt| selected(stmt.location)) {
+            continue;
+        }
+        let mut graphs = vec![];
+        for stmt in statements {
+            let mut stmt_graphs = vec![];
+            if !selected(stmt.location) {
+                graphs.push(stmt_graphs);
+                continue;
+            }
+            for (idx, path) in paths.iter().enumerate() {
+                let mut borrows = stmt.extra.after.clone();
+                borrows.filter_for_path(path);
+                let filename = format!(
+                    "block_{}_stmt_{}_path_{}.dot",
+                    block.index(),
+                    stmt.location.statement_index,
+                    idx
+                );
+                generate_dot_graph(
+                    &cgx,
+                    &stmt.states.after,
+                    &borrows,
+                    ProgramPoint::After(stmt.location),
+                    &format!("{}/{}", dir_path, filename),
+                    config,
+                )?;
+                let path: Vec<_> = path.iter().map(|block| block.index()).collect();
+                stmt_graphs.push((path, filename));
+            }
+            graphs.push(stmt_graphs);
+        }
+        std::fs::write(
+            format!("{}/block_{}_paths.json", dir_path, block.index()),
+            serde_json::to_string_pretty(&graphs).unwrap(),
+        )?;
+    }
+    Ok(())
+}
+
+/// The acyclic paths from the start block to each block, ignoring cleanup
+/// blocks. Once [`MAX_PATHS_PER_BLOCK`] paths to a block have been found,
+/// further paths to it are neither recorded nor extended.
+fn acyclic_paths(body: &Body<'_>) -> IndexVec<BasicBlock, Vec<Vec<BasicBlock>>> {
+    let mut paths = IndexVec::from_elem_n(vec![], body.basic_blocks.len());
+    let mut worklist = vec![vec![START_BLOCK]];
+    while let Some(path) = worklist.pop() {
+        let block = *path.last().unwrap();
+        if paths[block].len() >= MAX_PATHS_PER_BLOCK {
+            continue;
+        }
+        for succ in body[block].terminator().successors() {
+            if !body[succ].is_cleanup && !path.contains(&succ) {
+                worklist.push([path.as_slice(), &[succ]].concat());
+            }
+        }
+        paths[block].push(path);
+    }
+    for block_paths in paths.iter_mut() {
+        block_paths.sort();
+    }
+    paths
+}
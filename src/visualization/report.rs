@@ -0,0 +1,167 @@
+// © 2023, ETH Zurich
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A standalone HTML report showing the source of a function, with each line
+//! linked to the PCS states of the MIR locations it was lowered to.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, Write},
+};
+
+use dot::escape_html;
+use itertools::Itertools;
+use rustc_interface::middle::mir::{Body, Location};
+
+use crate::{
+    rustc_interface,
+    utils::{display::as_local, PlaceRepacker},
+    FpcsOutput,
+};
+
+use super::mir_graph::{format_borrows_edge, format_stmt, format_terminator};
+
+const STYLE: &str = "
+body { font-family: sans-serif; }
+table.source { border-collapse: collapse; }
+table.source td { padding: 0 8px; vertical-align: top; }
+table.source tr:target, div.location:target { background: #fff3b0; }
+td.lineno { color: #888; text-align: right; }
+td.locations a { margin-right: 4px; }
+pre { margin: 0; }
+div.location { border-top: 1px solid #ccc; padding: 4px 0; }
+";
+
+/// Writes the report for the function analysed by `output` to `file_path`.
+///
+/// Locations whose span lies outside the function (even after going to the
+/// callsite of macro expansions) are listed in the report but not linked to
+/// any source line.
+pub fn generate_source_report(output: &mut FpcsOutput<'_, '_>, file_path: &str) -> io::Result<()> {
+    let repacker = output.repacker();
+    let body = repacker.body();
+    let tcx = repacker.tcx();
+    let source_map = tcx.sess.source_map();
+    let first = source_map.lookup_char_pos(body.span.lo());
+    let last = source_map.lookup_char_pos(body.span.hi());
+
+    let mut locations_of_line: BTreeMap<usize, Vec<Location>> = BTreeMap::new();
+    let mut line_of_location = BTreeMap::new();
+    for (block, data) in body.basic_blocks.iter_enumerated() {
+        for statement_index in 0..=data.statements.len() {
+            let location = Location {
+                block,
+                statement_index,
+            };
+            if let Some(span) = as_local(body.source_info(location).span, body.span) {
+                let line = source_map.lookup_char_pos(span.lo()).line;
+                locations_of_line.entry(line).or_default().push(location);
+                line_of_location.insert(location, line);
+            }
+        }
+    }
+
+    let name = escape_html(&tcx.def_path_str(body.source.def_id()));
+    let mut out = File::create(file_path)?;
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(
+        out,
+        "<html><head><meta charset=\"utf-8\"><title>{name}</title>"
+    )?;
+    writeln!(out, "<style>{STYLE}</style></head><body>")?;
+    writeln!(out, "<h1>{name}</h1>")?;
+
+    writeln!(out, "<table class=\"source\">")?;
+    for line in first.line..=last.line {
+        let text = first.file.get_line(line - 1).unwrap_or_default();
+        let links = locations_of_line
+            .get(&line)
+            .into_iter()
+            .flatten()
+            .map(|location| format!("<a href=\"#{}\">{location:?}</a>", location_id(*location)))
+            .join("");
+        writeln!(
+            out,
+            "<tr id=\"line_{line}\"><td class=\"lineno\">{line}</td>\
+             <td><pre>{}</pre></td><td class=\"locations\">{links}</td></tr>",
+            escape_html(&text)
+        )?;
+    }
+    writeln!(out, "</table>")?;
+
+    writeln!(out, "<h2>States after each location</h2>")?;
+    for block in body.basic_blocks.indices() {
+        for stmt in output.get_all_for_bb(block).statements {
+            let location = stmt.location;
+            let source_link = match line_of_location.get(&location) {
+                Some(line) => format!(" (<a href=\"#line_{line}\">line {line}</a>)"),
+                None => String::new(),
+            };
+            writeln!(
+                out,
+                "<div class=\"location\" id=\"{}\"><h3>{location:?}: <code>{}</code>{source_link}</h3>",
+                location_id(location),
+                escape_html(&format_location(body, location, repacker))
+            )?;
+
+            let capabilities = stmt
+                .states
+                .after
+                .capabilities()
+                .into_iter()
+                .map(|(place, kind)| format!("{}: {kind:?}", place.to_short_string(repacker)))
+                .sorted()
+                .collect_vec();
+            write_list(&mut out, "Capabilities", &capabilities)?;
+
+            let edges = stmt
+                .extra
+                .after
+                .graph()
+                .sorted_edges()
+                .into_iter()
+                .map(|edge| {
+                    format!(
+                        "{} [{}]",
+                        format_borrows_edge(edge, repacker),
+                        edge.conditions()
+                    )
+                })
+                .sorted()
+                .collect_vec();
+            write_list(&mut out, "Borrows", &edges)?;
+            writeln!(out, "</div>")?;
+        }
+    }
+    writeln!(out, "</body></html>")
+}
+
+fn location_id(location: Location) -> String {
+    format!("bb{}_{}", location.block.index(), location.statement_index)
+}
+
+fn format_location<'tcx>(
+    body: &Body<'tcx>,
+    location: Location,
+    repacker: PlaceRepacker<'_, 'tcx>,
+) -> String {
+    body.stmt_at(location).either(
+        |stmt| format_stmt(stmt, repacker),
+        |terminator| format_terminator(&terminator.kind, repacker),
+    )
+}
+
+fn write_list(out: &mut impl Write, title: &str, items: &[String]) -> io::Result<()> {
+    if items.is_empty() {
+        return Ok(());
+    }
+    writeln!(out, "<h4>{title}</h4><ul>")?;
+    for item in items {
+        writeln!(out, "<li><code>{}</code></li>", escape_html(item))?;
+    }
+    writeln!(out, "</ul>")
+}
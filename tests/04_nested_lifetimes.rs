@@ -0,0 +1,21 @@
+fn array<'a>(x: &'a mut i32) -> [&'a mut i32; 1] {
+    [x]
+}
+
+fn slice<'a, 'b>(xs: &'b mut [&'a mut i32]) -> &'b mut &'a mut i32 {
+    &mut xs[0]
+}
+
+fn raw<'a>(p: *mut &'a mut i32, x: &'a mut i32) -> *mut &'a mut i32 {
+    unsafe {
+        *p = x;
+    }
+    p
+}
+
+fn main() {
+    let mut a = 1;
+    let mut arr = array(&mut a);
+    let r = slice(&mut arr);
+    **r = 2;
+}
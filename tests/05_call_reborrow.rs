@@ -0,0 +1,11 @@
+fn g<'a>(x: &'a mut i32) -> &'a mut i32 {
+    x
+}
+
+fn main() {
+    let mut a = 1;
+    let x = &mut a;
+    let y = g(x);
+    *y = 2;
+    *x = 3;
+}
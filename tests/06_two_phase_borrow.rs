@@ -0,0 +1,9 @@
+fn push_len(v: &mut Vec<usize>) {
+    v.push(v.len());
+}
+
+fn main() {
+    let mut v = vec![1];
+    push_len(&mut v);
+    v.push(v.len());
+}
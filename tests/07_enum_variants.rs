@@ -0,0 +1,19 @@
+enum Shape {
+    Rect(u32, u32),
+    Circle(u32),
+}
+
+fn grow(shape: &mut Shape) -> &mut u32 {
+    match shape {
+        Shape::Rect(w, _) => w,
+        Shape::Circle(r) => r,
+    }
+}
+
+fn main() {
+    let mut s = Shape::Rect(1, 2);
+    *grow(&mut s) += 1;
+    if let Shape::Circle(r) = &mut s {
+        *r += 1;
+    }
+}
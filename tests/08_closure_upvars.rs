@@ -0,0 +1,16 @@
+fn closure_captures(x: &mut i32, y: &mut (i32, i32)) {
+    let mut c = || {
+        *x += 1;
+        y.0 += 1;
+    };
+    c();
+}
+
+async fn async_captures(x: &mut i32, y: &mut (i32, i32)) -> i32 {
+    *x += 1;
+    let r = &mut y.1;
+    *r += *x;
+    y.0
+}
+
+fn main() {}
@@ -0,0 +1,15 @@
+struct P {
+    a: i32,
+    b: i32,
+}
+fn first<'a>(x: &'a mut P, y: &'a mut P, c: bool) -> &'a mut i32 {
+    if c {
+        &mut x.a
+    } else {
+        &mut y.b
+    }
+}
+fn both<'a, 'b>(x: &'a mut i32, y: &'b mut i32) -> (&'a mut i32, &'b mut i32) {
+    (x, y)
+}
+fn main() {}
@@ -0,0 +1,21 @@
+struct Pair<'a> {
+    a: &'a mut i32,
+    b: &'a i32,
+}
+
+fn bump<'a>(x: &'a mut i32) -> &'a mut i32 {
+    *x += 1;
+    x
+}
+
+fn main() {
+    let mut x = 1;
+    let y = 2;
+    let r = &mut x;
+    let p = Pair { a: r, b: &y };
+    let s = p.a;
+    let t = bump(s);
+    *t = 3;
+    let q = (p.b, p.b);
+    assert!(*q.0 == *q.1);
+}
@@ -0,0 +1,26 @@
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+struct Segment<'a> {
+    from: &'a mut Point,
+    to: &'a mut Point,
+}
+
+fn shift(seg: &mut Segment<'_>) {
+    let from_x = &mut seg.from.x;
+    *from_x += 1;
+    let to_y = &mut seg.to.y;
+    *to_y += 1;
+    seg.from.y = seg.to.x;
+}
+
+fn main() {
+    let mut a = Point { x: 0, y: 0 };
+    let mut b = Point { x: 1, y: 1 };
+    let mut seg = Segment { from: &mut a, to: &mut b };
+    shift(&mut seg);
+    let x = &mut seg.from.x;
+    *x = 2;
+}
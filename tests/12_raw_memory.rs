@@ -0,0 +1,28 @@
+struct S {
+    v: Vec<i32>,
+}
+
+fn read_copy(x: i32) -> i32 {
+    let y = unsafe { std::ptr::read(&x) };
+    x + y
+}
+
+fn read_forget(s: S) -> S {
+    let t = unsafe { std::ptr::read(&s) };
+    std::mem::forget(s);
+    t
+}
+
+fn main() {
+    let mut s = S { v: vec![1] };
+    let v = unsafe { std::ptr::read(&s.v) };
+    unsafe { std::ptr::write(&mut s.v, v) };
+    let mut a = String::new();
+    let b = String::from("x");
+    unsafe { std::ptr::copy_nonoverlapping(&b, &mut a as *mut String, 1) };
+    std::mem::forget(b);
+    let w = unsafe { std::ptr::read(&mut a as *mut String) };
+    drop(w);
+    read_copy(1);
+    read_forget(s);
+}
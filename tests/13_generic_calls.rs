@@ -0,0 +1,41 @@
+fn id<T>(x: T) -> T {
+    x
+}
+
+fn first<T>(v: &mut Vec<T>) -> &mut T {
+    &mut v[0]
+}
+
+trait Getter {
+    fn get(&mut self) -> &mut i32;
+}
+
+struct Cell {
+    value: i32,
+}
+
+impl Getter for Cell {
+    fn get(&mut self) -> &mut i32 {
+        &mut self.value
+    }
+}
+
+fn through_trait<G: Getter>(g: &mut G) -> &mut i32 {
+    g.get()
+}
+
+fn main() {
+    let mut a = 1;
+    let r = id(&mut a);
+    *r = 2;
+    let mut b = 3;
+    let mut v = Vec::new();
+    v.push(&mut b);
+    let s = first(&mut v);
+    **s = 4;
+    let mut c = Cell { value: 5 };
+    let t = c.get();
+    *t = 6;
+    let u = through_trait(&mut c);
+    *u = 7;
+}
@@ -0,0 +1,34 @@
+use std::pin::Pin;
+
+fn swap_refs<'a>(x: &mut &'a mut i32, y: &mut &'a mut i32) {
+    std::mem::swap(x, y);
+}
+
+fn read_ref<'a>(r: &&'a mut i32) -> &'a mut i32 {
+    unsafe { std::ptr::read(r) }
+}
+
+fn leak(b: Box<i32>) -> &'static mut i32 {
+    Box::leak(b)
+}
+
+fn pinned<'a>(p: &'a mut Pin<&mut i32>) -> Pin<&'a mut i32> {
+    p.as_mut()
+}
+
+fn main() {
+    let mut a = 1;
+    let mut b = 2;
+    let mut x = &mut a;
+    let mut y = &mut b;
+    swap_refs(&mut x, &mut y);
+    *x = 3;
+    let z = read_ref(&y);
+    *z = 4;
+    let l = leak(Box::new(5));
+    *l = 6;
+    let mut c = 7;
+    let mut p = Pin::new(&mut c);
+    let q = pinned(&mut p);
+    *q.get_mut() = 8;
+}
@@ -0,0 +1,26 @@
+// Under `cargo test`, `pcs` names this crate rather than the tool
+#[cfg_attr(not(test), pcs::havoc)]
+fn havoc<'a>(x: &'a mut i32, _y: &'a mut i32) -> &'a mut i32 {
+    x
+}
+
+#[cfg_attr(not(test), pcs::trusted)]
+fn trusted<'a>(x: &'a mut i32, _y: &'a mut i32) -> &'a mut i32 {
+    x
+}
+
+#[cfg_attr(not(test), pcs::precise)]
+fn precise<'a>(x: &'a mut i32, _y: &mut i32) -> &'a mut i32 {
+    x
+}
+
+fn main() {
+    let mut a = 1;
+    let mut b = 2;
+    let r = havoc(&mut a, &mut b);
+    *r = 3;
+    let s = trusted(&mut a, &mut b);
+    *s = 4;
+    let t = precise(&mut a, &mut b);
+    *t = 5;
+}
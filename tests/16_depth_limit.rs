@@ -0,0 +1,38 @@
+// pcs-projection-depth-limit: 2
+struct Node<'a> {
+    value: i32,
+    next: Option<&'a mut Node<'a>>,
+}
+
+fn set_deep(x: &mut &mut &mut i32) {
+    ***x = 1;
+}
+
+fn set_second(l: &mut Node<'_>) {
+    if let Some(ref mut second) = l.next {
+        second.value = 2;
+        if let Some(ref mut third) = second.next {
+            third.value = 3;
+        }
+    }
+}
+
+fn main() {
+    let mut a = 0;
+    let mut b = &mut a;
+    set_deep(&mut &mut b);
+    let mut third = Node {
+        value: 0,
+        next: None,
+    };
+    let mut second = Node {
+        value: 0,
+        next: Some(&mut third),
+    };
+    let mut first = Node {
+        value: 0,
+        next: Some(&mut second),
+    };
+    set_second(&mut first);
+    first.value = 1;
+}
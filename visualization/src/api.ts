@@ -5,6 +5,7 @@ export type MirGraphNode = {
   block: number;
   stmts: string[];
   terminator: string;
+  expirations: string[][];
 };
 
 export type MirGraphEdge = {
@@ -35,6 +36,22 @@ export async function getPCSIterations(
   return iterations;
 }
 
+// For each statement, the path and dot file of each per-path graph
+export type PathGraphs = [number[], string][][];
+
+export async function getPathGraphs(
+  functionName: string,
+  block: number
+): Promise<PathGraphs> {
+  try {
+    return await fetchJsonFile(`data/${functionName}/block_${block}_paths.json`);
+  } catch (error) {
+    // Only generated when the analysis is run with PCS_VISUALIZE_PATHS, and
+    // only for the blocks containing a selected location
+    return [];
+  }
+}
+
 export async function getGraphData(func: string): Promise<MirGraph> {
   const graphFilePath = `data/${func}/mir.json`;
   return await fetchJsonFile(graphFilePath);
@@ -10,6 +10,19 @@ interface BasicBlockTableProps {
   showStorageStmts: boolean;
 }
 
+function Expirations({ expirations }: { expirations?: string[] }) {
+  if (!expirations || expirations.length === 0) {
+    return null;
+  }
+  return (
+    <div className="expires">
+      {expirations.map((expiration, i) => (
+        <div key={i}>expires here: {expiration}</div>
+      ))}
+    </div>
+  );
+}
+
 export function isStorageStmt(stmt: string) {
   return stmt.startsWith("StorageLive") || stmt.startsWith("StorageDead");
 }
@@ -63,6 +76,7 @@ export default function BasicBlockTable({
               <td>{i}</td>
               <td>
                 <code>{stmt}</code>
+                <Expirations expirations={data.expirations?.[i]} />
               </td>
             </tr>
           );
@@ -86,6 +100,9 @@ export default function BasicBlockTable({
           <td>T</td>
           <td>
             <code>{data.terminator}</code>
+            <Expirations
+              expirations={data.expirations?.[data.stmts.length]}
+            />
           </td>
         </tr>
       </tbody>
@@ -106,6 +123,7 @@ export function computeTableHeight(
         block: data.block,
         stmts: data.stmts,
         terminator: data.terminator,
+        expirations: data.expirations,
       },
       setCurrentPoint: () => {},
       showStorageStmts,
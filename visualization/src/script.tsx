@@ -32,6 +32,8 @@ import {
   getPathData,
   getPaths,
   getPCSIterations,
+  getPathGraphs,
+  PathGraphs,
   PCSIterations,
 } from "./api";
 import { filterNodesAndEdges } from "./mir_graph";
@@ -86,6 +88,7 @@ function layoutUnsizedNodes(
         block: node.block,
         stmts: node.stmts,
         terminator: node.terminator,
+        expirations: node.expirations,
       },
       height: computeTableHeight(node, showStorageStmts),
       width: 300,
@@ -121,6 +124,7 @@ async function main() {
 
   const App: React.FC<{}> = () => {
     const [iterations, setIterations] = useState<PCSIterations>([]);
+    const [pathGraphs, setPathGraphs] = useState<PathGraphs>([]);
     const [selected, setSelected] = useState<Selection>(999); // HACK - always show last iteration
     const [pathData, setPathData] = useState<PathData | null>(null);
     const [currentPoint, setCurrentPoint] = useState<CurrentPoint>({
@@ -148,6 +152,9 @@ async function main() {
     const [showStorageStmts, setShowStorageStmts] = useState(
       localStorage.getItem("showStorageStmts") === "true"
     );
+    const [showPathGraphs, setShowPathGraphs] = useState(
+      localStorage.getItem("showPathGraphs") === "true"
+    );
 
     const { filteredNodes, filteredEdges } = filterNodesAndEdges(nodes, edges, {
       showUnwindEdges,
@@ -181,6 +188,26 @@ async function main() {
         dotGraph.innerHTML = "";
         return;
       }
+      if (showPathGraphs && pathGraphs.length > currentPoint.stmt) {
+        const graphs = await Promise.all(
+          pathGraphs[currentPoint.stmt].map(async ([path, filename]) => {
+            const dotData = await fetchDotFile(
+              `data/${selectedFunction}/${filename}`
+            );
+            return [path, dotData] as const;
+          })
+        );
+        Viz.instance().then(function (viz) {
+          dotGraph.innerHTML = "";
+          for (const [path, dotData] of graphs) {
+            const heading = document.createElement("h4");
+            heading.textContent = path.map((block) => `bb${block}`).join(" -> ");
+            dotGraph.appendChild(heading);
+            dotGraph.appendChild(viz.renderSVGElement(dotData));
+          }
+        });
+        return;
+      }
       if (iterations.length <= currentPoint.stmt) {
         return;
       }
@@ -210,7 +237,14 @@ async function main() {
 
     useEffect(() => {
       loadPCSDotGraph();
-    }, [iterations, currentPoint, selectedFunction, selected]);
+    }, [
+      iterations,
+      pathGraphs,
+      showPathGraphs,
+      currentPoint,
+      selectedFunction,
+      selected,
+    ]);
 
     useEffect(() => {
       if (selectedFunction) {
@@ -265,6 +299,7 @@ async function main() {
     useEffect(() => {
       if (currentPoint.type != "stmt") {
         setIterations([]);
+        setPathGraphs([]);
         return;
       }
       const fetchIterations = async () => {
@@ -273,6 +308,9 @@ async function main() {
           currentPoint.block
         );
         setIterations(iterations);
+        setPathGraphs(
+          await getPathGraphs(selectedFunction, currentPoint.block)
+        );
       };
 
       fetchIterations();
@@ -377,6 +415,7 @@ async function main() {
     addLocalStorageCallback("showPathBlocksOnly", showPathBlocksOnly);
     addLocalStorageCallback("showPCS", showPCS);
     addLocalStorageCallback("showStorageStmts", showStorageStmts);
+    addLocalStorageCallback("showPathGraphs", showPathGraphs);
 
     const isBlockOnSelectedPath = useCallback(
       (block: number) => {
@@ -412,7 +451,10 @@ async function main() {
                 {functions[func]}
               </option>
             ))}
-          </select>
+          </select>{" "}
+          <a href={`data/${selectedFunction}/report.html`} target="_blank">
+            Source report
+          </a>
           <br />
           <label htmlFor="path-select">Select Path:</label>
           <select
@@ -453,6 +495,15 @@ async function main() {
             />
             Show storage statements
           </label>
+          <br />
+          <label>
+            <input
+              type="checkbox"
+              checked={showPathGraphs}
+              onChange={(e) => setShowPathGraphs(e.target.checked)}
+            />
+            Show one PCS per path
+          </label>
         </div>
         <div
           className="graph-container"
@@ -12,6 +12,8 @@ export type BasicBlockData = {
   block: number;
   stmts: string[];
   terminator: string;
+  /** For each statement and the terminator, the borrows that expire there */
+  expirations: string[][];
 };
 
 export type DagreInputNode<T> = {
@@ -40,6 +42,8 @@ export type DagreNode<T> = {
 export type MaybeOldPlace = {
   place: string;
   at?: string;
+  // Set for the places that reference arguments of the function point to
+  remote?: boolean;
 };
 
 export type Borrow = {